@@ -0,0 +1,218 @@
+use std::future::Future;
+
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::agent::error::AgentError;
+use crate::agent::tool::ToolDefinition;
+use crate::bedrock::models::claude::claude_request_message::{
+    ChatOptions, ContentBlock, ConversationRequest, MessageContent, Role, Tool as ClaudeTool,
+};
+use crate::bedrock::models::claude::{ClaudeClient, Message as ClaudeMessage};
+use crate::ollama::{ChatRequestBuilder, Message as OllamaMessage, OllamaClient, Tool as OllamaTool};
+use crate::session::{OllamaChatOptions, SessionMessage};
+
+/// A call of a tool requested by the model.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCallRequest {
+    pub id: String,
+    pub name: String,
+    pub input: Value,
+}
+
+/// The result of a tool call, sent back to the model. A failed call carries its error message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCallResult {
+    pub id: String,
+    pub name: String,
+    pub output: Result<Value, String>,
+}
+
+impl ToolCallResult {
+    /// The output as sent to the model: the JSON result, or the error message.
+    pub fn content(&self) -> String {
+        match &self.output {
+            Ok(Value::String(text)) => text.clone(),
+            Ok(value) => value.to_string(),
+            Err(message) => message.clone(),
+        }
+    }
+}
+
+/// The reply of the model at one step of an agent.
+///
+/// # Fields
+///
+/// * `message` - The assistant message to append to the conversation.
+/// * `text` - The text of the reply, which is the final answer when no tool is called.
+/// * `tool_calls` - The tools to call before the next step.
+#[derive(Debug, Clone)]
+pub struct ModelTurn<M> {
+    pub message: M,
+    pub text: String,
+    pub tool_calls: Vec<ToolCallRequest>,
+}
+
+/// A chat model that can call tools, driven by an `Agent`.
+pub trait AgentBackend {
+    /// The message type of the conversation.
+    type Message: SessionMessage;
+
+    /// The model and sampling options of the requests.
+    type Options: Clone + Send + Sync;
+
+    /// Sends the conversation and the tool definitions to the model.
+    fn turn(
+        &self,
+        system: Option<&str>,
+        messages: &[Self::Message],
+        tools: &[ToolDefinition],
+        options: &Self::Options,
+    ) -> impl Future<Output = Result<ModelTurn<Self::Message>, AgentError>> + Send;
+
+    /// The messages carrying the results of the tool calls of a turn.
+    fn tool_results(results: &[ToolCallResult]) -> Vec<Self::Message>;
+}
+
+impl AgentBackend for ClaudeClient {
+    type Message = ClaudeMessage;
+    type Options = ChatOptions;
+
+    fn turn(
+        &self,
+        system: Option<&str>,
+        messages: &[Self::Message],
+        tools: &[ToolDefinition],
+        options: &Self::Options,
+    ) -> impl Future<Output = Result<ModelTurn<Self::Message>, AgentError>> + Send {
+        let tools: Vec<ClaudeTool> = tools
+            .iter()
+            .map(|tool| {
+                ClaudeTool::new(tool.name.clone(), tool.parameters.clone())
+                    .with_description(tool.description.clone())
+            })
+            .collect();
+        let request = ConversationRequest {
+            system: system.map(str::to_string),
+            messages: messages.to_vec(),
+            max_tokens: Some(options.max_tokens as i32),
+            tools: (!tools.is_empty()).then_some(tools),
+            ..Default::default()
+        };
+
+        async move {
+            let response = self.chat(&request, options).await?;
+            let mut text = Vec::new();
+            let mut tool_calls = Vec::new();
+            for block in &response.content {
+                match block {
+                    ContentBlock::Text { text: block_text } => text.push(block_text.as_str()),
+                    ContentBlock::ToolUse { id, name, input } => tool_calls.push(ToolCallRequest {
+                        id: id.clone(),
+                        name: name.clone(),
+                        input: input.clone(),
+                    }),
+                    _ => {}
+                }
+            }
+            Ok(ModelTurn {
+                text: text.join("\n"),
+                tool_calls,
+                message: ClaudeMessage {
+                    role: Role::Assistant,
+                    content: MessageContent::Blocks(response.content),
+                },
+            })
+        }
+    }
+
+    /// Claude takes the results of a turn as `tool_result` blocks of one user message.
+    fn tool_results(results: &[ToolCallResult]) -> Vec<Self::Message> {
+        let blocks = results
+            .iter()
+            .map(|result| ContentBlock::ToolResult {
+                tool_use_id: result.id.clone(),
+                content: result.content(),
+                is_error: result.output.is_err().then_some(true),
+            })
+            .collect::<Vec<_>>();
+        vec![ClaudeMessage::new_user_message(blocks)]
+    }
+}
+
+impl AgentBackend for OllamaClient {
+    type Message = OllamaMessage;
+    type Options = OllamaChatOptions;
+
+    fn turn(
+        &self,
+        system: Option<&str>,
+        messages: &[Self::Message],
+        tools: &[ToolDefinition],
+        options: &Self::Options,
+    ) -> impl Future<Output = Result<ModelTurn<Self::Message>, AgentError>> + Send {
+        let mut all_messages = Vec::with_capacity(messages.len() + 1);
+        if let Some(system) = system {
+            all_messages.push(OllamaMessage::new("system".to_string(), system.to_string()));
+        }
+        all_messages.extend_from_slice(messages);
+        // Ollama gives the calls no id: number them by their position in the conversation.
+        let call_prefix = format!("call_{}", messages.len());
+
+        let tools = tools
+            .iter()
+            .map(|tool| {
+                OllamaTool::function(
+                    tool.name.clone(),
+                    tool.description.clone(),
+                    tool.parameters.clone(),
+                )
+            })
+            .collect();
+        // The models only call tools when the response is not streamed.
+        let mut builder = ChatRequestBuilder::new(options.model.clone())
+            .messages(all_messages)
+            .tools(tools)
+            .stream(false);
+        if let Some(chat_options) = &options.options {
+            builder = builder.options(chat_options.clone());
+        }
+        if let Some(keep_alive) = &options.keep_alive {
+            builder = builder.keep_alive(keep_alive.clone());
+        }
+        let request = builder.build();
+
+        async move {
+            let responses: Vec<_> = self.chat(request).await?.try_collect().await?;
+            let mut message = OllamaMessage::new("assistant".to_string(), String::new());
+            for response in responses {
+                message.content.push_str(&response.message.content);
+                message.tool_calls.extend(response.message.tool_calls);
+            }
+            let tool_calls = message
+                .tool_calls
+                .iter()
+                .enumerate()
+                .map(|(index, call)| ToolCallRequest {
+                    id: format!("{}_{}", call_prefix, index),
+                    name: call.function.name.clone(),
+                    input: call.function.arguments.clone(),
+                })
+                .collect();
+            Ok(ModelTurn {
+                text: message.content.clone(),
+                tool_calls,
+                message,
+            })
+        }
+    }
+
+    /// Ollama takes each result in a message with the role `tool`, in the order of the calls.
+    fn tool_results(results: &[ToolCallResult]) -> Vec<Self::Message> {
+        results
+            .iter()
+            .map(|result| OllamaMessage::new("tool".to_string(), result.content()))
+            .collect()
+    }
+}
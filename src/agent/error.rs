@@ -0,0 +1,43 @@
+use serde_json::Error as SerdeJsonError;
+use thiserror::Error;
+
+use crate::bedrock::models::claude::error::ClaudeError;
+use crate::ollama::error::OllamaError;
+
+#[derive(Error, Debug)]
+pub enum AgentError {
+    #[error("JSON error: {0}")]
+    Json(#[from] SerdeJsonError),
+
+    #[error("Ollama error: {0}")]
+    Ollama(#[from] OllamaError),
+
+    #[error("Claude error: {0}")]
+    Claude(#[from] ClaudeError),
+
+    #[error("Duplicate tool: {0}")]
+    DuplicateTool(String),
+
+    #[error("No final answer after {0} steps")]
+    MaxSteps(usize),
+}
+
+/// The failure of a tool call. It is sent back to the model, which can correct its input or
+/// answer without the tool.
+#[derive(Error, Debug)]
+pub enum ToolError {
+    #[error("Invalid input: {0}")]
+    InvalidInput(#[from] SerdeJsonError),
+
+    #[error("Unknown tool: {0}")]
+    UnknownTool(String),
+
+    #[error("{0}")]
+    Failed(String),
+}
+
+impl ToolError {
+    pub fn failed<S: Into<String>>(message: S) -> Self {
+        ToolError::Failed(message.into())
+    }
+}
@@ -0,0 +1,12 @@
+//! Tool-calling agents: a registry of tools with JSON inputs and outputs, and a runner that
+//! loops over a chat model until it answers without calling a tool.
+
+pub mod backend;
+pub mod error;
+pub mod runner;
+pub mod tool;
+
+pub use backend::{AgentBackend, ModelTurn, ToolCallRequest, ToolCallResult};
+pub use error::{AgentError, ToolError};
+pub use runner::{Agent, AgentEvent, AgentResponse, DEFAULT_MAX_STEPS};
+pub use tool::{Tool, ToolDefinition, ToolFuture, ToolHandler, ToolRegistry};
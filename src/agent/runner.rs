@@ -0,0 +1,339 @@
+use async_stream::try_stream;
+use futures::future::join_all;
+use futures::stream::BoxStream;
+use futures::TryStreamExt;
+
+use crate::agent::backend::{AgentBackend, ToolCallRequest, ToolCallResult};
+use crate::agent::error::AgentError;
+use crate::agent::tool::ToolRegistry;
+
+/// The number of model calls after which an agent gives up by default.
+pub const DEFAULT_MAX_STEPS: usize = 10;
+
+/// An event of an agent run, for showing its progress.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AgentEvent<M> {
+    /// The model is called for the `step`th time, from 1.
+    StepStarted { step: usize },
+    /// The text of the reply of the model, sent along with tool calls or as the final answer.
+    Text { step: usize, text: String },
+    /// The model calls a tool. The calls of a step run in parallel.
+    ToolCall { step: usize, call: ToolCallRequest },
+    /// A tool call completed.
+    ToolResult { step: usize, result: ToolCallResult },
+    /// The model answered without calling a tool.
+    Finished(AgentResponse<M>),
+}
+
+/// The outcome of an agent run.
+///
+/// # Fields
+///
+/// * `text` - The final answer of the model.
+/// * `steps` - The number of model calls.
+/// * `messages` - The conversation, including the tool calls, their results and the answer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentResponse<M> {
+    pub text: String,
+    pub steps: usize,
+    pub messages: Vec<M>,
+}
+
+/// Drives a tool-calling loop: the model is called with the conversation and the tools, the
+/// tools it calls are run in parallel, their results are appended to the conversation, and
+/// the model is called again, until it answers without calling a tool.
+///
+/// # Example
+///
+/// ```no_run
+/// use hiramu::agent::{Agent, Tool, ToolRegistry};
+/// use hiramu::bedrock::models::claude::{ChatOptions, ClaudeClient, ClaudeError, Message};
+/// use serde_json::json;
+///
+/// # async fn run(client: ClaudeClient) -> Result<(), hiramu::agent::AgentError> {
+/// let tools = ToolRegistry::new().with_tool(Tool::new(
+///     "get_weather",
+///     "Returns the weather in a city.",
+///     json!({ "type": "object", "properties": { "city": { "type": "string" } } }),
+///     |input: serde_json::Value| async move { Ok(json!({ "city": input["city"], "sky": "sunny" })) },
+/// ));
+/// let response = Agent::new(&client, tools, ChatOptions::default().with_max_tokens(1024))
+///     .max_steps(5)
+///     .run(&[Message::new_user_message("What's the weather in Paris?".to_string())])
+///     .await?;
+/// println!("{}", response.text);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Agent<'a, B: AgentBackend> {
+    backend: &'a B,
+    tools: ToolRegistry,
+    options: B::Options,
+    system: Option<String>,
+    max_steps: usize,
+}
+
+impl<'a, B: AgentBackend + Sync> Agent<'a, B> {
+    pub fn new(backend: &'a B, tools: ToolRegistry, options: B::Options) -> Self {
+        Self {
+            backend,
+            tools,
+            options,
+            system: None,
+            max_steps: DEFAULT_MAX_STEPS,
+        }
+    }
+
+    pub fn system<S: Into<String>>(mut self, system: S) -> Self {
+        self.system = Some(system.into());
+        self
+    }
+
+    /// Sets the number of model calls after which the run fails with `MaxSteps`.
+    pub fn max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Runs the loop until the final answer.
+    pub async fn run(&self, messages: &[B::Message]) -> Result<AgentResponse<B::Message>, AgentError> {
+        let mut events = self.run_stream(messages);
+        while let Some(event) = events.try_next().await? {
+            if let AgentEvent::Finished(response) = event {
+                return Ok(response);
+            }
+        }
+        Err(AgentError::MaxSteps(self.max_steps))
+    }
+
+    /// Runs the loop and streams its events, ending with `Finished` or an error.
+    pub fn run_stream<'s>(
+        &'s self,
+        messages: &[B::Message],
+    ) -> BoxStream<'s, Result<AgentEvent<B::Message>, AgentError>> {
+        let mut conversation = messages.to_vec();
+        let definitions = self.tools.definitions();
+
+        Box::pin(try_stream! {
+            for step in 1..=self.max_steps {
+                yield AgentEvent::StepStarted { step };
+                let turn = self
+                    .backend
+                    .turn(self.system.as_deref(), &conversation, &definitions, &self.options)
+                    .await?;
+                conversation.push(turn.message);
+                if !turn.text.is_empty() {
+                    yield AgentEvent::Text { step, text: turn.text.clone() };
+                }
+                if turn.tool_calls.is_empty() {
+                    yield AgentEvent::Finished(AgentResponse {
+                        text: turn.text,
+                        steps: step,
+                        messages: conversation,
+                    });
+                    return;
+                }
+
+                for call in &turn.tool_calls {
+                    yield AgentEvent::ToolCall { step, call: call.clone() };
+                }
+                let results = self.call_tools(&turn.tool_calls).await;
+                conversation.extend(B::tool_results(&results));
+                for result in results {
+                    yield AgentEvent::ToolResult { step, result };
+                }
+            }
+            Err(AgentError::MaxSteps(self.max_steps))?;
+        })
+    }
+
+    /// Runs the tool calls in parallel, each in its own task.
+    async fn call_tools(&self, calls: &[ToolCallRequest]) -> Vec<ToolCallResult> {
+        let tasks = calls.iter().map(|call| {
+            let future = self.tools.call(&call.name, call.input.clone());
+            tokio::spawn(future)
+        });
+        let outputs = join_all(tasks).await;
+        calls
+            .iter()
+            .zip(outputs)
+            .map(|(call, output)| ToolCallResult {
+                id: call.id.clone(),
+                name: call.name.clone(),
+                output: match output {
+                    Ok(Ok(value)) => Ok(value),
+                    Ok(Err(err)) => Err(err.to_string()),
+                    Err(err) => Err(format!("the tool failed: {}", err)),
+                },
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::error::ToolError;
+    use crate::agent::tool::Tool;
+    use crate::bedrock::models::claude::claude_request_message::ChatOptions;
+    use crate::bedrock::models::claude::{ClaudeClient, Message as ClaudeMessage};
+    use crate::ollama::{Message, OllamaClient};
+    use crate::session::OllamaChatOptions;
+    use crate::testing::{
+        BedrockStubServer, MockEndpoint, MockOllamaServer, MockResponse, StubResponse,
+    };
+    use serde_json::{json, Value};
+
+    fn weather_tools() -> ToolRegistry {
+        ToolRegistry::new()
+            .with_tool(Tool::new(
+                "get_weather",
+                "Returns the weather in a city.",
+                json!({ "type": "object", "properties": { "city": { "type": "string" } } }),
+                |input: Value| async move {
+                    Ok(json!({ "city": input["city"], "sky": "sunny" }))
+                },
+            ))
+            .with_tool(Tool::new(
+                "get_time",
+                "Returns the time in a city.",
+                json!({ "type": "object" }),
+                |_: Value| async move { Err(ToolError::failed("the clock is broken")) },
+            ))
+    }
+
+    fn ollama_reply(content: &str, tool_calls: Value) -> MockResponse {
+        MockResponse::json(json!({
+            "model": "llama3",
+            "created_at": "2024-05-01T00:00:00Z",
+            "message": { "role": "assistant", "content": content, "tool_calls": tool_calls },
+            "done": true
+        }))
+    }
+
+    fn claude_reply(content: Value, stop_reason: &str) -> StubResponse {
+        StubResponse::json(json!({
+            "id": "msg_stub",
+            "type": "message",
+            "role": "assistant",
+            "model": "anthropic.claude-3-haiku-20240307-v1:0",
+            "content": content,
+            "stop_reason": stop_reason,
+            "stop_sequence": null,
+            "usage": { "input_tokens": 100, "output_tokens": 20 }
+        }))
+    }
+
+    fn question() -> Vec<Message> {
+        vec![Message::new("user".to_string(), "Weather and time in Paris?".to_string())]
+    }
+
+    #[tokio::test]
+    async fn test_ollama_agent_calls_tools() {
+        let server = MockOllamaServer::start().await.unwrap();
+        server.enqueue(
+            MockEndpoint::Chat,
+            ollama_reply(
+                "",
+                json!([
+                    { "function": { "name": "get_weather", "arguments": { "city": "Paris" } } },
+                    { "function": { "name": "get_time", "arguments": {} } }
+                ]),
+            ),
+        );
+        server.enqueue(MockEndpoint::Chat, ollama_reply("It is sunny in Paris.", json!([])));
+        let client = OllamaClient::new(server.base_url());
+        let agent = Agent::new(&client, weather_tools(), OllamaChatOptions::new("llama3"))
+            .system("Use the tools.");
+
+        let events: Vec<_> = agent.run_stream(&question()).try_collect().await.unwrap();
+        assert_eq!(events.len(), 8);
+        assert!(matches!(events[0], AgentEvent::StepStarted { step: 1 }));
+        match &events[3] {
+            AgentEvent::ToolResult { step, result } => {
+                assert_eq!(*step, 1);
+                assert_eq!(
+                    *result,
+                    ToolCallResult {
+                        id: "call_1_0".to_string(),
+                        name: "get_weather".to_string(),
+                        output: Ok(json!({ "city": "Paris", "sky": "sunny" })),
+                    }
+                );
+            }
+            other => panic!("expected a tool result, got {:?}", other),
+        }
+        match &events[7] {
+            AgentEvent::Finished(response) => {
+                assert_eq!(response.text, "It is sunny in Paris.");
+                assert_eq!(response.steps, 2);
+                assert_eq!(response.messages.len(), 5);
+            }
+            other => panic!("expected the end of the run, got {:?}", other),
+        }
+
+        let requests = server.requests_to(MockEndpoint::Chat);
+        assert_eq!(requests[0].body["stream"], false);
+        assert_eq!(requests[0].body["tools"][0]["function"]["name"], "get_time");
+        let messages = requests[1].body["messages"].as_array().unwrap();
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(
+            messages[3],
+            json!({ "role": "tool", "content": r#"{"city":"Paris","sky":"sunny"}"# })
+        );
+        assert_eq!(messages[4]["content"], "the clock is broken");
+    }
+
+    #[tokio::test]
+    async fn test_agent_stops_at_max_steps() {
+        let server = MockOllamaServer::start().await.unwrap();
+        server.set_default(
+            MockEndpoint::Chat,
+            ollama_reply("", json!([{ "function": { "name": "get_weather", "arguments": {} } }])),
+        );
+        let client = OllamaClient::new(server.base_url());
+
+        let result = Agent::new(&client, weather_tools(), OllamaChatOptions::new("llama3"))
+            .max_steps(3)
+            .run(&question())
+            .await;
+        assert!(matches!(result, Err(AgentError::MaxSteps(3))), "{:?}", result.err());
+        assert_eq!(server.requests_to(MockEndpoint::Chat).len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_claude_agent_sends_tool_results() {
+        let server = BedrockStubServer::start().await.unwrap();
+        server.enqueue(claude_reply(
+            json!([
+                { "type": "text", "text": "Let me check." },
+                { "type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": { "city": "Paris" } },
+                { "type": "tool_use", "id": "toolu_2", "name": "unknown", "input": {} }
+            ]),
+            "tool_use",
+        ));
+        server.enqueue(claude_reply(json!([{ "type": "text", "text": "Sunny." }]), "end_turn"));
+        let client = ClaudeClient::new(server.client_options()).await.unwrap();
+
+        let response = Agent::new(&client, weather_tools(), ChatOptions::default())
+            .run(&[ClaudeMessage::new_user_message("Weather in Paris?".to_string())])
+            .await
+            .unwrap();
+        assert_eq!(response.text, "Sunny.");
+        assert_eq!(response.steps, 2);
+
+        let requests = server.requests();
+        assert_eq!(requests[0].body["tools"][1]["name"], "get_weather");
+        let results = &requests[1].body["messages"][2];
+        assert_eq!(results["role"], "user");
+        assert_eq!(
+            results["content"][0],
+            json!({
+                "type": "tool_result",
+                "tool_use_id": "toolu_1",
+                "content": r#"{"city":"Paris","sky":"sunny"}"#
+            })
+        );
+        assert_eq!(results["content"][1]["is_error"], true);
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::agent::error::{AgentError, ToolError};
+use crate::extract::schema_for;
+
+/// The future of a tool call.
+pub type ToolFuture = BoxFuture<'static, Result<Value, ToolError>>;
+
+/// The name, description and input schema of a tool, as shown to the model.
+///
+/// # Fields
+///
+/// * `name` - The name the model calls the tool by, e.g. `get_weather`.
+/// * `description` - What the tool does and when to use it.
+/// * `parameters` - The JSON Schema of the input, an object.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: Value,
+}
+
+/// The code run when the model calls a tool.
+///
+/// Implemented for the closures taking the JSON input and returning a future of the JSON
+/// result.
+pub trait ToolHandler: Send + Sync {
+    fn call(&self, input: Value) -> ToolFuture;
+}
+
+impl<F, Fut> ToolHandler for F
+where
+    F: Fn(Value) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<Value, ToolError>> + Send + 'static,
+{
+    fn call(&self, input: Value) -> ToolFuture {
+        self(input).boxed()
+    }
+}
+
+/// A tool the model can call: its definition and its handler.
+#[derive(Clone)]
+pub struct Tool {
+    definition: ToolDefinition,
+    handler: Arc<dyn ToolHandler>,
+}
+
+impl Tool {
+    /// Creates a tool taking a JSON input described by `parameters`.
+    pub fn new<N, D, H>(name: N, description: D, parameters: Value, handler: H) -> Self
+    where
+        N: Into<String>,
+        D: Into<String>,
+        H: ToolHandler + 'static,
+    {
+        Self {
+            definition: ToolDefinition {
+                name: name.into(),
+                description: description.into(),
+                parameters,
+            },
+            handler: Arc::new(handler),
+        }
+    }
+
+    /// Creates a tool taking a typed input. The input schema is generated from `I`, and an
+    /// input that does not deserialize into `I` is reported to the model as invalid.
+    pub fn typed<I, N, D, F, Fut>(name: N, description: D, handler: F) -> Self
+    where
+        I: DeserializeOwned + JsonSchema + Send + 'static,
+        N: Into<String>,
+        D: Into<String>,
+        F: Fn(I) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Value, ToolError>> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+        Self::new(name, description, schema_for::<I>(), move |input: Value| {
+            let handler = handler.clone();
+            async move {
+                let input: I = serde_json::from_value(input)?;
+                handler(input).await
+            }
+        })
+    }
+
+    pub fn definition(&self) -> &ToolDefinition {
+        &self.definition
+    }
+
+    pub fn name(&self) -> &str {
+        &self.definition.name
+    }
+
+    /// Runs the handler on `input`.
+    pub fn call(&self, input: Value) -> ToolFuture {
+        self.handler.call(input)
+    }
+}
+
+/// The tools available to an agent, by name.
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    tools: BTreeMap<String, Tool>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a tool, or fails if a tool with the same name is registered.
+    pub fn register(&mut self, tool: Tool) -> Result<(), AgentError> {
+        if self.tools.contains_key(tool.name()) {
+            return Err(AgentError::DuplicateTool(tool.name().to_string()));
+        }
+        self.tools.insert(tool.name().to_string(), tool);
+        Ok(())
+    }
+
+    /// Adds a tool, replacing a tool with the same name.
+    pub fn with_tool(mut self, tool: Tool) -> Self {
+        self.tools.insert(tool.name().to_string(), tool);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Tool> {
+        self.tools.get(name)
+    }
+
+    /// The definitions of the tools, sorted by name.
+    pub fn definitions(&self) -> Vec<ToolDefinition> {
+        self.tools.values().map(|tool| tool.definition().clone()).collect()
+    }
+
+    /// Calls the tool `name`. An unknown tool fails the call, not the agent.
+    pub fn call(&self, name: &str, input: Value) -> ToolFuture {
+        match self.get(name) {
+            Some(tool) => tool.call(input),
+            None => {
+                let err = ToolError::UnknownTool(name.to_string());
+                async move { Err(err) }.boxed()
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tools.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Deserialize, JsonSchema)]
+    struct AddInput {
+        a: i64,
+        b: i64,
+    }
+
+    fn registry() -> ToolRegistry {
+        ToolRegistry::new()
+            .with_tool(Tool::typed("add", "Adds two integers.", |input: AddInput| async move {
+                Ok(json!(input.a + input.b))
+            }))
+            .with_tool(Tool::new(
+                "fail",
+                "Always fails.",
+                json!({ "type": "object" }),
+                |_input: Value| async { Err(ToolError::failed("the service is down")) },
+            ))
+    }
+
+    #[tokio::test]
+    async fn test_registry_calls() {
+        let registry = registry();
+        assert_eq!(registry.len(), 2);
+        let definitions = registry.definitions();
+        assert_eq!(definitions[0].name, "add");
+        assert_eq!(definitions[0].parameters["required"], json!(["a", "b"]));
+
+        assert_eq!(registry.call("add", json!({ "a": 2, "b": 3 })).await.unwrap(), json!(5));
+        assert!(matches!(
+            registry.call("add", json!({ "a": "two" })).await,
+            Err(ToolError::InvalidInput(_))
+        ));
+        assert_eq!(
+            registry.call("fail", json!({})).await.unwrap_err().to_string(),
+            "the service is down"
+        );
+        assert!(matches!(
+            registry.call("divide", json!({})).await,
+            Err(ToolError::UnknownTool(name)) if name == "divide"
+        ));
+
+        let mut registry = registry;
+        assert!(matches!(
+            registry.register(Tool::new("add", "Again.", json!({}), |_: Value| async { Ok(Value::Null) })),
+            Err(AgentError::DuplicateTool(name)) if name == "add"
+        ));
+    }
+}
@@ -0,0 +1,394 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+use aws_sdk_bedrock::types::{
+    ModelInvocationJobOutputDataConfig, ModelInvocationJobStatus, ModelInvocationJobSummary,
+};
+use aws_sdk_bedrock::operation::get_model_invocation_job::GetModelInvocationJobOutput;
+use aws_smithy_types::DateTime;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::bedrock::error::BedrockError;
+
+/// A line of the JSONL input file of a batch inference job.
+///
+/// `model_input` is the body that would be sent to `InvokeModel`, e.g. a
+/// `ConversationRequest` or a `MistralRequest`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchRecord<T> {
+    pub record_id: String,
+    pub model_input: T,
+}
+
+impl<T> BatchRecord<T> {
+    pub fn new<S: Into<String>>(record_id: S, model_input: T) -> Self {
+        Self {
+            record_id: record_id.into(),
+            model_input,
+        }
+    }
+}
+
+/// The error reported for a record the model could not process.
+#[derive(Error, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[error("record error {}: {error_message}", error_code.map(|code| code.to_string()).unwrap_or_default())]
+pub struct BatchRecordError {
+    #[serde(default)]
+    pub error_code: Option<i64>,
+    #[serde(default)]
+    pub error_message: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchOutputRecord<O> {
+    record_id: String,
+    model_output: Option<O>,
+    error: Option<BatchRecordError>,
+}
+
+/// Writes the records as the JSONL input format of a batch inference job, one record per line.
+///
+/// The file must then be uploaded to the S3 location given as `input_s3_uri` to
+/// `BatchJobConfig`.
+///
+/// # Arguments
+///
+/// * `writer` - Where to write the JSONL, e.g. a `File` or a `Vec<u8>`.
+/// * `records` - The records to write. Record ids should be unique within the job.
+///
+/// # Returns
+///
+/// The number of records written.
+///
+pub fn write_batch_input<T, W, I>(mut writer: W, records: I) -> Result<usize, BedrockError>
+where
+    T: Serialize,
+    W: Write,
+    I: IntoIterator<Item = BatchRecord<T>>,
+{
+    let mut count = 0;
+    for record in records {
+        serde_json::to_writer(&mut writer, &record)?;
+        writer.write_all(b"\n")?;
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+/// Parses an output file of a batch inference job, e.g. `input.jsonl.out`.
+///
+/// # Arguments
+///
+/// * `reader` - The JSONL output, as downloaded from the job output S3 location.
+///
+/// # Returns
+///
+/// The model outputs keyed by record id, or the error reported for the record.
+///
+pub fn read_batch_output<O, R>(
+    reader: R,
+) -> Result<HashMap<String, Result<O, BatchRecordError>>, BedrockError>
+where
+    O: DeserializeOwned,
+    R: BufRead,
+{
+    let mut outputs = HashMap::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: BatchOutputRecord<O> = serde_json::from_str(&line)?;
+        let output = match (record.model_output, record.error) {
+            (_, Some(error)) => Err(error),
+            (Some(output), None) => Ok(output),
+            (None, None) => Err(BatchRecordError {
+                error_code: None,
+                error_message: "no model output".to_string(),
+            }),
+        };
+        outputs.insert(record.record_id, output);
+    }
+    Ok(outputs)
+}
+
+/// The parameters of a batch inference job.
+///
+/// # Fields
+///
+/// * `job_name` - A name for the job, unique in the account and region.
+/// * `role_arn` - The service role Bedrock assumes to read the input and write the output.
+/// * `model_id` - The model id, e.g. `anthropic.claude-3-haiku-20240307-v1:0`.
+/// * `input_s3_uri` - The S3 location of the input JSONL file, or of a folder of files.
+/// * `output_s3_uri` - The S3 folder where the job writes its output.
+/// * `output_kms_key_id` - The KMS key used to encrypt the output.
+/// * `timeout_hours` - How long the job can run before it is stopped.
+/// * `client_request_token` - An idempotency token, so that a retried submission
+///   does not create a second job.
+///
+#[derive(Debug, Clone)]
+pub struct BatchJobConfig {
+    pub job_name: String,
+    pub role_arn: String,
+    pub model_id: String,
+    pub input_s3_uri: String,
+    pub output_s3_uri: String,
+    pub output_kms_key_id: Option<String>,
+    pub timeout_hours: Option<i32>,
+    pub client_request_token: Option<String>,
+}
+
+impl BatchJobConfig {
+    pub fn new<S: Into<String>>(
+        job_name: S,
+        role_arn: S,
+        model_id: S,
+        input_s3_uri: S,
+        output_s3_uri: S,
+    ) -> Self {
+        Self {
+            job_name: job_name.into(),
+            role_arn: role_arn.into(),
+            model_id: model_id.into(),
+            input_s3_uri: input_s3_uri.into(),
+            output_s3_uri: output_s3_uri.into(),
+            output_kms_key_id: None,
+            timeout_hours: None,
+            client_request_token: None,
+        }
+    }
+
+    pub fn output_kms_key_id<S: Into<String>>(mut self, output_kms_key_id: S) -> Self {
+        self.output_kms_key_id = Some(output_kms_key_id.into());
+        self
+    }
+
+    pub fn timeout_hours(mut self, timeout_hours: i32) -> Self {
+        self.timeout_hours = Some(timeout_hours);
+        self
+    }
+
+    pub fn client_request_token<S: Into<String>>(mut self, client_request_token: S) -> Self {
+        self.client_request_token = Some(client_request_token.into());
+        self
+    }
+}
+
+/// The state of a batch inference job.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchJob {
+    pub job_arn: String,
+    pub job_name: Option<String>,
+    pub model_id: String,
+    pub status: Option<ModelInvocationJobStatus>,
+    /// Why the job failed or was stopped.
+    pub message: Option<String>,
+    pub submit_time: DateTime,
+    pub end_time: Option<DateTime>,
+    pub output_s3_uri: Option<String>,
+}
+
+impl BatchJob {
+    /// Whether the job has stopped running, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self.status,
+            Some(ModelInvocationJobStatus::Completed)
+                | Some(ModelInvocationJobStatus::PartiallyCompleted)
+                | Some(ModelInvocationJobStatus::Failed)
+                | Some(ModelInvocationJobStatus::Stopped)
+                | Some(ModelInvocationJobStatus::Expired)
+        )
+    }
+
+    /// Whether the job has not finished yet: it is submitted, being validated, scheduled,
+    /// in progress or stopping. `false` for a status this crate does not know, or no status.
+    pub fn is_running(&self) -> bool {
+        matches!(
+            self.status,
+            Some(ModelInvocationJobStatus::Submitted)
+                | Some(ModelInvocationJobStatus::Validating)
+                | Some(ModelInvocationJobStatus::Scheduled)
+                | Some(ModelInvocationJobStatus::InProgress)
+                | Some(ModelInvocationJobStatus::Stopping)
+        )
+    }
+
+    /// Whether the job wrote its output. A partially completed job has output for some records,
+    /// and errors for the others.
+    pub fn has_output(&self) -> bool {
+        matches!(
+            self.status,
+            Some(ModelInvocationJobStatus::Completed)
+                | Some(ModelInvocationJobStatus::PartiallyCompleted)
+        )
+    }
+}
+
+fn output_s3_uri(config: Option<&ModelInvocationJobOutputDataConfig>) -> Option<String> {
+    match config {
+        Some(ModelInvocationJobOutputDataConfig::S3OutputDataConfig(s3)) => {
+            Some(s3.s3_uri.clone())
+        }
+        _ => None,
+    }
+}
+
+impl From<GetModelInvocationJobOutput> for BatchJob {
+    fn from(output: GetModelInvocationJobOutput) -> Self {
+        BatchJob {
+            output_s3_uri: output_s3_uri(output.output_data_config.as_ref()),
+            job_arn: output.job_arn,
+            job_name: output.job_name,
+            model_id: output.model_id,
+            status: output.status,
+            message: output.message,
+            submit_time: output.submit_time,
+            end_time: output.end_time,
+        }
+    }
+}
+
+impl From<ModelInvocationJobSummary> for BatchJob {
+    fn from(summary: ModelInvocationJobSummary) -> Self {
+        BatchJob {
+            output_s3_uri: output_s3_uri(summary.output_data_config.as_ref()),
+            job_arn: summary.job_arn,
+            job_name: Some(summary.job_name),
+            model_id: summary.model_id,
+            status: summary.status,
+            message: summary.message,
+            submit_time: summary.submit_time,
+            end_time: summary.end_time,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bedrock::models::claude::claude_request_message::{
+        ContentBlock, ConversationRequest, ConversationResponse, Message,
+    };
+    use crate::bedrock::models::mistral::{MistralRequestBuilder, MistralResponse};
+
+    #[test]
+    fn test_write_batch_input() {
+        let records = vec![
+            BatchRecord::new(
+                "ticket-1",
+                ConversationRequest {
+                    messages: vec![Message::new_user_message("Summarize ticket 1".to_string())],
+                    ..Default::default()
+                },
+            ),
+            BatchRecord::new(
+                "ticket-2",
+                ConversationRequest {
+                    messages: vec![Message::new_user_message("Summarize ticket 2".to_string())],
+                    ..Default::default()
+                },
+            ),
+        ];
+
+        let mut buffer = Vec::new();
+        let count = write_batch_input(&mut buffer, records).unwrap();
+        let jsonl = String::from_utf8(buffer).unwrap();
+        let lines: Vec<serde_json::Value> = jsonl
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(count, 2);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["recordId"], "ticket-1");
+        assert_eq!(lines[1]["modelInput"]["anthropic_version"], "bedrock-2023-05-31");
+    }
+
+    #[test]
+    fn test_write_mistral_batch_input() {
+        let request = MistralRequestBuilder::new("<s>[INST] Hello [/INST]".to_string()).build();
+
+        let mut buffer = Vec::new();
+        write_batch_input(&mut buffer, vec![BatchRecord::new("a", request)]).unwrap();
+
+        assert!(String::from_utf8(buffer).unwrap().starts_with(
+            r#"{"recordId":"a","modelInput":{"prompt":"<s>[INST] Hello [/INST]""#
+        ));
+    }
+
+    #[test]
+    fn test_read_claude_batch_output() {
+        let jsonl = r#"{"recordId":"ticket-1","modelInput":{},"modelOutput":{"id":"msg_1","model":"claude","type":"message","role":"assistant","content":[{"type":"text","text":"A summary"}],"stop_reason":"end_turn","stop_sequence":null,"usage":{"input_tokens":10,"output_tokens":3}}}
+
+{"recordId":"ticket-2","modelInput":{},"error":{"errorCode":400,"errorMessage":"Malformed input"}}
+"#;
+
+        let outputs = read_batch_output::<ConversationResponse, _>(jsonl.as_bytes()).unwrap();
+
+        assert_eq!(outputs.len(), 2);
+        let response = outputs["ticket-1"].as_ref().unwrap();
+        assert!(matches!(&response.content[0], ContentBlock::Text { text } if text == "A summary"));
+        let error = outputs["ticket-2"].as_ref().unwrap_err();
+        assert_eq!(error.error_code, Some(400));
+        assert_eq!(error.error_message, "Malformed input");
+    }
+
+    #[test]
+    fn test_read_mistral_batch_output() {
+        let jsonl = r#"{"recordId":"a","modelInput":{},"modelOutput":{"outputs":[{"text":"Hi","stop_reason":"stop"}]}}"#;
+
+        let outputs = read_batch_output::<MistralResponse, _>(jsonl.as_bytes()).unwrap();
+
+        assert_eq!(outputs["a"].as_ref().unwrap().outputs[0].text, "Hi");
+    }
+
+    #[test]
+    fn test_batch_job_status() {
+        let summary = ModelInvocationJobSummary::builder()
+            .job_arn("arn:aws:bedrock:us-east-1:123456789012:model-invocation-job/abc")
+            .job_name("nightly")
+            .model_id("anthropic.claude-3-haiku-20240307-v1:0")
+            .role_arn("arn:aws:iam::123456789012:role/batch")
+            .status(ModelInvocationJobStatus::PartiallyCompleted)
+            .submit_time(DateTime::from_secs(0))
+            .build()
+            .unwrap();
+
+        let job = BatchJob::from(summary);
+
+        assert!(job.is_finished());
+        assert!(!job.is_running());
+        assert!(job.has_output());
+        assert_eq!(job.job_name.as_deref(), Some("nightly"));
+    }
+
+    #[test]
+    fn test_batch_job_unknown_status() {
+        let summary = ModelInvocationJobSummary::builder()
+            .job_arn("arn:aws:bedrock:us-east-1:123456789012:model-invocation-job/abc")
+            .job_name("nightly")
+            .model_id("anthropic.claude-3-haiku-20240307-v1:0")
+            .role_arn("arn:aws:iam::123456789012:role/batch")
+            .status(ModelInvocationJobStatus::InProgress)
+            .submit_time(DateTime::from_secs(0))
+            .build()
+            .unwrap();
+        let mut job = BatchJob::from(summary);
+        assert!(job.is_running());
+
+        job.status = Some(ModelInvocationJobStatus::from("Archived"));
+        assert!(!job.is_running());
+        assert!(!job.is_finished());
+
+        job.status = None;
+        assert!(!job.is_running());
+        assert!(!job.is_finished());
+    }
+}
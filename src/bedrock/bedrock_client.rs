@@ -1,23 +1,125 @@
 use crate::bedrock::error::BedrockError;
-use aws_config::Region;
+use crate::bedrock::guardrail::{GuardrailAction, GuardrailCheck, GuardrailConfig, GuardrailSource};
+use crate::telemetry::{GenAiSpan, OPERATION_INVOKE_MODEL, PROVIDER_BEDROCK};
+use aws_config::provider_config::ProviderConfig;
+use aws_config::retry::RetryConfig;
+use aws_config::sts::AssumeRoleProvider;
+use aws_config::timeout::TimeoutConfig;
+use aws_config::web_identity_token::{StaticConfiguration, WebIdentityTokenCredentialsProvider};
+use aws_config::{AppName, Region, SdkConfig};
 use aws_sdk_bedrock::config::BehaviorVersion;
-use aws_sdk_bedrockruntime::Client;
+use aws_sdk_bedrockruntime::config::{
+    Credentials, HttpClient, ProvideCredentials, SharedCredentialsProvider, SharedHttpClient,
+};
+use aws_sdk_bedrockruntime::types::{GuardrailContentBlock, GuardrailTextBlock, Trace};
+use aws_sdk_bedrockruntime::{Client, Config as RuntimeConfig};
 use futures::stream::Stream;
 use serde_json::Value;
 use std::borrow::Cow;
+use std::path::PathBuf;
+use std::time::Duration;
 use tokio_stream::wrappers::UnboundedReceiverStream;
 
+/// A role to assume with STS, on top of the credentials resolved before it.
+///
+/// # Fields
+///
+/// * `role_arn` - The ARN of the role to assume.
+/// * `session_name` - The name of the role session, visible in CloudTrail.
+/// * `external_id` - The external id required by the trust policy of the role, if any.
+/// * `session_length` - How long the temporary credentials are valid. STS defaults to one hour.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssumeRoleConfig {
+    pub role_arn: String,
+    pub session_name: Option<String>,
+    pub external_id: Option<String>,
+    pub session_length: Option<Duration>,
+}
+
+impl AssumeRoleConfig {
+    pub fn new<S: Into<String>>(role_arn: S) -> Self {
+        Self {
+            role_arn: role_arn.into(),
+            session_name: None,
+            external_id: None,
+            session_length: None,
+        }
+    }
+
+    pub fn session_name<S: Into<String>>(mut self, session_name: S) -> Self {
+        self.session_name = Some(session_name.into());
+        self
+    }
+
+    pub fn external_id<S: Into<String>>(mut self, external_id: S) -> Self {
+        self.external_id = Some(external_id.into());
+        self
+    }
+
+    pub fn session_length(mut self, session_length: Duration) -> Self {
+        self.session_length = Some(session_length);
+        self
+    }
+}
+
+/// A role to assume with a web identity token, e.g. the service account token of an EKS pod.
+///
+/// The default credentials chain already assumes the role given by the `AWS_ROLE_ARN` and
+/// `AWS_WEB_IDENTITY_TOKEN_FILE` environment variables that IRSA sets. Use this to configure
+/// the token file and role explicitly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebIdentityConfig {
+    pub role_arn: String,
+    pub token_file: PathBuf,
+    pub session_name: String,
+}
+
+impl WebIdentityConfig {
+    pub fn new<S: Into<String>, P: Into<PathBuf>>(role_arn: S, token_file: P) -> Self {
+        Self {
+            role_arn: role_arn.into(),
+            token_file: token_file.into(),
+            session_name: "hiramu".to_string(),
+        }
+    }
+
+    pub fn session_name<S: Into<String>>(mut self, session_name: S) -> Self {
+        self.session_name = session_name.into();
+        self
+    }
+}
+
 /// Configuration options for creating a `BedrockClient`.
 ///
 /// This struct holds optional configuration values that can be used when creating a `BedrockClient`.
-/// These include the AWS profile name, region, endpoint URL, and behavior version.
+/// Every value left unset is resolved by the AWS SDK from the environment, the shared config
+/// files or the instance metadata, like the AWS CLI does.
+///
+/// Credentials are resolved in this order: the static credentials or credentials provider,
+/// else the web identity, else the default credentials chain. The roles added with
+/// `assume_role` are then assumed one after the other, each with the credentials of the
+/// previous one.
 ///
 /// # Fields
 ///
 /// * `profile_name` - The name of the AWS profile to use for authentication. If `None`, the default profile is used.
 /// * `region` - The AWS region to use. If `None`, the region is determined from the environment or AWS configuration.
 /// * `endpoint_url` - The endpoint URL to use for the Bedrock service. If `None`, the default endpoint for the region is used.
+///   The STS calls made to assume the roles still go to the STS endpoint of the region.
 /// * `behavior_version` - The behavior version to use for the Bedrock service. If `None`, the latest version is used.
+/// * `guardrail` - The guardrail applied to every invocation, unless another one is given per call.
+/// * `credentials_provider` - The credentials to use instead of the default credentials chain.
+/// * `web_identity` - A role to assume with a web identity token instead of the default credentials chain.
+/// * `assume_roles` - The roles to assume, in order, on top of the resolved credentials.
+/// * `retry_config` - How failed requests are retried. If `None`, the SDK standard retry mode is used.
+/// * `operation_timeout` - The time limit of a call, retries included.
+/// * `operation_attempt_timeout` - The time limit of a single attempt of a call.
+/// * `app_name` - The application name added to the user agent of the requests.
+/// * `http_client` - The HTTP client used to send the requests, e.g. to use a proxy or to record them.
+/// * `sdk_config` - An already loaded `SdkConfig` to use instead of loading one; the other options override it,
+///   except `profile_name` and `behavior_version`, which only apply to loading and cannot be combined with it.
+/// * `runtime_config` - A complete Bedrock runtime `Config`, used as is by `BedrockClient`.
 ///
 #[derive(Debug, Clone)]
 pub struct BedrockClientOptions {
@@ -25,15 +127,37 @@ pub struct BedrockClientOptions {
     region: Option<String>,
     endpoint_url: Option<String>,
     behavior_version: Option<BehaviorVersion>,
+    guardrail: Option<GuardrailConfig>,
+    credentials_provider: Option<SharedCredentialsProvider>,
+    web_identity: Option<WebIdentityConfig>,
+    assume_roles: Vec<AssumeRoleConfig>,
+    retry_config: Option<RetryConfig>,
+    operation_timeout: Option<Duration>,
+    operation_attempt_timeout: Option<Duration>,
+    app_name: Option<String>,
+    http_client: Option<SharedHttpClient>,
+    sdk_config: Option<SdkConfig>,
+    runtime_config: Option<RuntimeConfig>,
 }
 
 impl BedrockClientOptions {
     pub fn new() -> Self {
         Self {
             profile_name: None,
-            region: Some("us-west-2".to_string()),
+            region: None,
             endpoint_url: None,
-            behavior_version: Some(BehaviorVersion::v2023_11_09()),
+            behavior_version: None,
+            guardrail: None,
+            credentials_provider: None,
+            web_identity: None,
+            assume_roles: Vec::new(),
+            retry_config: None,
+            operation_timeout: None,
+            operation_attempt_timeout: None,
+            app_name: None,
+            http_client: None,
+            sdk_config: None,
+            runtime_config: None,
         }
     }
 
@@ -56,10 +180,231 @@ impl BedrockClientOptions {
         self.behavior_version = Some(behavior_version);
         self
     }
+
+    pub fn guardrail(mut self, guardrail: GuardrailConfig) -> Self {
+        self.guardrail = Some(guardrail);
+        self
+    }
+
+    /// Uses static credentials, e.g. read from a secret store.
+    pub fn credentials<S: Into<String>>(
+        mut self,
+        access_key_id: S,
+        secret_access_key: S,
+        session_token: Option<String>,
+    ) -> Self {
+        let credentials = Credentials::new(
+            access_key_id,
+            secret_access_key,
+            session_token,
+            None,
+            "hiramu",
+        );
+        self.credentials_provider = Some(SharedCredentialsProvider::new(credentials));
+        self
+    }
+
+    pub fn credentials_provider(mut self, provider: impl ProvideCredentials + 'static) -> Self {
+        self.credentials_provider = Some(SharedCredentialsProvider::new(provider));
+        self
+    }
+
+    pub fn web_identity(mut self, web_identity: WebIdentityConfig) -> Self {
+        self.web_identity = Some(web_identity);
+        self
+    }
+
+    /// Assumes a role with the credentials resolved so far. Call it several times to chain roles.
+    pub fn assume_role(mut self, assume_role: AssumeRoleConfig) -> Self {
+        self.assume_roles.push(assume_role);
+        self
+    }
+
+    pub fn retry_config(mut self, retry_config: RetryConfig) -> Self {
+        self.retry_config = Some(retry_config);
+        self
+    }
+
+    /// Uses the standard retry mode with at most `max_attempts` attempts per call.
+    pub fn max_attempts(self, max_attempts: u32) -> Self {
+        self.retry_config(RetryConfig::standard().with_max_attempts(max_attempts))
+    }
+
+    pub fn operation_timeout(mut self, operation_timeout: Duration) -> Self {
+        self.operation_timeout = Some(operation_timeout);
+        self
+    }
+
+    pub fn operation_attempt_timeout(mut self, operation_attempt_timeout: Duration) -> Self {
+        self.operation_attempt_timeout = Some(operation_attempt_timeout);
+        self
+    }
+
+    pub fn app_name<S: Into<String>>(mut self, app_name: S) -> Self {
+        self.app_name = Some(app_name.into());
+        self
+    }
+
+    pub fn http_client(mut self, http_client: impl HttpClient + 'static) -> Self {
+        self.http_client = Some(SharedHttpClient::new(http_client));
+        self
+    }
+
+    pub fn sdk_config(mut self, sdk_config: SdkConfig) -> Self {
+        self.sdk_config = Some(sdk_config);
+        self
+    }
+
+    /// Uses a complete Bedrock runtime configuration. `BedrockClient` then ignores the other
+    /// options, except `guardrail`.
+    pub fn runtime_config(mut self, runtime_config: RuntimeConfig) -> Self {
+        self.runtime_config = Some(runtime_config);
+        self
+    }
+}
+
+impl Default for BedrockClientOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Loads the shared AWS configuration described by the options.
+///
+/// Used by both the runtime and the control plane clients.
+pub(crate) async fn load_sdk_config(options: BedrockClientOptions) -> Result<SdkConfig, BedrockError> {
+    let base_config = match options.sdk_config {
+        Some(_) if options.profile_name.is_some() || options.behavior_version.is_some() => {
+            return Err(BedrockError::Config(
+                "profile_name and behavior_version cannot be combined with sdk_config".to_string(),
+            ));
+        }
+        Some(sdk_config) => sdk_config,
+        None => {
+            let mut config_loader = aws_config::ConfigLoader::default();
+
+            if let Some(profile_name) = options.profile_name {
+                config_loader = config_loader.profile_name(profile_name);
+            }
+
+            if let Some(region) = &options.region {
+                config_loader = config_loader.region(Region::new(region.clone()));
+            }
+
+            config_loader = config_loader
+                .behavior_version(options.behavior_version.unwrap_or_else(BehaviorVersion::latest));
+
+            if let Some(http_client) = &options.http_client {
+                config_loader = config_loader.http_client(http_client.clone());
+            }
+
+            config_loader.load().await
+        }
+    };
+
+    // The endpoint URL is only set once the credentials providers are built: the STS
+    // clients of the assumed roles copy it from the config, and must not call Bedrock.
+    let endpoint_url = options
+        .endpoint_url
+        .or_else(|| base_config.endpoint_url().map(str::to_string));
+    let mut builder = base_config.to_builder();
+    builder.set_endpoint_url(None);
+
+    if let Some(region) = options.region {
+        builder = builder.region(Region::new(region));
+    }
+
+    if let Some(retry_config) = options.retry_config {
+        builder = builder.retry_config(retry_config);
+    }
+
+    if options.operation_timeout.is_some() || options.operation_attempt_timeout.is_some() {
+        let mut timeout_config = TimeoutConfig::builder();
+        timeout_config.set_operation_timeout(options.operation_timeout);
+        timeout_config.set_operation_attempt_timeout(options.operation_attempt_timeout);
+        let mut timeout_config = timeout_config.build();
+        if let Some(defaults) = base_config.timeout_config() {
+            timeout_config.take_defaults_from(defaults);
+        }
+        builder = builder.timeout_config(timeout_config);
+    }
+
+    if let Some(app_name) = options.app_name {
+        let app_name = AppName::new(app_name)
+            .map_err(|err| BedrockError::Config(format!("invalid app name: {}", err)))?;
+        builder = builder.app_name(app_name);
+    }
+
+    if let Some(http_client) = &options.http_client {
+        builder = builder.http_client(http_client.clone());
+    }
+
+    let config = builder.build();
+
+    let credentials_provider = match (options.credentials_provider, options.web_identity) {
+        (Some(credentials_provider), _) => Some(credentials_provider),
+        (None, Some(web_identity)) => {
+            let mut provider_config =
+                ProviderConfig::without_region().with_region(config.region().cloned());
+            if let Some(http_client) = options.http_client {
+                provider_config = provider_config.with_http_client(http_client);
+            }
+            let provider = WebIdentityTokenCredentialsProvider::builder()
+                .static_configuration(StaticConfiguration {
+                    web_identity_token_file: web_identity.token_file,
+                    role_arn: web_identity.role_arn,
+                    session_name: web_identity.session_name,
+                })
+                .configure(&provider_config)
+                .build();
+            Some(SharedCredentialsProvider::new(provider))
+        }
+        (None, None) if !options.assume_roles.is_empty() => config.credentials_provider(),
+        (None, None) => return Ok(with_endpoint_url(config, endpoint_url)),
+    };
+
+    let mut credentials_provider = credentials_provider.ok_or_else(|| {
+        BedrockError::Config("no credentials to assume the roles with".to_string())
+    })?;
+
+    for assume_role in options.assume_roles {
+        if config.region().is_none() {
+            return Err(BedrockError::Config(format!(
+                "a region is required to assume role {}",
+                assume_role.role_arn
+            )));
+        }
+
+        let mut provider = AssumeRoleProvider::builder(assume_role.role_arn).configure(&config);
+        if let Some(session_name) = assume_role.session_name {
+            provider = provider.session_name(session_name);
+        }
+        if let Some(external_id) = assume_role.external_id {
+            provider = provider.external_id(external_id);
+        }
+        if let Some(session_length) = assume_role.session_length {
+            provider = provider.session_length(session_length);
+        }
+        let provider = provider.build_from_provider(credentials_provider).await;
+        credentials_provider = SharedCredentialsProvider::new(provider);
+    }
+
+    let config = config
+        .into_builder()
+        .credentials_provider(credentials_provider)
+        .build();
+    Ok(with_endpoint_url(config, endpoint_url))
+}
+
+fn with_endpoint_url(config: SdkConfig, endpoint_url: Option<String>) -> SdkConfig {
+    let mut builder = config.into_builder();
+    builder.set_endpoint_url(endpoint_url);
+    builder.build()
 }
 
 pub struct BedrockClient {
     client: Client,
+    guardrail: Option<GuardrailConfig>,
 }
 
 //
@@ -75,10 +420,12 @@ impl BedrockClient {
     ///
     /// # Returns
     ///
-    /// This function returns a new `BedrockClient`.
-    pub async fn new(options: BedrockClientOptions) -> Self {
-        let client = Self::create_client(options).await;
-        Self { client }
+    /// This function returns a new `BedrockClient`, or a `BedrockError::Config` if the options
+    /// are invalid.
+    pub async fn new(options: BedrockClientOptions) -> Result<Self, BedrockError> {
+        let guardrail = options.guardrail.clone();
+        let client = Self::create_client(options).await?;
+        Ok(Self { client, guardrail })
     }
     /// Creates a new `Client` using the provided options.
     ///
@@ -91,28 +438,14 @@ impl BedrockClient {
     /// # Returns
     ///
     /// This function returns a new `Client`.
-    async fn create_client(options: BedrockClientOptions) -> Client {
-        let mut config_loader = aws_config::ConfigLoader::default();
-
-        if let Some(profile_name) = options.profile_name {
-            config_loader = config_loader.profile_name(profile_name);
+    async fn create_client(options: BedrockClientOptions) -> Result<Client, BedrockError> {
+        if let Some(runtime_config) = options.runtime_config {
+            return Ok(Client::from_conf(runtime_config));
         }
 
-        if let Some(region) = options.region {
-            config_loader = config_loader.region(Region::new(region));
-        }
-
-        if let Some(endpoint_url) = options.endpoint_url {
-            config_loader = config_loader.endpoint_url(endpoint_url);
-        }
+        let config = load_sdk_config(options).await?;
 
-        if let Some(behavior_version) = options.behavior_version {
-            config_loader = config_loader.behavior_version(behavior_version);
-        }
-
-        let config = config_loader.load().await;
-
-        Client::new(&config)
+        Ok(Client::new(&config))
     }
 
     /// Generates a raw stream of responses from the Bedrock service.
@@ -134,6 +467,33 @@ impl BedrockClient {
         model_id: String,
         payload: Value,
     ) -> Result<impl Stream<Item = Result<Value, BedrockError>>, BedrockError> {
+        self.generate_raw_stream_with_guardrail(model_id, payload, None)
+            .await
+    }
+
+    /// Generates a raw stream of responses, checked by a guardrail.
+    ///
+    /// When the guardrail intervenes, the stream carries the blocked message configured on the
+    /// guardrail instead of the model completion. Use `GuardrailOutcome::from_response` on the
+    /// chunks to read the guardrail action and trace.
+    ///
+    /// # Arguments
+    ///
+    /// * `model_id` - A string that represents the model ID.
+    /// * `payload` - A `Value` that represents the payload to send in the request.
+    /// * `guardrail` - The guardrail to apply. If `None`, the guardrail of the client options is used.
+    ///
+    /// # Returns
+    ///
+    /// This function returns a `Result` that contains a stream of responses if the operation was successful,
+    /// or an error if the operation failed.
+    pub async fn generate_raw_stream_with_guardrail(
+        &self,
+        model_id: String,
+        payload: Value,
+        guardrail: Option<&GuardrailConfig>,
+    ) -> Result<impl Stream<Item = Result<Value, BedrockError>>, BedrockError> {
+        let guardrail = guardrail.or(self.guardrail.as_ref()).cloned();
         let payload_bytes = serde_json::to_vec(&payload);
 
         let payload_bytes = match payload_bytes {
@@ -146,14 +506,21 @@ impl BedrockClient {
         let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
 
         let client = self.client.clone();
+        let mut span = GenAiSpan::debug(OPERATION_INVOKE_MODEL, PROVIDER_BEDROCK, &model_id);
 
         tokio::spawn(async move {
-            let resp = client
-                .invoke_model_with_response_stream()
-                .model_id(&model_id)
-                .content_type("application/json")
-                .body(payload_blob)
-                .send()
+            let resp = span
+                .instrument(
+                    client
+                        .invoke_model_with_response_stream()
+                        .model_id(&model_id)
+                        .content_type("application/json")
+                        .body(payload_blob)
+                        .set_guardrail_identifier(guardrail.as_ref().map(|g| g.identifier.clone()))
+                        .set_guardrail_version(guardrail.as_ref().map(|g| g.version.clone()))
+                        .set_trace(guardrail.as_ref().map(trace_of))
+                        .send(),
+                )
                 .await;
 
             match resp {
@@ -166,16 +533,29 @@ impl BedrockClient {
                                 payload_part,
                             ))) => {
                                 if let Some(blob) = &payload_part.bytes {
-                                    let data: Cow<'_, str> =
-                                        String::from_utf8_lossy(&blob.as_ref());
-                                    let value: Value = serde_json::from_str(&data).unwrap();
-                                    sender.send(Ok(value)).unwrap();
+                                    span.first_token();
+                                    let data: Cow<'_, str> = String::from_utf8_lossy(blob.as_ref());
+                                    let item = match serde_json::from_str::<Value>(&data) {
+                                        Ok(value) => {
+                                            record_invocation_metrics(&span, &value);
+                                            Ok(value)
+                                        }
+                                        Err(err) => {
+                                            span.error(&err);
+                                            Err(BedrockError::from(err))
+                                        }
+                                    };
+                                    // The receiver is gone, e.g. the caller dropped the stream.
+                                    if sender.send(item).is_err() {
+                                        break;
+                                    }
                                 }
                             }
                             Err(err) => {
                                 let sdk_error = err;
+                                span.error(&sdk_error);
                                 let bedrock_error = BedrockError::from(sdk_error);
-                                sender.send(Err(bedrock_error)).unwrap();
+                                let _ = sender.send(Err(bedrock_error));
                                 break;
                             }
                             Ok(None) => {
@@ -186,8 +566,9 @@ impl BedrockClient {
                     }
                 }
                 Err(err) => {
+                    span.error(&err);
                     let bedrock_error = BedrockError::from(err);
-                    sender.send(Err(bedrock_error)).unwrap();
+                    let _ = sender.send(Err(bedrock_error));
                 }
             }
         });
@@ -214,26 +595,275 @@ impl BedrockClient {
         model_id: String,
         payload: Value,
     ) -> Result<Value, BedrockError> {
-        let payload_bytes = serde_json::to_vec(&payload).unwrap();
+        self.generate_raw_with_guardrail(model_id, payload, None)
+            .await
+    }
+
+    /// Generates a raw response, checked by a guardrail.
+    ///
+    /// When the guardrail intervenes, the response carries the blocked message configured on the
+    /// guardrail instead of the model completion. Use `GuardrailOutcome::from_response` on the
+    /// response to read the guardrail action and trace.
+    ///
+    /// # Arguments
+    ///
+    /// * `model_id` - A string that represents the model ID.
+    /// * `payload` - A `Value` that represents the payload to send in the request.
+    /// * `guardrail` - The guardrail to apply. If `None`, the guardrail of the client options is used.
+    ///
+    /// # Returns
+    ///
+    /// This function returns a `Result` that contains a response if the operation was successful,
+    /// or an error if the operation failed.
+    pub async fn generate_raw_with_guardrail(
+        &self,
+        model_id: String,
+        payload: Value,
+        guardrail: Option<&GuardrailConfig>,
+    ) -> Result<Value, BedrockError> {
+        let guardrail = guardrail.or(self.guardrail.as_ref());
+        let payload_bytes = serde_json::to_vec(&payload)?;
         let payload_blob = aws_smithy_types::Blob::new(payload_bytes);
 
         let client = self.client.clone();
+        let span = GenAiSpan::debug(OPERATION_INVOKE_MODEL, PROVIDER_BEDROCK, &model_id);
 
         // Invoke the model with the payload
-        let resp = client
-            .invoke_model()
-            .model_id(model_id)
-            .content_type("application/json")
-            .body(payload_blob)
-            .send()
+        let resp = span
+            .instrument(
+                client
+                    .invoke_model()
+                    .model_id(model_id)
+                    .content_type("application/json")
+                    .body(payload_blob)
+                    .set_guardrail_identifier(guardrail.map(|g| g.identifier.clone()))
+                    .set_guardrail_version(guardrail.map(|g| g.version.clone()))
+                    .set_trace(guardrail.map(trace_of))
+                    .send(),
+            )
             .await;
 
         let resp = match resp {
             Ok(resp) => resp,
-            Err(err) => return Err(BedrockError::from(err)),
+            Err(err) => {
+                span.error(&err);
+                return Err(BedrockError::from(err));
+            }
         };
 
-        let response: serde_json::Value = serde_json::from_slice(resp.body().as_ref()).unwrap();
+        let response: serde_json::Value = serde_json::from_slice(resp.body().as_ref())?;
         Ok(response)
     }
+
+    /// Checks a text against a guardrail, without invoking a model.
+    ///
+    /// # Arguments
+    ///
+    /// * `guardrail` - The guardrail to apply. Its `trace` flag is ignored: assessments are always returned.
+    /// * `source` - Whether the text is a prompt or a model completion.
+    /// * `text` - The text to check.
+    ///
+    /// # Returns
+    ///
+    /// This function returns a `Result` that contains the guardrail action, the assessments and
+    /// the text to use instead when the guardrail intervened, or an error if the operation failed.
+    pub async fn apply_guardrail(
+        &self,
+        guardrail: &GuardrailConfig,
+        source: GuardrailSource,
+        text: &str,
+    ) -> Result<GuardrailCheck, BedrockError> {
+        let text_block = GuardrailTextBlock::builder()
+            .text(text)
+            .build()
+            .map_err(|err| BedrockError::Unknown(err.to_string()))?;
+
+        let output = self
+            .client
+            .apply_guardrail()
+            .guardrail_identifier(&guardrail.identifier)
+            .guardrail_version(&guardrail.version)
+            .source(source.into())
+            .content(GuardrailContentBlock::Text(text_block))
+            .send()
+            .await
+            .map_err(aws_sdk_bedrockruntime::Error::from)?;
+
+        let action = match output.action {
+            aws_sdk_bedrockruntime::types::GuardrailAction::GuardrailIntervened => {
+                GuardrailAction::Intervened
+            }
+            _ => GuardrailAction::None,
+        };
+
+        Ok(GuardrailCheck {
+            action,
+            outputs: output.outputs.into_iter().filter_map(|o| o.text).collect(),
+            assessments: output.assessments,
+        })
+    }
+}
+
+/// Records the token counts that Bedrock appends to the last chunk of a stream.
+fn record_invocation_metrics(span: &GenAiSpan, chunk: &Value) {
+    if let Some(metrics) = chunk.get("amazon-bedrock-invocationMetrics") {
+        let count = |key: &str| metrics.get(key).and_then(Value::as_u64).unwrap_or(0);
+        span.usage(count("inputTokenCount"), count("outputTokenCount"));
+    }
+}
+
+fn trace_of(guardrail: &GuardrailConfig) -> Trace {
+    if guardrail.trace {
+        Trace::Enabled
+    } else {
+        Trace::Disabled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_load_sdk_config() {
+        let options = BedrockClientOptions::new()
+            .region("eu-west-1")
+            .credentials("AKIDEXAMPLE", "secret", None)
+            .max_attempts(5)
+            .operation_timeout(Duration::from_secs(30))
+            .app_name("nightly-summaries");
+
+        let config = load_sdk_config(options).await.unwrap();
+
+        assert_eq!(config.region(), Some(&Region::new("eu-west-1")));
+        assert_eq!(config.retry_config().unwrap().max_attempts(), 5);
+        assert_eq!(
+            config.timeout_config().unwrap().operation_timeout(),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(config.app_name().unwrap().as_ref(), "nightly-summaries");
+
+        let credentials = config
+            .credentials_provider()
+            .unwrap()
+            .provide_credentials()
+            .await
+            .unwrap();
+        assert_eq!(credentials.access_key_id(), "AKIDEXAMPLE");
+    }
+
+    #[tokio::test]
+    async fn test_load_sdk_config_overrides_sdk_config() {
+        let sdk_config = SdkConfig::builder()
+            .region(Region::new("us-east-1"))
+            .behavior_version(BehaviorVersion::latest())
+            .build();
+        let options = BedrockClientOptions::new()
+            .sdk_config(sdk_config)
+            .endpoint_url("http://localhost:8080");
+
+        let config = load_sdk_config(options).await.unwrap();
+
+        assert_eq!(config.region(), Some(&Region::new("us-east-1")));
+        assert_eq!(config.endpoint_url(), Some("http://localhost:8080"));
+    }
+
+    #[tokio::test]
+    async fn test_load_sdk_config_rejects_loading_options_with_sdk_config() {
+        let sdk_config = SdkConfig::builder()
+            .region(Region::new("us-east-1"))
+            .behavior_version(BehaviorVersion::latest())
+            .build();
+        let options = BedrockClientOptions::new()
+            .sdk_config(sdk_config)
+            .profile_name("production");
+
+        let result = load_sdk_config(options).await;
+
+        assert!(matches!(result, Err(BedrockError::Config(_))));
+    }
+
+    #[tokio::test]
+    async fn test_load_sdk_config_invalid_app_name() {
+        let options = BedrockClientOptions::new()
+            .region("us-east-1")
+            .app_name("not a valid app name");
+
+        let result = load_sdk_config(options).await;
+
+        assert!(matches!(result, Err(BedrockError::Config(_))));
+    }
+
+    /// An HTTP client keeping the URI of every request, and answering with a 403.
+    #[derive(Debug, Clone, Default)]
+    struct UriRecorder {
+        uris: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl HttpClient for UriRecorder {
+        fn http_connector(
+            &self,
+            _: &aws_smithy_runtime_api::client::http::HttpConnectorSettings,
+            _: &aws_sdk_bedrockruntime::config::RuntimeComponents,
+        ) -> aws_smithy_runtime_api::client::http::SharedHttpConnector {
+            aws_smithy_runtime_api::client::http::SharedHttpConnector::new(self.clone())
+        }
+    }
+
+    impl aws_smithy_runtime_api::client::http::HttpConnector for UriRecorder {
+        fn call(
+            &self,
+            request: aws_sdk_bedrockruntime::config::http::HttpRequest,
+        ) -> aws_smithy_runtime_api::client::http::HttpConnectorFuture {
+            self.uris.lock().unwrap().push(request.uri().to_string());
+            let response = aws_sdk_bedrockruntime::config::http::HttpResponse::new(
+                403.try_into().unwrap(),
+                aws_smithy_types::body::SdkBody::empty(),
+            );
+            aws_smithy_runtime_api::client::http::HttpConnectorFuture::ready(Ok(response))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_assume_role_does_not_use_endpoint_url() {
+        let recorder = UriRecorder::default();
+        let options = BedrockClientOptions::new()
+            .region("us-east-1")
+            .endpoint_url("https://vpce-0123.bedrock-runtime.us-east-1.vpce.amazonaws.com")
+            .credentials("AKIDEXAMPLE", "secret", None)
+            .assume_role(AssumeRoleConfig::new("arn:aws:iam::123456789012:role/bedrock"))
+            .http_client(recorder.clone());
+
+        let config = load_sdk_config(options).await.unwrap();
+
+        assert_eq!(
+            config.endpoint_url(),
+            Some("https://vpce-0123.bedrock-runtime.us-east-1.vpce.amazonaws.com")
+        );
+        // The STS call fails with the 403 of the recorder, but its URI is recorded.
+        let credentials = config
+            .credentials_provider()
+            .unwrap()
+            .provide_credentials()
+            .await;
+        assert!(credentials.is_err());
+        let uris = recorder.uris.lock().unwrap();
+        assert!(!uris.is_empty());
+        assert!(uris.iter().all(|uri| uri.starts_with("https://sts.us-east-1.amazonaws.com")));
+    }
+
+    #[tokio::test]
+    async fn test_assume_role_requires_credentials() {
+        let sdk_config = SdkConfig::builder()
+            .region(Region::new("us-east-1"))
+            .behavior_version(BehaviorVersion::latest())
+            .build();
+        let options = BedrockClientOptions::new()
+            .sdk_config(sdk_config)
+            .assume_role(AssumeRoleConfig::new("arn:aws:iam::123456789012:role/bedrock"));
+
+        let result = load_sdk_config(options).await;
+
+        assert!(matches!(result, Err(BedrockError::Config(_))));
+    }
 }
@@ -0,0 +1,566 @@
+use std::time::Duration;
+
+use aws_sdk_bedrock::types::{
+    CustomModelSummary, FoundationModelDetails, FoundationModelLifecycleStatus,
+    FoundationModelSummary, InferenceType, ModelInvocationJobInputDataConfig,
+    ModelInvocationJobOutputDataConfig, ModelInvocationJobS3InputDataConfig,
+    ModelInvocationJobS3OutputDataConfig, ModelInvocationJobStatus, ModelModality,
+    ProvisionedModelStatus, ProvisionedModelSummary, S3InputFormat,
+};
+use aws_sdk_bedrock::Client;
+
+use crate::bedrock::batch_inference::{BatchJob, BatchJobConfig};
+use crate::bedrock::bedrock_client::{load_sdk_config, BedrockClientOptions};
+use crate::bedrock::error::BedrockError;
+use crate::bedrock::model_info::{ModelInfo, ModelName};
+use crate::bedrock::model_target::{ModelTarget, ModelTargetError};
+
+/// Filters applied when listing foundation models.
+///
+/// # Fields
+///
+/// * `provider` - Only list the models of this provider, e.g. `Anthropic`.
+/// * `output_modality` - Only list the models that produce this modality.
+/// * `inference_type` - Only list the models that support this inference type.
+///
+#[derive(Debug, Clone, Default)]
+pub struct FoundationModelFilter {
+    provider: Option<String>,
+    output_modality: Option<ModelModality>,
+    inference_type: Option<InferenceType>,
+}
+
+impl FoundationModelFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn provider<S: Into<String>>(mut self, provider: S) -> Self {
+        self.provider = Some(provider.into());
+        self
+    }
+
+    pub fn output_modality(mut self, output_modality: ModelModality) -> Self {
+        self.output_modality = Some(output_modality);
+        self
+    }
+
+    pub fn inference_type(mut self, inference_type: InferenceType) -> Self {
+        self.inference_type = Some(inference_type);
+        self
+    }
+}
+
+/// A provisioned throughput that is ready to serve requests.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProvisionedModel {
+    /// The ARN to pass as model id when invoking the provisioned throughput.
+    pub arn: String,
+    pub name: String,
+    /// The catalogue entry of the underlying foundation model, if known.
+    pub model: Option<ModelName>,
+    pub model_units: i32,
+}
+
+impl ProvisionedModel {
+    /// The target to invoke the provisioned throughput, with its base model set.
+    pub fn target(&self) -> Result<ModelTarget, ModelTargetError> {
+        let target = ModelTarget::parse(&self.arn)?;
+        Ok(match self.model {
+            Some(model) => target.with_base_model(model),
+            None => target,
+        })
+    }
+}
+
+/// The catalogue models that can be used in a region, as reported by the control plane.
+///
+/// Bedrock does not expose whether access to a model has been granted to the account:
+/// a model listed as available can still fail with an `AccessDeniedException` until access
+/// is requested in the console.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelAvailability {
+    /// Active models that can be invoked on demand.
+    pub on_demand: Vec<ModelName>,
+    /// Models that are still offered but marked as legacy.
+    pub legacy: Vec<ModelName>,
+    /// Active models that can only be invoked through a provisioned throughput.
+    pub provisioned_only: Vec<ModelName>,
+    /// Catalogue models that are not offered in the region.
+    pub unavailable: Vec<ModelName>,
+    /// Provisioned throughputs that are in service.
+    pub provisioned: Vec<ProvisionedModel>,
+    /// Model ids offered in the region that are not in `ModelInfo::MODELS`.
+    pub uncatalogued: Vec<String>,
+}
+
+impl ModelAvailability {
+    /// Compares the models listed by the control plane with the static catalogue.
+    pub fn reconcile(
+        foundation_models: &[FoundationModelSummary],
+        provisioned_models: &[ProvisionedModelSummary],
+    ) -> Self {
+        let mut availability = ModelAvailability::default();
+
+        for model in ModelInfo::MODELS {
+            let listed = foundation_models
+                .iter()
+                .find(|summary| summary.model_id == model.text);
+            match listed {
+                None => availability.unavailable.push(model.name),
+                Some(summary) if is_legacy(summary) => availability.legacy.push(model.name),
+                Some(summary) if supports_on_demand(summary) => {
+                    availability.on_demand.push(model.name)
+                }
+                Some(_) => availability.provisioned_only.push(model.name),
+            }
+        }
+
+        availability.uncatalogued = foundation_models
+            .iter()
+            .filter(|summary| {
+                ModelInfo::from_id(without_context_window(&summary.model_id)).is_none()
+            })
+            .map(|summary| summary.model_id.clone())
+            .collect();
+
+        availability.provisioned = provisioned_models
+            .iter()
+            .filter(|summary| summary.status == ProvisionedModelStatus::InService)
+            .map(|summary| ProvisionedModel {
+                arn: summary.provisioned_model_arn.clone(),
+                name: summary.provisioned_model_name.clone(),
+                model: model_id_from_arn(&summary.foundation_model_arn)
+                    .map(without_context_window)
+                    .and_then(ModelInfo::from_id)
+                    .map(|model| model.name),
+                model_units: summary.model_units,
+            })
+            .collect();
+
+        availability
+    }
+
+    /// Whether the model can be invoked, on demand or through a provisioned throughput.
+    pub fn is_available(&self, name: ModelName) -> bool {
+        self.on_demand.contains(&name)
+            || self.legacy.contains(&name)
+            || self.provisioned.iter().any(|p| p.model == Some(name))
+    }
+
+    /// The in-service provisioned throughputs for a model.
+    pub fn provisioned_for(&self, name: ModelName) -> impl Iterator<Item = &ProvisionedModel> {
+        self.provisioned.iter().filter(move |p| p.model == Some(name))
+    }
+}
+
+fn is_legacy(summary: &FoundationModelSummary) -> bool {
+    summary
+        .model_lifecycle()
+        .map(|lifecycle| *lifecycle.status() == FoundationModelLifecycleStatus::Legacy)
+        .unwrap_or(false)
+}
+
+fn supports_on_demand(summary: &FoundationModelSummary) -> bool {
+    summary
+        .inference_types_supported()
+        .contains(&InferenceType::OnDemand)
+}
+
+/// Extracts the model id from a foundation model ARN,
+/// e.g. `arn:aws:bedrock:us-east-1::foundation-model/anthropic.claude-v2`.
+fn model_id_from_arn(arn: &str) -> Option<&str> {
+    arn.split_once(":foundation-model/").map(|(_, id)| id)
+}
+
+/// Strips the context window suffix of the model variants offered for provisioned
+/// throughput, e.g. `anthropic.claude-3-sonnet-20240229-v1:0:200k`.
+fn without_context_window(model_id: &str) -> &str {
+    match model_id.rsplit_once(':') {
+        Some((base, suffix))
+            if suffix.len() > 1
+                && suffix.ends_with('k')
+                && suffix[..suffix.len() - 1].bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            base
+        }
+        _ => model_id,
+    }
+}
+
+/// A client for the Bedrock control plane, used to discover the models of a region.
+///
+/// `BedrockClient` only talks to the runtime endpoint that invokes models; this client
+/// talks to the management endpoint that lists them.
+pub struct BedrockControlClient {
+    client: Client,
+}
+
+impl BedrockControlClient {
+    /// Constructs a new `BedrockControlClient`.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The same `BedrockClientOptions` used for a `BedrockClient`.
+    ///
+    pub async fn new(options: BedrockClientOptions) -> Result<Self, BedrockError> {
+        let config = load_sdk_config(options).await?;
+        Ok(Self {
+            client: Client::new(&config),
+        })
+    }
+
+    /// Lists the foundation models offered in the region.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - Restricts the models by provider, output modality or inference type.
+    ///
+    pub async fn list_foundation_models(
+        &self,
+        filter: &FoundationModelFilter,
+    ) -> Result<Vec<FoundationModelSummary>, BedrockError> {
+        let output = self
+            .client
+            .list_foundation_models()
+            .set_by_provider(filter.provider.clone())
+            .set_by_output_modality(filter.output_modality.clone())
+            .set_by_inference_type(filter.inference_type.clone())
+            .send()
+            .await
+            .map_err(aws_sdk_bedrock::Error::from)?;
+
+        Ok(output.model_summaries().to_vec())
+    }
+
+    /// Fetches the details of a foundation model.
+    ///
+    /// # Arguments
+    ///
+    /// * `model_id` - The model id or ARN, e.g. `anthropic.claude-3-haiku-20240307-v1:0`.
+    ///
+    pub async fn get_foundation_model(
+        &self,
+        model_id: &str,
+    ) -> Result<FoundationModelDetails, BedrockError> {
+        let output = self
+            .client
+            .get_foundation_model()
+            .model_identifier(model_id)
+            .send()
+            .await
+            .map_err(aws_sdk_bedrock::Error::from)?;
+
+        output.model_details.ok_or_else(|| {
+            BedrockError::Unknown(format!("no details returned for model {}", model_id))
+        })
+    }
+
+    /// Lists the custom models of the account.
+    pub async fn list_custom_models(&self) -> Result<Vec<CustomModelSummary>, BedrockError> {
+        let models = self
+            .client
+            .list_custom_models()
+            .into_paginator()
+            .items()
+            .send()
+            .try_collect()
+            .await
+            .map_err(aws_sdk_bedrock::Error::from)?;
+
+        Ok(models)
+    }
+
+    /// Lists the provisioned throughputs of the account, whatever their status.
+    pub async fn list_provisioned_throughputs(
+        &self,
+    ) -> Result<Vec<ProvisionedModelSummary>, BedrockError> {
+        let throughputs = self
+            .client
+            .list_provisioned_model_throughputs()
+            .into_paginator()
+            .items()
+            .send()
+            .try_collect()
+            .await
+            .map_err(aws_sdk_bedrock::Error::from)?;
+
+        Ok(throughputs)
+    }
+
+    /// Lists the models of the region and reconciles them with `ModelInfo::MODELS`.
+    pub async fn model_availability(&self) -> Result<ModelAvailability, BedrockError> {
+        let foundation_models = self
+            .list_foundation_models(&FoundationModelFilter::new())
+            .await?;
+        let provisioned_models = self.list_provisioned_throughputs().await?;
+
+        Ok(ModelAvailability::reconcile(
+            &foundation_models,
+            &provisioned_models,
+        ))
+    }
+
+    /// Submits a batch inference job.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The model, the S3 locations of the JSONL input and of the output,
+    ///   and the role Bedrock assumes to access them.
+    ///
+    /// # Returns
+    ///
+    /// The ARN of the job, used to poll or stop it.
+    ///
+    pub async fn submit_batch_job(&self, config: &BatchJobConfig) -> Result<String, BedrockError> {
+        let input = ModelInvocationJobS3InputDataConfig::builder()
+            .s3_input_format(S3InputFormat::Jsonl)
+            .s3_uri(&config.input_s3_uri)
+            .build()
+            .map_err(|err| BedrockError::Unknown(err.to_string()))?;
+        let output = ModelInvocationJobS3OutputDataConfig::builder()
+            .s3_uri(&config.output_s3_uri)
+            .set_s3_encryption_key_id(config.output_kms_key_id.clone())
+            .build()
+            .map_err(|err| BedrockError::Unknown(err.to_string()))?;
+
+        let response = self
+            .client
+            .create_model_invocation_job()
+            .job_name(&config.job_name)
+            .role_arn(&config.role_arn)
+            .model_id(&config.model_id)
+            .input_data_config(ModelInvocationJobInputDataConfig::S3InputDataConfig(input))
+            .output_data_config(ModelInvocationJobOutputDataConfig::S3OutputDataConfig(output))
+            .set_timeout_duration_in_hours(config.timeout_hours)
+            .set_client_request_token(config.client_request_token.clone())
+            .send()
+            .await
+            .map_err(aws_sdk_bedrock::Error::from)?;
+
+        Ok(response.job_arn)
+    }
+
+    /// Fetches the state of a batch inference job.
+    ///
+    /// # Arguments
+    ///
+    /// * `job_arn` - The ARN returned by `submit_batch_job`.
+    ///
+    pub async fn get_batch_job(&self, job_arn: &str) -> Result<BatchJob, BedrockError> {
+        let output = self
+            .client
+            .get_model_invocation_job()
+            .job_identifier(job_arn)
+            .send()
+            .await
+            .map_err(aws_sdk_bedrock::Error::from)?;
+
+        Ok(BatchJob::from(output))
+    }
+
+    /// Lists the batch inference jobs of the account.
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - Only list the jobs with this status.
+    ///
+    pub async fn list_batch_jobs(
+        &self,
+        status: Option<ModelInvocationJobStatus>,
+    ) -> Result<Vec<BatchJob>, BedrockError> {
+        let jobs: Vec<_> = self
+            .client
+            .list_model_invocation_jobs()
+            .set_status_equals(status)
+            .into_paginator()
+            .items()
+            .send()
+            .try_collect()
+            .await
+            .map_err(aws_sdk_bedrock::Error::from)?;
+
+        Ok(jobs.into_iter().map(BatchJob::from).collect())
+    }
+
+    /// Stops a batch inference job. Records already processed are still written to the output.
+    ///
+    /// # Arguments
+    ///
+    /// * `job_arn` - The ARN returned by `submit_batch_job`.
+    ///
+    pub async fn stop_batch_job(&self, job_arn: &str) -> Result<(), BedrockError> {
+        self.client
+            .stop_model_invocation_job()
+            .job_identifier(job_arn)
+            .send()
+            .await
+            .map_err(aws_sdk_bedrock::Error::from)?;
+
+        Ok(())
+    }
+
+    /// Polls a batch inference job until it has finished, successfully or not.
+    ///
+    /// Batch jobs can take hours: wrap the call in `tokio::time::timeout` to give up earlier.
+    /// Fails with `BedrockError::Unknown` if the job has no status, or a status this crate
+    /// does not know, rather than polling it forever.
+    ///
+    /// # Arguments
+    ///
+    /// * `job_arn` - The ARN returned by `submit_batch_job`.
+    /// * `poll_interval` - The delay between two status checks.
+    ///
+    /// # Returns
+    ///
+    /// The final state of the job. Check `BatchJob::has_output` before reading the output.
+    ///
+    pub async fn wait_for_batch_job(
+        &self,
+        job_arn: &str,
+        poll_interval: Duration,
+    ) -> Result<BatchJob, BedrockError> {
+        loop {
+            let job = self.get_batch_job(job_arn).await?;
+            if job.is_finished() {
+                return Ok(job);
+            }
+            if !job.is_running() {
+                return Err(BedrockError::Unknown(format!(
+                    "batch job {} has an unexpected status: {:?}",
+                    job.job_arn, job.status
+                )));
+            }
+            tokio::time::sleep(poll_interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_bedrock::types::FoundationModelLifecycle;
+    use aws_smithy_types::DateTime;
+
+    fn foundation_model(
+        model_id: &str,
+        status: FoundationModelLifecycleStatus,
+        inference_type: InferenceType,
+    ) -> FoundationModelSummary {
+        FoundationModelSummary::builder()
+            .model_arn(format!(
+                "arn:aws:bedrock:us-east-1::foundation-model/{}",
+                model_id
+            ))
+            .model_id(model_id)
+            .inference_types_supported(inference_type)
+            .model_lifecycle(
+                FoundationModelLifecycle::builder()
+                    .status(status)
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap()
+    }
+
+    fn provisioned_model(model_id: &str, status: ProvisionedModelStatus) -> ProvisionedModelSummary {
+        let foundation_model_arn = format!(
+            "arn:aws:bedrock:us-east-1::foundation-model/{}",
+            model_id
+        );
+        ProvisionedModelSummary::builder()
+            .provisioned_model_name("production")
+            .provisioned_model_arn("arn:aws:bedrock:us-east-1:123456789012:provisioned-model/abc123")
+            .model_arn(&foundation_model_arn)
+            .desired_model_arn(&foundation_model_arn)
+            .foundation_model_arn(&foundation_model_arn)
+            .model_units(1)
+            .desired_model_units(1)
+            .status(status)
+            .creation_time(DateTime::from_secs(0))
+            .last_modified_time(DateTime::from_secs(0))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let foundation_models = vec![
+            foundation_model(
+                "anthropic.claude-3-haiku-20240307-v1:0",
+                FoundationModelLifecycleStatus::Active,
+                InferenceType::OnDemand,
+            ),
+            foundation_model(
+                "anthropic.claude-instant-v1",
+                FoundationModelLifecycleStatus::Legacy,
+                InferenceType::OnDemand,
+            ),
+            foundation_model(
+                "anthropic.claude-v2",
+                FoundationModelLifecycleStatus::Active,
+                InferenceType::Provisioned,
+            ),
+            foundation_model(
+                "anthropic.claude-3-sonnet-20240229-v1:0:200k",
+                FoundationModelLifecycleStatus::Active,
+                InferenceType::Provisioned,
+            ),
+            foundation_model(
+                "example.new-model-v1",
+                FoundationModelLifecycleStatus::Active,
+                InferenceType::OnDemand,
+            ),
+        ];
+        let provisioned_models = vec![
+            provisioned_model("anthropic.claude-v2", ProvisionedModelStatus::InService),
+            provisioned_model("anthropic.claude-v2", ProvisionedModelStatus::Creating),
+            provisioned_model(
+                "anthropic.claude-3-sonnet-20240229-v1:0:200k",
+                ProvisionedModelStatus::InService,
+            ),
+        ];
+
+        let availability = ModelAvailability::reconcile(&foundation_models, &provisioned_models);
+
+        assert_eq!(availability.on_demand, vec![ModelName::AnthropicClaudeHaiku1x]);
+        assert_eq!(availability.legacy, vec![ModelName::AnthropicClaudeInstantx]);
+        assert_eq!(availability.provisioned_only, vec![ModelName::AnthropicClaude2x]);
+        assert!(!availability.on_demand.contains(&ModelName::AnthropicClaude2x));
+        assert_eq!(availability.uncatalogued, vec!["example.new-model-v1".to_string()]);
+        assert_eq!(availability.provisioned.len(), 2);
+        assert_eq!(
+            availability.provisioned[1].model,
+            Some(ModelName::AnthropicClaudeSonnet1x)
+        );
+        assert!(availability.is_available(ModelName::AnthropicClaudeSonnet1x));
+        let target = availability.provisioned[0].target().unwrap();
+        assert_eq!(target.base_model(), Some(ModelName::AnthropicClaude2x));
+        assert!(availability.is_available(ModelName::AnthropicClaude2x));
+        assert!(!availability.is_available(ModelName::AnthropicClaudeOpus1x));
+        assert!(availability.unavailable.contains(&ModelName::AnthropicClaudeOpus1x));
+    }
+
+    #[test]
+    fn test_model_id_from_arn() {
+        assert_eq!(
+            model_id_from_arn("arn:aws:bedrock:us-east-1::foundation-model/anthropic.claude-v2:0:100k"),
+            Some("anthropic.claude-v2:0:100k")
+        );
+        assert_eq!(model_id_from_arn("anthropic.claude-v2"), None);
+    }
+
+    #[test]
+    fn test_without_context_window() {
+        assert_eq!(
+            without_context_window("anthropic.claude-3-sonnet-20240229-v1:0:200k"),
+            "anthropic.claude-3-sonnet-20240229-v1:0"
+        );
+        assert_eq!(
+            without_context_window("anthropic.claude-3-sonnet-20240229-v1:0"),
+            "anthropic.claude-3-sonnet-20240229-v1:0"
+        );
+        assert_eq!(without_context_window("anthropic.claude-v2"), "anthropic.claude-v2");
+    }
+}
@@ -7,6 +7,7 @@ use aws_sdk_bedrockruntime::operation::invoke_model_with_response_stream::Invoke
 use aws_sdk_bedrockruntime::types::error::ResponseStreamError;
 use aws_smithy_types::event_stream::RawMessage;
 
+/// The AWS SDK errors are boxed to keep `BedrockError`, and the `Result`s that carry it, small.
 #[derive(Error, Debug)]
 pub enum BedrockError {
     #[error("HTTP error: {0}")]
@@ -21,21 +22,54 @@ pub enum BedrockError {
     #[error("UTF-8 error: {0}")]
     Utf8(#[from] std::str::Utf8Error),
 
+    #[error("Configuration error: {0}")]
+    Config(String),
+
     #[error("Bedrock runtime error: {0}")]
-    AwsBedrockRuntimeError(#[from] aws_sdk_bedrockruntime::Error),
+    AwsBedrockRuntimeError(Box<aws_sdk_bedrockruntime::Error>),
 
     #[error("Bedrock error: {0}")]
-    BedrockError(#[from] aws_sdk_bedrock::Error),
+    BedrockError(Box<aws_sdk_bedrock::Error>),
 
     #[error("AWS SDK error: {0}")]
-    AwsSdkError(#[from] SdkError<ResponseStreamError, RawMessage>),
+    AwsSdkError(Box<SdkError<ResponseStreamError, RawMessage>>),
 
     #[error("AWS SDK invoke model error: {0}")]
-    AwsSdkErrorInvoke(#[from] SdkError<InvokeModelWithResponseStreamError>),
+    AwsSdkErrorInvoke(Box<SdkError<InvokeModelWithResponseStreamError>>),
 
     #[error("AWS SDK invoke model error: {0}")]
-    AwsSdkErrorInvokeModel(#[from] SdkError<InvokeModelError>),
+    AwsSdkErrorInvokeModel(Box<SdkError<InvokeModelError>>),
 
     #[error("Unknown error: {0}")]
     Unknown(String),
 }
+
+impl From<aws_sdk_bedrockruntime::Error> for BedrockError {
+    fn from(err: aws_sdk_bedrockruntime::Error) -> Self {
+        BedrockError::AwsBedrockRuntimeError(Box::new(err))
+    }
+}
+
+impl From<aws_sdk_bedrock::Error> for BedrockError {
+    fn from(err: aws_sdk_bedrock::Error) -> Self {
+        BedrockError::BedrockError(Box::new(err))
+    }
+}
+
+impl From<SdkError<ResponseStreamError, RawMessage>> for BedrockError {
+    fn from(err: SdkError<ResponseStreamError, RawMessage>) -> Self {
+        BedrockError::AwsSdkError(Box::new(err))
+    }
+}
+
+impl From<SdkError<InvokeModelWithResponseStreamError>> for BedrockError {
+    fn from(err: SdkError<InvokeModelWithResponseStreamError>) -> Self {
+        BedrockError::AwsSdkErrorInvoke(Box::new(err))
+    }
+}
+
+impl From<SdkError<InvokeModelError>> for BedrockError {
+    fn from(err: SdkError<InvokeModelError>) -> Self {
+        BedrockError::AwsSdkErrorInvokeModel(Box::new(err))
+    }
+}
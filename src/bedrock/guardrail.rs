@@ -0,0 +1,148 @@
+use aws_sdk_bedrockruntime::types::{GuardrailAssessment, GuardrailContentSource};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The guardrail applied to the prompts and completions of a model invocation.
+///
+/// # Fields
+///
+/// * `identifier` - The guardrail id or ARN.
+/// * `version` - The guardrail version, e.g. `1` or `DRAFT`.
+/// * `trace` - Whether Bedrock returns the assessment trace of the guardrail in the response.
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GuardrailConfig {
+    pub identifier: String,
+    pub version: String,
+    #[serde(default)]
+    pub trace: bool,
+}
+
+impl GuardrailConfig {
+    pub fn new<S: Into<String>>(identifier: S, version: S) -> Self {
+        Self {
+            identifier: identifier.into(),
+            version: version.into(),
+            trace: false,
+        }
+    }
+
+    pub fn trace(mut self, trace: bool) -> Self {
+        self.trace = trace;
+        self
+    }
+}
+
+/// The action a guardrail took on a model invocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GuardrailAction {
+    #[serde(rename = "NONE")]
+    None,
+    #[serde(rename = "INTERVENED", alias = "GUARDRAIL_INTERVENED")]
+    Intervened,
+}
+
+/// The guardrail fields Bedrock adds to a response body when a guardrail is configured.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GuardrailOutcome {
+    pub action: GuardrailAction,
+    /// The assessment trace, when `GuardrailConfig::trace` is enabled.
+    pub trace: Option<Value>,
+}
+
+impl GuardrailOutcome {
+    /// The key of the guardrail action in a response body or a stream chunk.
+    pub const ACTION_KEY: &'static str = "amazon-bedrock-guardrailAction";
+    /// The key of the trace in a response body or a stream chunk.
+    pub const TRACE_KEY: &'static str = "amazon-bedrock-trace";
+
+    /// Reads the guardrail outcome of a response body or a stream chunk, as returned by
+    /// `BedrockClient::generate_raw` and `BedrockClient::generate_raw_stream`.
+    ///
+    /// Returns `None` when the body carries no guardrail action, e.g. when no guardrail
+    /// was configured.
+    pub fn from_response(response: &Value) -> Option<Self> {
+        let action = serde_json::from_value(response.get(Self::ACTION_KEY)?.clone()).ok()?;
+        Some(Self::new(action, response.get(Self::TRACE_KEY)))
+    }
+
+    /// Builds an outcome from the action and the `amazon-bedrock-trace` value of a response.
+    pub(crate) fn new(action: GuardrailAction, trace: Option<&Value>) -> Self {
+        let trace = trace.map(|trace| trace.get("guardrail").unwrap_or(trace).clone());
+        Self { action, trace }
+    }
+
+    pub fn intervened(&self) -> bool {
+        self.action == GuardrailAction::Intervened
+    }
+}
+
+/// Whether the text checked by `BedrockClient::apply_guardrail` is a prompt or a completion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuardrailSource {
+    Input,
+    Output,
+}
+
+impl From<GuardrailSource> for GuardrailContentSource {
+    fn from(source: GuardrailSource) -> Self {
+        match source {
+            GuardrailSource::Input => GuardrailContentSource::Input,
+            GuardrailSource::Output => GuardrailContentSource::Output,
+        }
+    }
+}
+
+/// The result of `BedrockClient::apply_guardrail`.
+#[derive(Debug, Clone)]
+pub struct GuardrailCheck {
+    pub action: GuardrailAction,
+    /// The text to use instead of the input when the guardrail intervened, e.g. the
+    /// blocked message or the input with PII masked.
+    pub outputs: Vec<String>,
+    /// The policies that matched, one assessment per checked text.
+    pub assessments: Vec<GuardrailAssessment>,
+}
+
+impl GuardrailCheck {
+    pub fn intervened(&self) -> bool {
+        self.action == GuardrailAction::Intervened
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_outcome_from_response() {
+        let response = json!({
+            "content": [{"type": "text", "text": "Sorry, I cannot answer that."}],
+            "amazon-bedrock-guardrailAction": "INTERVENED",
+            "amazon-bedrock-trace": {"guardrail": {"input": {"abc123": {"topicPolicy": {}}}}}
+        });
+
+        let outcome = GuardrailOutcome::from_response(&response).unwrap();
+
+        assert!(outcome.intervened());
+        assert_eq!(
+            outcome.trace,
+            Some(json!({"input": {"abc123": {"topicPolicy": {}}}}))
+        );
+    }
+
+    #[test]
+    fn test_outcome_without_guardrail() {
+        let outcome = GuardrailOutcome::from_response(&json!({"content": []}));
+
+        assert_eq!(outcome, None);
+
+        let outcome =
+            GuardrailOutcome::from_response(&json!({"amazon-bedrock-guardrailAction": "NONE"}))
+                .unwrap();
+
+        assert!(!outcome.intervened());
+        assert_eq!(outcome.trace, None);
+    }
+}
@@ -1,9 +1,21 @@
 
+pub mod batch_inference;
 pub mod bedrock_client;
+pub mod bedrock_control_client;
 pub mod model_info;
+pub mod model_target;
 pub mod models;
 pub mod error;
+pub mod guardrail;
 
-pub use bedrock_client::BedrockClient;
+pub use batch_inference::{
+    read_batch_output, write_batch_input, BatchJob, BatchJobConfig, BatchRecord, BatchRecordError,
+};
+pub use bedrock_client::{AssumeRoleConfig, BedrockClient, BedrockClientOptions, WebIdentityConfig};
+pub use bedrock_control_client::{BedrockControlClient, FoundationModelFilter, ModelAvailability};
 pub use error::BedrockError;
-pub use model_info::{ModelName,ModelInfo};
\ No newline at end of file
+pub use guardrail::{
+    GuardrailAction, GuardrailCheck, GuardrailConfig, GuardrailOutcome, GuardrailSource,
+};
+pub use model_info::{Capability, Modality, ModelInfo, ModelName, Pricing, Provider};
+pub use model_target::{ModelTarget, ModelTargetError, ModelTargetKind};
\ No newline at end of file
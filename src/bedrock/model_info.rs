@@ -1,4 +1,10 @@
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum ModelName {
     AmazonTitanTextG1Express1x,
     AmazonTitanTextG1Lite1x,
@@ -25,10 +31,138 @@ pub enum ModelName {
     StabilityStableDiffusionXL1x,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+impl ModelName {
+    /// The Bedrock model id, e.g. `anthropic.claude-3-haiku-20240307-v1:0`.
+    pub fn model_id(&self) -> &'static str {
+        match self {
+            ModelName::AmazonTitanTextG1Express1x => "amazon.titan-text-express-v1",
+            ModelName::AmazonTitanTextG1Lite1x => "amazon.titan-text-lite-v1",
+            ModelName::AmazonTitanEmbeddingsG1Text1x => "amazon.titan-embed-text-v1",
+            ModelName::AmazonTitanMultimodalEmbeddingsG1x => "amazon.titan-embed-image-v1",
+            ModelName::AmazonTitanImageGeneratorG1x => "amazon.titan-image-generator-v1",
+            ModelName::AnthropicClaude2x => "anthropic.claude-v2",
+            ModelName::AnthropicClaudeSonnet1x => "anthropic.claude-3-sonnet-20240229-v1:0",
+            ModelName::AnthropicClaudeHaiku1x => "anthropic.claude-3-haiku-20240307-v1:0",
+            ModelName::AnthropicClaudeOpus1x => "anthropic.claude-3-opus-20240229-v1:0",
+            ModelName::AnthropicClaudeInstantx => "anthropic.claude-instant-v1",
+            ModelName::AI21JurassicMid1x => "ai21.j2-mid-v1",
+            ModelName::AI21JurassicUltra1x => "ai21.j2-ultra-v1",
+            ModelName::CohereCmdTxt14x => "cohere.command-text-v14",
+            ModelName::CohereCmdLightTxt15x => "cohere.command-light-text-v14",
+            ModelName::CohereEmbedEnglish3x => "cohere.embed-english-v3",
+            ModelName::CohereEmbedMultilingual3x => "cohere.embed-multilingual-v3",
+            ModelName::MetaLlama2Chat13B1x => "meta.llama2-13b-chat-v1",
+            ModelName::MetaLlama2Chat70B1x => "meta.llama2-70b-chat-v1",
+            ModelName::MistralMistral7BInstruct0x => "mistral.mistral-7b-instruct-v0:2",
+            ModelName::MistralMixtral8X7BInstruct0x => "mistral.mixtral-8x7b-instruct-v0:1",
+            ModelName::MistralLarge => "mistral.mistral-large-2402-v1:0",
+            ModelName::StabilityStableDiffusionXL0x => "stability.stable-diffusion-xl-v0",
+            ModelName::StabilityStableDiffusionXL1x => "stability.stable-diffusion-xl-v1",
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("Unknown model id: {0}")]
+pub struct UnknownModelError(pub String);
+
+impl fmt::Display for ModelName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.model_id())
+    }
+}
+
+impl FromStr for ModelName {
+    type Err = UnknownModelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ModelInfo::from_id(s)
+            .map(|model| model.name)
+            .ok_or_else(|| UnknownModelError(s.to_string()))
+    }
+}
+
+impl Serialize for ModelName {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.model_id())
+    }
+}
+
+impl<'de> Deserialize<'de> for ModelName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let id = String::deserialize(deserializer)?;
+        id.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// The company that trains the model.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum Provider {
+    Amazon,
+    Anthropic,
+    AI21,
+    Cohere,
+    Meta,
+    Mistral,
+    Stability,
+}
+
+/// What the model produces.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum Modality {
+    Text,
+    Image,
+    Embedding,
+}
+
+/// A feature to look models up by.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum Capability {
+    TextGeneration,
+    ImageGeneration,
+    Embedding,
+    Streaming,
+    ToolUse,
+}
+
+/// On-demand price in USD per 1,000 tokens, as listed for `us-east-1`.
+///
+/// Prices vary by region and change over time: treat them as estimates, and override
+/// them from your own price list when accuracy matters.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct Pricing {
+    pub input_per_1k_tokens: f64,
+    pub output_per_1k_tokens: f64,
+}
+
+impl Pricing {
+    /// The cost in USD of a request with the given token counts.
+    pub fn cost(&self, input_tokens: u64, output_tokens: u64) -> f64 {
+        (input_tokens as f64 * self.input_per_1k_tokens
+            + output_tokens as f64 * self.output_per_1k_tokens)
+            / 1000.0
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct ModelInfo {
     pub name: ModelName,
     pub text: &'static str,
+    pub provider: Provider,
+    pub modality: Modality,
+    /// Whether the model supports `InvokeModelWithResponseStream`.
+    pub streaming: bool,
+    /// Whether the model can call tools.
+    pub tool_use: bool,
+    /// The maximum number of tokens the model accepts, prompt and output together.
+    /// `None` for models that do not take a text prompt, such as image generators.
+    pub context_window: Option<u32>,
+    /// The maximum number of tokens the model generates in one response.
+    pub max_output_tokens: Option<u32>,
+    /// The size of the vectors returned by embedding models.
+    pub embedding_dimensions: Option<u32>,
+    /// `None` for models that are not priced per token.
+    pub pricing: Option<Pricing>,
 }
 
 impl ModelInfo {
@@ -36,99 +170,434 @@ impl ModelInfo {
         ModelInfo {
             name: ModelName::AmazonTitanTextG1Express1x,
             text: "amazon.titan-text-express-v1",
+            provider: Provider::Amazon,
+            modality: Modality::Text,
+            streaming: true,
+            tool_use: false,
+            context_window: Some(8_192),
+            max_output_tokens: Some(8_192),
+            embedding_dimensions: None,
+            pricing: Some(Pricing {
+                input_per_1k_tokens: 0.0002,
+                output_per_1k_tokens: 0.0006,
+            }),
         },
         ModelInfo {
             name: ModelName::AmazonTitanTextG1Lite1x,
             text: "amazon.titan-text-lite-v1",
+            provider: Provider::Amazon,
+            modality: Modality::Text,
+            streaming: true,
+            tool_use: false,
+            context_window: Some(4_096),
+            max_output_tokens: Some(4_096),
+            embedding_dimensions: None,
+            pricing: Some(Pricing {
+                input_per_1k_tokens: 0.00015,
+                output_per_1k_tokens: 0.0002,
+            }),
         },
         ModelInfo {
             name: ModelName::AmazonTitanEmbeddingsG1Text1x,
             text: "amazon.titan-embed-text-v1",
+            provider: Provider::Amazon,
+            modality: Modality::Embedding,
+            streaming: false,
+            tool_use: false,
+            context_window: Some(8_192),
+            max_output_tokens: None,
+            embedding_dimensions: Some(1_536),
+            pricing: Some(Pricing {
+                input_per_1k_tokens: 0.0001,
+                output_per_1k_tokens: 0.0,
+            }),
         },
         ModelInfo {
             name: ModelName::AmazonTitanMultimodalEmbeddingsG1x,
             text: "amazon.titan-embed-image-v1",
+            provider: Provider::Amazon,
+            modality: Modality::Embedding,
+            streaming: false,
+            tool_use: false,
+            context_window: Some(128),
+            max_output_tokens: None,
+            embedding_dimensions: Some(1_024),
+            pricing: Some(Pricing {
+                input_per_1k_tokens: 0.0008,
+                output_per_1k_tokens: 0.0,
+            }),
         },
         ModelInfo {
             name: ModelName::AmazonTitanImageGeneratorG1x,
             text: "amazon.titan-image-generator-v1",
+            provider: Provider::Amazon,
+            modality: Modality::Image,
+            streaming: false,
+            tool_use: false,
+            context_window: None,
+            max_output_tokens: None,
+            embedding_dimensions: None,
+            pricing: None,
         },
         ModelInfo {
             name: ModelName::AnthropicClaude2x,
             text: "anthropic.claude-v2",
+            provider: Provider::Anthropic,
+            modality: Modality::Text,
+            streaming: true,
+            tool_use: false,
+            context_window: Some(100_000),
+            max_output_tokens: Some(4_096),
+            embedding_dimensions: None,
+            pricing: Some(Pricing {
+                input_per_1k_tokens: 0.008,
+                output_per_1k_tokens: 0.024,
+            }),
         },
         ModelInfo {
             name: ModelName::AnthropicClaudeSonnet1x,
             text: "anthropic.claude-3-sonnet-20240229-v1:0",
+            provider: Provider::Anthropic,
+            modality: Modality::Text,
+            streaming: true,
+            tool_use: true,
+            context_window: Some(200_000),
+            max_output_tokens: Some(4_096),
+            embedding_dimensions: None,
+            pricing: Some(Pricing {
+                input_per_1k_tokens: 0.003,
+                output_per_1k_tokens: 0.015,
+            }),
         },
         ModelInfo {
             name: ModelName::AnthropicClaudeHaiku1x,
             text: "anthropic.claude-3-haiku-20240307-v1:0",
+            provider: Provider::Anthropic,
+            modality: Modality::Text,
+            streaming: true,
+            tool_use: true,
+            context_window: Some(200_000),
+            max_output_tokens: Some(4_096),
+            embedding_dimensions: None,
+            pricing: Some(Pricing {
+                input_per_1k_tokens: 0.00025,
+                output_per_1k_tokens: 0.00125,
+            }),
         },
         ModelInfo {
             name: ModelName::AnthropicClaudeOpus1x,
             text: "anthropic.claude-3-opus-20240229-v1:0",
+            provider: Provider::Anthropic,
+            modality: Modality::Text,
+            streaming: true,
+            tool_use: true,
+            context_window: Some(200_000),
+            max_output_tokens: Some(4_096),
+            embedding_dimensions: None,
+            pricing: Some(Pricing {
+                input_per_1k_tokens: 0.015,
+                output_per_1k_tokens: 0.075,
+            }),
         },
         ModelInfo {
             name: ModelName::AnthropicClaudeInstantx,
             text: "anthropic.claude-instant-v1",
+            provider: Provider::Anthropic,
+            modality: Modality::Text,
+            streaming: true,
+            tool_use: false,
+            context_window: Some(100_000),
+            max_output_tokens: Some(4_096),
+            embedding_dimensions: None,
+            pricing: Some(Pricing {
+                input_per_1k_tokens: 0.0008,
+                output_per_1k_tokens: 0.0024,
+            }),
         },
         ModelInfo {
             name: ModelName::AI21JurassicMid1x,
             text: "ai21.j2-mid-v1",
+            provider: Provider::AI21,
+            modality: Modality::Text,
+            streaming: false,
+            tool_use: false,
+            context_window: Some(8_191),
+            max_output_tokens: Some(8_191),
+            embedding_dimensions: None,
+            pricing: Some(Pricing {
+                input_per_1k_tokens: 0.0125,
+                output_per_1k_tokens: 0.0125,
+            }),
         },
         ModelInfo {
             name: ModelName::AI21JurassicUltra1x,
             text: "ai21.j2-ultra-v1",
+            provider: Provider::AI21,
+            modality: Modality::Text,
+            streaming: false,
+            tool_use: false,
+            context_window: Some(8_191),
+            max_output_tokens: Some(8_191),
+            embedding_dimensions: None,
+            pricing: Some(Pricing {
+                input_per_1k_tokens: 0.0188,
+                output_per_1k_tokens: 0.0188,
+            }),
         },
         ModelInfo {
             name: ModelName::CohereCmdTxt14x,
             text: "cohere.command-text-v14",
+            provider: Provider::Cohere,
+            modality: Modality::Text,
+            streaming: true,
+            tool_use: false,
+            context_window: Some(4_000),
+            max_output_tokens: Some(4_000),
+            embedding_dimensions: None,
+            pricing: Some(Pricing {
+                input_per_1k_tokens: 0.0015,
+                output_per_1k_tokens: 0.002,
+            }),
         },
         ModelInfo {
             name: ModelName::CohereCmdLightTxt15x,
             text: "cohere.command-light-text-v14",
+            provider: Provider::Cohere,
+            modality: Modality::Text,
+            streaming: true,
+            tool_use: false,
+            context_window: Some(4_000),
+            max_output_tokens: Some(4_000),
+            embedding_dimensions: None,
+            pricing: Some(Pricing {
+                input_per_1k_tokens: 0.0003,
+                output_per_1k_tokens: 0.0006,
+            }),
         },
         ModelInfo {
             name: ModelName::CohereEmbedEnglish3x,
             text: "cohere.embed-english-v3",
+            provider: Provider::Cohere,
+            modality: Modality::Embedding,
+            streaming: false,
+            tool_use: false,
+            context_window: Some(512),
+            max_output_tokens: None,
+            embedding_dimensions: Some(1_024),
+            pricing: Some(Pricing {
+                input_per_1k_tokens: 0.0001,
+                output_per_1k_tokens: 0.0,
+            }),
         },
         ModelInfo {
             name: ModelName::CohereEmbedMultilingual3x,
             text: "cohere.embed-multilingual-v3",
+            provider: Provider::Cohere,
+            modality: Modality::Embedding,
+            streaming: false,
+            tool_use: false,
+            context_window: Some(512),
+            max_output_tokens: None,
+            embedding_dimensions: Some(1_024),
+            pricing: Some(Pricing {
+                input_per_1k_tokens: 0.0001,
+                output_per_1k_tokens: 0.0,
+            }),
         },
         ModelInfo {
             name: ModelName::MetaLlama2Chat13B1x,
             text: "meta.llama2-13b-chat-v1",
+            provider: Provider::Meta,
+            modality: Modality::Text,
+            streaming: true,
+            tool_use: false,
+            context_window: Some(4_096),
+            max_output_tokens: Some(2_048),
+            embedding_dimensions: None,
+            pricing: Some(Pricing {
+                input_per_1k_tokens: 0.00075,
+                output_per_1k_tokens: 0.001,
+            }),
         },
         ModelInfo {
             name: ModelName::MetaLlama2Chat70B1x,
             text: "meta.llama2-70b-chat-v1",
+            provider: Provider::Meta,
+            modality: Modality::Text,
+            streaming: true,
+            tool_use: false,
+            context_window: Some(4_096),
+            max_output_tokens: Some(2_048),
+            embedding_dimensions: None,
+            pricing: Some(Pricing {
+                input_per_1k_tokens: 0.00195,
+                output_per_1k_tokens: 0.00256,
+            }),
         },
         ModelInfo {
             name: ModelName::MistralMistral7BInstruct0x,
             text: "mistral.mistral-7b-instruct-v0:2",
+            provider: Provider::Mistral,
+            modality: Modality::Text,
+            streaming: true,
+            tool_use: false,
+            context_window: Some(32_000),
+            max_output_tokens: Some(8_192),
+            embedding_dimensions: None,
+            pricing: Some(Pricing {
+                input_per_1k_tokens: 0.00015,
+                output_per_1k_tokens: 0.0002,
+            }),
         },
         ModelInfo {
             name: ModelName::MistralMixtral8X7BInstruct0x,
             text: "mistral.mixtral-8x7b-instruct-v0:1",
+            provider: Provider::Mistral,
+            modality: Modality::Text,
+            streaming: true,
+            tool_use: false,
+            context_window: Some(32_000),
+            max_output_tokens: Some(4_096),
+            embedding_dimensions: None,
+            pricing: Some(Pricing {
+                input_per_1k_tokens: 0.00045,
+                output_per_1k_tokens: 0.0007,
+            }),
         },
         ModelInfo {
             name: ModelName::MistralLarge,
             text: "mistral.mistral-large-2402-v1:0",
+            provider: Provider::Mistral,
+            modality: Modality::Text,
+            streaming: true,
+            tool_use: true,
+            context_window: Some(32_000),
+            max_output_tokens: Some(8_192),
+            embedding_dimensions: None,
+            pricing: Some(Pricing {
+                input_per_1k_tokens: 0.008,
+                output_per_1k_tokens: 0.024,
+            }),
         },
         ModelInfo {
             name: ModelName::StabilityStableDiffusionXL0x,
             text: "stability.stable-diffusion-xl-v0",
+            provider: Provider::Stability,
+            modality: Modality::Image,
+            streaming: false,
+            tool_use: false,
+            context_window: None,
+            max_output_tokens: None,
+            embedding_dimensions: None,
+            pricing: None,
         },
         ModelInfo {
             name: ModelName::StabilityStableDiffusionXL1x,
             text: "stability.stable-diffusion-xl-v1",
+            provider: Provider::Stability,
+            modality: Modality::Image,
+            streaming: false,
+            tool_use: false,
+            context_window: None,
+            max_output_tokens: None,
+            embedding_dimensions: None,
+            pricing: None,
         },
     ];
 
     pub fn from_model_name(name: ModelName) -> String  {
-        ModelInfo::MODELS.iter().find(|model| model.name == name).unwrap().text.to_string()
+        ModelInfo::get(name).text.to_string()
     }
 
-}
\ No newline at end of file
+    pub fn context_window_of(name: ModelName) -> Option<u32> {
+        ModelInfo::get(name).context_window
+    }
+
+    /// Returns the catalogue entry of a model.
+    pub fn get(name: ModelName) -> &'static ModelInfo {
+        ModelInfo::MODELS.iter().find(|model| model.name == name).unwrap()
+    }
+
+    /// Looks a model up by its Bedrock model id.
+    pub fn from_id(id: &str) -> Option<&'static ModelInfo> {
+        ModelInfo::MODELS.iter().find(|model| model.text == id)
+    }
+
+    /// Returns the models offered by a provider.
+    pub fn by_provider(provider: Provider) -> impl Iterator<Item = &'static ModelInfo> {
+        ModelInfo::MODELS.iter().filter(move |model| model.provider == provider)
+    }
+
+    /// Returns the models that have a capability.
+    pub fn with_capability(capability: Capability) -> impl Iterator<Item = &'static ModelInfo> {
+        ModelInfo::MODELS.iter().filter(move |model| model.has_capability(capability))
+    }
+
+    /// Returns the models that have all the given capabilities.
+    pub fn with_capabilities(capabilities: &[Capability]) -> impl Iterator<Item = &'static ModelInfo> + '_ {
+        ModelInfo::MODELS
+            .iter()
+            .filter(move |model| capabilities.iter().all(|c| model.has_capability(*c)))
+    }
+
+    pub fn has_capability(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::TextGeneration => self.modality == Modality::Text,
+            Capability::ImageGeneration => self.modality == Modality::Image,
+            Capability::Embedding => self.modality == Modality::Embedding,
+            Capability::Streaming => self.streaming,
+            Capability::ToolUse => self.tool_use,
+        }
+    }
+
+    /// The estimated cost in USD of a request, if the model is priced per token.
+    pub fn cost(&self, input_tokens: u64, output_tokens: u64) -> Option<f64> {
+        self.pricing.map(|pricing| pricing.cost(input_tokens, output_tokens))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_name_round_trip() {
+        for model in ModelInfo::MODELS {
+            assert_eq!(model.name.model_id(), model.text);
+            assert_eq!(model.text.parse::<ModelName>().unwrap(), model.name);
+        }
+        assert!("unknown.model-v1".parse::<ModelName>().is_err());
+    }
+
+    #[test]
+    fn test_model_name_serde() {
+        let json = serde_json::to_string(&ModelName::AnthropicClaudeHaiku1x).unwrap();
+        assert_eq!(json, r#""anthropic.claude-3-haiku-20240307-v1:0""#);
+
+        let name: ModelName = serde_json::from_str(&json).unwrap();
+        assert_eq!(name, ModelName::AnthropicClaudeHaiku1x);
+    }
+
+    #[test]
+    fn test_with_capabilities() {
+        let models: Vec<_> =
+            ModelInfo::with_capabilities(&[Capability::TextGeneration, Capability::ToolUse])
+                .map(|model| model.name)
+                .collect();
+
+        assert!(models.contains(&ModelName::AnthropicClaudeHaiku1x));
+        assert!(!models.contains(&ModelName::AnthropicClaude2x));
+        assert!(ModelInfo::with_capability(Capability::Embedding)
+            .all(|model| model.embedding_dimensions.is_some()));
+    }
+
+    #[test]
+    fn test_cost() {
+        let haiku = ModelInfo::get(ModelName::AnthropicClaudeHaiku1x);
+
+        let cost = haiku.cost(2_000, 1_000).unwrap();
+
+        assert!((cost - 0.00175).abs() < 1e-9);
+        assert!(ModelInfo::get(ModelName::StabilityStableDiffusionXL1x).cost(1, 1).is_none());
+    }
+}
@@ -0,0 +1,364 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::bedrock::model_info::{ModelInfo, ModelName};
+
+/// The geographic prefixes of the system-defined cross-region inference profiles.
+const INFERENCE_PROFILE_PREFIXES: [&str; 8] =
+    ["us", "us-gov", "eu", "apac", "jp", "au", "ca", "global"];
+
+/// What a `ModelTarget` designates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ModelTargetKind {
+    /// A base model, by id (`anthropic.claude-v2`) or ARN.
+    Foundation,
+    /// A system-defined cross-region inference profile, by id (`us.anthropic.claude-v2`) or ARN.
+    InferenceProfile,
+    /// An application inference profile ARN, created to track the usage of a workload.
+    ApplicationInferenceProfile,
+    /// A provisioned throughput ARN.
+    ProvisionedThroughput,
+    /// A custom (fine-tuned or imported) model ARN.
+    CustomModel,
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ModelTargetError {
+    #[error("Invalid model id: {0}")]
+    InvalidModelId(String),
+
+    #[error("Invalid model ARN: {0}")]
+    InvalidArn(String),
+}
+
+/// The model an invocation is sent to: a base model, an inference profile, a provisioned
+/// throughput or a custom model.
+///
+/// `id` is passed unchanged as the model id of the invocation. The underlying base model is
+/// derived from the id for base models and system-defined inference profiles. ARNs of
+/// provisioned throughputs, custom models and application inference profiles do not name
+/// it, so it has to be given with `with_base_model` for capability lookups to work.
+///
+/// # Examples
+///
+/// ```
+/// use hiramu::bedrock::{ModelName, ModelTarget, ModelTargetKind};
+///
+/// let target: ModelTarget = "us.anthropic.claude-3-haiku-20240307-v1:0".parse().unwrap();
+/// assert_eq!(target.kind(), ModelTargetKind::InferenceProfile);
+/// assert_eq!(target.base_model(), Some(ModelName::AnthropicClaudeHaiku1x));
+///
+/// let target: ModelTarget = "arn:aws:bedrock:us-east-1:123456789012:provisioned-model/abc123"
+///     .parse::<ModelTarget>()
+///     .unwrap()
+///     .with_base_model(ModelName::AnthropicClaudeHaiku1x);
+/// assert!(target.model_info().is_some());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ModelTarget {
+    id: String,
+    kind: ModelTargetKind,
+    base_model: Option<ModelName>,
+}
+
+impl ModelTarget {
+    /// Parses and validates a model id, inference profile id or ARN.
+    pub fn parse(id: &str) -> Result<Self, ModelTargetError> {
+        let id = id.trim();
+        if id.starts_with("arn:") {
+            return Self::parse_arn(id);
+        }
+
+        if !is_valid_model_id(id) {
+            return Err(ModelTargetError::InvalidModelId(id.to_string()));
+        }
+        let (kind, model_id) = match split_inference_profile_prefix(id) {
+            Some(model_id) => (ModelTargetKind::InferenceProfile, model_id),
+            None => (ModelTargetKind::Foundation, id),
+        };
+
+        Ok(Self {
+            id: id.to_string(),
+            kind,
+            base_model: ModelInfo::from_id(model_id).map(|model| model.name),
+        })
+    }
+
+    fn parse_arn(arn: &str) -> Result<Self, ModelTargetError> {
+        let invalid = || ModelTargetError::InvalidArn(arn.to_string());
+
+        let parts: Vec<&str> = arn.splitn(6, ':').collect();
+        let [_, partition, service, region, account, resource] = parts[..] else {
+            return Err(invalid());
+        };
+        if !partition.starts_with("aws") || service != "bedrock" || region.is_empty() {
+            return Err(invalid());
+        }
+        let (resource_type, resource_id) = resource.split_once('/').ok_or_else(invalid)?;
+        if resource_id.is_empty() {
+            return Err(invalid());
+        }
+
+        let kind = match resource_type {
+            "foundation-model" => ModelTargetKind::Foundation,
+            "inference-profile" => ModelTargetKind::InferenceProfile,
+            "application-inference-profile" => ModelTargetKind::ApplicationInferenceProfile,
+            "provisioned-model" => ModelTargetKind::ProvisionedThroughput,
+            "custom-model" => ModelTargetKind::CustomModel,
+            _ => return Err(invalid()),
+        };
+        let is_account_id = account.len() == 12 && account.bytes().all(|b| b.is_ascii_digit());
+        if kind != ModelTargetKind::Foundation && !is_account_id {
+            return Err(invalid());
+        }
+
+        let base_model = match kind {
+            ModelTargetKind::Foundation => ModelInfo::from_id(resource_id),
+            ModelTargetKind::InferenceProfile => {
+                split_inference_profile_prefix(resource_id).and_then(ModelInfo::from_id)
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            id: arn.to_string(),
+            kind,
+            base_model: base_model.map(|model| model.name),
+        })
+    }
+
+    /// Sets the base model behind a provisioned throughput, a custom model or an application
+    /// inference profile.
+    pub fn with_base_model(mut self, base_model: ModelName) -> Self {
+        self.base_model = Some(base_model);
+        self
+    }
+
+    /// The id to pass as model id when invoking the target.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn kind(&self) -> ModelTargetKind {
+        self.kind
+    }
+
+    /// The base model served by the target, if known.
+    pub fn base_model(&self) -> Option<ModelName> {
+        self.base_model
+    }
+
+    /// The catalogue entry of the base model, if known.
+    pub fn model_info(&self) -> Option<&'static ModelInfo> {
+        self.base_model.map(ModelInfo::get)
+    }
+
+    /// The region of an ARN target. `None` for ids, which are resolved in the client region.
+    pub fn region(&self) -> Option<&str> {
+        if self.id.starts_with("arn:") {
+            self.id.split(':').nth(3)
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns the base model id of a cross-region inference profile id, e.g.
+/// `anthropic.claude-v2` for `us.anthropic.claude-v2`.
+fn split_inference_profile_prefix(id: &str) -> Option<&str> {
+    let (prefix, model_id) = id.split_once('.')?;
+    if INFERENCE_PROFILE_PREFIXES.contains(&prefix) && model_id.contains('.') {
+        Some(model_id)
+    } else {
+        None
+    }
+}
+
+/// Model ids are `provider.model` with an optional `:version` suffix, e.g.
+/// `anthropic.claude-3-haiku-20240307-v1:0`.
+fn is_valid_model_id(id: &str) -> bool {
+    let valid_chars = id
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '_'));
+    let has_provider = matches!(id.split_once('.'), Some((provider, model)) if !provider.is_empty() && !model.is_empty());
+    valid_chars && has_provider
+}
+
+impl From<ModelName> for ModelTarget {
+    fn from(name: ModelName) -> Self {
+        Self {
+            id: name.model_id().to_string(),
+            kind: ModelTargetKind::Foundation,
+            base_model: Some(name),
+        }
+    }
+}
+
+impl From<ModelTarget> for String {
+    fn from(target: ModelTarget) -> Self {
+        target.id
+    }
+}
+
+impl FromStr for ModelTarget {
+    type Err = ModelTargetError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for ModelTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.id)
+    }
+}
+
+/// A target is serialized as its id, unless its base model was set with `with_base_model`,
+/// in which case it is serialized as `{"id": .., "base_model": ..}` so that it survives a
+/// round trip.
+impl Serialize for ModelTarget {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let derived = ModelTarget::parse(&self.id).ok().and_then(|target| target.base_model);
+        if self.base_model == derived {
+            serializer.serialize_str(&self.id)
+        } else {
+            SerializedTarget::WithBaseModel {
+                id: self.id.clone(),
+                base_model: self.base_model,
+            }
+            .serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ModelTarget {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match SerializedTarget::deserialize(deserializer)? {
+            SerializedTarget::Id(id) => id.parse().map_err(serde::de::Error::custom),
+            SerializedTarget::WithBaseModel { id, base_model } => {
+                let mut target: ModelTarget = id.parse().map_err(serde::de::Error::custom)?;
+                if base_model.is_some() {
+                    target.base_model = base_model;
+                }
+                Ok(target)
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SerializedTarget {
+    Id(String),
+    WithBaseModel {
+        id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        base_model: Option<ModelName>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_model_ids() {
+        let target = ModelTarget::parse("anthropic.claude-3-haiku-20240307-v1:0").unwrap();
+        assert_eq!(target.kind(), ModelTargetKind::Foundation);
+        assert_eq!(target.base_model(), Some(ModelName::AnthropicClaudeHaiku1x));
+        assert_eq!(target.region(), None);
+
+        let target = ModelTarget::parse("eu.anthropic.claude-3-haiku-20240307-v1:0").unwrap();
+        assert_eq!(target.kind(), ModelTargetKind::InferenceProfile);
+        assert_eq!(target.base_model(), Some(ModelName::AnthropicClaudeHaiku1x));
+        assert_eq!(target.id(), "eu.anthropic.claude-3-haiku-20240307-v1:0");
+
+        let target = ModelTarget::parse("example.new-model-v1").unwrap();
+        assert_eq!(target.kind(), ModelTargetKind::Foundation);
+        assert_eq!(target.model_info(), None);
+    }
+
+    #[test]
+    fn test_parse_arns() {
+        let target = ModelTarget::parse(
+            "arn:aws:bedrock:us-east-1::foundation-model/anthropic.claude-v2",
+        )
+        .unwrap();
+        assert_eq!(target.kind(), ModelTargetKind::Foundation);
+        assert_eq!(target.base_model(), Some(ModelName::AnthropicClaude2x));
+        assert_eq!(target.region(), Some("us-east-1"));
+
+        let target = ModelTarget::parse(
+            "arn:aws:bedrock:us-east-1:123456789012:inference-profile/us.anthropic.claude-v2",
+        )
+        .unwrap();
+        assert_eq!(target.kind(), ModelTargetKind::InferenceProfile);
+        assert_eq!(target.base_model(), Some(ModelName::AnthropicClaude2x));
+
+        let target = ModelTarget::parse(
+            "arn:aws:bedrock:us-east-1:123456789012:application-inference-profile/a1b2c3",
+        )
+        .unwrap();
+        assert_eq!(target.kind(), ModelTargetKind::ApplicationInferenceProfile);
+        assert_eq!(target.base_model(), None);
+
+        let target =
+            ModelTarget::parse("arn:aws:bedrock:us-west-2:123456789012:provisioned-model/abc123")
+                .unwrap()
+                .with_base_model(ModelName::AnthropicClaude2x);
+        assert_eq!(target.kind(), ModelTargetKind::ProvisionedThroughput);
+        assert_eq!(target.model_info().unwrap().name, ModelName::AnthropicClaude2x);
+
+        let target = ModelTarget::parse(
+            "arn:aws:bedrock:us-west-2:123456789012:custom-model/anthropic.claude-v2/abc123",
+        )
+        .unwrap();
+        assert_eq!(target.kind(), ModelTargetKind::CustomModel);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for id in [
+            "",
+            "claude",
+            "anthropic.claude v2",
+            "arn:aws:s3:us-east-1:123456789012:provisioned-model/abc123",
+            "arn:aws:bedrock:us-east-1:123456789012:provisioned-model/",
+            "arn:aws:bedrock:us-east-1:123:provisioned-model/abc123",
+            "arn:aws:bedrock:us-east-1:123456789012:knowledge-base/abc123",
+            "arn:aws:bedrock:us-east-1",
+        ] {
+            assert!(ModelTarget::parse(id).is_err(), "{} should be invalid", id);
+        }
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let target = ModelTarget::from(ModelName::AnthropicClaude2x);
+
+        let json = serde_json::to_string(&target).unwrap();
+        assert_eq!(json, "\"anthropic.claude-v2\"");
+
+        let parsed: ModelTarget = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, target);
+    }
+
+    #[test]
+    fn test_serde_round_trip_with_base_model() {
+        let target =
+            ModelTarget::parse("arn:aws:bedrock:us-west-2:123456789012:provisioned-model/abc123")
+                .unwrap()
+                .with_base_model(ModelName::AnthropicClaude2x);
+
+        let json = serde_json::to_value(&target).unwrap();
+        assert_eq!(json["base_model"], "anthropic.claude-v2");
+
+        let parsed: ModelTarget = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, target);
+        assert_eq!(parsed.kind(), ModelTargetKind::ProvisionedThroughput);
+    }
+}
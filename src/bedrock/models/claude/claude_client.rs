@@ -1,10 +1,19 @@
 use crate::bedrock::bedrock_client::{BedrockClient, BedrockClientOptions};
+use crate::bedrock::guardrail::GuardrailAction;
 use crate::bedrock::models::claude::claude_request_message::{
-    ChatOptions, ConversationRequest, ConversationResponse, StreamResult,
+    ChatOptions, ContentBlock, ConversationRequest, ConversationResponse, MessageContent,
+    StopReason, StreamResult,
 };
+use crate::bedrock::models::claude::claude_stream::ConversationStream;
 use crate::bedrock::models::claude::error::ClaudeError;
+use crate::cache::response_cache::{is_deterministic, CachingStream, ResponseCache};
+use crate::cache::CacheKey;
+use crate::telemetry::{GenAiSpan, OPERATION_CHAT, PROVIDER_BEDROCK};
+use crate::tokenizer::{HeuristicTokenizer, Tokenizer};
+use crate::usage::usage_ledger::UsageReporter;
+use futures::future::Either;
 use futures::stream::Stream;
-use futures::TryStreamExt;
+use futures::StreamExt;
 use serde_json::Value;
 
 use super::claude_request_message::{
@@ -14,16 +23,73 @@ use super::claude_request_message::{
 
 pub type ClaudeOptions = BedrockClientOptions;
 
+/// The tokens Claude charges for an image of about one megapixel.
+const IMAGE_TOKENS: usize = 1600;
+
 pub struct ClaudeClient {
     client: BedrockClient,
+    usage: Option<UsageReporter>,
+    cache: Option<ResponseCache>,
 }
 
+const CACHE_CHAT: &str = "claude.chat";
+const CACHE_CHAT_STREAM: &str = "claude.chat_stream";
+
 impl ClaudeClient {
-    /// Constructs a new `BedrockClient`.
-    pub async fn new(options: ClaudeOptions) -> Self {
-        Self {
-            client: BedrockClient::new(options).await,
+    /// Constructs a new `ClaudeClient`, or fails if the options are invalid.
+    pub async fn new(options: ClaudeOptions) -> Result<Self, ClaudeError> {
+        Ok(Self {
+            client: BedrockClient::new(options).await?,
+            usage: None,
+            cache: None,
+        })
+    }
+
+    /// Reports the token usage of every call into a usage ledger, and checks its budgets
+    /// before sending a call.
+    pub fn with_usage(mut self, usage: UsageReporter) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    /// Serves the requests with a temperature of zero from a response cache, and stores their
+    /// responses. A cached stream is replayed event by event.
+    pub fn with_cache(mut self, cache: ResponseCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// The cache and key of a request, unless it bypasses the cache. The options are part of
+    /// the key, since they carry the model and the sampling parameters.
+    fn cache_key(
+        &self,
+        operation: &str,
+        request: &ConversationRequest,
+        options: &ChatOptions,
+    ) -> Option<(ResponseCache, CacheKey)> {
+        let cache = self.cache.as_ref()?;
+        let deterministic = is_deterministic(options.temperature.map(f64::from));
+        let key = cache.key_for(operation, &options.model_id, &(options, request), deterministic)?;
+        Some((cache.clone(), key))
+    }
+
+    fn check_budget(
+        &self,
+        request: &ConversationRequest,
+        options: &ChatOptions,
+    ) -> Result<(), ClaudeError> {
+        if let Some(usage) = &self.usage {
+            let input_tokens = estimate_input_tokens(request) as u64;
+            // The body sends the max_tokens of the request when it is set.
+            let max_tokens = request
+                .max_tokens
+                .map_or(options.max_tokens as u64, |max_tokens| max_tokens.max(0) as u64);
+            match options.model_target() {
+                Ok(target) => usage.check_target(&target, input_tokens, max_tokens)?,
+                Err(_) => usage.check(&options.model_id, input_tokens, max_tokens)?,
+            };
         }
+        Ok(())
     }
 
     pub async fn chat(
@@ -31,21 +97,66 @@ impl ClaudeClient {
         request: &ConversationRequest,
         options: &ChatOptions,
     ) -> Result<ConversationResponse, ClaudeError> {
+        let cache_key = self.cache_key(CACHE_CHAT, request, options);
+        if let Some(response) = cache_key.as_ref().and_then(|(cache, key)| cache.get(key)) {
+            return Ok(response);
+        }
+
+        let span = chat_span(request, options);
+        if let Err(err) = self.check_budget(request, options) {
+            span.error(&err);
+            return Err(err);
+        }
+
         let model_id = options.model_id.to_string();
-        let payload = serde_json::to_value(request);
+        let payload = payload(request, options);
 
         let payload = match payload {
             Ok(payload) => payload,
             Err(err) => return Err(ClaudeError::Json(err)),
         };
 
-        let response = self.client.generate_raw(model_id, payload).await;
+        let response = span
+            .instrument(self.client.generate_raw_with_guardrail(
+                model_id,
+                payload,
+                options.guardrail.as_ref(),
+            ))
+            .await;
         match response {
             Ok(response) => {
-                let conversation_response = serde_json::from_value(response).unwrap();
+                let mut conversation_response: ConversationResponse =
+                    match serde_json::from_value(response) {
+                        Ok(conversation_response) => conversation_response,
+                        Err(err) => {
+                            let err = ClaudeError::Json(err);
+                            span.error(&err);
+                            return Err(err);
+                        }
+                    };
+                if conversation_response.guardrail_action == Some(GuardrailAction::Intervened) {
+                    conversation_response.stop_reason = StopReason::GuardrailIntervened;
+                }
+                let input_tokens = conversation_response.usage.input_tokens.max(0) as u64;
+                let output_tokens = conversation_response.usage.output_tokens.max(0) as u64;
+                if let Some(usage) = &self.usage {
+                    record_usage(usage, options, input_tokens, output_tokens);
+                }
+                span.response_model(&conversation_response.model);
+                span.usage(input_tokens, output_tokens);
+                span.finish_reason(conversation_response.stop_reason.as_str());
+                span.output(|| {
+                    serde_json::to_string(&conversation_response.content).unwrap_or_default()
+                });
+                if let Some((cache, key)) = &cache_key {
+                    cache.put(key, &conversation_response);
+                }
                 Ok(conversation_response)
             }
-            Err(err) => Err(ClaudeError::from(err)),
+            Err(err) => {
+                span.error(&err);
+                Err(ClaudeError::from(err))
+            }
         }
     }
 
@@ -54,23 +165,180 @@ impl ClaudeClient {
         request: &ConversationRequest,
         options: &ChatOptions,
     ) -> Result<impl Stream<Item = Result<StreamResultData, ClaudeError>>, ClaudeError> {
+        let cache_key = self.cache_key(CACHE_CHAT_STREAM, request, options);
+        if let Some(replay) = cache_key.as_ref().and_then(|(cache, key)| cache.replay(key)) {
+            return Ok(Either::Left(replay));
+        }
+
+        let mut span = chat_span(request, options);
+        if let Err(err) = self.check_budget(request, options) {
+            span.error(&err);
+            return Err(err);
+        }
+
         let model_id = options.model_id.to_string();
-        let payload = serde_json::to_value(request).map_err(|err| ClaudeError::Json(err))?;
+        let payload = payload(request, options).map_err(ClaudeError::Json)?;
+
+        let response = span
+            .instrument(self.client.generate_raw_stream_with_guardrail(
+                model_id,
+                payload,
+                options.guardrail.as_ref(),
+            ))
+            .await;
+        let response = match response {
+            Ok(response) => response,
+            Err(err) => {
+                span.error(&err);
+                return Err(err.into());
+            }
+        };
 
-        let response = self.client.generate_raw_stream(model_id, payload).await?;
+        let usage = self.usage.clone();
+        let usage_options = options.clone();
+        // The completion is only accumulated when it is recorded in the span.
+        let mut completion = crate::telemetry::capture_content().then(String::new);
+        let stream = response.map(move |chunk| {
+            let result = chunk
+                .map_err(ClaudeError::from)
+                .and_then(deserialize_stream_result);
+            let result = match result {
+                Ok(result) => result,
+                Err(err) => {
+                    span.error(&err);
+                    return Err(err);
+                }
+            };
+            match &result {
+                StreamResultData::ContentBlockDelta(content_block_delta) => {
+                    span.first_token();
+                    if let (Some(completion), Some(text)) =
+                        (completion.as_mut(), content_block_delta.delta.text())
+                    {
+                        completion.push_str(text);
+                    }
+                }
+                StreamResultData::MessageDelta(message_delta) => {
+                    span.finish_reason(&message_delta.delta.stop_reason);
+                }
+                StreamResultData::MessageStop(message_stop) => {
+                    let metrics = &message_stop.invocation_metrics;
+                    let input_tokens = metrics.input_token_count.max(0) as u64;
+                    let output_tokens = metrics.output_token_count.max(0) as u64;
+                    if let Some(usage) = &usage {
+                        record_usage(usage, &usage_options, input_tokens, output_tokens);
+                    }
+                    span.usage(input_tokens, output_tokens);
+                    if message_stop.guardrail_action == Some(GuardrailAction::Intervened) {
+                        span.finish_reason(StopReason::GuardrailIntervened.as_str());
+                    }
+                    if let Some(completion) = completion.take() {
+                        span.output(|| completion);
+                    }
+                }
+                _ => {}
+            }
+            Ok(result)
+        });
 
-        let stream = response
-            .map_err(ClaudeError::from)
-            .and_then(|chunk| async move {
-                let stream_result = deserialize_stream_result(chunk)?;
-                Ok(stream_result)
-            });
+        Ok(Either::Right(CachingStream::new(stream, cache_key)))
+    }
 
-        Ok(stream)
+    /// Streams the text of the response, while accumulating the full `ConversationResponse`.
+    ///
+    /// The returned `ConversationStream` yields the text deltas as `String`s. Call
+    /// `collect_response` or `into_response` on it to get the final response, including
+    /// the invocation metrics reported by Bedrock.
+    pub async fn chat_with_text_stream(
+        &self,
+        request: &ConversationRequest,
+        options: &ChatOptions,
+    ) -> Result<
+        ConversationStream<impl Stream<Item = Result<StreamResultData, ClaudeError>>>,
+        ClaudeError,
+    > {
+        let stream = self.chat_with_stream(request, options).await?;
+        Ok(ConversationStream::new(stream))
     }
 }
 
-fn deserialize_stream_result(value: Value) -> Result<StreamResultData, ClaudeError> {
+/// Opens the span of a chat call, with the request parameters and, if content capture is
+/// enabled, the messages.
+fn chat_span(request: &ConversationRequest, options: &ChatOptions) -> GenAiSpan {
+    let span = GenAiSpan::new(OPERATION_CHAT, PROVIDER_BEDROCK, &options.model_id);
+    span.request(
+        Some(options.max_tokens as u64),
+        options.temperature.map(f64::from),
+        options.top_p.map(f64::from),
+    );
+    span.input(|| serde_json::to_string(&request.messages).unwrap_or_default());
+    span
+}
+
+/// The body of an invocation: the request, with the sampling parameters of the options.
+fn payload(request: &ConversationRequest, options: &ChatOptions) -> Result<Value, serde_json::Error> {
+    let mut payload = serde_json::to_value(request)?;
+    if let Value::Object(body) = &mut payload {
+        if let Some(temperature) = options.temperature {
+            body.insert("temperature".to_string(), serde_json::to_value(temperature)?);
+        }
+        if let Some(top_p) = options.top_p {
+            body.insert("top_p".to_string(), serde_json::to_value(top_p)?);
+        }
+        if let Some(top_k) = options.top_k {
+            body.insert("top_k".to_string(), top_k.into());
+        }
+        if let Some(stop_sequences) = options.stop_sequences.as_ref().filter(|stop| !stop.is_empty()) {
+            body.insert("stop_sequences".to_string(), serde_json::to_value(stop_sequences)?);
+        }
+    }
+    Ok(payload)
+}
+
+/// Records the usage of a call, priced with the base model of the options when it is set.
+fn record_usage(usage: &UsageReporter, options: &ChatOptions, input_tokens: u64, output_tokens: u64) {
+    match options.model_target() {
+        Ok(target) => usage.record_target(&target, input_tokens, output_tokens),
+        Err(_) => usage.record(&options.model_id, input_tokens, output_tokens),
+    };
+}
+
+/// Estimates the prompt tokens of a request, to check it against the usage budgets.
+fn estimate_input_tokens(request: &ConversationRequest) -> usize {
+    let tokenizer = HeuristicTokenizer::new();
+    let system = request
+        .system
+        .as_deref()
+        .map(|system| tokenizer.count_tokens(system))
+        .unwrap_or(0);
+    let messages: usize = request
+        .messages
+        .iter()
+        .map(|message| match &message.content {
+            MessageContent::Text(text) => tokenizer.count_tokens(text),
+            MessageContent::Blocks(blocks) => blocks
+                .iter()
+                .map(|block| match block {
+                    ContentBlock::Text { text } => tokenizer.count_tokens(text),
+                    ContentBlock::Image { .. } => IMAGE_TOKENS,
+                    ContentBlock::ToolUse { name, input, .. } => {
+                        tokenizer.count_tokens(name) + tokenizer.count_tokens(&input.to_string())
+                    }
+                    ContentBlock::ToolResult { content, .. } => tokenizer.count_tokens(content),
+                })
+                .sum(),
+        })
+        .sum();
+    let tools: usize = request
+        .tools
+        .iter()
+        .flatten()
+        .map(|tool| tokenizer.count_tokens(&serde_json::to_string(tool).unwrap_or_default()))
+        .sum();
+    system + messages + tools
+}
+
+pub(crate) fn deserialize_stream_result(value: Value) -> Result<StreamResultData, ClaudeError> {
     let stream_result: StreamResult = serde_json::from_value(value)
         .map_err(|err| ClaudeError::Deserialization(err.to_string()))?;
 
@@ -1,6 +1,13 @@
 use serde::{Deserialize, Serialize};
 
-
+use crate::bedrock::guardrail::{GuardrailAction, GuardrailConfig, GuardrailOutcome};
+use crate::bedrock::model_info::ModelName;
+use crate::bedrock::model_target::{ModelTarget, ModelTargetError};
+
+/// The model and sampling parameters of a Claude call. The sampling parameters that are
+/// set are sent with the request; `top_p` and `top_k` are unset by default, since recent
+/// models reject `temperature` and `top_p` together.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatOptions {
     pub model_id: String,
     pub temperature: Option<f32>,
@@ -8,6 +15,13 @@ pub struct ChatOptions {
     pub top_k: Option<u32>,
     pub max_tokens: u32,
     pub stop_sequences: Option<Vec<String>>,
+    /// Overrides the guardrail of the client options for the calls made with these options.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guardrail: Option<GuardrailConfig>,
+    /// The base model behind `model_id` when the id does not name it, e.g. a provisioned
+    /// throughput ARN, used for pricing and capability lookups.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_model: Option<ModelName>,
 }
 
 impl Default for ChatOptions {
@@ -15,10 +29,12 @@ impl Default for ChatOptions {
         ChatOptions {
             model_id: "anthropic.claude-3-haiku-20240307-v1:0".to_string(),
             temperature: Some(0.5),
-            top_p: Some(1.0),
-            top_k: Some(50),
+            top_p: None,
+            top_k: None,
             max_tokens: 100,
             stop_sequences: Some(vec![]),
+            guardrail: None,
+            base_model: None,
         }
     }
 }
@@ -26,9 +42,27 @@ impl Default for ChatOptions {
 impl ChatOptions {
     pub fn with_model_id(mut self, model_id: String) -> Self {
         self.model_id = model_id;
+        self.base_model = None;
         self
     }
 
+    /// Sets the model id and base model from a base model, inference profile, provisioned
+    /// throughput or custom model target.
+    pub fn with_model_target(mut self, model_target: &ModelTarget) -> Self {
+        self.model_id = model_target.id().to_string();
+        self.base_model = model_target.base_model();
+        self
+    }
+
+    /// The target of `model_id`, with `base_model` if it is set.
+    pub fn model_target(&self) -> Result<ModelTarget, ModelTargetError> {
+        let target = ModelTarget::parse(&self.model_id)?;
+        Ok(match self.base_model {
+            Some(base_model) => target.with_base_model(base_model),
+            None => target,
+        })
+    }
+
     pub fn with_temperature(mut self, temperature: f32) -> Self {
         self.temperature = Some(temperature);
         self
@@ -54,6 +88,11 @@ impl ChatOptions {
         self
     }
 
+    pub fn with_guardrail(mut self, guardrail: GuardrailConfig) -> Self {
+        self.guardrail = Some(guardrail);
+        self
+    }
+
     pub fn add_stop_sequence(mut self, stop_sequence: String) -> Self {
         match &mut self.stop_sequences {
             Some(sequences) => sequences.push(stop_sequence),
@@ -63,7 +102,7 @@ impl ChatOptions {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Role {
     #[serde(rename = "user")]
     User,
@@ -71,29 +110,40 @@ pub enum Role {
     Assistant,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum MessageContent {
     Text(String),
     Blocks(Vec<ContentBlock>),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
     pub role: Role,
     pub content: MessageContent,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum ContentBlock {
     #[serde(rename = "text")]
     Text { text: String },
     #[serde(rename = "image")]
     Image { source: ImageSource },
-}
-
-#[derive(Debug, Serialize, Deserialize)]
+    /// A call of a tool by the model, with the arguments in `input`.
+    #[serde(rename = "tool_use")]
+    ToolUse { id: String, name: String, input: serde_json::Value },
+    /// The result of a tool call, sent back in a user message.
+    #[serde(rename = "tool_result")]
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        is_error: Option<bool>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ImageSource {
     #[serde(rename = "type")]
     pub source_type: String,
@@ -101,12 +151,53 @@ pub struct ImageSource {
     pub data: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A tool the model can call. The arguments of a call are described by `input_schema`,
+/// a JSON Schema of an object.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tool {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub input_schema: serde_json::Value,
+}
+
+impl Tool {
+    pub fn new<S: Into<String>>(name: S, input_schema: serde_json::Value) -> Self {
+        Tool {
+            name: name.into(),
+            description: None,
+            input_schema,
+        }
+    }
+
+    pub fn with_description<S: Into<String>>(mut self, description: S) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+}
+
+/// How the model chooses between answering and calling a tool.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolChoice {
+    /// The model decides.
+    Auto,
+    /// The model calls one of the tools.
+    Any,
+    /// The model calls the named tool.
+    Tool { name: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConversationRequest {
     pub system: Option<String>,
     pub messages: Vec<Message>,
     pub max_tokens: Option<i32>,
     pub anthropic_version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Tool>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
 }
 
 impl Default for ConversationRequest {
@@ -116,6 +207,8 @@ impl Default for ConversationRequest {
             messages: Vec::new(),
             max_tokens: Some(1024),
             anthropic_version: "bedrock-2023-05-31".to_string(),
+            tools: None,
+            tool_choice: None,
         }
     }
 }
@@ -166,7 +259,7 @@ impl From<Vec<ContentBlock>> for MessageContent {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum StopReason {
     #[serde(rename = "end_turn")]
     EndTurn,
@@ -174,9 +267,29 @@ pub enum StopReason {
     MaxTokens,
     #[serde(rename = "stop_sequence")]
     StopSequence,
+    /// The model called a tool: the content has a `tool_use` block.
+    #[serde(rename = "tool_use")]
+    ToolUse,
+    /// A guardrail blocked the prompt or the completion: the content is the blocked message
+    /// configured on the guardrail.
+    #[serde(rename = "guardrail_intervened")]
+    GuardrailIntervened,
+}
+
+impl StopReason {
+    /// The name of the stop reason in the Anthropic API.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StopReason::EndTurn => "end_turn",
+            StopReason::MaxTokens => "max_tokens",
+            StopReason::StopSequence => "stop_sequence",
+            StopReason::ToolUse => "tool_use",
+            StopReason::GuardrailIntervened => "guardrail_intervened",
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConversationResponse {
     pub id: String,
     pub model: String,
@@ -187,15 +300,57 @@ pub struct ConversationResponse {
     pub stop_reason: StopReason,
     pub stop_sequence: Option<String>,
     pub usage: UsageInfo,
+    /// Only present when the response was assembled from a stream, since Bedrock reports
+    /// these metrics in the `message_stop` event.
+    #[serde(
+        rename = "amazon-bedrock-invocationMetrics",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub invocation_metrics: Option<InvocationMetrics>,
+    /// Only present when a guardrail is configured.
+    #[serde(
+        rename = "amazon-bedrock-guardrailAction",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub guardrail_action: Option<GuardrailAction>,
+    /// The guardrail assessment, when the guardrail trace is enabled.
+    #[serde(
+        rename = "amazon-bedrock-trace",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub trace: Option<serde_json::Value>,
+}
+
+impl ConversationResponse {
+    /// The guardrail action and trace of the response, if a guardrail is configured.
+    pub fn guardrail_outcome(&self) -> Option<GuardrailOutcome> {
+        self.guardrail_action
+            .map(|action| GuardrailOutcome::new(action, self.trace.as_ref()))
+    }
+
+    /// The input of the first call of the tool `name` in the response, if any.
+    pub fn tool_input(&self, name: &str) -> Option<&serde_json::Value> {
+        self.content.iter().find_map(|block| match block {
+            ContentBlock::ToolUse {
+                name: tool_name,
+                input,
+                ..
+            } if tool_name == name => Some(input),
+            _ => None,
+        })
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UsageInfo {
     pub input_tokens: i32,
     pub output_tokens: i32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StreamResult {
     #[serde(rename = "type")]
     pub result_type: String,
@@ -204,7 +359,7 @@ pub struct StreamResult {
 }
 
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum StreamResultData {
     MessageStart(MessageStart),
     ContentBlockStart(ContentBlockStart),
@@ -214,65 +369,111 @@ pub enum StreamResultData {
     MessageStop(MessageStop),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The `message_start` event of a stream, carrying the id, model and input token usage of
+/// the response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MessageStart {
-    pub message: Message,
+    pub message: MessageStartData,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageStartData {
+    pub id: String,
+    pub model: String,
+    #[serde(rename = "type")]
+    pub message_type: String,
+    pub role: Role,
+    pub content: Vec<ContentBlock>,
+    pub stop_reason: Option<StopReason>,
+    pub stop_sequence: Option<String>,
+    pub usage: UsageInfo,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Usage {
     pub input_tokens: i32,
     pub output_tokens: i32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContentBlockStart {
     pub content_block: ContentBlock,
     pub index: i32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContentBlockDelta {
     pub delta: Delta,
     pub index: i32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Delta {
-    pub text: String,
-    #[serde(rename = "type")]
-    pub delta_type: String,
+/// The content added to a block by a `content_block_delta` event.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Delta {
+    /// Text appended to a text block.
+    #[serde(rename = "text_delta")]
+    Text { text: String },
+    /// A fragment of the JSON input of a `tool_use` block. The input is only valid JSON once
+    /// all its fragments are concatenated, at the `content_block_stop` event.
+    #[serde(rename = "input_json_delta")]
+    InputJson { partial_json: String },
+    /// A delta of a type this crate does not model.
+    #[serde(other)]
+    Other,
+}
+
+impl Delta {
+    /// The text appended by a text delta.
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            Delta::Text { text } => Some(text),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContentBlockStop {
     pub index: i32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MessageDelta {
     pub delta: MessageDeltaData,
     pub usage: MessageDeltaUsage,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MessageDeltaData {
     pub stop_reason: String,
     pub stop_sequence: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MessageDeltaUsage {
     pub output_tokens: i32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MessageStop {
     #[serde(rename = "amazon-bedrock-invocationMetrics")]
     pub invocation_metrics: InvocationMetrics,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
+    #[serde(
+        rename = "amazon-bedrock-guardrailAction",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub guardrail_action: Option<GuardrailAction>,
+    #[serde(
+        rename = "amazon-bedrock-trace",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub trace: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InvocationMetrics {
     #[serde(rename = "firstByteLatency")]
     pub first_byte_latency: i32,
@@ -388,6 +589,20 @@ pub struct StreamInvocationMetricsContent {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_chat_options_keep_model_target() {
+        let target =
+            ModelTarget::parse("arn:aws:bedrock:us-west-2:123456789012:provisioned-model/abc123")
+                .unwrap()
+                .with_base_model(ModelName::AnthropicClaude2x);
+        let options = ChatOptions::default().with_model_target(&target);
+
+        let json = serde_json::to_string(&options).unwrap();
+        let parsed: ChatOptions = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed.model_target().unwrap(), target);
+    }
+
     #[test]
     fn test_deserialize_stream_documents() {
         let documents = [
@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::stream::{Stream, StreamExt};
+use pin_project::pin_project;
+
+use crate::bedrock::guardrail::GuardrailAction;
+use crate::bedrock::models::claude::claude_request_message::{
+    ContentBlock, ConversationResponse, Delta, InvocationMetrics, MessageStartData, StopReason,
+    StreamResultData, UsageInfo,
+};
+use crate::bedrock::models::claude::error::ClaudeError;
+
+/// Folds the events of a Claude response stream into a `ConversationResponse`.
+///
+/// Events are pushed one at a time with `push`, which hands back the text delta carried by
+/// the event, if any. The JSON fragments of a `tool_use` block are buffered, and parsed into
+/// its `input` when the block stops. Once the stream is exhausted, `finish` returns the same
+/// `ConversationResponse` that `ClaudeClient::chat` would have returned, with the
+/// `InvocationMetrics` of the `message_stop` event attached.
+#[derive(Debug, Default)]
+pub struct ConversationAccumulator {
+    message: Option<MessageStartData>,
+    content: Vec<ContentBlock>,
+    /// The JSON fragments received for the `tool_use` blocks, by block index.
+    tool_inputs: HashMap<usize, String>,
+    /// The first tool input that failed to parse.
+    tool_input_error: Option<String>,
+    stop_reason: Option<String>,
+    stop_sequence: Option<String>,
+    output_tokens: Option<i32>,
+    invocation_metrics: Option<InvocationMetrics>,
+    guardrail_action: Option<GuardrailAction>,
+    trace: Option<serde_json::Value>,
+}
+
+impl ConversationAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an event and returns the text delta it carries, if any.
+    pub fn push(&mut self, event: StreamResultData) -> Option<String> {
+        match event {
+            StreamResultData::MessageStart(message_start) => {
+                self.content = message_start.message.content.clone();
+                self.message = Some(message_start.message);
+                None
+            }
+            StreamResultData::ContentBlockStart(content_block_start) => {
+                let index = content_block_start.index.max(0) as usize;
+                if self.content.len() <= index {
+                    self.content.resize_with(index + 1, || ContentBlock::Text {
+                        text: String::new(),
+                    });
+                }
+                self.content[index] = content_block_start.content_block;
+                None
+            }
+            StreamResultData::ContentBlockDelta(content_block_delta) => {
+                let index = content_block_delta.index.max(0) as usize;
+                match content_block_delta.delta {
+                    Delta::Text { text } => {
+                        match self.content.get_mut(index) {
+                            Some(ContentBlock::Text { text: block_text }) => {
+                                block_text.push_str(&text)
+                            }
+                            Some(_) => {}
+                            None => {
+                                self.content.resize_with(index, || ContentBlock::Text {
+                                    text: String::new(),
+                                });
+                                self.content.push(ContentBlock::Text { text: text.clone() });
+                            }
+                        }
+                        Some(text)
+                    }
+                    Delta::InputJson { partial_json } => {
+                        self.tool_inputs
+                            .entry(index)
+                            .or_default()
+                            .push_str(&partial_json);
+                        None
+                    }
+                    Delta::Other => None,
+                }
+            }
+            StreamResultData::ContentBlockStop(content_block_stop) => {
+                let index = content_block_stop.index.max(0) as usize;
+                self.complete_tool_input(index);
+                None
+            }
+            StreamResultData::MessageDelta(message_delta) => {
+                self.stop_reason = Some(message_delta.delta.stop_reason);
+                self.stop_sequence = message_delta.delta.stop_sequence;
+                self.output_tokens = Some(message_delta.usage.output_tokens);
+                None
+            }
+            StreamResultData::MessageStop(message_stop) => {
+                self.invocation_metrics = Some(message_stop.invocation_metrics);
+                self.guardrail_action = message_stop.guardrail_action;
+                self.trace = message_stop.trace;
+                None
+            }
+        }
+    }
+
+    /// Parses the buffered JSON fragments of a `tool_use` block into its input.
+    fn complete_tool_input(&mut self, index: usize) {
+        let Some(partial_json) = self.tool_inputs.remove(&index) else {
+            return;
+        };
+        if partial_json.trim().is_empty() {
+            return;
+        }
+        if let Some(ContentBlock::ToolUse { input, .. }) = self.content.get_mut(index) {
+            match serde_json::from_str(&partial_json) {
+                Ok(value) => *input = value,
+                Err(err) => {
+                    self.tool_input_error
+                        .get_or_insert_with(|| format!("invalid input of tool_use block {}: {}", index, err));
+                }
+            }
+        }
+    }
+
+    /// The text received so far, all text blocks concatenated.
+    pub fn text(&self) -> String {
+        self.content
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The metrics reported by Bedrock in the `message_stop` event, once received.
+    pub fn invocation_metrics(&self) -> Option<&InvocationMetrics> {
+        self.invocation_metrics.as_ref()
+    }
+
+    /// Builds the final response. Fails if the stream ended before the `message_start`
+    /// or `message_delta` events were received, or if the input of a `tool_use` block is
+    /// not valid JSON.
+    pub fn finish(mut self) -> Result<ConversationResponse, ClaudeError> {
+        let unfinished: Vec<usize> = self.tool_inputs.keys().copied().collect();
+        for index in unfinished {
+            self.complete_tool_input(index);
+        }
+        if let Some(err) = self.tool_input_error {
+            return Err(ClaudeError::Deserialization(err));
+        }
+
+        let message = self.message.ok_or_else(|| {
+            ClaudeError::IncompleteStream("no message_start event received".to_string())
+        })?;
+
+        let stop_reason = match self.stop_reason {
+            _ if self.guardrail_action == Some(GuardrailAction::Intervened) => {
+                StopReason::GuardrailIntervened
+            }
+            Some(stop_reason) => parse_stop_reason(stop_reason)?,
+            None => message.stop_reason.ok_or_else(|| {
+                ClaudeError::IncompleteStream("no message_delta event received".to_string())
+            })?,
+        };
+
+        Ok(ConversationResponse {
+            id: message.id,
+            model: message.model,
+            response_type: message.message_type,
+            role: message.role,
+            content: self.content,
+            stop_reason,
+            stop_sequence: self.stop_sequence.or(message.stop_sequence),
+            usage: UsageInfo {
+                input_tokens: message.usage.input_tokens,
+                output_tokens: self.output_tokens.unwrap_or(message.usage.output_tokens),
+            },
+            invocation_metrics: self.invocation_metrics,
+            guardrail_action: self.guardrail_action,
+            trace: self.trace,
+        })
+    }
+}
+
+fn parse_stop_reason(stop_reason: String) -> Result<StopReason, ClaudeError> {
+    serde_json::from_value(serde_json::Value::String(stop_reason))
+        .map_err(|err| ClaudeError::Deserialization(err.to_string()))
+}
+
+/// A stream adaptor that yields the text deltas of a Claude response stream while
+/// accumulating the full `ConversationResponse`.
+///
+/// Use `collect_response` to drain the stream and get the response directly, or poll the
+/// stream for the text and call `into_response` once it is exhausted.
+#[pin_project]
+pub struct ConversationStream<S> {
+    #[pin]
+    inner: S,
+    accumulator: ConversationAccumulator,
+}
+
+impl<S> ConversationStream<S>
+where
+    S: Stream<Item = Result<StreamResultData, ClaudeError>>,
+{
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            accumulator: ConversationAccumulator::new(),
+        }
+    }
+
+    /// The state accumulated from the events received so far.
+    pub fn accumulator(&self) -> &ConversationAccumulator {
+        &self.accumulator
+    }
+
+    /// Builds the final response from the events received so far.
+    pub fn into_response(self) -> Result<ConversationResponse, ClaudeError> {
+        self.accumulator.finish()
+    }
+
+    /// Drains the remaining events and builds the final response.
+    pub async fn collect_response(self) -> Result<ConversationResponse, ClaudeError> {
+        let mut stream = Box::pin(self);
+        while let Some(result) = stream.next().await {
+            result?;
+        }
+        std::mem::take(stream.as_mut().project().accumulator).finish()
+    }
+}
+
+impl<S> Stream for ConversationStream<S>
+where
+    S: Stream<Item = Result<StreamResultData, ClaudeError>>,
+{
+    type Item = Result<String, ClaudeError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            match futures::ready!(this.inner.as_mut().poll_next(cx)) {
+                Some(Ok(event)) => {
+                    if let Some(text) = this.accumulator.push(event) {
+                        return Poll::Ready(Some(Ok(text)));
+                    }
+                }
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bedrock::models::claude::claude_client::deserialize_stream_result;
+    use crate::bedrock::models::claude::claude_request_message::Role;
+    use futures::TryStreamExt;
+
+    const DOCUMENTS: [&str; 7] = [
+        r#"{"message":{"content":[],"id":"msg_01SSVH6oAf3LoGzpz3YrdxgH","model":"claude-3-haiku-48k-20240307","role":"assistant","stop_reason":null,"stop_sequence":null,"type":"message","usage":{"input_tokens":20,"output_tokens":1}},"type":"message_start"}"#,
+        r#"{"content_block":{"text":"","type":"text"},"index":0,"type":"content_block_start"}"#,
+        r#"{"delta":{"text":"The capital","type":"text_delta"},"index":0,"type":"content_block_delta"}"#,
+        r#"{"delta":{"text":" is Paris.","type":"text_delta"},"index":0,"type":"content_block_delta"}"#,
+        r#"{"index":0,"type":"content_block_stop"}"#,
+        r#"{"delta":{"stop_reason":"end_turn","stop_sequence":null},"type":"message_delta","usage":{"output_tokens":10}}"#,
+        r#"{"amazon-bedrock-invocationMetrics":{"firstByteLatency":320,"inputTokenCount":20,"invocationLatency":394,"outputTokenCount":10},"type":"message_stop"}"#,
+    ];
+
+    fn events(documents: &[&str]) -> Vec<Result<StreamResultData, ClaudeError>> {
+        documents
+            .iter()
+            .map(|document| deserialize_stream_result(serde_json::from_str(document).unwrap()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_stream_yields_text_deltas() {
+        let stream = ConversationStream::new(futures::stream::iter(events(&DOCUMENTS)));
+
+        let deltas: Vec<String> = stream.try_collect().await.unwrap();
+
+        assert_eq!(deltas, vec!["The capital", " is Paris."]);
+    }
+
+    #[tokio::test]
+    async fn test_collect_response() {
+        let stream = ConversationStream::new(futures::stream::iter(events(&DOCUMENTS)));
+
+        let response = stream.collect_response().await.unwrap();
+
+        assert_eq!(response.id, "msg_01SSVH6oAf3LoGzpz3YrdxgH");
+        assert_eq!(response.role, Role::Assistant);
+        assert_eq!(response.stop_reason, StopReason::EndTurn);
+        assert_eq!(response.usage.input_tokens, 20);
+        assert_eq!(response.usage.output_tokens, 10);
+        assert!(matches!(
+            response.content.as_slice(),
+            [ContentBlock::Text { text }] if text == "The capital is Paris."
+        ));
+        assert_eq!(response.invocation_metrics.unwrap().first_byte_latency, 320);
+    }
+
+    #[tokio::test]
+    async fn test_collect_guardrail_intervention() {
+        let mut documents = DOCUMENTS;
+        documents[6] = r#"{"amazon-bedrock-guardrailAction":"INTERVENED","amazon-bedrock-trace":{"guardrail":{"input":{}}},"amazon-bedrock-invocationMetrics":{"firstByteLatency":120,"inputTokenCount":20,"invocationLatency":130,"outputTokenCount":10},"type":"message_stop"}"#;
+        let stream = ConversationStream::new(futures::stream::iter(events(&documents)));
+
+        let response = stream.collect_response().await.unwrap();
+
+        assert_eq!(response.stop_reason, StopReason::GuardrailIntervened);
+        let outcome = response.guardrail_outcome().unwrap();
+        assert!(outcome.intervened());
+        assert_eq!(outcome.trace, Some(serde_json::json!({"input": {}})));
+    }
+
+    #[test]
+    fn test_finish_without_message_delta() {
+        let mut accumulator = ConversationAccumulator::new();
+        for event in events(&DOCUMENTS[..3]) {
+            accumulator.push(event.unwrap());
+        }
+
+        assert_eq!(accumulator.text(), "The capital");
+        assert!(matches!(
+            accumulator.finish(),
+            Err(ClaudeError::IncompleteStream(_))
+        ));
+    }
+}
@@ -2,6 +2,7 @@ use serde_json::Error as SerdeJsonError;
 use thiserror::Error;
 
 use crate::bedrock::error::BedrockError;
+use crate::usage::error::UsageError;
 
 
 #[derive(Error, Debug)]
@@ -26,4 +27,10 @@ pub enum ClaudeError {
 
     #[error("Deserialization error: {0}")]
     Deserialization(String),
+
+    #[error("Incomplete stream: {0}")]
+    IncompleteStream(String),
+
+    #[error("Usage error: {0}")]
+    Usage(#[from] UsageError),
 }
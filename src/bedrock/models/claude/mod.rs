@@ -1,14 +1,17 @@
 pub mod claude_request_message;
 pub mod claude_client;
+pub mod claude_stream;
 pub mod error;
 
 pub use claude_client::ClaudeClient;
+pub use claude_stream::{ConversationAccumulator, ConversationStream};
 pub use error::ClaudeError;
 pub use claude_request_message::ChatOptions;
 pub use claude_request_message::Message;
 pub use claude_request_message::ConversationRequest;
 pub use claude_request_message::ConversationResponse;
 pub use claude_request_message::StreamResult;
+pub use claude_request_message::{ContentBlock, StopReason, Tool, ToolChoice};
 
 
 
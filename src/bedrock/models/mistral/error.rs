@@ -2,6 +2,8 @@ use crate::bedrock::error::BedrockError;
 use serde_json::Error as SerdeJsonError;
 use thiserror::Error;
 
+use crate::usage::error::UsageError;
+
 #[derive(Error, Debug)]
 pub enum MistralError {
     #[error("HTTP error: {0}")]
@@ -24,4 +26,7 @@ pub enum MistralError {
 
     #[error("Bedrock error: {0}")]
     Bedrock(#[from] BedrockError),
-}
\ No newline at end of file
+
+    #[error("Usage error: {0}")]
+    Usage(#[from] UsageError),
+}
@@ -1,21 +1,76 @@
 use crate::bedrock::bedrock_client::{BedrockClient, BedrockClientOptions};
+use crate::bedrock::model_info::ModelInfo;
 use crate::bedrock::models::mistral::error::MistralError;
 use crate::bedrock::models::mistral::mistral_request_message::{MistralRequest, MistralResponse};
+use crate::cache::response_cache::{is_deterministic, CachingStream, ResponseCache};
+use crate::cache::CacheKey;
+use crate::telemetry::{GenAiSpan, OPERATION_TEXT_COMPLETION, PROVIDER_BEDROCK};
+use crate::tokenizer::{HeuristicTokenizer, Tokenizer};
+use crate::usage::usage_ledger::UsageReporter;
+use futures::future::Either;
 use futures::stream::Stream;
-use futures::TryStreamExt;
+use futures::StreamExt;
+use serde_json::Value;
 
 pub type MistralOptions = BedrockClientOptions;
 
 pub struct MistralClient {
     client: BedrockClient,
+    usage: Option<UsageReporter>,
+    cache: Option<ResponseCache>,
 }
 
+const CACHE_GENERATE: &str = "mistral.generate";
+const CACHE_GENERATE_STREAM: &str = "mistral.generate_stream";
+
 impl MistralClient {
-    /// Constructs a new `MistralClient`.
-    pub async fn new(options: MistralOptions) -> Self {
-        Self {
-            client: BedrockClient::new(options).await,
+    /// Constructs a new `MistralClient`, or fails if the options are invalid.
+    pub async fn new(options: MistralOptions) -> Result<Self, MistralError> {
+        Ok(Self {
+            client: BedrockClient::new(options).await?,
+            usage: None,
+            cache: None,
+        })
+    }
+
+    /// Reports the token usage of every call into a usage ledger, and checks its budgets
+    /// before sending a call.
+    pub fn with_usage(mut self, usage: UsageReporter) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    /// Serves the requests with a temperature of zero from a response cache, and stores their
+    /// responses. A cached stream is replayed chunk by chunk.
+    pub fn with_cache(mut self, cache: ResponseCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// The cache and key of a request, unless it bypasses the cache.
+    fn cache_key(
+        &self,
+        operation: &str,
+        model_id: &str,
+        request: &MistralRequest,
+    ) -> Option<(ResponseCache, CacheKey)> {
+        let cache = self.cache.as_ref()?;
+        let deterministic = is_deterministic(request.temperature.map(f64::from));
+        let key = cache.key_for(operation, model_id, request, deterministic)?;
+        Some((cache.clone(), key))
+    }
+
+    fn check_budget(&self, model_id: &str, request: &MistralRequest) -> Result<(), MistralError> {
+        if let Some(usage) = &self.usage {
+            let input_tokens = HeuristicTokenizer::new().count_tokens(&request.prompt) as u64;
+            // Without `max_tokens`, the model may write up to its own limit.
+            let max_output_tokens = request
+                .max_tokens
+                .or_else(|| ModelInfo::from_id(model_id).and_then(|model| model.max_output_tokens))
+                .unwrap_or(0) as u64;
+            usage.check(model_id, input_tokens, max_output_tokens)?;
         }
+        Ok(())
     }
 
     /// Generates a response from the Mistral model.
@@ -24,11 +79,59 @@ impl MistralClient {
         model_id: String,
         request: &MistralRequest,
     ) -> Result<MistralResponse, MistralError> {
+        let cache_key = self.cache_key(CACHE_GENERATE, &model_id, request);
+        if let Some(response) = cache_key.as_ref().and_then(|(cache, key)| cache.get(key)) {
+            return Ok(response);
+        }
+
+        let span = completion_span(&model_id, request);
+        let result = span
+            .instrument(self.generate_in_span(model_id, request))
+            .await;
+        match &result {
+            Ok(response) => {
+                let stop_reason = response.outputs.first().and_then(|o| o.stop_reason.as_deref());
+                if let Some(stop_reason) = stop_reason {
+                    span.finish_reason(stop_reason);
+                }
+                span.output(|| serde_json::to_string(&response.outputs).unwrap_or_default());
+                if let Some((cache, key)) = &cache_key {
+                    cache.put(key, response);
+                }
+            }
+            Err(err) => span.error(err),
+        }
+        result
+    }
+
+    async fn generate_in_span(
+        &self,
+        model_id: String,
+        request: &MistralRequest,
+    ) -> Result<MistralResponse, MistralError> {
+        self.check_budget(&model_id, request)?;
+
         let payload = serde_json::to_value(request).map_err(MistralError::Json)?;
 
-        let response = self.client.generate_raw(model_id, payload).await?;
+        let response = self.client.generate_raw(model_id.clone(), payload).await?;
+
+        let mistral_response: MistralResponse =
+            serde_json::from_value(response).map_err(MistralError::Json)?;
 
-        let mistral_response = serde_json::from_value(response).map_err(MistralError::Json)?;
+        // The response body carries no token counts: estimate them from the text.
+        if let Some(usage) = &self.usage {
+            let tokenizer = HeuristicTokenizer::new();
+            let output_tokens: usize = mistral_response
+                .outputs
+                .iter()
+                .map(|output| tokenizer.count_tokens(&output.text))
+                .sum();
+            usage.record(
+                &model_id,
+                tokenizer.count_tokens(&request.prompt) as u64,
+                output_tokens as u64,
+            );
+        }
         Ok(mistral_response)
     }
 
@@ -38,29 +141,112 @@ impl MistralClient {
         model_id: String,
         request: &MistralRequest,
     ) -> Result<impl Stream<Item = Result<MistralResponse, MistralError>>, MistralError> {
-        let payload = serde_json::to_value(request).map_err(MistralError::Json)?;
+        let cache_key = self.cache_key(CACHE_GENERATE_STREAM, &model_id, request);
+        if let Some(replay) = cache_key.as_ref().and_then(|(cache, key)| cache.replay(key)) {
+            return Ok(Either::Left(replay));
+        }
 
-        let response = self.client.generate_raw_stream(model_id, payload).await?;
+        let mut span = completion_span(&model_id, request);
+        if let Err(err) = self.check_budget(&model_id, request) {
+            span.error(&err);
+            return Err(err);
+        }
+
+        let payload = serde_json::to_value(request).map_err(MistralError::Json)?;
 
+        let response = span
+            .instrument(self.client.generate_raw_stream(model_id.clone(), payload))
+            .await;
+        let response = match response {
+            Ok(response) => response,
+            Err(err) => {
+                span.error(&err);
+                return Err(err.into());
+            }
+        };
 
-        Ok(response
-            .map_ok(|value| serde_json::from_value(value).map_err(MistralError::Json))
-            .map_err(|err| MistralError::Bedrock(err))
-            .and_then(futures::future::ready))
+        let usage = self.usage.clone();
+        // The completion is only accumulated when it is recorded in the span.
+        let mut completion = crate::telemetry::capture_content().then(String::new);
+        let stream = response.map(move |chunk| {
+            let value = match chunk {
+                Ok(value) => value,
+                Err(err) => {
+                    span.error(&err);
+                    return Err(MistralError::Bedrock(err));
+                }
+            };
+            span.first_token();
+            if let Some(metrics) = value.get("amazon-bedrock-invocationMetrics") {
+                let count = |key: &str| metrics.get(key).and_then(Value::as_u64).unwrap_or(0);
+                let (input_tokens, output_tokens) =
+                    (count("inputTokenCount"), count("outputTokenCount"));
+                if let Some(usage) = &usage {
+                    usage.record(&model_id, input_tokens, output_tokens);
+                }
+                span.usage(input_tokens, output_tokens);
+            }
+            let response: MistralResponse = serde_json::from_value(value).map_err(|err| {
+                let err = MistralError::Json(err);
+                span.error(&err);
+                err
+            })?;
+            for output in &response.outputs {
+                if let Some(completion) = completion.as_mut() {
+                    completion.push_str(&output.text);
+                }
+                if let Some(stop_reason) = &output.stop_reason {
+                    span.finish_reason(stop_reason);
+                    if let Some(completion) = completion.take() {
+                        span.output(|| completion);
+                    }
+                }
+            }
+            Ok(response)
+        });
+        Ok(Either::Right(CachingStream::new(stream, cache_key)))
     }
 }
 
+/// Opens the span of a completion call, with the request parameters and, if content capture
+/// is enabled, the prompt.
+fn completion_span(model_id: &str, request: &MistralRequest) -> GenAiSpan {
+    let span = GenAiSpan::new(OPERATION_TEXT_COMPLETION, PROVIDER_BEDROCK, model_id);
+    span.request(
+        request.max_tokens.map(u64::from),
+        request.temperature.map(f64::from),
+        request.top_p.map(f64::from),
+    );
+    span.input(|| request.prompt.clone());
+    span
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::bedrock::{models::mistral::mistral_request_message::MistralRequestBuilder, ModelInfo};
+    use crate::testing::{Cassette, FixtureHttpClient, FixtureMode};
     use futures::stream::StreamExt;
 
+    /// A client replaying the synthetic `tests/fixtures/<name>.json`, or recording it from
+    /// Bedrock with `HIRAMU_FIXTURES=record` and the `bedrock` profile.
+    async fn fixture_client(name: &str) -> MistralClient {
+        let path = format!("{}/tests/fixtures/{}.json", env!("CARGO_MANIFEST_DIR"), name);
+        let cassette = Cassette::open(path, FixtureMode::from_env()).unwrap();
+        let options = match cassette.is_recording() {
+            true => MistralOptions::new().profile_name("bedrock"),
+            false => MistralOptions::new().credentials("AKIDFIXTURE", "fixture-secret", None),
+        };
+        let options = options
+            .region("us-west-2")
+            .max_attempts(1)
+            .http_client(FixtureHttpClient::new(cassette));
+        MistralClient::new(options).await.unwrap()
+    }
+
     #[tokio::test]
     async fn test_generate() {
-        let options = MistralOptions::new().profile_name("bedrock").region("us-west-2");
-        let client = MistralClient::new(options).await;
+        let client = fixture_client("mistral_generate").await;
 
         let request = MistralRequestBuilder::new("<s>[INST] What is the capital of France ?[/INST]".to_string())
             .max_tokens(200)
@@ -76,28 +262,20 @@ mod tests {
             Err(err) => panic!("Error: {:?}", err),
         };
 
-        println!("Response: {:?}", response.outputs[0].text.to_string());
-
-        assert!(!response.outputs.is_empty());
+        assert!(response.outputs[0].text.contains("Paris"));
+        assert_eq!(response.outputs[0].stop_reason.as_deref(), Some("stop"));
     }
 
     #[tokio::test]
     async fn test_generate_with_stream() {
-        let options = MistralOptions::new().profile_name("bedrock").region("us-west-2");
-        let client = MistralClient::new(options).await;
+        let client = fixture_client("mistral_generate_with_stream").await;
 
         let request = MistralRequestBuilder::new("<s>[INST] What is the capital of France ?[/INST]".to_string())
             .max_tokens(200)
             .temperature(0.8)
             .build();
 
-       let model_name = ModelInfo::from_model_name(crate::bedrock::ModelName::MistralMixtral8X7BInstruct0x);
-
-        // display the request as a pretty-printed JSON string
-        let display_request = serde_json::to_string_pretty(&request).unwrap();
-        println!("Request: {}", display_request);
-
-
+        let model_name = ModelInfo::from_model_name(crate::bedrock::ModelName::MistralMixtral8X7BInstruct0x);
 
         let mut stream = client
             .generate_with_stream(model_name.to_owned(), &request)
@@ -105,11 +283,12 @@ mod tests {
             .unwrap();
 
         let mut response_text = String::new();
+        let mut last = None;
         while let Some(result) = stream.next().await {
             match result {
                 Ok(response) => {
-                    println!("Response: {:?}", response.outputs[0].text.to_string());
                     response_text.push_str(&response.outputs[0].text);
+                    last = Some(response);
                 }
                 Err(err) => {
                     panic!("Error: {:?}", err);
@@ -117,9 +296,7 @@ mod tests {
             }
         }
 
-        assert!(!response_text.is_empty());
-
+        assert!(response_text.contains("Paris"));
+        assert!(last.unwrap().invocation_metrics.is_some());
     }
-
-
-}
\ No newline at end of file
+}
@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::bedrock::models::claude::claude_request_message::InvocationMetrics;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MistralRequest {
     pub prompt: String,
@@ -20,6 +22,14 @@ pub struct MistralRequest {
 #[derive(Debug, Deserialize,Serialize)]
 pub struct MistralResponse {
     pub outputs: Vec<MistralOutput>,
+    /// The token counts and latencies of the invocation, only present on the last chunk of
+    /// a stream.
+    #[serde(
+        rename = "amazon-bedrock-invocationMetrics",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub invocation_metrics: Option<InvocationMetrics>,
 }
 
 #[derive(Debug, Deserialize,Serialize)]
@@ -28,6 +38,71 @@ pub struct MistralOutput {
     pub stop_reason: Option<String>,
 }
 
+/// A message of a conversation with a Mistral instruct model. The role is `system`, `user`
+/// or `assistant`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MistralMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl MistralMessage {
+    pub fn new<R: Into<String>, C: Into<String>>(role: R, content: C) -> Self {
+        Self {
+            role: role.into(),
+            content: content.into(),
+        }
+    }
+
+    pub fn system<C: Into<String>>(content: C) -> Self {
+        Self::new("system", content)
+    }
+
+    pub fn user<C: Into<String>>(content: C) -> Self {
+        Self::new("user", content)
+    }
+
+    pub fn assistant<C: Into<String>>(content: C) -> Self {
+        Self::new("assistant", content)
+    }
+}
+
+/// Renders a conversation in the instruction format of the Mistral models, e.g.
+/// `<s>[INST] What is 2+2? [/INST] 4</s>[INST] And 3+3? [/INST]`.
+///
+/// The models have no system prompt: the system messages are prepended to the first
+/// instruction. A message with an unknown role is read as a user message.
+pub fn instruct_prompt(messages: &[MistralMessage]) -> String {
+    let system: Vec<&str> = messages
+        .iter()
+        .filter(|message| message.role == "system")
+        .map(|message| message.content.as_str())
+        .collect();
+    let mut prompt = String::from("<s>");
+    let mut first_instruction = true;
+    for message in messages {
+        match message.role.as_str() {
+            "system" => {}
+            "assistant" => {
+                prompt.push(' ');
+                prompt.push_str(&message.content);
+                prompt.push_str("</s>");
+            }
+            _ => {
+                prompt.push_str("[INST] ");
+                if first_instruction && !system.is_empty() {
+                    prompt.push_str(&system.join("\n\n"));
+                    prompt.push_str("\n\n");
+                }
+                first_instruction = false;
+                prompt.push_str(&message.content);
+                prompt.push_str(" [/INST]");
+            }
+        }
+    }
+    prompt
+}
+
 pub struct MistralRequestBuilder {
     prompt: String,
     max_tokens: Option<u32>,
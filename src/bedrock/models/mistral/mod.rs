@@ -10,3 +10,4 @@ pub use mistral_request_message::MistralRequest;
 pub use mistral_request_message::MistralResponse;
 pub use mistral_request_message::MistralOptionsBuilder;
 pub use mistral_request_message::MistralRequestBuilder;
+pub use mistral_request_message::{instruct_prompt, MistralMessage};
@@ -0,0 +1,118 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::cache::error::CacheError;
+
+/// The key of a cached response: the hex SHA-256 of the canonical JSON of the operation,
+/// the model id and the full request.
+///
+/// The JSON object keys are sorted before hashing, so two equal requests have the same key
+/// whatever the order their fields were serialized in.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct CacheKey(String);
+
+impl CacheKey {
+    /// Computes the key of a request.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The client operation, e.g. `ollama.chat`, so that the responses of two
+    ///   operations taking the same request are not mixed up.
+    /// * `model` - The model id.
+    /// * `request` - The full request, including the sampling options.
+    ///
+    pub fn new<R: Serialize>(operation: &str, model: &str, request: &R) -> Result<Self, CacheError> {
+        let value = serde_json::json!({
+            "operation": operation,
+            "model": model,
+            "request": serde_json::to_value(request)?,
+        });
+        let canonical = serde_json::to_string(&canonicalize(value))?;
+        Ok(Self(format!("{:x}", Sha256::digest(canonical.as_bytes()))))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for CacheKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A cached response. A response that is not streamed is stored as a single chunk.
+///
+/// # Fields
+///
+/// * `created_at` - When the response was stored, to expire it after the cache TTL.
+/// * `chunks` - The JSON of the response, or of each chunk of a streamed response.
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub created_at: DateTime<Utc>,
+    pub chunks: Vec<Value>,
+}
+
+impl CacheEntry {
+    pub fn new(chunks: Vec<Value>) -> Self {
+        Self {
+            created_at: Utc::now(),
+            chunks,
+        }
+    }
+}
+
+/// The storage of a `ResponseCache`.
+pub trait CacheBackend: Send + Sync {
+    fn get(&self, key: &CacheKey) -> Result<Option<CacheEntry>, CacheError>;
+
+    fn put(&self, key: &CacheKey, entry: CacheEntry) -> Result<(), CacheError>;
+
+    fn remove(&self, key: &CacheKey) -> Result<(), CacheError>;
+
+    fn clear(&self) -> Result<(), CacheError>;
+}
+
+/// Sorts the keys of the JSON objects, recursively.
+fn canonicalize(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(String, Value)> = map.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, canonicalize(value)))
+                    .collect(),
+            )
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(canonicalize).collect()),
+        value => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_key_is_canonical() {
+        let a = serde_json::json!({"prompt": "hi", "options": {"temperature": 0.0, "seed": 1}});
+        let b = serde_json::json!({"options": {"seed": 1, "temperature": 0.0}, "prompt": "hi"});
+        let key_a = CacheKey::new("ollama.generate", "llama3", &a).unwrap();
+        let key_b = CacheKey::new("ollama.generate", "llama3", &b).unwrap();
+        assert_eq!(key_a, key_b);
+        assert_eq!(key_a.as_str().len(), 64);
+
+        let other_model = CacheKey::new("ollama.generate", "mistral", &a).unwrap();
+        let other_operation = CacheKey::new("ollama.chat", "llama3", &a).unwrap();
+        assert_ne!(key_a, other_model);
+        assert_ne!(key_a, other_operation);
+    }
+}
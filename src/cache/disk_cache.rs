@@ -0,0 +1,88 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::cache::cache_backend::{CacheBackend, CacheEntry, CacheKey};
+use crate::cache::error::CacheError;
+
+/// An on-disk backend that stores each entry as a JSON file named after its key, so the
+/// cache survives restarts and can be shared between processes.
+pub struct DiskCache {
+    directory: PathBuf,
+}
+
+impl DiskCache {
+    /// Opens a cache in `directory`, creating it if needed.
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Result<Self, CacheError> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory)?;
+        Ok(Self { directory })
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    fn path(&self, key: &CacheKey) -> PathBuf {
+        self.directory.join(format!("{}.json", key))
+    }
+}
+
+impl CacheBackend for DiskCache {
+    fn get(&self, key: &CacheKey) -> Result<Option<CacheEntry>, CacheError> {
+        let file = match File::open(self.path(key)) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Some(serde_json::from_reader(BufReader::new(file))?))
+    }
+
+    fn put(&self, key: &CacheKey, entry: CacheEntry) -> Result<(), CacheError> {
+        // Written to a temporary file first, so a reader never sees a partial entry.
+        let path = self.path(key);
+        let temporary = path.with_extension(format!("{}.tmp", std::process::id()));
+        serde_json::to_writer(BufWriter::new(File::create(&temporary)?), &entry)?;
+        fs::rename(temporary, path)?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &CacheKey) -> Result<(), CacheError> {
+        match fs::remove_file(self.path(key)) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn clear(&self) -> Result<(), CacheError> {
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "json") {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disk_cache_round_trip() {
+        let directory = std::env::temp_dir().join(format!("hiramu-disk-cache-{}", std::process::id()));
+        let cache = DiskCache::new(&directory).unwrap();
+        let key = CacheKey::new("ollama.embeddings", "nomic-embed-text", &"llamas").unwrap();
+
+        assert!(cache.get(&key).unwrap().is_none());
+
+        let entry = CacheEntry::new(vec![serde_json::json!({"embedding": [0.5, 0.25]})]);
+        cache.put(&key, entry.clone()).unwrap();
+        assert_eq!(cache.get(&key).unwrap(), Some(entry));
+
+        cache.clear().unwrap();
+        assert!(cache.get(&key).unwrap().is_none());
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,11 @@
+use serde_json::Error as SerdeJsonError;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CacheError {
+    #[error("JSON error: {0}")]
+    Json(#[from] SerdeJsonError),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
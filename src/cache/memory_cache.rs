@@ -0,0 +1,149 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use crate::cache::cache_backend::{CacheBackend, CacheEntry, CacheKey};
+use crate::cache::error::CacheError;
+
+#[derive(Default)]
+struct LruState {
+    /// The entries, with the generation of their last use.
+    entries: HashMap<CacheKey, (CacheEntry, u64)>,
+    /// The keys by the generation of their last use, from the least to the most recent.
+    order: BTreeMap<u64, CacheKey>,
+    generation: u64,
+}
+
+impl LruState {
+    /// Marks `key` as the most recently used, in O(log n).
+    fn touch(&mut self, key: &CacheKey) {
+        self.generation += 1;
+        let generation = self.generation;
+        if let Some((_, used)) = self.entries.get_mut(key) {
+            self.order.remove(used);
+            *used = generation;
+            self.order.insert(generation, key.clone());
+        }
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some((_, used)) = self.entries.remove(key) {
+            self.order.remove(&used);
+        }
+    }
+}
+
+/// An in-memory backend that evicts the least recently used entry once `capacity` entries
+/// are stored.
+pub struct MemoryCache {
+    capacity: usize,
+    state: Mutex<LruState>,
+}
+
+impl MemoryCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            state: Mutex::new(LruState::default()),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl CacheBackend for MemoryCache {
+    fn get(&self, key: &CacheKey) -> Result<Option<CacheEntry>, CacheError> {
+        let mut state = self.state.lock().unwrap();
+        let entry = state.entries.get(key).map(|(entry, _)| entry.clone());
+        if entry.is_some() {
+            state.touch(key);
+        }
+        Ok(entry)
+    }
+
+    fn put(&self, key: &CacheKey, entry: CacheEntry) -> Result<(), CacheError> {
+        let mut state = self.state.lock().unwrap();
+        match state.entries.get_mut(key) {
+            Some((stored, _)) => *stored = entry,
+            None => {
+                state.entries.insert(key.clone(), (entry, 0));
+            }
+        }
+        state.touch(key);
+        while state.entries.len() > self.capacity {
+            match state.order.pop_first() {
+                Some((_, oldest)) => {
+                    state.entries.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    fn remove(&self, key: &CacheKey) -> Result<(), CacheError> {
+        let mut state = self.state.lock().unwrap();
+        state.remove(key);
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<(), CacheError> {
+        let mut state = self.state.lock().unwrap();
+        state.entries.clear();
+        state.order.clear();
+        state.generation = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str) -> CacheKey {
+        CacheKey::new("test", "model", &name).unwrap()
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let cache = MemoryCache::new(2);
+        cache.put(&key("a"), CacheEntry::new(vec![1.into()])).unwrap();
+        cache.put(&key("b"), CacheEntry::new(vec![2.into()])).unwrap();
+
+        // "a" becomes the most recently used, so "b" is evicted.
+        assert!(cache.get(&key("a")).unwrap().is_some());
+        cache.put(&key("c"), CacheEntry::new(vec![3.into()])).unwrap();
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(&key("a")).unwrap().is_some());
+        assert!(cache.get(&key("b")).unwrap().is_none());
+        assert!(cache.get(&key("c")).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_put_and_remove_keep_the_order_consistent() {
+        let cache = MemoryCache::new(2);
+        cache.put(&key("a"), CacheEntry::new(vec![1.into()])).unwrap();
+        cache.put(&key("b"), CacheEntry::new(vec![2.into()])).unwrap();
+
+        // Replacing "a" makes it the most recently used, and removing "b" frees its slot.
+        cache.put(&key("a"), CacheEntry::new(vec![10.into()])).unwrap();
+        cache.remove(&key("b")).unwrap();
+        cache.put(&key("c"), CacheEntry::new(vec![3.into()])).unwrap();
+        cache.put(&key("d"), CacheEntry::new(vec![4.into()])).unwrap();
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(&key("a")).unwrap().is_none());
+        assert!(cache.get(&key("c")).unwrap().is_some());
+        assert!(cache.get(&key("d")).unwrap().is_some());
+    }
+}
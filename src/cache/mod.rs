@@ -0,0 +1,11 @@
+pub mod cache_backend;
+pub mod disk_cache;
+pub mod error;
+pub mod memory_cache;
+pub mod response_cache;
+
+pub use cache_backend::{CacheBackend, CacheEntry, CacheKey};
+pub use disk_cache::DiskCache;
+pub use error::CacheError;
+pub use memory_cache::MemoryCache;
+pub use response_cache::{is_deterministic, CachingStream, ResponseCache};
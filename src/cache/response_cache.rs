@@ -0,0 +1,263 @@
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use chrono::Utc;
+use futures::stream::Stream;
+use pin_project::pin_project;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::cache::cache_backend::{CacheBackend, CacheEntry, CacheKey};
+use crate::cache::disk_cache::DiskCache;
+use crate::cache::error::CacheError;
+use crate::cache::memory_cache::MemoryCache;
+
+/// A stream replaying the chunks of a cached streamed response.
+pub type Replay<T, E> = futures::stream::Iter<std::vec::IntoIter<Result<T, E>>>;
+
+/// Whether a request with this sampling temperature always gets the same response. A request
+/// without a temperature uses the default of the provider, which is above zero.
+pub fn is_deterministic(temperature: Option<f64>) -> bool {
+    matches!(temperature, Some(temperature) if temperature <= 0.0)
+}
+
+/// A cache of model responses, set on a client with `with_cache`.
+///
+/// Only deterministic requests are cached, i.e. embeddings and requests with a temperature of
+/// zero, unless `force` is set. The cache is best effort: a backend error or an entry that no
+/// longer deserializes is a miss, and a failed write is ignored, so the cache never fails a call.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use hiramu::cache::ResponseCache;
+/// use hiramu::ollama::OllamaClient;
+///
+/// let cache = ResponseCache::disk(".cache/hiramu")
+///     .unwrap()
+///     .ttl(Duration::from_secs(7 * 24 * 3600));
+/// let client = OllamaClient::new("http://localhost:11434".to_string()).with_cache(cache);
+/// ```
+#[derive(Clone)]
+pub struct ResponseCache {
+    backend: Arc<dyn CacheBackend>,
+    ttl: Option<Duration>,
+    force: bool,
+}
+
+impl ResponseCache {
+    pub fn new(backend: impl CacheBackend + 'static) -> Self {
+        Self::shared(Arc::new(backend))
+    }
+
+    /// A cache over a backend shared with other caches.
+    pub fn shared(backend: Arc<dyn CacheBackend>) -> Self {
+        Self {
+            backend,
+            ttl: None,
+            force: false,
+        }
+    }
+
+    /// An in-memory LRU cache of `capacity` responses.
+    pub fn memory(capacity: usize) -> Self {
+        Self::new(MemoryCache::new(capacity))
+    }
+
+    /// An on-disk cache in `directory`.
+    pub fn disk<P: Into<PathBuf>>(directory: P) -> Result<Self, CacheError> {
+        Ok(Self::new(DiskCache::new(directory)?))
+    }
+
+    /// Expires the responses older than `ttl`. By default they never expire.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Also caches the requests with a temperature above zero.
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    pub fn backend(&self) -> &Arc<dyn CacheBackend> {
+        &self.backend
+    }
+
+    /// The key of a request, or `None` if the request bypasses the cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The client operation, e.g. `ollama.chat`.
+    /// * `model` - The model id.
+    /// * `request` - The full request.
+    /// * `deterministic` - Whether the request always gets the same response, see `is_deterministic`.
+    ///
+    pub fn key_for<R: Serialize>(
+        &self,
+        operation: &str,
+        model: &str,
+        request: &R,
+        deterministic: bool,
+    ) -> Option<CacheKey> {
+        if !(deterministic || self.force) {
+            return None;
+        }
+        CacheKey::new(operation, model, request).ok()
+    }
+
+    /// The chunks of an entry, or `None` if it is missing or expired.
+    pub fn lookup(&self, key: &CacheKey) -> Option<Vec<Value>> {
+        let entry = self.backend.get(key).ok()??;
+        if let Some(ttl) = self.ttl {
+            let age = Utc::now().signed_duration_since(entry.created_at);
+            if age.to_std().is_ok_and(|age| age > ttl) {
+                let _ = self.backend.remove(key);
+                return None;
+            }
+        }
+        Some(entry.chunks)
+    }
+
+    /// A cached response that was not streamed.
+    pub fn get<T: DeserializeOwned>(&self, key: &CacheKey) -> Option<T> {
+        let chunk = self.lookup(key)?.into_iter().next()?;
+        serde_json::from_value(chunk).ok()
+    }
+
+    /// Caches a response that was not streamed.
+    pub fn put<T: Serialize>(&self, key: &CacheKey, response: &T) {
+        if let Ok(chunk) = serde_json::to_value(response) {
+            self.put_chunks(key, vec![chunk]);
+        }
+    }
+
+    /// A stream replaying the chunks of a cached streamed response.
+    pub fn replay<T: DeserializeOwned, E>(&self, key: &CacheKey) -> Option<Replay<T, E>> {
+        let chunks = self
+            .lookup(key)?
+            .into_iter()
+            .map(|chunk| serde_json::from_value(chunk).map(Ok))
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+        Some(futures::stream::iter(chunks))
+    }
+
+    fn put_chunks(&self, key: &CacheKey, chunks: Vec<Value>) {
+        let _ = self.backend.put(key, CacheEntry::new(chunks));
+    }
+}
+
+/// A stream wrapper that stores the chunks of a streamed response in a cache once the stream
+/// completes without error. Without a cache and key, it passes the chunks through.
+#[pin_project]
+pub struct CachingStream<S> {
+    #[pin]
+    inner: S,
+    target: Option<(ResponseCache, CacheKey)>,
+    chunks: Vec<Value>,
+}
+
+impl<S> CachingStream<S> {
+    pub fn new(inner: S, target: Option<(ResponseCache, CacheKey)>) -> Self {
+        Self {
+            inner,
+            target,
+            chunks: Vec::new(),
+        }
+    }
+}
+
+impl<S, T, E> Stream for CachingStream<S>
+where
+    S: Stream<Item = Result<T, E>>,
+    T: Serialize,
+{
+    type Item = Result<T, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let item = futures::ready!(this.inner.poll_next(cx));
+        if this.target.is_some() {
+            match &item {
+                Some(Ok(chunk)) => match serde_json::to_value(chunk) {
+                    Ok(chunk) => this.chunks.push(chunk),
+                    Err(_) => *this.target = None,
+                },
+                Some(Err(_)) => *this.target = None,
+                None => {
+                    if let Some((cache, key)) = this.target.take() {
+                        cache.put_chunks(&key, std::mem::take(this.chunks));
+                    }
+                }
+            }
+        }
+        Poll::Ready(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream::{self, StreamExt};
+
+    #[test]
+    fn test_bypass_non_deterministic_requests() {
+        let cache = ResponseCache::memory(8);
+        assert!(cache.key_for("ollama.chat", "llama3", &"hi", is_deterministic(Some(0.0))).is_some());
+        assert!(cache.key_for("ollama.chat", "llama3", &"hi", is_deterministic(Some(0.7))).is_none());
+        assert!(cache.key_for("ollama.chat", "llama3", &"hi", is_deterministic(None)).is_none());
+
+        let cache = cache.force(true);
+        assert!(cache.key_for("ollama.chat", "llama3", &"hi", false).is_some());
+    }
+
+    #[test]
+    fn test_ttl_expires_entries() {
+        let cache = ResponseCache::memory(8).ttl(Duration::from_secs(60));
+        let key = CacheKey::new("test", "model", &"request").unwrap();
+
+        let mut entry = CacheEntry::new(vec![serde_json::json!("stale")]);
+        entry.created_at = Utc::now() - chrono::TimeDelta::try_minutes(2).unwrap();
+        cache.backend().put(&key, entry).unwrap();
+        assert_eq!(cache.get::<String>(&key), None);
+
+        cache.put(&key, &"fresh");
+        assert_eq!(cache.get::<String>(&key), Some("fresh".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay_stream() {
+        let cache = ResponseCache::memory(8);
+        let key = CacheKey::new("test", "model", &"request").unwrap();
+
+        let live = stream::iter(vec![Ok::<_, String>("Hello".to_string()), Ok(" world".to_string())]);
+        let recorded: Vec<_> = CachingStream::new(live, Some((cache.clone(), key.clone())))
+            .collect()
+            .await;
+        assert_eq!(recorded.len(), 2);
+
+        let replayed: Vec<Result<String, String>> =
+            cache.replay(&key).unwrap().collect().await;
+        assert_eq!(replayed, vec![Ok("Hello".to_string()), Ok(" world".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_stream_is_not_cached() {
+        let cache = ResponseCache::memory(8);
+        let key = CacheKey::new("test", "model", &"request").unwrap();
+
+        let live = stream::iter(vec![Ok("Hello".to_string()), Err("disconnected".to_string())]);
+        let _: Vec<_> = CachingStream::new(live, Some((cache.clone(), key.clone())))
+            .collect()
+            .await;
+
+        assert!(cache.replay::<String, String>(&key).is_none());
+    }
+}
@@ -25,7 +25,7 @@ pub async fn demo_generate_raw() {
         .region(region);
     
 
-    let client = BedrockClient::new(options).await;
+    let client = BedrockClient::new(options).await.unwrap();
 
     let result = client
         .generate_raw(
@@ -24,7 +24,7 @@ pub async fn demo_bedrock_mistral_raw_stream(model_id: &str, prompt: &str) {
         .region(region);
     
 
-    let client = BedrockClient::new(options).await;
+    let client = BedrockClient::new(options).await.unwrap();
 
     let stream = client
         .generate_raw_stream(
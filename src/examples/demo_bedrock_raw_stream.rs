@@ -29,7 +29,7 @@ pub async fn demo_generate_raw_stream() {
         .region(region);
     
 
-    let client = BedrockClient::new(options).await;
+    let client = BedrockClient::new(options).await.unwrap();
 
     let stream = client
         .generate_raw_stream(
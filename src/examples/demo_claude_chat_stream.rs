@@ -11,7 +11,7 @@ pub async fn demo_chat_claude_with_stream() {
         .profile_name("bedrock")
         .region("us-west-2");
 
-    let client = ClaudeClient::new(claude_options).await;
+    let client = ClaudeClient::new(claude_options).await.unwrap();
 
     let mut conversation_request = ConversationRequest::default();
 
@@ -6,7 +6,7 @@ use crate::{bedrock::models::claude::{claude_client::{ClaudeClient, ClaudeOption
 pub async fn demo_claude_multimedia() {
     let claude_options = ClaudeOptions::new().profile_name("bedrock").region("us-west-2");
 
-    let client = ClaudeClient::new(claude_options).await;
+    let client = ClaudeClient::new(claude_options).await.unwrap();
 
     let image_url = "./data/mario.png";
 
@@ -13,7 +13,7 @@ pub async fn demo_mistra_with_stream(model_id: &str, prompt: &str) {
         .profile_name("bedrock")
         .region("us-west-2");
 
-    let client = MistralClient::new(mistral_otions).await;
+    let client = MistralClient::new(mistral_otions).await.unwrap();
 
 
 
@@ -5,16 +5,16 @@ use futures::stream::TryStream;
 use futures_util::TryStreamExt;
 
 use crate::ollama::options::OptionsBuilder;
-use crate::ollama::{ChatRequestBuilder, ChatResponse, Message};
 use crate::ollama::{GenerateRequestBuilder, GenerateResponse};
 use crate::ollama::OllamaClient;
 use crate::ollama::OllamaError;
+use crate::session::{ChatSession, OllamaChatOptions};
 
 
 pub async fn chat_response_loop(max_loop: u32,question: Option<&str>) {
     let client = OllamaClient::new("http://localhost:11434".to_string());
 
-    let mut messages = Vec::new();
+    let mut session = ChatSession::new(OllamaChatOptions::new("mistral"));
     let mut counter = 0;
 
     loop {
@@ -23,32 +23,14 @@ pub async fn chat_response_loop(max_loop: u32,question: Option<&str>) {
             Some(q) => q.to_string(),
             None => prompt_input("\n> ").unwrap(),
         };
-        
-        messages.push(Message {
-            role: "user".to_string(),
-            content: input,
-            images: vec![],
-        });
-
-        let request = ChatRequestBuilder::new("mistral".to_string())
-            .messages(messages.clone())
-            .build();
 
-        let response_stream = client.chat(request).await.unwrap();
+        // the session appends the user and assistant turns to its history
+        let mut response_stream = session.send_stream(&client, input).await.unwrap();
 
-        let response = process_and_collect_chat_response(response_stream, |chunk| {
+        while let Some(chunk) = response_stream.try_next().await.unwrap() {
             print!("{}", chunk);
             io::stdout().flush().unwrap();
-        })
-        .await
-        .unwrap();
-        // get last response from the chat
-
-        messages.push(Message {
-            role: "assistant".to_string(),
-            content: response,
-            images: vec![],
-        });
+        }
 
         counter += 1;
         if counter >= max_loop {
@@ -96,26 +78,6 @@ fn prompt_input(prompt: &str) -> Result<String, std::io::Error> {
     Ok(input.trim().to_string())
 }
 
-async fn process_and_collect_chat_response<F>(
-    response: impl TryStream<Ok = ChatResponse, Error = OllamaError>,
-    callback: F,
-) -> Result<String, OllamaError>
-where
-    F: Fn(&str) + Send + Sync + 'static,
-{
-    let words = response
-        .try_fold(String::new(), |mut f, chunk| async {
-            let response = chunk.message.content;
-            callback(&response);
-            f.push_str(&response);
-            Ok(f)
-        })
-        .await
-        .unwrap();
-
-    Ok(words)
-}
-
 pub async fn print_generate_response(
     response: impl TryStream<Ok = GenerateResponse, Error = OllamaError>,
 ) -> Result<(), OllamaError> {
@@ -5,7 +5,7 @@ use futures::TryStreamExt;
 use crate::bedrock::model_info::{ModelInfo, ModelName};
 use crate::bedrock::models::claude::claude_client::{ClaudeClient, ClaudeOptions};
 use crate::bedrock::models::claude::claude_request_message::{
-    ChatOptions, ContentBlockDelta, ConversationRequest, Message, StreamResultData,
+    ChatOptions, ConversationRequest, Message,
 };
 
 pub async fn chat_with_claude() {
@@ -13,7 +13,7 @@ pub async fn chat_with_claude() {
         .profile_name("bedrock")
         .region("us-west-2");
 
-    let client = ClaudeClient::new(claude_options).await;
+    let client = ClaudeClient::new(claude_options).await.unwrap();
 
     let mut conversation_request = ConversationRequest::default();
     conversation_request
@@ -27,31 +27,19 @@ pub async fn chat_with_claude() {
             ModelName::AnthropicClaudeHaiku1x,
         ));
 
-    let response_stream = client
-        .chat_with_stream(&conversation_request, &chat_options)
+    let mut response_stream = client
+        .chat_with_text_stream(&conversation_request, &chat_options)
         .await
         .unwrap();
 
-    response_stream
-        .try_for_each(|chunk| async move {
-            match chunk {
-                StreamResultData::ContentBlockStart(..) => {
-                    println!("\n------------------------------");
-                }
-                StreamResultData::ContentBlockStop(..) => {
-                    println!("\n------------------------------");
-                }
+    while let Some(text) = response_stream.try_next().await.unwrap() {
+        print!("{}", text);
+        std::io::stdout().flush().unwrap();
+    }
 
-                StreamResultData::ContentBlockDelta(ContentBlockDelta { delta, .. }) => {
-                    print!("{}", delta.text);
-                    std::io::stdout().flush().unwrap();
-                }
-                _ => {}
-            }
-            Ok(())
-        })
-        .await
-        .unwrap();
+    let response = response_stream.into_response().unwrap();
+    println!("\n------------------------------");
+    println!("Stop reason: {:?}, usage: {:?}", response.stop_reason, response.usage);
 }
 
 // Main
@@ -7,7 +7,7 @@ async fn generating_text_with_mistral() {
         .profile_name("bedrock")
         .region("us-west-2");
 
-    let client = MistralClient::new(mistral_options).await;
+    let client = MistralClient::new(mistral_options).await.unwrap();
 
     let request =
         MistralRequestBuilder::new("<s>[INST] What is the capital of France?[/INST]".to_string())
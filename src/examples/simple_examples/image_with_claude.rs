@@ -11,7 +11,7 @@ async fn image_with_claude() {
         .profile_name("bedrock")
         .region("us-west-2");
 
-    let client = ClaudeClient::new(claude_options).await;
+    let client = ClaudeClient::new(claude_options).await.unwrap();
 
     let image_url = "./data/mario.png";
     let input_text = "What's in this image?".to_string();
@@ -43,7 +43,7 @@ async fn image_with_claude() {
                 }
 
                 StreamResultData::ContentBlockDelta(ContentBlockDelta { delta, .. }) => {
-                    print!("{}", delta.text);
+                    print!("{}", delta.text().unwrap_or_default());
                     std::io::stdout().flush().unwrap();
                 }
                 _ => {}
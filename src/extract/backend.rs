@@ -0,0 +1,337 @@
+use std::future::Future;
+
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::bedrock::models::claude::claude_request_message::{
+    ChatOptions, ContentBlock, ContentBlockDelta, ConversationRequest, Delta, StreamResultData,
+    Tool, ToolChoice,
+};
+use crate::bedrock::models::claude::{ClaudeClient, Message as ClaudeMessage};
+use crate::bedrock::models::mistral::{
+    instruct_prompt, MistralClient, MistralMessage, MistralRequest, MistralRequestBuilder,
+};
+use crate::extract::error::ExtractError;
+use crate::ollama::{ChatRequestBuilder, Message as OllamaMessage, OllamaClient};
+use crate::session::{OllamaChatOptions, SessionMessage};
+
+/// A stream of the JSON text written by a model.
+pub type JsonStream = BoxStream<'static, Result<String, ExtractError>>;
+
+/// What an `ExtractionBackend` asks the model for.
+///
+/// # Fields
+///
+/// * `name` - The name of the schema, e.g. `Person`, used as the tool name on Claude.
+/// * `schema` - The JSON Schema the document must conform to.
+/// * `system` - The system prompt of the caller, if any.
+/// * `messages` - The conversation, including the feedback of the failed attempts.
+pub struct JsonRequest<'a, M> {
+    pub name: &'a str,
+    pub schema: &'a Value,
+    pub system: Option<&'a str>,
+    pub messages: &'a [M],
+}
+
+impl<M> JsonRequest<'_, M> {
+    /// The system prompt asking for a JSON document, for the models instructed by prompt.
+    pub fn instructions(&self) -> String {
+        let schema = serde_json::to_string(self.schema).unwrap_or_default();
+        let instructions = format!(
+            "Respond only with a JSON document conforming to this JSON Schema, without any \
+             other text:\n{}",
+            schema
+        );
+        match self.system {
+            Some(system) => format!("{}\n\n{}", system, instructions),
+            None => instructions,
+        }
+    }
+}
+
+/// A model that `Extractor` can ask for a JSON document.
+///
+/// Implemented for `OllamaClient`, which is asked for JSON with the `json` format, for
+/// `ClaudeClient`, which is made to call a tool taking the schema as its input, and for
+/// `MistralClient`, which is instructed by prompt.
+pub trait ExtractionBackend {
+    /// The message type of the conversation.
+    type Message: SessionMessage;
+
+    /// The model and sampling options of the requests.
+    type Options: Clone + Send + Sync;
+
+    /// Asks the model for a document and returns its JSON text.
+    fn generate_json(
+        &self,
+        request: &JsonRequest<'_, Self::Message>,
+        options: &Self::Options,
+    ) -> impl Future<Output = Result<String, ExtractError>> + Send;
+
+    /// Streams the JSON text of the document as the model writes it.
+    fn stream_json(
+        &self,
+        request: &JsonRequest<'_, Self::Message>,
+        options: &Self::Options,
+    ) -> impl Future<Output = Result<JsonStream, ExtractError>> + Send;
+}
+
+fn ollama_request(
+    request: &JsonRequest<'_, OllamaMessage>,
+    options: &OllamaChatOptions,
+) -> crate::ollama::ChatRequest {
+    let mut messages = Vec::with_capacity(request.messages.len() + 1);
+    messages.push(OllamaMessage::new("system".to_string(), request.instructions()));
+    messages.extend_from_slice(request.messages);
+
+    let mut builder = ChatRequestBuilder::new(options.model.clone())
+        .messages(messages)
+        .format("json".to_string());
+    if let Some(chat_options) = &options.options {
+        builder = builder.options(chat_options.clone());
+    }
+    if let Some(keep_alive) = &options.keep_alive {
+        builder = builder.keep_alive(keep_alive.clone());
+    }
+    builder.build()
+}
+
+impl ExtractionBackend for OllamaClient {
+    type Message = OllamaMessage;
+    type Options = OllamaChatOptions;
+
+    fn generate_json(
+        &self,
+        request: &JsonRequest<'_, Self::Message>,
+        options: &Self::Options,
+    ) -> impl Future<Output = Result<String, ExtractError>> + Send {
+        let stream = self.stream_json(request, options);
+        async move { stream.await?.try_collect().await }
+    }
+
+    fn stream_json(
+        &self,
+        request: &JsonRequest<'_, Self::Message>,
+        options: &Self::Options,
+    ) -> impl Future<Output = Result<JsonStream, ExtractError>> + Send {
+        let request = ollama_request(request, options);
+        async move {
+            let stream = self.chat(request).await?;
+            let stream = stream
+                .map_ok(|response| response.message.content)
+                .map_err(ExtractError::from);
+            Ok(stream.boxed())
+        }
+    }
+}
+
+/// The property holding the document in the tool input, when the schema is not an object.
+const WRAPPED_PROPERTY: &str = "value";
+
+/// The input schema of the extraction tool. Claude takes objects only, so another schema is
+/// wrapped in an object with a single `value` property.
+fn tool_schema(schema: &Value) -> (Value, bool) {
+    if schema.get("type").and_then(Value::as_str) == Some("object") {
+        return (schema.clone(), false);
+    }
+    let mut inner = schema.clone();
+    let definitions = inner
+        .as_object_mut()
+        .and_then(|inner| inner.remove("definitions"));
+    let mut wrapper = json!({
+        "type": "object",
+        "properties": { WRAPPED_PROPERTY: inner },
+        "required": [WRAPPED_PROPERTY],
+    });
+    if let Some(definitions) = definitions {
+        wrapper["definitions"] = definitions;
+    }
+    (wrapper, true)
+}
+
+/// The conversation making Claude call the extraction tool, and whether the schema is
+/// wrapped.
+fn claude_conversation(
+    request: &JsonRequest<'_, ClaudeMessage>,
+    options: &ChatOptions,
+) -> (ConversationRequest, bool) {
+    let (input_schema, wrapped) = tool_schema(request.schema);
+    let name = request.name.to_string();
+    let tool = Tool::new(name.clone(), input_schema)
+        .with_description(format!("Records the extracted {}.", name));
+    let conversation = ConversationRequest {
+        system: request.system.map(str::to_string),
+        messages: request.messages.to_vec(),
+        max_tokens: Some(options.max_tokens as i32),
+        tools: Some(vec![tool]),
+        tool_choice: Some(ToolChoice::Tool { name }),
+        ..Default::default()
+    };
+    (conversation, wrapped)
+}
+
+impl ExtractionBackend for ClaudeClient {
+    type Message = ClaudeMessage;
+    type Options = ChatOptions;
+
+    fn generate_json(
+        &self,
+        request: &JsonRequest<'_, Self::Message>,
+        options: &Self::Options,
+    ) -> impl Future<Output = Result<String, ExtractError>> + Send {
+        let (conversation, wrapped) = claude_conversation(request, options);
+        let name = request.name.to_string();
+
+        async move {
+            let response = self.chat(&conversation, options).await?;
+            match response.tool_input(&name) {
+                Some(input) if wrapped => Ok(input
+                    .get(WRAPPED_PROPERTY)
+                    .cloned()
+                    .unwrap_or(Value::Null)
+                    .to_string()),
+                Some(input) => Ok(input.to_string()),
+                // The model answered in text despite the tool choice.
+                None => Ok(response
+                    .content
+                    .iter()
+                    .filter_map(|block| match block {
+                        ContentBlock::Text { text } => Some(text.as_str()),
+                        _ => None,
+                    })
+                    .collect()),
+            }
+        }
+    }
+
+    /// Streams the JSON fragments of the tool input. When the schema is wrapped, the
+    /// fragments are those of the wrapper, so the document is sent whole once complete.
+    fn stream_json(
+        &self,
+        request: &JsonRequest<'_, Self::Message>,
+        options: &Self::Options,
+    ) -> impl Future<Output = Result<JsonStream, ExtractError>> + Send {
+        let (conversation, wrapped) = claude_conversation(request, options);
+        let json = wrapped.then(|| self.generate_json(request, options));
+        let options = options.clone();
+        async move {
+            if let Some(json) = json {
+                let json = json.await?;
+                return Ok(stream::once(async move { Ok(json) }).boxed());
+            }
+            let stream = self.chat_with_stream(&conversation, &options).await?;
+            let stream = stream
+                .map_err(ExtractError::from)
+                .try_filter_map(|event| async move {
+                    Ok(match event {
+                        StreamResultData::ContentBlockDelta(ContentBlockDelta {
+                            delta: Delta::InputJson { partial_json },
+                            ..
+                        }) => Some(partial_json),
+                        _ => None,
+                    })
+                });
+            Ok(stream.boxed())
+        }
+    }
+}
+
+/// The options of the extraction requests sent to a `MistralClient`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MistralExtractOptions {
+    pub model_id: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub top_p: Option<f32>,
+}
+
+impl MistralExtractOptions {
+    pub fn new<S: Into<String>>(model_id: S) -> Self {
+        Self {
+            model_id: model_id.into(),
+            max_tokens: None,
+            temperature: None,
+            top_p: None,
+        }
+    }
+
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    pub fn top_p(mut self, top_p: f32) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+}
+
+fn mistral_request(
+    request: &JsonRequest<'_, MistralMessage>,
+    options: &MistralExtractOptions,
+) -> MistralRequest {
+    let mut messages = Vec::with_capacity(request.messages.len() + 1);
+    messages.push(MistralMessage::system(request.instructions()));
+    messages.extend_from_slice(request.messages);
+
+    let mut builder = MistralRequestBuilder::new(instruct_prompt(&messages));
+    if let Some(max_tokens) = options.max_tokens {
+        builder = builder.max_tokens(max_tokens);
+    }
+    if let Some(temperature) = options.temperature {
+        builder = builder.temperature(temperature);
+    }
+    if let Some(top_p) = options.top_p {
+        builder = builder.top_p(top_p);
+    }
+    builder.build()
+}
+
+impl ExtractionBackend for MistralClient {
+    type Message = MistralMessage;
+    type Options = MistralExtractOptions;
+
+    fn generate_json(
+        &self,
+        request: &JsonRequest<'_, Self::Message>,
+        options: &Self::Options,
+    ) -> impl Future<Output = Result<String, ExtractError>> + Send {
+        let mistral_request = mistral_request(request, options);
+        let model_id = options.model_id.clone();
+        async move {
+            let response = self.generate(model_id, &mistral_request).await?;
+            Ok(response.outputs.into_iter().map(|output| output.text).collect())
+        }
+    }
+
+    fn stream_json(
+        &self,
+        request: &JsonRequest<'_, Self::Message>,
+        options: &Self::Options,
+    ) -> impl Future<Output = Result<JsonStream, ExtractError>> + Send {
+        let mistral_request = mistral_request(request, options);
+        let model_id = options.model_id.clone();
+        async move {
+            let stream = self.generate_with_stream(model_id, &mistral_request).await?;
+            let stream = stream
+                .map_ok(|response| {
+                    response
+                        .outputs
+                        .into_iter()
+                        .map(|output| output.text)
+                        .collect::<String>()
+                })
+                .map_err(ExtractError::from);
+            Ok(stream.boxed())
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use serde_json::Error as SerdeJsonError;
+use thiserror::Error;
+
+use crate::bedrock::models::claude::error::ClaudeError;
+use crate::bedrock::models::mistral::error::MistralError;
+use crate::ollama::error::OllamaError;
+
+#[derive(Error, Debug)]
+pub enum ExtractError {
+    #[error("JSON error: {0}")]
+    Json(#[from] SerdeJsonError),
+
+    #[error("Ollama error: {0}")]
+    Ollama(#[from] OllamaError),
+
+    #[error("Claude error: {0}")]
+    Claude(#[from] ClaudeError),
+
+    #[error("Mistral error: {0}")]
+    Mistral(#[from] MistralError),
+
+    #[error("Invalid output: {0}")]
+    InvalidOutput(String),
+
+    /// The output of the last attempt still failed the validation.
+    #[error("Validation failed after {attempts} attempts: {}", errors.join("; "))]
+    Validation {
+        attempts: usize,
+        errors: Vec<String>,
+        output: String,
+    },
+}
@@ -0,0 +1,482 @@
+use async_stream::try_stream;
+use futures::stream::BoxStream;
+use futures::TryStreamExt;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::extract::backend::{ExtractionBackend, JsonRequest};
+use crate::extract::error::ExtractError;
+use crate::extract::partial::{json_payload, parse_partial};
+use crate::extract::schema::{schema_for, schema_name, validate};
+use crate::session::SessionMessage;
+
+/// The number of times a failed extraction is retried by default.
+pub const DEFAULT_MAX_RETRIES: usize = 2;
+
+/// An event of a streamed extraction.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExtractEvent<T> {
+    /// The document written so far, with its open strings, arrays and objects closed. The
+    /// properties not written yet are missing.
+    Partial(Value),
+    /// The complete document, parsed and validated.
+    Complete(T),
+}
+
+/// Extracts typed data from the output of a model.
+///
+/// The JSON Schema of the target type is generated with `schemars`, and the model is asked for
+/// a document conforming to it. The document is validated against the schema, parsed into the
+/// type, and checked by the optional validator. When any of these fails, the errors are sent
+/// back to the model, which is asked for a corrected document, up to `max_retries` times.
+///
+/// # Example
+///
+/// ```no_run
+/// use hiramu::extract::Extractor;
+/// use hiramu::ollama::{Message, OllamaClient};
+/// use hiramu::session::OllamaChatOptions;
+/// use schemars::JsonSchema;
+/// use serde::Deserialize;
+///
+/// #[derive(Debug, Deserialize, JsonSchema)]
+/// struct Person {
+///     name: String,
+///     age: u32,
+/// }
+///
+/// # async fn run() -> Result<(), hiramu::extract::ExtractError> {
+/// let client = OllamaClient::new("http://localhost:11434".to_string());
+/// let person: Person = Extractor::new(&client, OllamaChatOptions::new("llama3"))
+///     .max_retries(3)
+///     .extract(&[Message::new(
+///         "user".to_string(),
+///         "Ada Lovelace was 36 when she died.".to_string(),
+///     )])
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Extractor<'a, B: ExtractionBackend> {
+    backend: &'a B,
+    options: B::Options,
+    system: Option<String>,
+    max_retries: usize,
+}
+
+impl<'a, B: ExtractionBackend> Extractor<'a, B> {
+    pub fn new(backend: &'a B, options: B::Options) -> Self {
+        Self {
+            backend,
+            options,
+            system: None,
+            max_retries: DEFAULT_MAX_RETRIES,
+        }
+    }
+
+    /// Sets a system prompt, sent before the extraction instructions.
+    pub fn system<S: Into<String>>(mut self, system: S) -> Self {
+        self.system = Some(system.into());
+        self
+    }
+
+    /// Sets how many times an invalid document is sent back for correction.
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Extracts a `T` from the conversation.
+    pub async fn extract<T>(&self, messages: &[B::Message]) -> Result<T, ExtractError>
+    where
+        T: DeserializeOwned + JsonSchema,
+    {
+        self.extract_with(messages, |_: &T| Ok(())).await
+    }
+
+    /// Extracts a `T` from the conversation, and checks it with `validator`, whose error is
+    /// sent back to the model like a schema error, e.g. `the end date is before the start`.
+    pub async fn extract_with<T, F>(
+        &self,
+        messages: &[B::Message],
+        validator: F,
+    ) -> Result<T, ExtractError>
+    where
+        T: DeserializeOwned + JsonSchema,
+        F: Fn(&T) -> Result<(), String>,
+    {
+        let schema = schema_for::<T>();
+        let name = schema_name::<T>();
+        let mut conversation = messages.to_vec();
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let request = JsonRequest {
+                name: &name,
+                schema: &schema,
+                system: self.system.as_deref(),
+                messages: &conversation,
+            };
+            let output = self.backend.generate_json(&request, &self.options).await?;
+            let errors = match check(&schema, &output, &validator) {
+                Ok(value) => return Ok(value),
+                Err(errors) => errors,
+            };
+            if attempts > self.max_retries {
+                return Err(ExtractError::Validation {
+                    attempts,
+                    errors,
+                    output,
+                });
+            }
+            conversation.push(B::Message::new_assistant(output));
+            conversation.push(B::Message::new_user(feedback(&errors)));
+        }
+    }
+
+    /// Extracts a `T` from the conversation, and streams the document as the model writes it.
+    ///
+    /// The stream ends with the complete `T`, or with a `Validation` error: a streamed
+    /// extraction is not retried.
+    pub async fn extract_stream<T>(
+        &self,
+        messages: &[B::Message],
+    ) -> Result<BoxStream<'static, Result<ExtractEvent<T>, ExtractError>>, ExtractError>
+    where
+        T: DeserializeOwned + JsonSchema + Send + 'static,
+    {
+        let schema = schema_for::<T>();
+        let name = schema_name::<T>();
+        let request = JsonRequest {
+            name: &name,
+            schema: &schema,
+            system: self.system.as_deref(),
+            messages,
+        };
+        let mut deltas = self.backend.stream_json(&request, &self.options).await?;
+
+        Ok(Box::pin(try_stream! {
+            let mut output = String::new();
+            let mut last = None;
+            while let Some(delta) = deltas.try_next().await? {
+                output.push_str(&delta);
+                let partial = parse_partial(&output);
+                if partial.is_some() && partial != last {
+                    last = partial.clone();
+                    yield ExtractEvent::Partial(partial.unwrap_or_default());
+                }
+            }
+            match check(&schema, &output, &|_: &T| Ok(())) {
+                Ok(value) => yield ExtractEvent::Complete(value),
+                Err(errors) => Err(ExtractError::Validation {
+                    attempts: 1,
+                    errors,
+                    output,
+                })?,
+            }
+        }))
+    }
+}
+
+/// Extracts a `T` from the conversation, with the default retries.
+///
+/// # Arguments
+///
+/// * `backend` - The client of the model.
+/// * `options` - The model and sampling options of the requests.
+/// * `messages` - The conversation holding the data to extract.
+pub async fn extract<T, B>(
+    backend: &B,
+    options: B::Options,
+    messages: &[B::Message],
+) -> Result<T, ExtractError>
+where
+    T: DeserializeOwned + JsonSchema,
+    B: ExtractionBackend,
+{
+    Extractor::new(backend, options).extract(messages).await
+}
+
+/// Parses and validates the output of a model, or returns the errors to send back to it.
+fn check<T, F>(schema: &Value, output: &str, validator: &F) -> Result<T, Vec<String>>
+where
+    T: DeserializeOwned,
+    F: Fn(&T) -> Result<(), String>,
+{
+    let value: Value = serde_json::from_str(json_payload(output))
+        .map_err(|err| vec![format!("the output is not valid JSON: {}", err)])?;
+    let errors = validate(schema, &value);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let value: T = serde_json::from_value(value).map_err(|err| vec![err.to_string()])?;
+    validator(&value).map_err(|err| vec![err])?;
+    Ok(value)
+}
+
+/// The message asking the model to correct its document.
+fn feedback(errors: &[String]) -> String {
+    let errors: Vec<String> = errors.iter().map(|error| format!("- {}", error)).collect();
+    format!(
+        "The JSON document is invalid:\n{}\nRespond again with only the corrected JSON document.",
+        errors.join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bedrock::models::claude::claude_request_message::ChatOptions;
+    use crate::bedrock::models::claude::{ClaudeClient, Message as ClaudeMessage};
+    use crate::bedrock::models::mistral::{MistralClient, MistralMessage};
+    use crate::extract::backend::MistralExtractOptions;
+    use crate::ollama::{Message, OllamaClient};
+    use crate::session::OllamaChatOptions;
+    use crate::testing::{
+        BedrockStubServer, MockEndpoint, MockOllamaServer, MockResponse, StubOperation,
+        StubResponse,
+    };
+    use futures::TryStreamExt;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, PartialEq, Deserialize, JsonSchema)]
+    struct Person {
+        name: String,
+        age: u32,
+    }
+
+    fn question() -> Vec<Message> {
+        vec![Message::new(
+            "user".to_string(),
+            "Ada Lovelace was 36 when she died.".to_string(),
+        )]
+    }
+
+    #[tokio::test]
+    async fn test_extract_retries_with_errors() {
+        let server = MockOllamaServer::start().await.unwrap();
+        server.enqueue(MockEndpoint::Chat, MockResponse::text([r#"{"name": "Ada"#, r#"", "age": "36"}"#]));
+        server.enqueue(MockEndpoint::Chat, MockResponse::text([r#"{"name": "Ada", "age": 36}"#]));
+        let client = OllamaClient::new(server.base_url());
+
+        let person: Person = extract(&client, OllamaChatOptions::new("llama3"), &question())
+            .await
+            .unwrap();
+        assert_eq!(person, Person { name: "Ada".to_string(), age: 36 });
+
+        let requests = server.requests_to(MockEndpoint::Chat);
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].body["format"], "json");
+        let instructions = requests[0].body["messages"][0]["content"].as_str().unwrap();
+        assert!(instructions.contains(r#""required":["age","name"]"#), "{}", instructions);
+        let retry = requests[1].body["messages"].as_array().unwrap();
+        assert_eq!(retry.len(), 4);
+        assert_eq!(retry[2]["role"], "assistant");
+        assert_eq!(
+            retry[3]["content"],
+            "The JSON document is invalid:\n- /age: expected integer, got string\n\
+             Respond again with only the corrected JSON document."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extract_gives_up() {
+        let server = MockOllamaServer::start().await.unwrap();
+        server.set_default(MockEndpoint::Chat, MockResponse::text(["I don't know."]));
+        let client = OllamaClient::new(server.base_url());
+
+        let result: Result<Person, _> = Extractor::new(&client, OllamaChatOptions::new("llama3"))
+            .max_retries(1)
+            .extract_with(&question(), |person: &Person| {
+                (person.age < 150).then_some(()).ok_or("too old".to_string())
+            })
+            .await;
+        match result {
+            Err(ExtractError::Validation { attempts, errors, output }) => {
+                assert_eq!(attempts, 2);
+                assert_eq!(output, "I don't know.");
+                assert!(errors[0].starts_with("the output is not valid JSON"), "{:?}", errors);
+            }
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+        assert_eq!(server.requests_to(MockEndpoint::Chat).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_extract_stream() {
+        let server = MockOllamaServer::start().await.unwrap();
+        server.enqueue(
+            MockEndpoint::Chat,
+            MockResponse::text(["{\"name\": \"A", "da\", \"a", "ge\": 36}"]),
+        );
+        let client = OllamaClient::new(server.base_url());
+
+        let events: Vec<ExtractEvent<Person>> =
+            Extractor::new(&client, OllamaChatOptions::new("llama3"))
+                .extract_stream(&question())
+                .await
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                ExtractEvent::Partial(json!({ "name": "A" })),
+                ExtractEvent::Partial(json!({ "name": "Ada" })),
+                ExtractEvent::Partial(json!({ "name": "Ada", "age": 36 })),
+                ExtractEvent::Complete(Person { name: "Ada".to_string(), age: 36 }),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extract_with_claude_tool_use() {
+        let server = BedrockStubServer::start().await.unwrap();
+        server.enqueue(StubResponse::json(json!({
+            "id": "msg_stub",
+            "type": "message",
+            "role": "assistant",
+            "model": "anthropic.claude-3-haiku-20240307-v1:0",
+            "content": [{
+                "type": "tool_use",
+                "id": "toolu_1",
+                "name": "Person",
+                "input": { "name": "Ada", "age": 36 }
+            }],
+            "stop_reason": "tool_use",
+            "stop_sequence": null,
+            "usage": { "input_tokens": 120, "output_tokens": 20 }
+        })));
+        server.enqueue(StubResponse::json(json!({
+            "id": "msg_stub",
+            "type": "message",
+            "role": "assistant",
+            "model": "anthropic.claude-3-haiku-20240307-v1:0",
+            "content": [{
+                "type": "tool_use",
+                "id": "toolu_2",
+                "name": "Array_of_String",
+                "input": { "value": ["Ada", "Charles"] }
+            }],
+            "stop_reason": "tool_use",
+            "stop_sequence": null,
+            "usage": { "input_tokens": 120, "output_tokens": 20 }
+        })));
+        let client = ClaudeClient::new(server.client_options()).await.unwrap();
+        let messages = [ClaudeMessage::new_user_message(
+            "Ada Lovelace was 36 when she died.".to_string(),
+        )];
+
+        let person: Person = extract(&client, ChatOptions::default(), &messages).await.unwrap();
+        assert_eq!(person, Person { name: "Ada".to_string(), age: 36 });
+
+        let names: Vec<String> = extract(&client, ChatOptions::default(), &messages).await.unwrap();
+        assert_eq!(names, vec!["Ada".to_string(), "Charles".to_string()]);
+
+        let requests = server.requests();
+        assert_eq!(requests[0].body["tool_choice"], json!({ "type": "tool", "name": "Person" }));
+        assert_eq!(requests[0].body["tools"][0]["input_schema"]["type"], "object");
+        assert_eq!(
+            requests[1].body["tools"][0]["input_schema"]["properties"]["value"]["type"],
+            "array"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extract_stream_with_claude_tool_use() {
+        let server = BedrockStubServer::start().await.unwrap();
+        let input_json = |index: usize, partial_json: &str| {
+            json!({
+                "type": "content_block_delta",
+                "index": index,
+                "delta": { "type": "input_json_delta", "partial_json": partial_json }
+            })
+        };
+        server.enqueue(StubResponse::chunks(vec![
+            json!({
+                "type": "message_start",
+                "message": {
+                    "id": "msg_stub",
+                    "type": "message",
+                    "role": "assistant",
+                    "content": [],
+                    "model": "anthropic.claude-3-haiku-20240307-v1:0",
+                    "stop_reason": null,
+                    "stop_sequence": null,
+                    "usage": { "input_tokens": 120, "output_tokens": 1 }
+                }
+            }),
+            json!({
+                "type": "content_block_start",
+                "index": 0,
+                "content_block": { "type": "tool_use", "id": "toolu_1", "name": "Person", "input": {} }
+            }),
+            input_json(0, "{\"name\": \"A"),
+            input_json(0, "da\", \"a"),
+            input_json(0, "ge\": 36}"),
+            json!({ "type": "content_block_stop", "index": 0 }),
+            json!({
+                "type": "message_delta",
+                "delta": { "stop_reason": "tool_use", "stop_sequence": null },
+                "usage": { "output_tokens": 20 }
+            }),
+            json!({
+                "type": "message_stop",
+                "amazon-bedrock-invocationMetrics": {
+                    "inputTokenCount": 120,
+                    "outputTokenCount": 20,
+                    "invocationLatency": 10,
+                    "firstByteLatency": 5
+                }
+            }),
+        ]));
+        let client = ClaudeClient::new(server.client_options()).await.unwrap();
+        let messages = [ClaudeMessage::new_user_message(
+            "Ada Lovelace was 36 when she died.".to_string(),
+        )];
+
+        let events: Vec<ExtractEvent<Person>> = Extractor::new(&client, ChatOptions::default())
+            .extract_stream(&messages)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                ExtractEvent::Partial(json!({ "name": "A" })),
+                ExtractEvent::Partial(json!({ "name": "Ada" })),
+                ExtractEvent::Partial(json!({ "name": "Ada", "age": 36 })),
+                ExtractEvent::Complete(Person { name: "Ada".to_string(), age: 36 }),
+            ]
+        );
+        assert_eq!(
+            server.requests()[0].operation,
+            Some(StubOperation::InvokeModelWithResponseStream)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extract_with_mistral_prompt() {
+        let server = BedrockStubServer::start().await.unwrap();
+        server.enqueue(StubResponse::text(["```json\n", r#"{"name": "Ada", "age": 36}"#, "\n```"]));
+        let client = MistralClient::new(server.client_options()).await.unwrap();
+
+        let person: Person = extract(
+            &client,
+            MistralExtractOptions::new("mistral.mistral-7b-instruct-v0:2"),
+            &[MistralMessage::user("Ada Lovelace was 36 when she died.")],
+        )
+        .await
+        .unwrap();
+        assert_eq!(person, Person { name: "Ada".to_string(), age: 36 });
+
+        let prompt = server.requests()[0].body["prompt"].as_str().unwrap().to_string();
+        assert!(prompt.starts_with("<s>[INST] Respond only with a JSON document"), "{}", prompt);
+        assert!(prompt.ends_with("Ada Lovelace was 36 when she died. [/INST]"), "{}", prompt);
+    }
+}
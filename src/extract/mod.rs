@@ -0,0 +1,14 @@
+//! Typed extraction: asks a model for a document conforming to the JSON Schema of a Rust
+//! type, validates it, and re-prompts the model with the errors until it is valid.
+
+pub mod backend;
+pub mod error;
+pub mod extractor;
+pub mod partial;
+pub mod schema;
+
+pub use backend::{ExtractionBackend, JsonRequest, JsonStream, MistralExtractOptions};
+pub use error::ExtractError;
+pub use extractor::{extract, ExtractEvent, Extractor, DEFAULT_MAX_RETRIES};
+pub use partial::parse_partial;
+pub use schema::{schema_for, validate};
@@ -0,0 +1,115 @@
+use serde_json::Value;
+
+/// Parses the JSON document a model is still writing, e.g. `{"name": "Ada", "tags": ["v`,
+/// by closing its open strings, arrays and objects. The incomplete tail that cannot be
+/// closed, such as a key without its value or a truncated `true`, is dropped.
+///
+/// Returns `None` until the document has started.
+pub fn parse_partial(text: &str) -> Option<Value> {
+    let start = text.find(['{', '['])?;
+    let mut candidate = &text[start..];
+    while !candidate.is_empty() {
+        if let Ok(value) = serde_json::from_str(&close(candidate)) {
+            return Some(value);
+        }
+        let mut end = candidate.len() - 1;
+        while !candidate.is_char_boundary(end) {
+            end -= 1;
+        }
+        candidate = &candidate[..end];
+    }
+    None
+}
+
+/// Completes a truncated JSON document with the closing quotes and brackets it needs.
+fn close(text: &str) -> String {
+    let mut closers = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in text.chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => closers.push('}'),
+            '[' => closers.push(']'),
+            '}' | ']' => {
+                closers.pop();
+            }
+            _ => {}
+        }
+    }
+
+    let mut closed = text.to_string();
+    if in_string {
+        if escaped {
+            closed.pop();
+        }
+        closed.push('"');
+    }
+    let trimmed = closed.trim_end().len();
+    closed.truncate(trimmed);
+    if closed.ends_with(',') {
+        closed.pop();
+    } else if closed.ends_with(':') {
+        closed.push_str("null");
+    }
+    closed.extend(closers.iter().rev());
+    closed
+}
+
+/// The JSON document in the output of a model, without the prose or the Markdown fence
+/// around it.
+pub fn json_payload(text: &str) -> &str {
+    let start = text.find(['{', '[']);
+    let end = text.rfind(['}', ']']);
+    match (start, end) {
+        (Some(start), Some(end)) if start < end => &text[start..=end],
+        _ => text.trim(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_partial() {
+        assert_eq!(parse_partial("Sure, here"), None);
+        assert_eq!(parse_partial("{"), Some(json!({})));
+        assert_eq!(parse_partial(r#"{"na"#), Some(json!({})));
+        assert_eq!(parse_partial(r#"{"name":"#), Some(json!({ "name": null })));
+        assert_eq!(parse_partial(r#"{"name": "Ad"#), Some(json!({ "name": "Ad" })));
+        assert_eq!(
+            parse_partial(r#"{"name": "Ada", "tags": ["vip", "ne"#),
+            Some(json!({ "name": "Ada", "tags": ["vip", "ne"] }))
+        );
+        assert_eq!(
+            parse_partial(r#"{"name": "A\"da\"#),
+            Some(json!({ "name": "A\"da" }))
+        );
+        assert_eq!(
+            parse_partial(r#"{"active": tr"#),
+            Some(json!({ "active": null }))
+        );
+        assert_eq!(
+            parse_partial(r#"```json {"a": {"b": [1, 2], "c": "é"#),
+            Some(json!({ "a": { "b": [1, 2], "c": "é" } }))
+        );
+    }
+
+    #[test]
+    fn test_json_payload() {
+        assert_eq!(json_payload("```json\n{\"a\": 1}\n```"), "{\"a\": 1}");
+        assert_eq!(json_payload("Here it is: [1, 2]. Done."), "[1, 2]");
+        assert_eq!(json_payload(" no json "), "no json");
+    }
+}
@@ -0,0 +1,306 @@
+use schemars::JsonSchema;
+use serde_json::Value;
+
+/// The JSON Schema of `T`, without the `$schema` keyword, which some providers reject in
+/// tool definitions.
+pub fn schema_for<T: JsonSchema>() -> Value {
+    let mut schema = serde_json::to_value(schemars::schema_for!(T)).unwrap_or_default();
+    if let Some(object) = schema.as_object_mut() {
+        object.remove("$schema");
+    }
+    schema
+}
+
+/// The name of the schema of `T`, usable as a tool name, e.g. `Person` or `Vec_of_String`.
+pub fn schema_name<T: JsonSchema>() -> String {
+    T::schema_name()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .collect()
+}
+
+/// Validates `value` against `schema`, and returns the errors, with the JSON pointer of each
+/// invalid value, e.g. `/age: expected integer, got string`.
+///
+/// The validation covers the keywords generated by `schemars`: `type`, `properties`,
+/// `required`, `additionalProperties`, `items`, `enum`, `const`, `anyOf`, `oneOf`, `allOf`,
+/// `$ref`, and the numeric and length bounds. The other keywords are ignored.
+pub fn validate(schema: &Value, value: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    validate_at(schema, schema, value, "", &mut errors);
+    errors
+}
+
+fn resolve<'a>(root: &'a Value, reference: &str) -> Option<&'a Value> {
+    let pointer = reference.strip_prefix('#')?;
+    root.pointer(pointer)
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(number) if number.is_i64() || number.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn has_type(value: &Value, expected: &str) -> bool {
+    let actual = type_name(value);
+    actual == expected
+        || (expected == "number" && actual == "integer")
+        || (expected == "integer" && value.as_f64().is_some_and(|number| number.fract() == 0.0))
+}
+
+fn location(path: &str) -> &str {
+    if path.is_empty() {
+        "/"
+    } else {
+        path
+    }
+}
+
+fn validate_at(root: &Value, schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    let schema = match schema {
+        Value::Bool(true) => return,
+        Value::Bool(false) => {
+            errors.push(format!("{}: no value is allowed", location(path)));
+            return;
+        }
+        Value::Object(schema) => schema,
+        _ => return,
+    };
+
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        match resolve(root, reference) {
+            Some(target) => validate_at(root, target, value, path, errors),
+            None => errors.push(format!("{}: unresolved reference {}", location(path), reference)),
+        }
+    }
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(expected) => vec![expected.as_str()],
+            Value::Array(expected) => expected.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|expected| has_type(value, expected)) {
+            errors.push(format!(
+                "{}: expected {}, got {}",
+                location(path),
+                types.join(" or "),
+                type_name(value)
+            ));
+            return;
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+            errors.push(format!(
+                "{}: expected one of {}, got {}",
+                location(path),
+                allowed.join(", "),
+                value
+            ));
+        }
+    }
+    if let Some(constant) = schema.get("const") {
+        if constant != value {
+            errors.push(format!("{}: expected {}, got {}", location(path), constant, value));
+        }
+    }
+
+    if let Some(all_of) = schema.get("allOf").and_then(Value::as_array) {
+        for sub_schema in all_of {
+            validate_at(root, sub_schema, value, path, errors);
+        }
+    }
+    for keyword in ["anyOf", "oneOf"] {
+        if let Some(variants) = schema.get(keyword).and_then(Value::as_array) {
+            let mut variant_errors = Vec::new();
+            let matched = variants.iter().any(|variant| {
+                let mut errors = Vec::new();
+                validate_at(root, variant, value, path, &mut errors);
+                let valid = errors.is_empty();
+                variant_errors.extend(errors);
+                valid
+            });
+            if !matched {
+                errors.push(format!(
+                    "{}: matches none of the allowed shapes ({})",
+                    location(path),
+                    variant_errors.join("; ")
+                ));
+            }
+        }
+    }
+
+    match value {
+        Value::Object(object) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            if let Some(required) = schema.get("required").and_then(Value::as_array) {
+                for name in required.iter().filter_map(Value::as_str) {
+                    if !object.contains_key(name) {
+                        errors.push(format!("{}: missing required property `{}`", location(path), name));
+                    }
+                }
+            }
+            for (name, property) in object {
+                let property_path = format!("{}/{}", path, name);
+                match properties.and_then(|properties| properties.get(name)) {
+                    Some(property_schema) => {
+                        validate_at(root, property_schema, property, &property_path, errors)
+                    }
+                    None => match schema.get("additionalProperties") {
+                        Some(Value::Bool(false)) => errors.push(format!(
+                            "{}: unknown property `{}`",
+                            location(path),
+                            name
+                        )),
+                        Some(additional) => {
+                            validate_at(root, additional, property, &property_path, errors)
+                        }
+                        None => {}
+                    },
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    validate_at(root, item_schema, item, &format!("{}/{}", path, index), errors);
+                }
+            }
+            if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+                if (items.len() as u64) < min {
+                    errors.push(format!("{}: expected at least {} items", location(path), min));
+                }
+            }
+            if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+                if items.len() as u64 > max {
+                    errors.push(format!("{}: expected at most {} items", location(path), max));
+                }
+            }
+        }
+        Value::String(text) => {
+            let length = text.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+                if length < min {
+                    errors.push(format!("{}: expected at least {} characters", location(path), min));
+                }
+            }
+            if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+                if length > max {
+                    errors.push(format!("{}: expected at most {} characters", location(path), max));
+                }
+            }
+        }
+        Value::Number(number) => {
+            let number = number.as_f64().unwrap_or_default();
+            if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+                if number < min {
+                    errors.push(format!("{}: expected at least {}, got {}", location(path), min, number));
+                }
+            }
+            if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+                if number > max {
+                    errors.push(format!("{}: expected at most {}, got {}", location(path), max, number));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use schemars::JsonSchema;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[allow(dead_code)]
+    #[derive(Debug, Deserialize, JsonSchema)]
+    enum Tier {
+        Free,
+        Pro,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Debug, Deserialize, JsonSchema)]
+    struct Address {
+        city: String,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Debug, Deserialize, JsonSchema)]
+    #[serde(deny_unknown_fields)]
+    struct Customer {
+        name: String,
+        #[validate(range(min = 0, max = 150))]
+        age: u8,
+        tier: Tier,
+        address: Option<Address>,
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn test_validate_generated_schema() {
+        let schema = schema_for::<Customer>();
+        assert!(schema.get("$schema").is_none());
+        assert_eq!(schema_name::<Customer>(), "Customer");
+        assert_eq!(schema_name::<Vec<String>>(), "Array_of_String");
+
+        let valid = json!({
+            "name": "Ada",
+            "age": 36,
+            "tier": "Pro",
+            "address": { "city": "London" },
+            "tags": ["vip"]
+        });
+        assert!(validate(&schema, &valid).is_empty(), "{:?}", validate(&schema, &valid));
+        let without_address = json!({ "name": "Ada", "age": 36, "tier": "Free", "address": null, "tags": [] });
+        assert!(validate(&schema, &without_address).is_empty());
+
+        let invalid = json!({
+            "age": "36",
+            "tier": "Enterprise",
+            "address": { "town": "London" },
+            "tags": [1],
+            "email": "ada@example.com"
+        });
+        let errors = validate(&schema, &invalid);
+        assert!(errors.contains(&"/: missing required property `name`".to_string()), "{:?}", errors);
+        assert!(errors.contains(&"/age: expected integer, got string".to_string()), "{:?}", errors);
+        assert!(errors.iter().any(|error| error.starts_with("/tier: ")), "{:?}", errors);
+        assert!(errors.iter().any(|error| error.starts_with("/address: ")), "{:?}", errors);
+        assert!(errors.contains(&"/tags/0: expected string, got integer".to_string()), "{:?}", errors);
+        assert!(errors.contains(&"/: unknown property `email`".to_string()), "{:?}", errors);
+    }
+
+    #[test]
+    fn test_validate_bounds() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "score": { "type": "number", "minimum": 0, "maximum": 1 },
+                "code": { "type": "string", "minLength": 2, "maxLength": 3 },
+                "items": { "type": "array", "minItems": 1 }
+            }
+        });
+        let errors = validate(&schema, &json!({ "score": 1.5, "code": "A", "items": [] }));
+        assert_eq!(
+            errors,
+            vec![
+                "/code: expected at least 2 characters",
+                "/items: expected at least 1 items",
+                "/score: expected at most 1, got 1.5",
+            ]
+        );
+    }
+}
@@ -0,0 +1,540 @@
+use std::sync::Arc;
+
+use async_stream::try_stream;
+use futures::stream::BoxStream;
+use futures::TryStreamExt;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::bedrock::models::claude::claude_request_message::{
+    ChatOptions, ContentBlockDelta, ConversationRequest, Delta, Message as ClaudeMessage,
+    StreamResultData,
+};
+use crate::bedrock::models::claude::ClaudeClient;
+use crate::bedrock::models::mistral::{
+    instruct_prompt, MistralClient, MistralMessage, MistralRequestBuilder,
+};
+use crate::gateway::error::GatewayError;
+use crate::gateway::routing::{Backend, ModelRoute};
+use crate::ollama::{ChatRequestBuilder, Message as OllamaMessage, OllamaClient};
+use crate::openai::model::{StreamOptions, Usage};
+use crate::tokenizer::{HeuristicTokenizer, Tokenizer};
+
+/// The completion limit of a Claude request when neither the request nor the route sets one.
+pub const DEFAULT_MAX_TOKENS: u32 = 1024;
+
+/// The stop sequences of a request, which OpenAI accepts as a string or a list.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Stop {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Stop {
+    pub fn into_vec(self) -> Vec<String> {
+        match self {
+            Stop::One(stop) => vec![stop],
+            Stop::Many(stops) => stops,
+        }
+    }
+}
+
+/// A message of an incoming chat completion request. The content is either a string or a
+/// list of parts, of which only the text parts are supported.
+#[derive(Debug, Clone, Deserialize)]
+pub struct IncomingMessage {
+    pub role: String,
+    #[serde(default)]
+    pub content: Option<Value>,
+}
+
+impl IncomingMessage {
+    /// The text of the message, with the text parts joined.
+    pub fn text(&self) -> Result<String, GatewayError> {
+        match &self.content {
+            None | Some(Value::Null) => Ok(String::new()),
+            Some(Value::String(text)) => Ok(text.clone()),
+            Some(Value::Array(parts)) => {
+                let mut text = String::new();
+                for part in parts {
+                    match part.get("type").and_then(Value::as_str) {
+                        Some("text") => {
+                            text.push_str(part.get("text").and_then(Value::as_str).unwrap_or(""))
+                        }
+                        Some(other) => {
+                            return Err(GatewayError::Unsupported(format!(
+                                "content parts of type `{}`",
+                                other
+                            )))
+                        }
+                        None => {
+                            return Err(GatewayError::InvalidRequest(
+                                "a content part has no type".to_string(),
+                            ))
+                        }
+                    }
+                }
+                Ok(text)
+            }
+            Some(_) => Err(GatewayError::InvalidRequest(
+                "the content of a message must be a string or a list of parts".to_string(),
+            )),
+        }
+    }
+
+    /// The role of the message, with `developer` read as `system`.
+    fn role(&self) -> Result<Role, GatewayError> {
+        match self.role.as_str() {
+            "system" | "developer" => Ok(Role::System),
+            "user" => Ok(Role::User),
+            "assistant" => Ok(Role::Assistant),
+            other => Err(GatewayError::Unsupported(format!("messages with the role `{}`", other))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+impl Role {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        }
+    }
+}
+
+/// The fields of an OpenAI chat completion request that the gateway translates. Unknown
+/// fields are ignored, and a request with tools is rejected.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChatCompletionParams {
+    pub model: String,
+    pub messages: Vec<IncomingMessage>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub max_completion_tokens: Option<u32>,
+    #[serde(default)]
+    pub stop: Option<Stop>,
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(default)]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(default)]
+    pub tools: Vec<Value>,
+}
+
+impl ChatCompletionParams {
+    /// The completion limit of the request, from `max_completion_tokens` or `max_tokens`.
+    pub fn max_tokens(&self) -> Option<u32> {
+        self.max_completion_tokens.or(self.max_tokens)
+    }
+
+    pub fn stop(&self) -> Vec<String> {
+        self.stop.clone().map(Stop::into_vec).unwrap_or_default()
+    }
+
+    /// Whether the last event of a stream reports the usage.
+    pub fn include_usage(&self) -> bool {
+        self.stream_options
+            .as_ref()
+            .is_some_and(|options| options.include_usage)
+    }
+
+    fn check(&self) -> Result<(), GatewayError> {
+        if !self.tools.is_empty() {
+            return Err(GatewayError::Unsupported("tools".to_string()));
+        }
+        if self.messages.is_empty() {
+            return Err(GatewayError::InvalidRequest(
+                "`messages` must not be empty".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A piece of a completion produced by a backend. The last piece carries the finish reason
+/// and, when the backend reports it, the usage.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompletionChunk {
+    pub text: String,
+    pub finish_reason: Option<String>,
+    pub usage: Option<Usage>,
+}
+
+pub type CompletionStream = BoxStream<'static, Result<CompletionChunk, GatewayError>>;
+
+/// The clients of the gateway, shared by the requests in flight.
+#[derive(Clone, Default)]
+pub struct Backends {
+    pub ollama: Option<Arc<OllamaClient>>,
+    pub claude: Option<Arc<ClaudeClient>>,
+    pub mistral: Option<Arc<MistralClient>>,
+}
+
+impl Backends {
+    /// Translates a request for `route` into a call of its client, and streams the completion.
+    ///
+    /// The call is made when the stream is first polled, so an error of the backend is the
+    /// first item of the stream.
+    pub fn complete(
+        &self,
+        route: &ModelRoute,
+        params: &ChatCompletionParams,
+    ) -> Result<CompletionStream, GatewayError> {
+        params.check()?;
+        match route.backend {
+            Backend::Ollama => {
+                let client = self.ollama.clone().ok_or(GatewayError::NotConfigured("ollama"))?;
+                ollama_completion(client, route, params)
+            }
+            Backend::Claude => {
+                let client = self.claude.clone().ok_or(GatewayError::NotConfigured("claude"))?;
+                claude_completion(client, route, params)
+            }
+            Backend::Mistral => {
+                let client = self.mistral.clone().ok_or(GatewayError::NotConfigured("mistral"))?;
+                mistral_completion(client, route, params)
+            }
+        }
+    }
+}
+
+fn usage(prompt_tokens: u32, completion_tokens: u32) -> Usage {
+    Usage {
+        prompt_tokens,
+        completion_tokens,
+        total_tokens: prompt_tokens + completion_tokens,
+    }
+}
+
+fn ollama_completion(
+    client: Arc<OllamaClient>,
+    route: &ModelRoute,
+    params: &ChatCompletionParams,
+) -> Result<CompletionStream, GatewayError> {
+    let messages = params
+        .messages
+        .iter()
+        .map(|message| {
+            let role = message.role()?.as_str();
+            Ok(OllamaMessage::new(role.to_string(), message.text()?))
+        })
+        .collect::<Result<Vec<_>, GatewayError>>()?;
+
+    let mut options = Map::new();
+    if let Some(temperature) = params.temperature {
+        options.insert("temperature".to_string(), temperature.into());
+    }
+    if let Some(top_p) = params.top_p {
+        options.insert("top_p".to_string(), top_p.into());
+    }
+    if let Some(max_tokens) = params.max_tokens().or(route.max_tokens) {
+        options.insert("num_predict".to_string(), max_tokens.into());
+    }
+    let stop = params.stop();
+    if !stop.is_empty() {
+        options.insert("stop".to_string(), stop.into());
+    }
+
+    let request = ChatRequestBuilder::new(route.model.clone())
+        .messages(messages)
+        .options(Value::Object(options))
+        .build();
+
+    Ok(Box::pin(try_stream! {
+        let stream = client.chat(request).await?;
+        let mut stream = Box::pin(stream.into_stream());
+        while let Some(response) = stream.try_next().await? {
+            let finish_reason = response.done.then(|| {
+                match response.done_reason.as_deref() {
+                    Some("length") => "length",
+                    _ => "stop",
+                }
+                .to_string()
+            });
+            let usage = response.done.then(|| {
+                usage(
+                    response.prompt_eval_count.unwrap_or(0),
+                    response.eval_count.unwrap_or(0),
+                )
+            });
+            yield CompletionChunk {
+                text: response.message.content,
+                finish_reason,
+                usage,
+            };
+        }
+    }))
+}
+
+/// The OpenAI finish reason of a Claude stop reason.
+fn claude_finish_reason(stop_reason: &str) -> &'static str {
+    match stop_reason {
+        "max_tokens" => "length",
+        "guardrail_intervened" => "content_filter",
+        _ => "stop",
+    }
+}
+
+fn claude_completion(
+    client: Arc<ClaudeClient>,
+    route: &ModelRoute,
+    params: &ChatCompletionParams,
+) -> Result<CompletionStream, GatewayError> {
+    // Claude takes the system prompt apart from the conversation.
+    let mut system = Vec::new();
+    let mut messages = Vec::new();
+    for message in &params.messages {
+        match message.role()? {
+            Role::System => system.push(message.text()?),
+            Role::User => messages.push(ClaudeMessage::new_user_message(message.text()?)),
+            Role::Assistant => {
+                messages.push(ClaudeMessage::new_assistant_message(message.text()?))
+            }
+        }
+    }
+
+    let max_tokens = params
+        .max_tokens()
+        .or(route.max_tokens)
+        .unwrap_or(DEFAULT_MAX_TOKENS);
+    let request = ConversationRequest {
+        system: (!system.is_empty()).then(|| system.join("\n\n")),
+        messages,
+        max_tokens: Some(max_tokens as i32),
+        ..ConversationRequest::default()
+    };
+    let mut options = ChatOptions::default()
+        .with_model_id(route.model.clone())
+        .with_max_tokens(max_tokens)
+        .with_stop_sequences(params.stop());
+    // The sampling parameters left out of the request are left to Claude.
+    options.temperature = params.temperature;
+    options.top_p = params.top_p;
+
+    Ok(Box::pin(try_stream! {
+        let stream = client.chat_with_stream(&request, &options).await?;
+        let mut stream = Box::pin(stream);
+        let mut prompt_tokens = 0;
+        let mut completion_tokens = 0;
+        let mut finish_reason = None;
+        while let Some(event) = stream.try_next().await? {
+            match event {
+                StreamResultData::MessageStart(message_start) => {
+                    prompt_tokens = message_start.message.usage.input_tokens.max(0) as u32;
+                }
+                StreamResultData::ContentBlockDelta(ContentBlockDelta {
+                    delta: Delta::Text { text },
+                    ..
+                }) => {
+                    yield CompletionChunk {
+                        text,
+                        ..CompletionChunk::default()
+                    };
+                }
+                StreamResultData::MessageDelta(message_delta) => {
+                    completion_tokens = message_delta.usage.output_tokens.max(0) as u32;
+                    finish_reason = Some(claude_finish_reason(&message_delta.delta.stop_reason));
+                }
+                StreamResultData::MessageStop(message_stop) => {
+                    // The invocation metrics are the counts Bedrock bills.
+                    let metrics = &message_stop.invocation_metrics;
+                    prompt_tokens = metrics.input_token_count.max(0) as u32;
+                    completion_tokens = metrics.output_token_count.max(0) as u32;
+                }
+                _ => {}
+            }
+        }
+        yield CompletionChunk {
+            text: String::new(),
+            finish_reason: Some(finish_reason.unwrap_or("stop").to_string()),
+            usage: Some(usage(prompt_tokens, completion_tokens)),
+        };
+    }))
+}
+
+/// Renders a conversation in the instruction format of the Mistral models.
+pub fn mistral_prompt(messages: &[IncomingMessage]) -> Result<String, GatewayError> {
+    let messages = messages
+        .iter()
+        .map(|message| {
+            let role = message.role()?.as_str();
+            Ok(MistralMessage::new(role, message.text()?))
+        })
+        .collect::<Result<Vec<_>, GatewayError>>()?;
+    Ok(instruct_prompt(&messages))
+}
+
+fn mistral_completion(
+    client: Arc<MistralClient>,
+    route: &ModelRoute,
+    params: &ChatCompletionParams,
+) -> Result<CompletionStream, GatewayError> {
+    let prompt = mistral_prompt(&params.messages)?;
+    let mut builder = MistralRequestBuilder::new(prompt);
+    if let Some(max_tokens) = params.max_tokens().or(route.max_tokens) {
+        builder = builder.max_tokens(max_tokens);
+    }
+    if let Some(temperature) = params.temperature {
+        builder = builder.temperature(temperature);
+    }
+    if let Some(top_p) = params.top_p {
+        builder = builder.top_p(top_p);
+    }
+    let stop = params.stop();
+    if !stop.is_empty() {
+        builder = builder.stop(stop);
+    }
+    let request = builder.build();
+    let model_id = route.model.clone();
+
+    Ok(Box::pin(try_stream! {
+        let stream = client.generate_with_stream(model_id, &request).await?;
+        let mut stream = Box::pin(stream);
+        // The responses carry no token counts: estimate them from the text.
+        let tokenizer = HeuristicTokenizer::new();
+        let mut completion = String::new();
+        let mut finish_reason = None;
+        while let Some(response) = stream.try_next().await? {
+            for output in response.outputs {
+                completion.push_str(&output.text);
+                if let Some(stop_reason) = output.stop_reason {
+                    finish_reason = Some(stop_reason);
+                }
+                yield CompletionChunk {
+                    text: output.text,
+                    ..CompletionChunk::default()
+                };
+            }
+        }
+        let finish_reason = match finish_reason.as_deref() {
+            Some("length") => "length",
+            _ => "stop",
+        };
+        yield CompletionChunk {
+            text: String::new(),
+            finish_reason: Some(finish_reason.to_string()),
+            usage: Some(usage(
+                tokenizer.count_tokens(&request.prompt) as u32,
+                tokenizer.count_tokens(&completion) as u32,
+            )),
+        };
+    }))
+}
+
+/// A completion collected from a stream.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Completion {
+    pub text: String,
+    pub finish_reason: Option<String>,
+    pub usage: Option<Usage>,
+}
+
+/// Collects a completion stream, for a request that is not streamed.
+pub async fn collect(stream: CompletionStream) -> Result<Completion, GatewayError> {
+    stream
+        .try_fold(Completion::default(), |mut completion, chunk| async move {
+            completion.text.push_str(&chunk.text);
+            completion.finish_reason = chunk.finish_reason.or(completion.finish_reason);
+            completion.usage = chunk.usage.or(completion.usage);
+            Ok(completion)
+        })
+        .await
+}
+
+/// Estimates the tokens of the texts, for the backends that report no usage.
+pub(crate) fn estimate_tokens<'a, I: IntoIterator<Item = &'a str>>(texts: I) -> u32 {
+    let tokenizer = HeuristicTokenizer::new();
+    texts
+        .into_iter()
+        .map(|text| tokenizer.count_tokens(text) as u32)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn params(value: Value) -> ChatCompletionParams {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_params_are_lenient() {
+        let params = params(json!({
+            "model": "gpt-4o-mini",
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "Hello, "},
+                    {"type": "text", "text": "world"}
+                ]}
+            ],
+            "stop": "\n",
+            "max_completion_tokens": 64,
+            "max_tokens": 32,
+            "stream_options": {"include_usage": true},
+            "logit_bias": {}
+        }));
+
+        assert_eq!(params.messages[1].text().unwrap(), "Hello, world");
+        assert_eq!(params.stop(), vec!["\n".to_string()]);
+        assert_eq!(params.max_tokens(), Some(64));
+        assert!(params.include_usage());
+        assert!(!params.stream);
+        assert!(params.check().is_ok());
+    }
+
+    #[test]
+    fn test_unsupported_requests() {
+        let with_tools = params(json!({
+            "model": "gpt-4o-mini",
+            "messages": [{"role": "user", "content": "Hi"}],
+            "tools": [{"type": "function", "function": {"name": "f", "parameters": {}}}]
+        }));
+        assert!(matches!(with_tools.check(), Err(GatewayError::Unsupported(_))));
+
+        let with_image = params(json!({
+            "model": "gpt-4o-mini",
+            "messages": [{"role": "user", "content": [
+                {"type": "image_url", "image_url": {"url": "https://example.com/cat.png"}}
+            ]}]
+        }));
+        assert!(matches!(
+            with_image.messages[0].text(),
+            Err(GatewayError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_mistral_prompt() {
+        let params = params(json!({
+            "model": "mistral",
+            "messages": [
+                {"role": "system", "content": "Answer with a number."},
+                {"role": "user", "content": "What is 2+2?"},
+                {"role": "assistant", "content": "4"},
+                {"role": "user", "content": "And 3+3?"}
+            ]
+        }));
+
+        assert_eq!(
+            mistral_prompt(&params.messages).unwrap(),
+            "<s>[INST] Answer with a number.\n\nWhat is 2+2? [/INST] 4</s>[INST] And 3+3? [/INST]"
+        );
+    }
+}
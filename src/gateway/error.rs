@@ -0,0 +1,103 @@
+use serde_json::Error as SerdeJsonError;
+use thiserror::Error;
+
+use crate::bedrock::error::BedrockError;
+use crate::bedrock::models::claude::error::ClaudeError;
+use crate::bedrock::models::mistral::error::MistralError;
+use crate::ollama::error::OllamaError;
+
+#[derive(Error, Debug)]
+pub enum GatewayError {
+    #[error("JSON error: {0}")]
+    Json(#[from] SerdeJsonError),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("HTTP error: {0}")]
+    Http(String),
+
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Unknown model: {0}")]
+    UnknownModel(String),
+
+    #[error("Unsupported: {0}")]
+    Unsupported(String),
+
+    #[error("No client configured for the {0} backend")]
+    NotConfigured(&'static str),
+
+    #[error("Ollama error: {0}")]
+    Ollama(#[from] OllamaError),
+
+    #[error("Claude error: {0}")]
+    Claude(#[from] ClaudeError),
+
+    #[error("Mistral error: {0}")]
+    Mistral(#[from] MistralError),
+}
+
+impl GatewayError {
+    /// The HTTP status of the error response. The status of a backend error is kept when it
+    /// tells the caller something, e.g. 429 so that it backs off, else it is 502.
+    pub fn status(&self) -> u16 {
+        match self {
+            GatewayError::Json(_) | GatewayError::InvalidRequest(_) => 400,
+            GatewayError::Unsupported(_) => 400,
+            GatewayError::Unauthorized(_) => 401,
+            GatewayError::NotFound(_) | GatewayError::UnknownModel(_) => 404,
+            GatewayError::Ollama(err) => match err {
+                OllamaError::BadRequest(_) => 400,
+                OllamaError::NotFound(_) => 404,
+                OllamaError::TooManyRequests(_) => 429,
+                _ => 502,
+            },
+            GatewayError::Claude(ClaudeError::Aws(err))
+            | GatewayError::Mistral(MistralError::Bedrock(err)) => bedrock_status(err),
+            GatewayError::Claude(ClaudeError::Usage(_))
+            | GatewayError::Mistral(MistralError::Usage(_)) => 429,
+            _ => 502,
+        }
+    }
+
+    /// The OpenAI error type, e.g. `invalid_request_error`.
+    pub fn error_type(&self) -> &'static str {
+        match self.status() {
+            400 | 404 => "invalid_request_error",
+            401 => "authentication_error",
+            429 => "rate_limit_error",
+            _ => "api_error",
+        }
+    }
+
+    /// The code of the error body, e.g. `model_not_found`.
+    pub fn code(&self) -> Option<&'static str> {
+        match self {
+            GatewayError::UnknownModel(_) => Some("model_not_found"),
+            GatewayError::Unauthorized(_) => Some("invalid_api_key"),
+            _ => None,
+        }
+    }
+}
+
+/// The status of the Bedrock response, for the errors the client can act on.
+fn bedrock_status(err: &BedrockError) -> u16 {
+    let status = match err {
+        BedrockError::AwsSdkErrorInvoke(err) => err.raw_response().map(|r| r.status().as_u16()),
+        BedrockError::AwsSdkErrorInvokeModel(err) => {
+            err.raw_response().map(|r| r.status().as_u16())
+        }
+        _ => None,
+    };
+    status
+        .filter(|status| matches!(status, 400 | 403 | 404 | 429))
+        .unwrap_or(502)
+}
@@ -0,0 +1,15 @@
+//! An OpenAI-compatible HTTP gateway in front of the hiramu clients.
+//!
+//! Tools that speak the OpenAI API can use Ollama and Bedrock models through the gateway:
+//! each model name is routed by a `RoutingTable` to the `OllamaClient`, `ClaudeClient` or
+//! `MistralClient` that serves it. The gateway requires the `gateway` feature.
+
+pub mod completion;
+pub mod error;
+pub mod routing;
+pub mod server;
+
+pub use completion::{ChatCompletionParams, CompletionChunk, CompletionStream};
+pub use error::GatewayError;
+pub use routing::{Backend, ModelRoute, RoutingTable};
+pub use server::{Gateway, GatewayServer};
@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::gateway::error::GatewayError;
+
+/// The hiramu client that serves a route.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Ollama,
+    Claude,
+    Mistral,
+}
+
+impl Backend {
+    /// The name of the backend, reported as the `owned_by` of a model.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Backend::Ollama => "ollama",
+            Backend::Claude => "claude",
+            Backend::Mistral => "mistral",
+        }
+    }
+}
+
+/// Where the requests for a public model name go.
+///
+/// # Fields
+///
+/// * `backend` - The client that serves the requests.
+/// * `model` - The model of the backend, e.g. `llama3` or a Bedrock model id.
+/// * `max_tokens` - The completion limit when the request sets none. Claude requires one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelRoute {
+    pub backend: Backend,
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+}
+
+impl ModelRoute {
+    pub fn new<S: Into<String>>(backend: Backend, model: S) -> Self {
+        Self {
+            backend,
+            model: model.into(),
+            max_tokens: None,
+        }
+    }
+
+    pub fn ollama<S: Into<String>>(model: S) -> Self {
+        Self::new(Backend::Ollama, model)
+    }
+
+    pub fn claude<S: Into<String>>(model_id: S) -> Self {
+        Self::new(Backend::Claude, model_id)
+    }
+
+    pub fn mistral<S: Into<String>>(model_id: S) -> Self {
+        Self::new(Backend::Mistral, model_id)
+    }
+
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+}
+
+/// Maps the model names of the OpenAI requests to the backends that serve them.
+///
+/// The table serializes as a JSON object keyed by model name, so that it can be kept in a
+/// configuration file:
+///
+/// ```json
+/// {
+///   "gpt-4o-mini": { "backend": "ollama", "model": "llama3" },
+///   "claude-3-haiku": { "backend": "claude", "model": "anthropic.claude-3-haiku-20240307-v1:0" }
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct RoutingTable {
+    routes: BTreeMap<String, ModelRoute>,
+}
+
+impl RoutingTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Routes the requests for `name` to `route`, replacing any previous route.
+    pub fn route<S: Into<String>>(mut self, name: S, route: ModelRoute) -> Self {
+        self.routes.insert(name.into(), route);
+        self
+    }
+
+    /// Loads a table from a JSON file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, GatewayError> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// The route of `name`, or an `UnknownModel` error.
+    pub fn resolve(&self, name: &str) -> Result<&ModelRoute, GatewayError> {
+        self.routes
+            .get(name)
+            .ok_or_else(|| GatewayError::UnknownModel(name.to_string()))
+    }
+
+    /// The routes, by model name.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &ModelRoute)> {
+        self.routes.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routing_table_from_json() {
+        let json = r#"{
+            "gpt-4o-mini": { "backend": "ollama", "model": "llama3" },
+            "claude": { "backend": "claude", "model": "anthropic.claude-3-haiku-20240307-v1:0", "max_tokens": 512 }
+        }"#;
+        let table: RoutingTable = serde_json::from_str(json).unwrap();
+
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.resolve("gpt-4o-mini").unwrap(), &ModelRoute::ollama("llama3"));
+        assert_eq!(
+            table.resolve("claude").unwrap(),
+            &ModelRoute::claude("anthropic.claude-3-haiku-20240307-v1:0").max_tokens(512)
+        );
+        assert!(matches!(
+            table.resolve("gpt-4"),
+            Err(GatewayError::UnknownModel(name)) if name == "gpt-4"
+        ));
+
+        let round_trip: RoutingTable =
+            serde_json::from_str(&serde_json::to_string(&table).unwrap()).unwrap();
+        assert_eq!(round_trip, table);
+    }
+}
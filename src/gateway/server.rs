@@ -0,0 +1,653 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use futures::stream::{self, Stream, StreamExt};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::oneshot;
+
+use crate::bedrock::models::claude::ClaudeClient;
+use crate::bedrock::models::mistral::MistralClient;
+use crate::gateway::completion::{
+    collect, estimate_tokens, Backends, ChatCompletionParams, CompletionStream,
+};
+use crate::gateway::error::GatewayError;
+use crate::gateway::routing::{Backend, RoutingTable};
+use crate::ollama::{EmbeddingsRequestBuilder, OllamaClient};
+use crate::openai::model::{
+    ChatCompletionChunk, ChatCompletionResponse, Choice, ChunkChoice, Delta, Embedding,
+    EmbeddingsInput, EmbeddingsResponse, EmbeddingsUsage, Message,
+};
+
+/// An OpenAI-compatible HTTP server backed by the hiramu clients.
+///
+/// The gateway serves `POST /v1/chat/completions`, streamed with server-sent events when the
+/// request sets `stream`, `POST /v1/embeddings` and `GET /v1/models`. The model of each request
+/// is looked up in a `RoutingTable`, and the request is translated into a call of the
+/// `OllamaClient`, `ClaudeClient` or `MistralClient` of its route.
+///
+/// ```no_run
+/// use hiramu::gateway::{Gateway, ModelRoute, RoutingTable};
+/// use hiramu::ollama::OllamaClient;
+///
+/// # async fn run() -> Result<(), hiramu::gateway::GatewayError> {
+/// let routes = RoutingTable::new().route("gpt-4o-mini", ModelRoute::ollama("llama3"));
+/// let server = Gateway::new(routes)
+///     .ollama(OllamaClient::new("http://localhost:11434".to_string()))
+///     .api_key("secret")
+///     .bind("127.0.0.1:8080".parse().unwrap())
+///     .await?;
+/// println!("Serving on {}", server.base_url());
+/// # Ok(())
+/// # }
+/// ```
+pub struct Gateway {
+    routes: RoutingTable,
+    backends: Backends,
+    api_keys: Vec<String>,
+}
+
+impl Gateway {
+    pub fn new(routes: RoutingTable) -> Self {
+        Self {
+            routes,
+            backends: Backends::default(),
+            api_keys: Vec::new(),
+        }
+    }
+
+    /// Serves the routes of the `ollama` backend.
+    pub fn ollama(mut self, client: OllamaClient) -> Self {
+        self.backends.ollama = Some(Arc::new(client));
+        self
+    }
+
+    /// Serves the routes of the `claude` backend.
+    pub fn claude(mut self, client: ClaudeClient) -> Self {
+        self.backends.claude = Some(Arc::new(client));
+        self
+    }
+
+    /// Serves the routes of the `mistral` backend.
+    pub fn mistral(mut self, client: MistralClient) -> Self {
+        self.backends.mistral = Some(Arc::new(client));
+        self
+    }
+
+    /// Requires the requests to send `Authorization: Bearer <key>` with one of the keys set.
+    /// Without a key, the gateway is open.
+    pub fn api_key<S: Into<String>>(mut self, key: S) -> Self {
+        self.api_keys.push(key.into());
+        self
+    }
+
+    /// Starts serving on `address` in a background task, until the returned server is dropped.
+    /// Bind to port 0 to get a free port.
+    pub async fn bind(self, address: SocketAddr) -> Result<GatewayServer, GatewayError> {
+        let gateway = Arc::new(self);
+        let make_service = make_service_fn(move |_| {
+            let gateway = gateway.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let gateway = gateway.clone();
+                    async move { Ok::<_, Infallible>(gateway.handle(request).await) }
+                }))
+            }
+        });
+
+        let server = Server::try_bind(&address)
+            .map_err(|err| GatewayError::Http(err.to_string()))?
+            .serve(make_service);
+        let address = server.local_addr();
+
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            stopped.await.ok();
+        }));
+
+        Ok(GatewayServer {
+            address,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    /// Serves on `address` until `shutdown` completes, e.g. on `tokio::signal::ctrl_c()`.
+    pub async fn serve<F>(self, address: SocketAddr, shutdown: F) -> Result<(), GatewayError>
+    where
+        F: Future<Output = ()>,
+    {
+        let _server = self.bind(address).await?;
+        shutdown.await;
+        Ok(())
+    }
+
+    /// Answers a request, with an error in the OpenAI format if it fails.
+    pub async fn handle(&self, request: Request<Body>) -> Response<Body> {
+        match self.dispatch(request).await {
+            Ok(response) => response,
+            Err(err) => error_response(&err),
+        }
+    }
+
+    async fn dispatch(&self, request: Request<Body>) -> Result<Response<Body>, GatewayError> {
+        self.authorize(&request)?;
+
+        let method = request.method().clone();
+        let path = request.uri().path().trim_end_matches('/').to_string();
+        match (&method, path.as_str()) {
+            (&Method::POST, "/v1/chat/completions") => {
+                let params: ChatCompletionParams = read_json(request).await?;
+                self.chat_completions(params).await
+            }
+            (&Method::POST, "/v1/embeddings") => {
+                let params: EmbeddingsParams = read_json(request).await?;
+                self.embeddings(params).await
+            }
+            (&Method::GET, "/v1/models") => Ok(json_response(200, &self.models())),
+            (&Method::GET, path) if path.starts_with("/v1/models/") => {
+                let name = &path["/v1/models/".len()..];
+                let route = self.routes.resolve(name)?;
+                Ok(json_response(200, &model_object(name, route.backend)))
+            }
+            (_, "/v1/chat/completions" | "/v1/embeddings" | "/v1/models") => Err(
+                GatewayError::InvalidRequest(format!("method {} is not allowed on {}", method, path)),
+            ),
+            _ => Err(GatewayError::NotFound(format!("unknown URL {}", path))),
+        }
+    }
+
+    fn authorize(&self, request: &Request<Body>) -> Result<(), GatewayError> {
+        if self.api_keys.is_empty() {
+            return Ok(());
+        }
+        let key = request
+            .headers()
+            .get(hyper::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| GatewayError::Unauthorized("missing bearer token".to_string()))?;
+        if self.api_keys.iter().any(|api_key| api_key == key) {
+            Ok(())
+        } else {
+            Err(GatewayError::Unauthorized("incorrect API key".to_string()))
+        }
+    }
+
+    async fn chat_completions(
+        &self,
+        params: ChatCompletionParams,
+    ) -> Result<Response<Body>, GatewayError> {
+        let route = self.routes.resolve(&params.model)?;
+        let mut completion = self.backends.complete(route, &params)?;
+
+        // Wait for the first chunk, so that a failed backend call is answered with an error
+        // status rather than an empty stream.
+        let first = completion.next().await.transpose()?;
+        let completion: CompletionStream = Box::pin(stream::iter(first.map(Ok)).chain(completion));
+
+        let id = completion_id();
+        let created = now();
+        if params.stream {
+            let events = sse_events(id, created, params.model.clone(), params.include_usage(), completion);
+            return Ok(Response::builder()
+                .status(200)
+                .header(hyper::header::CONTENT_TYPE, "text/event-stream")
+                .header(hyper::header::CACHE_CONTROL, "no-cache")
+                .body(Body::wrap_stream(events))
+                .unwrap());
+        }
+
+        let completion = collect(completion).await?;
+        let response = ChatCompletionResponse {
+            id,
+            object: "chat.completion".to_string(),
+            created,
+            model: params.model,
+            choices: vec![Choice {
+                index: 0,
+                message: Message::assistant(completion.text),
+                finish_reason: Some(completion.finish_reason.unwrap_or_else(|| "stop".to_string())),
+            }],
+            usage: completion.usage,
+            system_fingerprint: None,
+        };
+        Ok(json_response(200, &response))
+    }
+
+    async fn embeddings(&self, params: EmbeddingsParams) -> Result<Response<Body>, GatewayError> {
+        let route = self.routes.resolve(&params.model)?;
+        if route.backend != Backend::Ollama {
+            return Err(GatewayError::Unsupported(format!(
+                "embeddings with the {} backend",
+                route.backend.as_str()
+            )));
+        }
+        if params.encoding_format.as_deref().is_some_and(|format| format != "float") {
+            return Err(GatewayError::Unsupported(
+                "encoding formats other than `float`".to_string(),
+            ));
+        }
+        let client = self
+            .backends
+            .ollama
+            .as_ref()
+            .ok_or(GatewayError::NotConfigured("ollama"))?;
+
+        let inputs = match params.input {
+            EmbeddingsInput::Text(text) => vec![text],
+            EmbeddingsInput::Batch(texts) => texts,
+        };
+        // Ollama embeds one prompt per call.
+        let mut data = Vec::with_capacity(inputs.len());
+        for (index, input) in inputs.iter().enumerate() {
+            let request = EmbeddingsRequestBuilder::new(route.model.clone(), input.clone()).build();
+            let response = client.embeddings(request).await?;
+            data.push(Embedding {
+                index: index as u32,
+                embedding: response.embedding,
+            });
+        }
+
+        let prompt_tokens = estimate_tokens(inputs.iter().map(String::as_str));
+        let response = EmbeddingsResponse {
+            data,
+            model: params.model,
+            usage: Some(EmbeddingsUsage {
+                prompt_tokens,
+                total_tokens: prompt_tokens,
+            }),
+        };
+        Ok(json_response(200, &response))
+    }
+
+    fn models(&self) -> Value {
+        let data: Vec<Value> = self
+            .routes
+            .iter()
+            .map(|(name, route)| model_object(name, route.backend))
+            .collect();
+        json!({ "object": "list", "data": data })
+    }
+}
+
+/// A running `Gateway`, which stops when dropped.
+pub struct GatewayServer {
+    address: SocketAddr,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl GatewayServer {
+    /// The base URL of the OpenAI API of the gateway, e.g. `http://127.0.0.1:8080/v1`.
+    pub fn base_url(&self) -> String {
+        format!("http://{}/v1", self.address)
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+impl Drop for GatewayServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+/// The fields of an OpenAI embeddings request that the gateway translates.
+#[derive(Debug, Clone, Deserialize)]
+struct EmbeddingsParams {
+    model: String,
+    input: EmbeddingsInput,
+    #[serde(default)]
+    encoding_format: Option<String>,
+}
+
+async fn read_json<T: for<'de> Deserialize<'de>>(request: Request<Body>) -> Result<T, GatewayError> {
+    let body = hyper::body::to_bytes(request.into_body())
+        .await
+        .map_err(|err| GatewayError::Http(err.to_string()))?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+fn json_response<T: Serialize>(status: u16, body: &T) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_vec(body).unwrap_or_default()))
+        .unwrap()
+}
+
+/// The body of an error in the OpenAI format.
+fn error_body(err: &GatewayError) -> Value {
+    json!({
+        "error": {
+            "message": err.to_string(),
+            "type": err.error_type(),
+            "param": null,
+            "code": err.code(),
+        }
+    })
+}
+
+fn error_response(err: &GatewayError) -> Response<Body> {
+    json_response(err.status(), &error_body(err))
+}
+
+fn model_object(name: &str, backend: Backend) -> Value {
+    json!({
+        "id": name,
+        "object": "model",
+        "created": 0,
+        "owned_by": backend.as_str(),
+    })
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// A unique id for a completion, e.g. `chatcmpl-18c3f0a1b2c4d000-1`.
+fn completion_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or(0);
+    format!("chatcmpl-{:x}-{}", nanos, COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+fn sse_event<T: Serialize>(data: &T) -> Bytes {
+    let data = serde_json::to_string(data).unwrap_or_default();
+    Bytes::from(format!("data: {}\n\n", data))
+}
+
+/// Renders a completion as the server-sent events of an OpenAI stream: a chunk with the role,
+/// the content chunks, a chunk with the finish reason, a chunk with the usage if requested,
+/// and `[DONE]`. An error of the backend ends the stream with an error event.
+fn sse_events(
+    id: String,
+    created: u64,
+    model: String,
+    include_usage: bool,
+    mut completion: CompletionStream,
+) -> impl Stream<Item = Result<Bytes, Infallible>> + Send + 'static {
+    let chunk = move |delta: Delta, finish_reason: Option<String>| ChatCompletionChunk {
+        id: id.clone(),
+        object: "chat.completion.chunk".to_string(),
+        created,
+        model: model.clone(),
+        choices: vec![ChunkChoice {
+            index: 0,
+            delta,
+            finish_reason,
+        }],
+        usage: None,
+    };
+
+    async_stream::stream! {
+        yield Ok(sse_event(&chunk(
+            Delta {
+                role: Some("assistant".to_string()),
+                content: Some(String::new()),
+                ..Delta::default()
+            },
+            None,
+        )));
+
+        let mut finish_reason = None;
+        let mut usage = None;
+        while let Some(piece) = completion.next().await {
+            let piece = match piece {
+                Ok(piece) => piece,
+                Err(err) => {
+                    yield Ok(sse_event(&error_body(&err)));
+                    return;
+                }
+            };
+            if !piece.text.is_empty() {
+                yield Ok(sse_event(&chunk(
+                    Delta {
+                        content: Some(piece.text),
+                        ..Delta::default()
+                    },
+                    None,
+                )));
+            }
+            finish_reason = piece.finish_reason.or(finish_reason);
+            usage = piece.usage.or(usage);
+        }
+
+        yield Ok(sse_event(&chunk(
+            Delta::default(),
+            Some(finish_reason.unwrap_or_else(|| "stop".to_string())),
+        )));
+        if include_usage {
+            let mut last = chunk(Delta::default(), None);
+            last.choices.clear();
+            last.usage = Some(usage.unwrap_or_default());
+            yield Ok(sse_event(&last));
+        }
+        yield Ok(Bytes::from_static(b"data: [DONE]\n\n"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gateway::routing::ModelRoute;
+    use crate::openai::model::{ChatCompletionAccumulator, ChatCompletionRequestBuilder};
+    use crate::openai::{OpenAiClient, OpenAiError};
+    use crate::testing::{
+        BedrockException, BedrockStubServer, MockEndpoint, MockOllamaServer, MockResponse,
+        StubResponse,
+    };
+    use futures::TryStreamExt;
+
+    const CLAUDE_MODEL_ID: &str = "anthropic.claude-3-haiku-20240307-v1:0";
+    const MISTRAL_MODEL_ID: &str = "mistral.mistral-7b-instruct-v0:2";
+
+    fn routes() -> RoutingTable {
+        RoutingTable::new()
+            .route("gpt-4o-mini", ModelRoute::ollama("llama3"))
+            .route("claude", ModelRoute::claude(CLAUDE_MODEL_ID))
+            .route("mistral", ModelRoute::mistral(MISTRAL_MODEL_ID))
+    }
+
+    async fn start(gateway: Gateway) -> GatewayServer {
+        gateway
+            .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .await
+            .unwrap()
+    }
+
+    fn request(model: &str) -> crate::openai::model::ChatCompletionRequest {
+        ChatCompletionRequestBuilder::new(model)
+            .add_message(Message::system("Be brief."))
+            .add_message(Message::user("What is the capital of France?"))
+            .max_tokens(32)
+            .include_usage()
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_chat_with_ollama() {
+        let ollama = MockOllamaServer::start().await.unwrap();
+        ollama.enqueue(MockEndpoint::Chat, MockResponse::text(["Paris", "."]));
+        ollama.enqueue(MockEndpoint::Chat, MockResponse::text(["Paris", " is", " nice."]));
+        let gateway =
+            start(Gateway::new(routes()).ollama(OllamaClient::new(ollama.base_url()))).await;
+        let client = OpenAiClient::new(gateway.base_url());
+
+        let response = client.chat(request("gpt-4o-mini")).await.unwrap();
+        assert_eq!(response.text(), Some("Paris."));
+        assert_eq!(response.model, "gpt-4o-mini");
+        assert_eq!(response.choices[0].finish_reason.as_deref(), Some("stop"));
+        assert_eq!(response.usage.unwrap().completion_tokens, 2);
+
+        let sent = &ollama.requests_to(MockEndpoint::Chat)[0].body;
+        assert_eq!(sent["model"], "llama3");
+        assert_eq!(sent["messages"][0]["role"], "system");
+        assert_eq!(sent["options"]["num_predict"], 32);
+
+        let chunks: Vec<_> = client
+            .chat_stream(request("gpt-4o-mini"))
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(chunks[0].choices[0].delta.role.as_deref(), Some("assistant"));
+        let mut accumulator = ChatCompletionAccumulator::new();
+        for chunk in &chunks {
+            accumulator.push(chunk);
+        }
+        let response = accumulator.finish();
+        assert_eq!(response.text(), Some("Paris is nice."));
+        assert_eq!(response.choices[0].finish_reason.as_deref(), Some("stop"));
+        assert_eq!(response.usage.unwrap().completion_tokens, 3);
+    }
+
+    #[tokio::test]
+    async fn test_chat_with_claude_and_mistral() {
+        let bedrock = BedrockStubServer::start().await.unwrap();
+        bedrock.enqueue(StubResponse::text(["Paris", " is", " the", " capital."]));
+        bedrock.enqueue(StubResponse::text(["Paris", "."]));
+        let claude = ClaudeClient::new(bedrock.client_options()).await.unwrap();
+        let mistral = MistralClient::new(bedrock.client_options()).await.unwrap();
+        let gateway = start(Gateway::new(routes()).claude(claude).mistral(mistral)).await;
+        let client = OpenAiClient::new(gateway.base_url());
+
+        let chunks: Vec<_> = client
+            .chat_stream(request("claude"))
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        let mut accumulator = ChatCompletionAccumulator::new();
+        for chunk in &chunks {
+            accumulator.push(chunk);
+        }
+        let response = accumulator.finish();
+        assert_eq!(response.text(), Some("Paris is the capital."));
+        assert_eq!(response.usage.unwrap().completion_tokens, 4);
+
+        let response = client.chat(request("mistral")).await.unwrap();
+        assert_eq!(response.text(), Some("Paris."));
+
+        let requests = bedrock.requests();
+        assert_eq!(requests[0].model_id, CLAUDE_MODEL_ID);
+        assert_eq!(requests[0].body["system"], "Be brief.");
+        assert_eq!(requests[0].body["max_tokens"], 32);
+        assert_eq!(requests[1].model_id, MISTRAL_MODEL_ID);
+        assert_eq!(
+            requests[1].body["prompt"],
+            "<s>[INST] Be brief.\n\nWhat is the capital of France? [/INST]"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_claude_sampling_parameters() {
+        let bedrock = BedrockStubServer::start().await.unwrap();
+        let claude = ClaudeClient::new(bedrock.client_options()).await.unwrap();
+        let gateway = start(Gateway::new(routes()).claude(claude)).await;
+        let client = OpenAiClient::new(gateway.base_url());
+        let request = ChatCompletionRequestBuilder::new("claude")
+            .add_message(Message::user("What is the capital of France?"))
+            .temperature(0.25)
+            .top_p(0.5)
+            .stop(vec!["\n\n".to_string()])
+            .build();
+
+        client.chat(request).await.unwrap();
+
+        let body = &bedrock.requests()[0].body;
+        assert_eq!(body["temperature"], 0.25);
+        assert_eq!(body["top_p"], 0.5);
+        assert_eq!(body["stop_sequences"], json!(["\n\n"]));
+
+        let request = ChatCompletionRequestBuilder::new("claude")
+            .add_message(Message::user("What is the capital of France?"))
+            .build();
+        client.chat(request).await.unwrap();
+
+        let body = &bedrock.requests()[1].body;
+        assert!(body.get("temperature").is_none());
+        assert!(body.get("top_p").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_errors() {
+        let bedrock = BedrockStubServer::start().await.unwrap();
+        bedrock.enqueue(StubResponse::error(BedrockException::Throttling, "Too many requests"));
+        let claude = ClaudeClient::new(bedrock.client_options()).await.unwrap();
+        let gateway = start(Gateway::new(routes()).claude(claude).api_key("secret")).await;
+
+        let anonymous = OpenAiClient::new(gateway.base_url());
+        let err = anonymous.chat(request("claude")).await.unwrap_err();
+        assert!(matches!(err, OpenAiError::Unauthorized(_)), "{:?}", err);
+
+        let client = OpenAiClient::new(gateway.base_url()).with_api_key("secret");
+        let err = client.chat(request("gpt-4")).await.unwrap_err();
+        assert!(matches!(err, OpenAiError::NotFound(_)), "{:?}", err);
+
+        let err = client.chat(request("claude")).await.unwrap_err();
+        assert!(matches!(err, OpenAiError::TooManyRequests(_)), "{:?}", err);
+
+        // The route exists, but the gateway has no Ollama client.
+        let err = client.chat(request("gpt-4o-mini")).await.unwrap_err();
+        assert!(matches!(err, OpenAiError::UnknownApiError(_)), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn test_models_and_embeddings() {
+        let ollama = MockOllamaServer::start().await.unwrap();
+        ollama.enqueue(MockEndpoint::Embeddings, MockResponse::embedding(vec![0.1, 0.2]));
+        ollama.enqueue(MockEndpoint::Embeddings, MockResponse::embedding(vec![0.3, 0.4]));
+        let gateway =
+            start(Gateway::new(routes()).ollama(OllamaClient::new(ollama.base_url()))).await;
+
+        let models: Value = reqwest::get(format!("{}/models", gateway.base_url()))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let owners: Vec<_> = models["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|model| (model["id"].as_str().unwrap(), model["owned_by"].as_str().unwrap()))
+            .collect();
+        assert_eq!(
+            owners,
+            vec![("claude", "claude"), ("gpt-4o-mini", "ollama"), ("mistral", "mistral")]
+        );
+
+        let client = OpenAiClient::new(gateway.base_url());
+        let request = crate::openai::model::EmbeddingsRequestBuilder::new(
+            "gpt-4o-mini",
+            vec!["first".to_string(), "second".to_string()],
+        )
+        .build();
+        let response = client.embeddings(request).await.unwrap();
+        assert_eq!(response.embeddings(), vec![vec![0.1, 0.2], vec![0.3, 0.4]]);
+
+        let request =
+            crate::openai::model::EmbeddingsRequestBuilder::new("claude", "first").build();
+        let err = client.embeddings(request).await.unwrap_err();
+        assert!(matches!(err, OpenAiError::BadRequest(_)), "{:?}", err);
+    }
+}
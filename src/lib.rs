@@ -3,9 +3,26 @@
 #![doc = include_str!("../README.md")]
 
 pub mod ollama;
+pub mod openai;
 pub mod bedrock;
+pub mod session;
 pub mod error;
 pub mod util;
+pub mod tokenizer;
+pub mod usage;
+pub mod telemetry;
+pub mod stream_metrics;
+pub mod cache;
+pub mod extract;
+pub mod agent;
+pub mod prompt;
+pub mod splitter;
+pub mod vector;
+pub mod rag;
+#[cfg(feature = "gateway")]
+pub mod gateway;
+#[cfg(feature = "testing")]
+pub mod testing;
 pub mod examples;
 
 pub use error::HiramuError;
@@ -1,6 +1,8 @@
 use serde_json::Error as SerdeJsonError;
 use thiserror::Error;
 
+use crate::usage::error::UsageError;
+
 
 
 #[derive(Error, Debug)]
@@ -49,4 +51,7 @@ pub enum OllamaError {
 
     #[error("Unknown error: {0}")]
     Unknown(String),
-}
\ No newline at end of file
+
+    #[error("Usage error: {0}")]
+    Usage(#[from] UsageError),
+}
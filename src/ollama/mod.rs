@@ -7,5 +7,6 @@ pub use error::OllamaError;
 pub use ollama_client::OllamaClient;
 pub use model::{ GenerateRequest, GenerateRequestBuilder, GenerateResponse };
 pub use model::{ ChatRequest, ChatRequestBuilder, ChatResponse, Message };
+pub use model::{ Tool, ToolCall, ToolCallFunction, ToolFunction };
 pub use model::{ EmbeddingsRequest,EmbeddingsResponse, EmbeddingsRequestBuilder};
 pub use options::OptionsBuilder;
\ No newline at end of file
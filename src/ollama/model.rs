@@ -40,6 +40,9 @@ pub struct GenerateResponse {
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub response: String,
     pub done: bool,
+    /// Why the generation stopped, e.g. `stop` or `length`, on the last response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub done_reason: Option<String>,
     pub context: Option<Vec<u32>>,
     pub total_duration: Option<u128>,
     pub load_duration: Option<u128>,
@@ -257,6 +260,50 @@ pub struct ChatRequest {
     pub stream: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub keep_alive: Option<String>,
+    /// The tools the model can call. Most models only call tools when not streaming.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<Tool>,
+}
+
+/// A function the model can call in a chat.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tool {
+    #[serde(rename = "type")]
+    pub tool_type: String,
+    pub function: ToolFunction,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolFunction {
+    pub name: String,
+    pub description: String,
+    /// The JSON Schema of the arguments.
+    pub parameters: Value,
+}
+
+impl Tool {
+    pub fn function<N: Into<String>, D: Into<String>>(name: N, description: D, parameters: Value) -> Self {
+        Self {
+            tool_type: "function".to_string(),
+            function: ToolFunction {
+                name: name.into(),
+                description: description.into(),
+                parameters,
+            },
+        }
+    }
+}
+
+/// A call of a tool by the model, in an assistant message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub function: ToolCallFunction,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCallFunction {
+    pub name: String,
+    pub arguments: Value,
 }
 
 // Represents a message in a chat session, containing the role and content.
@@ -266,6 +313,10 @@ pub struct Message {
     pub content: String,
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub images: Vec<String>,
+    /// The tools called by the model, in an assistant message. The results are sent back in
+    /// messages with the role `tool`.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub tool_calls: Vec<ToolCall>,
 }
 
 // A convenient method to create a new message.
@@ -275,6 +326,7 @@ impl Message {
             role,
             content,
             images: Vec::new(),
+            tool_calls: Vec::new(),
         }
     }
 }
@@ -295,6 +347,9 @@ pub struct ChatResponse {
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub message: Message,
     pub done: bool,
+    /// Why the generation stopped, e.g. `stop` or `length`, on the last response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub done_reason: Option<String>,
     pub total_duration: Option<u128>,
     pub load_duration: Option<u128>,
     pub prompt_eval_count: Option<u32>,
@@ -325,6 +380,7 @@ pub struct ChatRequestBuilder {
     stream: Option<bool>,
     keep_alive: Option<String>,
     options_builder: Option<OptionsBuilder>,
+    tools: Vec<Tool>,
 }
 
 impl ChatRequestBuilder {
@@ -338,6 +394,7 @@ impl ChatRequestBuilder {
             stream: None,
             keep_alive: None,
             options_builder: None,
+            tools: Vec::new(),
         }
     }
     
@@ -383,6 +440,11 @@ impl ChatRequestBuilder {
         self
     }
 
+    pub fn tools(mut self, tools: Vec<Tool>) -> Self {
+        self.tools = tools;
+        self
+    }
+
     pub fn build(self) -> ChatRequest {
 
         let options = self.options_builder.map(|builder| builder.build());
@@ -397,6 +459,7 @@ impl ChatRequestBuilder {
             template: self.template,
             stream: self.stream,
             keep_alive: self.keep_alive,
+            tools: self.tools,
         }
     }
 }
@@ -417,7 +480,7 @@ pub struct EmbeddingsRequest {
     pub keep_alive: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmbeddingsResponse {
     pub embedding: Vec<f32>,
 }
@@ -1,15 +1,30 @@
+use crate::cache::response_cache::{is_deterministic, CachingStream, ResponseCache};
+use crate::cache::CacheKey;
 use crate::ollama::model::{ChatRequest, ChatResponse, GenerateRequest, GenerateResponse};
+use futures::future::Either;
 use futures::stream::TryStream;
-use futures::stream::TryStreamExt;
+use futures::stream::{StreamExt, TryStreamExt};
 use reqwest::{Client, RequestBuilder};
 use serde::de::DeserializeOwned;
 
 use super::error::OllamaError;
 use crate::ollama::model::{EmbeddingsRequest, EmbeddingsResponse};
+use crate::telemetry::{
+    GenAiSpan, OPERATION_CHAT, OPERATION_EMBEDDINGS, OPERATION_TEXT_COMPLETION, PROVIDER_OLLAMA,
+};
+use crate::tokenizer::{HeuristicTokenizer, Tokenizer};
+use crate::usage::usage_ledger::UsageReporter;
+use serde_json::Value;
+
+const CACHE_GENERATE: &str = "ollama.generate";
+const CACHE_CHAT: &str = "ollama.chat";
+const CACHE_EMBEDDINGS: &str = "ollama.embeddings";
 
 pub struct OllamaClient {
     client: Client,
     base_url: String,
+    usage: Option<UsageReporter>,
+    cache: Option<ResponseCache>,
 }
 
 async fn fetch_stream<T>(
@@ -21,15 +36,9 @@ where
     let response = request.send().await?;
 
     let status = response.status();
-    let body = response.bytes_stream();
 
     if status.is_success() {
-        Ok(body
-            .map_err(OllamaError::from)
-            .and_then(|chunk| async move {
-                let chunk = serde_json::from_slice(&chunk).map_err(OllamaError::from)?;
-                Ok(chunk)
-            }))
+        Ok(ndjson_stream(response.bytes_stream()))
     } else {
         let message = format!("API request failed with status code: {}", status);
         match status.as_u16() {
@@ -44,25 +53,170 @@ where
     }
 }
 
+/// Parses an NDJSON body. A line may span several chunks or share one with the next line.
+fn ndjson_stream<T, S, E>(mut body: S) -> impl TryStream<Ok = T, Error = OllamaError>
+where
+    T: DeserializeOwned,
+    S: futures::Stream<Item = Result<bytes::Bytes, E>> + Unpin,
+    OllamaError: From<E>,
+{
+    async_stream::try_stream! {
+        let mut buffer = Vec::new();
+        while let Some(chunk) = body.next().await {
+            buffer.extend_from_slice(&chunk.map_err(OllamaError::from)?);
+            while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                if !line.trim_ascii().is_empty() {
+                    yield serde_json::from_slice::<T>(&line).map_err(OllamaError::Json)?;
+                }
+            }
+        }
+        if !buffer.trim_ascii().is_empty() {
+            yield serde_json::from_slice::<T>(&buffer).map_err(OllamaError::Json)?;
+        }
+    }
+}
+
+/// Opens the span of a call, with the sampling options of the request.
+fn request_span(operation: &str, model: &str, options: Option<&Value>) -> GenAiSpan {
+    let span = GenAiSpan::new(operation, PROVIDER_OLLAMA, model);
+    if let Some(options) = options {
+        span.request(
+            options.get("num_predict").and_then(Value::as_u64),
+            options.get("temperature").and_then(Value::as_f64),
+            options.get("top_p").and_then(Value::as_f64),
+        );
+    }
+    span
+}
+
+/// Records the model and finish reason reported in the last response of a stream.
+fn record_done(span: &GenAiSpan, model: &str, done_reason: Option<&str>) {
+    span.response_model(model);
+    span.finish_reason(done_reason.unwrap_or("stop"));
+}
+
 impl OllamaClient {
     pub fn new(base_url: String) -> Self {
         Self {
             client: Client::new(),
             base_url,
+            usage: None,
+            cache: None,
+        }
+    }
+
+    /// Reports the token usage of every call into a usage ledger, and checks its budgets
+    /// before sending a call. Local models have no price unless one is set with
+    /// `UsageLedger::with_pricing`.
+    pub fn with_usage(mut self, usage: UsageReporter) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    /// Serves the deterministic requests from a response cache, and stores their responses.
+    /// A cached stream is replayed chunk by chunk.
+    pub fn with_cache(mut self, cache: ResponseCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// The cache and key of a request, unless it bypasses the cache.
+    fn cache_key<R: serde::Serialize>(
+        &self,
+        operation: &str,
+        model: &str,
+        request: &R,
+        options: Option<&Value>,
+    ) -> Option<(ResponseCache, CacheKey)> {
+        let cache = self.cache.as_ref()?;
+        let temperature = options
+            .and_then(|options| options.get("temperature"))
+            .and_then(Value::as_f64);
+        let deterministic = operation == CACHE_EMBEDDINGS || is_deterministic(temperature);
+        let key = cache.key_for(operation, model, request, deterministic)?;
+        Some((cache.clone(), key))
+    }
+
+    fn check_budget<'a>(
+        &self,
+        model: &str,
+        prompts: impl Iterator<Item = &'a str>,
+        options: Option<&Value>,
+    ) -> Result<(), OllamaError> {
+        if let Some(usage) = &self.usage {
+            let tokenizer = HeuristicTokenizer::new();
+            let input_tokens: usize = prompts.map(|prompt| tokenizer.count_tokens(prompt)).sum();
+            let max_output_tokens = options
+                .and_then(|options| options.get("num_predict"))
+                .and_then(Value::as_u64)
+                .unwrap_or(0);
+            usage.check(model, input_tokens as u64, max_output_tokens)?;
         }
+        Ok(())
     }
 
     pub async fn generate(
         &self,
         request: GenerateRequest,
     ) -> Result<impl TryStream<Ok = GenerateResponse, Error = OllamaError>, OllamaError> {
+        let cache_key =
+            self.cache_key(CACHE_GENERATE, &request.model, &request, request.options.as_ref());
+        if let Some(replay) = cache_key.as_ref().and_then(|(cache, key)| cache.replay(key)) {
+            return Ok(Either::Left(replay));
+        }
+
+        let mut span =
+            request_span(OPERATION_TEXT_COMPLETION, &request.model, request.options.as_ref());
+        span.input(|| request.prompt.clone().unwrap_or_default());
+
+        let prompts = request.system.iter().chain(request.prompt.iter());
+        if let Err(err) =
+            self.check_budget(&request.model, prompts.map(String::as_str), request.options.as_ref())
+        {
+            span.error(&err);
+            return Err(err);
+        }
+
         let url = format!("{}/api/generate", self.base_url);
 
         let request = self.client.post(&url).json(&request);
 
-        let stream = fetch_stream::<GenerateResponse>(request).await?;
+        let stream = match span.instrument(fetch_stream::<GenerateResponse>(request)).await {
+            Ok(stream) => stream,
+            Err(err) => {
+                span.error(&err);
+                return Err(err);
+            }
+        };
 
-        Ok(stream)
+        let usage = self.usage.clone();
+        // The completion is only accumulated when it is recorded in the span.
+        let mut completion = crate::telemetry::capture_content().then(String::new);
+        let stream = stream.into_stream().inspect(move |response| match response {
+            Ok(response) => {
+                span.first_token();
+                if let Some(completion) = completion.as_mut() {
+                    completion.push_str(&response.response);
+                }
+                if response.done {
+                    let (input_tokens, output_tokens) = (
+                        response.prompt_eval_count.unwrap_or(0) as u64,
+                        response.eval_count.unwrap_or(0) as u64,
+                    );
+                    if let Some(usage) = &usage {
+                        usage.record(&response.model, input_tokens, output_tokens);
+                    }
+                    record_done(&span, &response.model, response.done_reason.as_deref());
+                    span.usage(input_tokens, output_tokens);
+                    if let Some(completion) = completion.take() {
+                        span.output(|| completion);
+                    }
+                }
+            }
+            Err(err) => span.error(err),
+        });
+        Ok(Either::Right(CachingStream::new(stream, cache_key)))
     }
 
     // new method that generate a text
@@ -84,18 +238,102 @@ impl OllamaClient {
         &self,
         request: ChatRequest,
     ) -> Result<impl TryStream<Ok = ChatResponse, Error = OllamaError>, OllamaError> {
+        let cache_key =
+            self.cache_key(CACHE_CHAT, &request.model, &request, request.options.as_ref());
+        if let Some(replay) = cache_key.as_ref().and_then(|(cache, key)| cache.replay(key)) {
+            return Ok(Either::Left(replay));
+        }
+
+        let mut span = request_span(OPERATION_CHAT, &request.model, request.options.as_ref());
+        span.input(|| serde_json::to_string(&request.messages).unwrap_or_default());
+
+        let prompts = request.messages.iter().map(|message| message.content.as_str());
+        if let Err(err) = self.check_budget(&request.model, prompts, request.options.as_ref()) {
+            span.error(&err);
+            return Err(err);
+        }
+
         let url = format!("{}/api/chat", self.base_url);
 
         let request = self.client.post(&url).json(&request);
 
-        let stream = fetch_stream::<ChatResponse>(request).await?;
+        let stream = match span.instrument(fetch_stream::<ChatResponse>(request)).await {
+            Ok(stream) => stream,
+            Err(err) => {
+                span.error(&err);
+                return Err(err);
+            }
+        };
 
-        Ok(stream)
+        let usage = self.usage.clone();
+        // The completion is only accumulated when it is recorded in the span.
+        let mut completion = crate::telemetry::capture_content().then(String::new);
+        let stream = stream.into_stream().inspect(move |response| match response {
+            Ok(response) => {
+                span.first_token();
+                if let Some(completion) = completion.as_mut() {
+                    completion.push_str(&response.message.content);
+                }
+                if response.done {
+                    let (input_tokens, output_tokens) = (
+                        response.prompt_eval_count.unwrap_or(0) as u64,
+                        response.eval_count.unwrap_or(0) as u64,
+                    );
+                    if let Some(usage) = &usage {
+                        usage.record(&response.model, input_tokens, output_tokens);
+                    }
+                    record_done(&span, &response.model, response.done_reason.as_deref());
+                    span.usage(input_tokens, output_tokens);
+                    if let Some(completion) = completion.take() {
+                        span.output(|| completion);
+                    }
+                }
+            }
+            Err(err) => span.error(err),
+        });
+        Ok(Either::Right(CachingStream::new(stream, cache_key)))
     }
 
     pub async fn embeddings(
         &self,
         request: EmbeddingsRequest,
+    ) -> Result<EmbeddingsResponse, OllamaError> {
+        let cache_key =
+            self.cache_key(CACHE_EMBEDDINGS, &request.model, &request, request.options.as_ref());
+        if let Some(response) = cache_key.as_ref().and_then(|(cache, key)| cache.get(key)) {
+            return Ok(response);
+        }
+
+        let span = request_span(OPERATION_EMBEDDINGS, &request.model, request.options.as_ref());
+        span.input(|| request.prompt.clone());
+
+        if let Err(err) = self.check_budget(&request.model, std::iter::once(request.prompt.as_str()), None) {
+            span.error(&err);
+            return Err(err);
+        }
+
+        // The response carries no token counts: estimate the prompt tokens.
+        let model = request.model.clone();
+        let input_tokens = HeuristicTokenizer::new().count_tokens(&request.prompt) as u64;
+        let result = span.instrument(self.embeddings_in_span(request)).await;
+        match &result {
+            Ok(response) => {
+                if let Some(usage) = &self.usage {
+                    usage.record(&model, input_tokens, 0);
+                }
+                span.usage(input_tokens, 0);
+                if let Some((cache, key)) = &cache_key {
+                    cache.put(key, response);
+                }
+            }
+            Err(err) => span.error(err),
+        }
+        result
+    }
+
+    async fn embeddings_in_span(
+        &self,
+        request: EmbeddingsRequest,
     ) -> Result<EmbeddingsResponse, OllamaError> {
         let url = format!("{}/api/embeddings", self.base_url);
 
@@ -126,12 +364,42 @@ impl OllamaClient {
 #[cfg(test)]
 mod tests {
     use crate::ollama::{options::OptionsBuilder, EmbeddingsRequestBuilder, Message};
+    use crate::testing::{Cassette, FixtureMode, FixtureServer};
 
     use super::*;
 
+    /// A client replaying the synthetic `tests/fixtures/<name>.json`, or recording it from a
+    /// local Ollama with `HIRAMU_FIXTURES=record`.
+    async fn fixture_client(name: &str) -> (FixtureServer, OllamaClient) {
+        let path = format!("{}/tests/fixtures/{}.json", env!("CARGO_MANIFEST_DIR"), name);
+        let cassette = Cassette::open(path, FixtureMode::from_env()).unwrap();
+        let server = FixtureServer::start(cassette, "http://localhost:11434")
+            .await
+            .unwrap();
+        let client = OllamaClient::new(server.base_url());
+        (server, client)
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_lines_across_chunks() {
+        let chunks: Vec<Result<bytes::Bytes, OllamaError>> = vec![
+            Ok(bytes::Bytes::from_static(b"{\"n\":1}\n{\"n\"")),
+            Ok(bytes::Bytes::from_static(b":2}\n\n{\"n\":3}\n{\"n\":")),
+            Ok(bytes::Bytes::from_static(b"4}")),
+        ];
+
+        let values: Vec<Value> = ndjson_stream(futures::stream::iter(chunks))
+            .try_collect()
+            .await
+            .unwrap();
+
+        let numbers: Vec<_> = values.iter().map(|value| value["n"].as_u64().unwrap()).collect();
+        assert_eq!(numbers, vec![1, 2, 3, 4]);
+    }
+
     #[tokio::test]
     async fn test_ollama_chat() {
-        let client = OllamaClient::new("http://localhost:11434".to_string());
+        let (_server, client) = fixture_client("ollama_chat").await;
 
         let request = crate::ollama::ChatRequestBuilder::new("llama3:instruct".to_string())
             .add_message(Message::new(
@@ -160,14 +428,12 @@ mod tests {
             Err(err) => panic!("Error: {:?}", err),
         };
 
-        print!("Response: {:?}", response);
-
-        assert!(!response.is_empty());
+        assert!(response.contains("Paris"));
     }
 
     #[tokio::test]
     async fn test_ollama_max_predictions() {
-        let client = OllamaClient::new("http://localhost:11434".to_string());
+        let (_server, client) = fixture_client("ollama_max_predictions").await;
 
         let options_builder = OptionsBuilder::new().num_predict(1);
 
@@ -184,14 +450,12 @@ mod tests {
             Err(err) => panic!("Error: {:?}", err),
         };
 
-        print!("Response: {:?}", response);
-
-        assert!(response.len() == 5);
+        assert_eq!(response, "Paris");
     }
 
     #[tokio::test]
     async fn test_embeddings() {
-        let client = OllamaClient::new("http://localhost:11434".to_string());
+        let (_server, client) = fixture_client("ollama_embeddings").await;
 
         let request = crate::ollama::EmbeddingsRequestBuilder::new(
             "nomic-embed-text".to_string(),
@@ -208,8 +472,6 @@ mod tests {
             Err(err) => panic!("Error: {:?}", err),
         };
 
-        print!("Embeddings: {:?}", response);
-
         assert_eq!(response.embedding.len(), 768);
     }
 
@@ -229,7 +491,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_embeddings_error() {
-        let client = OllamaClient::new("http://localhost:11434".to_string());
+        let (_server, client) = fixture_client("ollama_embeddings_error").await;
 
         let request = EmbeddingsRequestBuilder::new(
             "invalid-model".to_string(),
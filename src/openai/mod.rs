@@ -0,0 +1,9 @@
+pub mod error;
+pub mod model;
+pub mod openai_client;
+
+pub use error::OpenAiError;
+pub use model::{ChatCompletionAccumulator, ChatCompletionChunk, ChatCompletionRequest, ChatCompletionRequestBuilder, ChatCompletionResponse};
+pub use model::{EmbeddingsInput, EmbeddingsRequest, EmbeddingsRequestBuilder, EmbeddingsResponse};
+pub use model::{Message, ResponseFormat, StreamOptions, Tool, ToolCall, ToolChoice};
+pub use openai_client::OpenAiClient;
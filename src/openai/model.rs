@@ -0,0 +1,707 @@
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{json, Value};
+
+/// A message of a chat completion request or response.
+///
+/// # Fields
+///
+/// * `role` - `system`, `user`, `assistant` or `tool`.
+/// * `content` - The text of the message. An assistant message that only calls tools has none.
+/// * `name` - An optional name of the participant.
+/// * `tool_calls` - The tools called by an assistant message.
+/// * `tool_call_id` - The call answered by a `tool` message.
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Message {
+    pub role: String,
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl Message {
+    pub fn new<R: Into<String>, C: Into<String>>(role: R, content: C) -> Self {
+        Self {
+            role: role.into(),
+            content: Some(content.into()),
+            name: None,
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+
+    pub fn system<C: Into<String>>(content: C) -> Self {
+        Self::new("system", content)
+    }
+
+    pub fn user<C: Into<String>>(content: C) -> Self {
+        Self::new("user", content)
+    }
+
+    pub fn assistant<C: Into<String>>(content: C) -> Self {
+        Self::new("assistant", content)
+    }
+
+    /// The result of a tool call, sent back to the model.
+    pub fn tool<I: Into<String>, C: Into<String>>(tool_call_id: I, content: C) -> Self {
+        Self {
+            tool_call_id: Some(tool_call_id.into()),
+            ..Self::new("tool", content)
+        }
+    }
+}
+
+/// A function the model may call.
+///
+/// # Fields
+///
+/// * `name` - The name of the function.
+/// * `description` - What the function does, read by the model to decide when to call it.
+/// * `parameters` - The JSON schema of the arguments.
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionDefinition {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub parameters: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tool {
+    #[serde(rename = "type")]
+    pub tool_type: String,
+    pub function: FunctionDefinition,
+}
+
+impl Tool {
+    pub fn function<N: Into<String>, D: Into<String>>(
+        name: N,
+        description: D,
+        parameters: Value,
+    ) -> Self {
+        Self {
+            tool_type: "function".to_string(),
+            function: FunctionDefinition {
+                name: name.into(),
+                description: Some(description.into()),
+                parameters,
+            },
+        }
+    }
+}
+
+/// Whether and which tool the model must call.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolChoice {
+    None,
+    Auto,
+    Required,
+    Function(String),
+}
+
+impl Serialize for ToolChoice {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ToolChoice::None => serializer.serialize_str("none"),
+            ToolChoice::Auto => serializer.serialize_str("auto"),
+            ToolChoice::Required => serializer.serialize_str("required"),
+            ToolChoice::Function(name) => {
+                json!({ "type": "function", "function": { "name": name } }).serialize(serializer)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionCall {
+    pub name: String,
+    /// The arguments as a JSON string, which the model may have left malformed.
+    pub arguments: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub tool_type: String,
+    pub function: FunctionCall,
+}
+
+impl ToolCall {
+    /// Parses the arguments of the call.
+    pub fn arguments(&self) -> Result<Value, serde_json::Error> {
+        serde_json::from_str(&self.function.arguments)
+    }
+}
+
+/// The format of the response: free text, any JSON object (JSON mode), or JSON following a
+/// schema (structured outputs).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResponseFormat {
+    #[serde(rename = "type")]
+    pub format_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json_schema: Option<Value>,
+}
+
+impl ResponseFormat {
+    pub fn text() -> Self {
+        Self {
+            format_type: "text".to_string(),
+            json_schema: None,
+        }
+    }
+
+    pub fn json_object() -> Self {
+        Self {
+            format_type: "json_object".to_string(),
+            json_schema: None,
+        }
+    }
+
+    /// A strict JSON schema, supported by OpenAI and by some compatible servers.
+    pub fn json_schema<N: Into<String>>(name: N, schema: Value) -> Self {
+        Self {
+            format_type: "json_schema".to_string(),
+            json_schema: Some(json!({ "name": name.into(), "schema": schema, "strict": true })),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StreamOptions {
+    pub include_usage: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatCompletionRequest {
+    pub model: String,
+    pub messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<Tool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+pub struct ChatCompletionRequestBuilder {
+    model: String,
+    messages: Vec<Message>,
+    temperature: Option<f32>,
+    top_p: Option<f32>,
+    max_tokens: Option<u32>,
+    stop: Vec<String>,
+    seed: Option<i64>,
+    tools: Vec<Tool>,
+    tool_choice: Option<ToolChoice>,
+    response_format: Option<ResponseFormat>,
+    stream_options: Option<StreamOptions>,
+    user: Option<String>,
+}
+
+impl ChatCompletionRequestBuilder {
+    pub fn new<S: Into<String>>(model: S) -> Self {
+        Self {
+            model: model.into(),
+            messages: Vec::new(),
+            temperature: None,
+            top_p: None,
+            max_tokens: None,
+            stop: Vec::new(),
+            seed: None,
+            tools: Vec::new(),
+            tool_choice: None,
+            response_format: None,
+            stream_options: None,
+            user: None,
+        }
+    }
+
+    pub fn messages(mut self, messages: Vec<Message>) -> Self {
+        self.messages = messages;
+        self
+    }
+
+    pub fn add_message(mut self, message: Message) -> Self {
+        self.messages.push(message);
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    pub fn top_p(mut self, top_p: f32) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    pub fn stop(mut self, stop: Vec<String>) -> Self {
+        self.stop = stop;
+        self
+    }
+
+    pub fn seed(mut self, seed: i64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn tools(mut self, tools: Vec<Tool>) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    pub fn add_tool(mut self, tool: Tool) -> Self {
+        self.tools.push(tool);
+        self
+    }
+
+    pub fn tool_choice(mut self, tool_choice: ToolChoice) -> Self {
+        self.tool_choice = Some(tool_choice);
+        self
+    }
+
+    pub fn response_format(mut self, response_format: ResponseFormat) -> Self {
+        self.response_format = Some(response_format);
+        self
+    }
+
+    /// Constrains the response to a JSON object. The prompt must still ask for JSON, or
+    /// OpenAI rejects the request.
+    pub fn json_mode(self) -> Self {
+        self.response_format(ResponseFormat::json_object())
+    }
+
+    /// The options of a streamed request. Ignored by `OpenAiClient::chat`.
+    pub fn stream_options(mut self, stream_options: StreamOptions) -> Self {
+        self.stream_options = Some(stream_options);
+        self
+    }
+
+    /// Asks for the usage of a streamed request, reported on the last chunk by the servers
+    /// that support it. Some servers reject the option.
+    pub fn include_usage(self) -> Self {
+        self.stream_options(StreamOptions {
+            include_usage: true,
+        })
+    }
+
+    pub fn user<S: Into<String>>(mut self, user: S) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+
+    pub fn build(self) -> ChatCompletionRequest {
+        ChatCompletionRequest {
+            model: self.model,
+            messages: self.messages,
+            temperature: self.temperature,
+            top_p: self.top_p,
+            max_tokens: self.max_tokens,
+            stop: self.stop,
+            seed: self.seed,
+            tools: self.tools,
+            tool_choice: self.tool_choice,
+            response_format: self.response_format,
+            stream: None,
+            stream_options: self.stream_options,
+            user: self.user,
+        }
+    }
+}
+
+impl From<ChatCompletionRequestBuilder> for String {
+    fn from(request: ChatCompletionRequestBuilder) -> Self {
+        serde_json::to_string(&request.build()).unwrap()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: u32,
+    #[serde(default)]
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Choice {
+    pub index: u32,
+    pub message: Message,
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatCompletionResponse {
+    pub id: String,
+    #[serde(default)]
+    pub object: String,
+    pub created: u64,
+    pub model: String,
+    pub choices: Vec<Choice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_fingerprint: Option<String>,
+}
+
+impl ChatCompletionResponse {
+    /// The text of the first choice.
+    pub fn text(&self) -> Option<&str> {
+        self.choices.first()?.message.content.as_deref()
+    }
+
+    /// The tool calls of the first choice.
+    pub fn tool_calls(&self) -> &[ToolCall] {
+        self.choices
+            .first()
+            .map(|choice| choice.message.tool_calls.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionCallDelta {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub arguments: Option<String>,
+}
+
+/// A part of a tool call. The first part of a call carries its id and function name, and the
+/// next ones the following pieces of its arguments.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCallDelta {
+    pub index: u32,
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default, rename = "type")]
+    pub tool_type: Option<String>,
+    #[serde(default)]
+    pub function: Option<FunctionCallDelta>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Delta {
+    #[serde(default)]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCallDelta>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChunkChoice {
+    pub index: u32,
+    pub delta: Delta,
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+}
+
+/// An event of a streamed chat completion.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatCompletionChunk {
+    pub id: String,
+    #[serde(default)]
+    pub object: String,
+    pub created: u64,
+    pub model: String,
+    pub choices: Vec<ChunkChoice>,
+    /// Only set on the last chunk, when the usage is requested in the stream options.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+}
+
+impl ChatCompletionChunk {
+    /// The text delta of the first choice.
+    pub fn text(&self) -> Option<&str> {
+        self.choices.first()?.delta.content.as_deref()
+    }
+}
+
+/// Folds the chunks of a streamed chat completion into the `ChatCompletionResponse` that a
+/// non-streamed call would have returned, including the tool calls whose arguments were
+/// streamed in pieces.
+#[derive(Debug, Default)]
+pub struct ChatCompletionAccumulator {
+    id: String,
+    created: u64,
+    model: String,
+    choices: Vec<Choice>,
+    usage: Option<Usage>,
+}
+
+impl ChatCompletionAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a chunk.
+    pub fn push(&mut self, chunk: &ChatCompletionChunk) {
+        self.id.clone_from(&chunk.id);
+        self.created = chunk.created;
+        self.model.clone_from(&chunk.model);
+        if chunk.usage.is_some() {
+            self.usage = chunk.usage;
+        }
+        for chunk_choice in &chunk.choices {
+            let choice = match self.choices.iter_mut().find(|c| c.index == chunk_choice.index) {
+                Some(choice) => choice,
+                None => {
+                    self.choices.push(Choice {
+                        index: chunk_choice.index,
+                        message: Message {
+                            content: None,
+                            ..Message::assistant("")
+                        },
+                        finish_reason: None,
+                    });
+                    self.choices.last_mut().unwrap()
+                }
+            };
+            let delta = &chunk_choice.delta;
+            if let Some(role) = &delta.role {
+                choice.message.role.clone_from(role);
+            }
+            if let Some(content) = &delta.content {
+                choice
+                    .message
+                    .content
+                    .get_or_insert_with(String::new)
+                    .push_str(content);
+            }
+            for call in &delta.tool_calls {
+                let calls = &mut choice.message.tool_calls;
+                let index = call.index as usize;
+                if calls.len() <= index {
+                    calls.resize_with(index + 1, || ToolCall {
+                        id: String::new(),
+                        tool_type: "function".to_string(),
+                        function: FunctionCall {
+                            name: String::new(),
+                            arguments: String::new(),
+                        },
+                    });
+                }
+                let tool_call = &mut calls[index];
+                if let Some(id) = &call.id {
+                    tool_call.id.clone_from(id);
+                }
+                if let Some(function) = &call.function {
+                    if let Some(name) = &function.name {
+                        tool_call.function.name.push_str(name);
+                    }
+                    if let Some(arguments) = &function.arguments {
+                        tool_call.function.arguments.push_str(arguments);
+                    }
+                }
+            }
+            if chunk_choice.finish_reason.is_some() {
+                choice.finish_reason.clone_from(&chunk_choice.finish_reason);
+            }
+        }
+    }
+
+    pub fn finish(self) -> ChatCompletionResponse {
+        ChatCompletionResponse {
+            id: self.id,
+            object: "chat.completion".to_string(),
+            created: self.created,
+            model: self.model,
+            choices: self.choices,
+            usage: self.usage,
+            system_fingerprint: None,
+        }
+    }
+}
+
+/// One text or a batch of texts to embed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum EmbeddingsInput {
+    Text(String),
+    Batch(Vec<String>),
+}
+
+impl From<String> for EmbeddingsInput {
+    fn from(text: String) -> Self {
+        EmbeddingsInput::Text(text)
+    }
+}
+
+impl From<&str> for EmbeddingsInput {
+    fn from(text: &str) -> Self {
+        EmbeddingsInput::Text(text.to_string())
+    }
+}
+
+impl From<Vec<String>> for EmbeddingsInput {
+    fn from(texts: Vec<String>) -> Self {
+        EmbeddingsInput::Batch(texts)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EmbeddingsRequest {
+    pub model: String,
+    pub input: EmbeddingsInput,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+pub struct EmbeddingsRequestBuilder {
+    model: String,
+    input: EmbeddingsInput,
+    dimensions: Option<u32>,
+    user: Option<String>,
+}
+
+impl EmbeddingsRequestBuilder {
+    pub fn new<S: Into<String>, I: Into<EmbeddingsInput>>(model: S, input: I) -> Self {
+        Self {
+            model: model.into(),
+            input: input.into(),
+            dimensions: None,
+            user: None,
+        }
+    }
+
+    /// Shortens the embeddings, for the models that support it.
+    pub fn dimensions(mut self, dimensions: u32) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
+
+    pub fn user<S: Into<String>>(mut self, user: S) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+
+    pub fn build(self) -> EmbeddingsRequest {
+        EmbeddingsRequest {
+            model: self.model,
+            input: self.input,
+            dimensions: self.dimensions,
+            user: self.user,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Embedding {
+    pub index: u32,
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct EmbeddingsUsage {
+    pub prompt_tokens: u32,
+    pub total_tokens: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmbeddingsResponse {
+    pub data: Vec<Embedding>,
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<EmbeddingsUsage>,
+}
+
+impl EmbeddingsResponse {
+    /// The embeddings, in the order of the inputs.
+    pub fn embeddings(mut self) -> Vec<Vec<f32>> {
+        self.data.sort_by_key(|embedding| embedding.index);
+        self.data.into_iter().map(|embedding| embedding.embedding).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_builder() {
+        let json_request: String = ChatCompletionRequestBuilder::new("gpt-4o-mini")
+            .add_message(Message::user("Weather in Paris?"))
+            .add_tool(Tool::function(
+                "get_weather",
+                "Gets the weather of a city",
+                json!({ "type": "object", "properties": { "city": { "type": "string" } } }),
+            ))
+            .tool_choice(ToolChoice::Function("get_weather".to_string()))
+            .json_mode()
+            .into();
+
+        let expected_json = r#"{"model":"gpt-4o-mini","messages":[{"role":"user","content":"Weather in Paris?"}],"tools":[{"type":"function","function":{"name":"get_weather","description":"Gets the weather of a city","parameters":{"properties":{"city":{"type":"string"}},"type":"object"}}}],"tool_choice":{"function":{"name":"get_weather"},"type":"function"},"response_format":{"type":"json_object"}}"#;
+        assert_eq!(json_request, expected_json);
+    }
+
+    #[test]
+    fn test_accumulate_streamed_tool_call() {
+        let chunk = |delta: Value, finish_reason: Option<&str>| -> ChatCompletionChunk {
+            serde_json::from_value(json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "created": 1700000000,
+                "model": "gpt-4o-mini",
+                "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }],
+            }))
+            .unwrap()
+        };
+
+        let mut accumulator = ChatCompletionAccumulator::new();
+        accumulator.push(&chunk(
+            json!({ "role": "assistant", "tool_calls": [{ "index": 0, "id": "call_1", "type": "function", "function": { "name": "get_weather", "arguments": "" } }] }),
+            None,
+        ));
+        accumulator.push(&chunk(
+            json!({ "tool_calls": [{ "index": 0, "function": { "arguments": "{\"city\":" } }] }),
+            None,
+        ));
+        accumulator.push(&chunk(
+            json!({ "tool_calls": [{ "index": 0, "function": { "arguments": "\"Paris\"}" } }] }),
+            Some("tool_calls"),
+        ));
+
+        let response = accumulator.finish();
+        assert_eq!(response.text(), None);
+        assert_eq!(response.choices[0].finish_reason.as_deref(), Some("tool_calls"));
+        let call = &response.tool_calls()[0];
+        assert_eq!(call.id, "call_1");
+        assert_eq!(call.function.name, "get_weather");
+        assert_eq!(call.arguments().unwrap(), json!({ "city": "Paris" }));
+    }
+}
@@ -0,0 +1,429 @@
+use futures::stream::{StreamExt, TryStream};
+use reqwest::{Client, RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::openai::error::OpenAiError;
+use crate::openai::model::{
+    ChatCompletionChunk, ChatCompletionRequest, ChatCompletionResponse, EmbeddingsRequest,
+    EmbeddingsResponse,
+};
+
+/// The base URL of the OpenAI API.
+pub const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+/// A client for the OpenAI chat completions and embeddings API, and for the servers
+/// compatible with it, such as vLLM, the llama.cpp server, LM Studio, or Ollama on
+/// `http://localhost:11434/v1`.
+///
+/// # Example
+///
+/// ```no_run
+/// use hiramu::openai::{ChatCompletionRequestBuilder, Message, OpenAiClient};
+///
+/// # async fn example() -> Result<(), hiramu::openai::OpenAiError> {
+/// let client = OpenAiClient::new("http://localhost:11434/v1");
+/// let request = ChatCompletionRequestBuilder::new("llama3")
+///     .add_message(Message::user("What is the capital of France?"))
+///     .build();
+/// let response = client.chat(request).await?;
+/// println!("{}", response.text().unwrap_or_default());
+/// # Ok(())
+/// # }
+/// ```
+pub struct OpenAiClient {
+    client: Client,
+    base_url: String,
+    api_key: Option<String>,
+    organization: Option<String>,
+}
+
+/// Maps an error status to an `OpenAiError`, with the message of the error body if any.
+async fn check_status(response: Response) -> Result<Response, OpenAiError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    let detail = serde_json::from_str::<Value>(&body)
+        .ok()
+        .and_then(|body| body["error"]["message"].as_str().map(str::to_string))
+        .unwrap_or(body);
+    let message = format!("API request failed with status code: {}: {}", status, detail);
+    match status.as_u16() {
+        400 => Err(OpenAiError::BadRequest(message)),
+        401 => Err(OpenAiError::Unauthorized(message)),
+        403 => Err(OpenAiError::Forbidden(message)),
+        404 => Err(OpenAiError::NotFound(message)),
+        429 => Err(OpenAiError::TooManyRequests(message)),
+        500 => Err(OpenAiError::InternalServerError(message)),
+        _ => Err(OpenAiError::UnknownApiError(message)),
+    }
+}
+
+/// Parses the data of a server-sent event, which may be an error instead of a chunk.
+fn parse_event<T: DeserializeOwned>(data: &str) -> Result<T, OpenAiError> {
+    let value: Value = serde_json::from_str(data)?;
+    if let Some(error) = value.get("error") {
+        let message = error["message"].as_str().map(str::to_string);
+        return Err(OpenAiError::Stream(message.unwrap_or_else(|| error.to_string())));
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Sends a request answered with server-sent events, and streams the data of the events
+/// until the `[DONE]` event.
+async fn fetch_events<T>(
+    request: RequestBuilder,
+) -> Result<impl TryStream<Ok = T, Error = OpenAiError>, OpenAiError>
+where
+    T: DeserializeOwned,
+{
+    let response = check_status(request.send().await?).await?;
+    let mut body = response.bytes_stream();
+
+    Ok(async_stream::try_stream! {
+        let mut buffer = Vec::new();
+        let mut data = String::new();
+        'events: while let Some(chunk) = body.next().await {
+            buffer.extend_from_slice(&chunk.map_err(OpenAiError::from)?);
+            while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let line = std::str::from_utf8(&line).map_err(OpenAiError::from)?;
+                let line = line.trim_end_matches(['\r', '\n']);
+                if line.is_empty() {
+                    // A blank line ends an event.
+                    if data == "[DONE]" {
+                        break 'events;
+                    }
+                    if !data.is_empty() {
+                        yield parse_event::<T>(&data)?;
+                        data.clear();
+                    }
+                } else if let Some(field) = line.strip_prefix("data:") {
+                    if !data.is_empty() {
+                        data.push('\n');
+                    }
+                    data.push_str(field.strip_prefix(' ').unwrap_or(field));
+                }
+            }
+        }
+        if !data.is_empty() && data != "[DONE]" {
+            yield parse_event::<T>(&data)?;
+        }
+    })
+}
+
+impl OpenAiClient {
+    /// A client for the API at `base_url`, e.g. `https://api.openai.com/v1`.
+    pub fn new<S: Into<String>>(base_url: S) -> Self {
+        Self {
+            client: Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            api_key: None,
+            organization: None,
+        }
+    }
+
+    /// A client configured from the `OPENAI_BASE_URL` and `OPENAI_API_KEY` environment
+    /// variables, for the OpenAI API by default.
+    pub fn from_env() -> Self {
+        let base_url = std::env::var("OPENAI_BASE_URL").unwrap_or_else(|_| DEFAULT_BASE_URL.to_string());
+        let client = Self::new(base_url);
+        match std::env::var("OPENAI_API_KEY") {
+            Ok(api_key) => client.with_api_key(api_key),
+            Err(_) => client,
+        }
+    }
+
+    /// Sends the key as a bearer token. Local servers usually need none.
+    pub fn with_api_key<S: Into<String>>(mut self, api_key: S) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    pub fn with_organization<S: Into<String>>(mut self, organization: S) -> Self {
+        self.organization = Some(organization.into());
+        self
+    }
+
+    /// Sends the requests with `client`, e.g. to set a proxy or timeouts.
+    pub fn with_http_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn post(&self, path: &str) -> RequestBuilder {
+        let mut request = self.client.post(format!("{}{}", self.base_url, path));
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        if let Some(organization) = &self.organization {
+            request = request.header("OpenAI-Organization", organization);
+        }
+        request
+    }
+
+    /// Sends a chat completion request and waits for the whole response.
+    pub async fn chat(
+        &self,
+        mut request: ChatCompletionRequest,
+    ) -> Result<ChatCompletionResponse, OpenAiError> {
+        request.stream = None;
+        request.stream_options = None;
+        let response = check_status(self.post("/chat/completions").json(&request).send().await?).await?;
+        Ok(response.json().await?)
+    }
+
+    /// Sends a chat completion request and streams the response chunks. The usage is only
+    /// reported if the request asks for it, e.g. with
+    /// `ChatCompletionRequestBuilder::include_usage`.
+    ///
+    /// Use a `ChatCompletionAccumulator` to assemble the full response from the chunks.
+    pub async fn chat_stream(
+        &self,
+        mut request: ChatCompletionRequest,
+    ) -> Result<impl TryStream<Ok = ChatCompletionChunk, Error = OpenAiError>, OpenAiError> {
+        request.stream = Some(true);
+        fetch_events(self.post("/chat/completions").json(&request)).await
+    }
+
+    pub async fn embeddings(
+        &self,
+        request: EmbeddingsRequest,
+    ) -> Result<EmbeddingsResponse, OpenAiError> {
+        let response = check_status(self.post("/embeddings").json(&request).send().await?).await?;
+        Ok(response.json().await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::openai::model::{
+        ChatCompletionAccumulator, ChatCompletionRequestBuilder, EmbeddingsRequestBuilder,
+        Message, Tool,
+    };
+    use crate::testing::{Cassette, FixtureBody, FixtureServer, Interaction, RecordedRequest, RecordedResponse};
+    use futures::TryStreamExt;
+    use serde::Serialize;
+    use serde_json::json;
+
+    /// The interaction of a request sent to `path`, answered with `status` and `chunks`.
+    fn interaction<B: Serialize>(
+        path: &str,
+        body: &B,
+        status: u16,
+        content_type: &str,
+        chunks: Vec<String>,
+    ) -> Interaction {
+        Interaction {
+            request: RecordedRequest::new(
+                "POST",
+                format!("/v1{}", path),
+                &serde_json::to_vec(body).unwrap(),
+            ),
+            response: RecordedResponse {
+                status,
+                headers: vec![("content-type".to_string(), content_type.to_string())],
+                chunks: chunks.into_iter().map(FixtureBody::Text).collect(),
+            },
+        }
+    }
+
+    /// A server replaying `interactions`, and a client pointed at it.
+    async fn replay(interactions: Vec<Interaction>) -> (FixtureServer, OpenAiClient) {
+        let server = FixtureServer::replay(Cassette::replaying(interactions))
+            .await
+            .unwrap();
+        let client = OpenAiClient::new(format!("{}/v1", server.base_url()));
+        (server, client)
+    }
+
+    /// The body of a streamed request.
+    fn streamed(request: &ChatCompletionRequest) -> Value {
+        let mut body = serde_json::to_value(request).unwrap();
+        body["stream"] = json!(true);
+        body
+    }
+
+    /// The server-sent events of `events`, one per chunk.
+    fn sse(events: &[Value]) -> Vec<String> {
+        let mut chunks: Vec<String> = events
+            .iter()
+            .map(|event| format!("data: {}\n\n", event))
+            .collect();
+        chunks.push("data: [DONE]\n\n".to_string());
+        chunks
+    }
+
+    fn chunk(delta: Value, finish_reason: Option<&str>) -> Value {
+        json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion.chunk",
+            "created": 1700000000,
+            "model": "gpt-4o-mini",
+            "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }],
+        })
+    }
+
+    #[tokio::test]
+    async fn test_chat() {
+        let request = ChatCompletionRequestBuilder::new("gpt-4o-mini")
+            .add_message(Message::user("What is the capital of France?"))
+            .temperature(0.0)
+            .include_usage()
+            .build();
+        let response = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1700000000,
+            "model": "gpt-4o-mini",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "Paris." },
+                "finish_reason": "stop",
+            }],
+            "usage": { "prompt_tokens": 14, "completion_tokens": 2, "total_tokens": 16 },
+        });
+        // The stream options are not sent with a request that is not streamed.
+        let sent = json!({
+            "model": "gpt-4o-mini",
+            "messages": [{ "role": "user", "content": "What is the capital of France?" }],
+            "temperature": 0.0,
+        });
+        let (server, client) = replay(vec![interaction(
+            "/chat/completions",
+            &sent,
+            200,
+            "application/json",
+            vec![response.to_string()],
+        )])
+        .await;
+
+        let response = client.with_api_key("sk-test").chat(request).await.unwrap();
+
+        assert_eq!(response.text(), Some("Paris."));
+        assert_eq!(response.usage.unwrap().total_tokens, 16);
+        assert_eq!(server.cassette().remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_with_tool_call() {
+        let request = ChatCompletionRequestBuilder::new("gpt-4o-mini")
+            .add_message(Message::user("Weather in Paris?"))
+            .add_tool(Tool::function("get_weather", "Gets the weather", json!({ "type": "object" })))
+            .include_usage()
+            .build();
+        let sent = streamed(&request);
+        assert_eq!(sent["stream_options"], json!({ "include_usage": true }));
+        let events = [
+            chunk(json!({ "role": "assistant", "content": "" }), None),
+            chunk(
+                json!({ "tool_calls": [{ "index": 0, "id": "call_1", "type": "function", "function": { "name": "get_weather", "arguments": "{\"city\"" } }] }),
+                None,
+            ),
+            chunk(
+                json!({ "tool_calls": [{ "index": 0, "function": { "arguments": ":\"Paris\"}" } }] }),
+                Some("tool_calls"),
+            ),
+            json!({
+                "id": "chatcmpl-1",
+                "created": 1700000000,
+                "model": "gpt-4o-mini",
+                "choices": [],
+                "usage": { "prompt_tokens": 30, "completion_tokens": 8, "total_tokens": 38 },
+            }),
+        ];
+        let (server, client) = replay(vec![interaction(
+            "/chat/completions",
+            &sent,
+            200,
+            "text/event-stream",
+            sse(&events),
+        )])
+        .await;
+
+        let chunks: Vec<ChatCompletionChunk> = client
+            .chat_stream(request)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(chunks.len(), 4);
+
+        let mut accumulator = ChatCompletionAccumulator::new();
+        chunks.iter().for_each(|chunk| accumulator.push(chunk));
+        let response = accumulator.finish();
+        assert_eq!(response.tool_calls()[0].arguments().unwrap(), json!({ "city": "Paris" }));
+        assert_eq!(response.usage.unwrap().completion_tokens, 8);
+        assert_eq!(server.cassette().remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_stream_error_event() {
+        let request = ChatCompletionRequestBuilder::new("gpt-4o-mini")
+            .add_message(Message::user("Hi"))
+            .build();
+        // Without `include_usage`, no stream options are sent.
+        let sent = streamed(&request);
+        assert!(sent.get("stream_options").is_none());
+        let chunks = vec![
+            format!("data: {}\n\n", chunk(json!({ "content": "Par" }), None)),
+            format!(
+                "data: {}\n\n",
+                json!({ "error": { "message": "The server had an error", "type": "server_error" } })
+            ),
+        ];
+        let (_server, client) = replay(vec![interaction(
+            "/chat/completions",
+            &sent,
+            200,
+            "text/event-stream",
+            chunks,
+        )])
+        .await;
+
+        let result: Result<Vec<_>, _> = client.chat_stream(request).await.unwrap().try_collect().await;
+        assert!(matches!(result, Err(OpenAiError::Stream(message)) if message == "The server had an error"));
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_and_errors() {
+        let request = EmbeddingsRequestBuilder::new(
+            "text-embedding-3-small",
+            vec!["llamas".to_string(), "alpacas".to_string()],
+        )
+        .build();
+        let embeddings = json!({
+            "object": "list",
+            "data": [
+                { "object": "embedding", "index": 1, "embedding": [0.0, 1.0] },
+                { "object": "embedding", "index": 0, "embedding": [1.0, 0.0] },
+            ],
+            "model": "text-embedding-3-small",
+            "usage": { "prompt_tokens": 4, "total_tokens": 4 },
+        });
+        let error = json!({ "error": { "message": "Rate limit reached", "type": "requests" } });
+        let (_server, client) = replay(vec![
+            interaction("/embeddings", &request, 200, "application/json", vec![embeddings.to_string()]),
+            interaction("/embeddings", &request, 429, "application/json", vec![error.to_string()]),
+        ])
+        .await;
+
+        let vectors = client.embeddings(request.clone()).await.unwrap().embeddings();
+        assert_eq!(vectors, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+
+        match client.embeddings(request).await {
+            Err(OpenAiError::TooManyRequests(message)) => {
+                assert!(message.contains("Rate limit reached"))
+            }
+            other => panic!("expected a rate limit error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::bedrock::models::claude::claude_request_message::{
+    ConversationRequest, Message as ClaudeMessage,
+};
+use crate::bedrock::models::mistral::{instruct_prompt, MistralMessage};
+use crate::ollama::Message as OllamaMessage;
+use crate::prompt::error::PromptError;
+use crate::prompt::template::{Partials, PromptTemplate, Variables};
+use crate::session::SessionRole;
+
+/// A rendered message of a `ChatPrompt`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptMessage {
+    pub role: SessionRole,
+    pub content: String,
+}
+
+/// A rendered chat template, to convert into the messages of a provider.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChatPrompt {
+    pub messages: Vec<PromptMessage>,
+}
+
+impl ChatPrompt {
+    /// The system messages, joined by blank lines.
+    pub fn system(&self) -> Option<String> {
+        let system: Vec<&str> = self
+            .messages
+            .iter()
+            .filter(|message| message.role == SessionRole::System)
+            .map(|message| message.content.as_str())
+            .collect();
+        (!system.is_empty()).then(|| system.join("\n\n"))
+    }
+
+    pub fn to_ollama_messages(&self) -> Vec<OllamaMessage> {
+        self.messages
+            .iter()
+            .map(|message| {
+                OllamaMessage::new(role_name(message.role).to_string(), message.content.clone())
+            })
+            .collect()
+    }
+
+    /// A Claude request with the system messages as its system prompt. The caller sets
+    /// `max_tokens`.
+    pub fn to_claude_request(&self) -> ConversationRequest {
+        let messages = self
+            .messages
+            .iter()
+            .filter_map(|message| match message.role {
+                SessionRole::System => None,
+                SessionRole::User => Some(ClaudeMessage::new_user_message(message.content.clone())),
+                SessionRole::Assistant => {
+                    Some(ClaudeMessage::new_assistant_message(message.content.clone()))
+                }
+            })
+            .collect();
+        ConversationRequest {
+            system: self.system(),
+            messages,
+            ..Default::default()
+        }
+    }
+
+    pub fn to_mistral_messages(&self) -> Vec<MistralMessage> {
+        self.messages
+            .iter()
+            .map(|message| MistralMessage::new(role_name(message.role), message.content.clone()))
+            .collect()
+    }
+
+    /// The prompt in the instruction format of the Mistral models.
+    pub fn to_mistral_prompt(&self) -> String {
+        instruct_prompt(&self.to_mistral_messages())
+    }
+}
+
+fn role_name(role: SessionRole) -> &'static str {
+    match role {
+        SessionRole::System => "system",
+        SessionRole::User => "user",
+        SessionRole::Assistant => "assistant",
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ChatEntry {
+    Message {
+        role: SessionRole,
+        template: PromptTemplate,
+    },
+    Examples {
+        input: PromptTemplate,
+        output: PromptTemplate,
+        examples: Vec<Variables>,
+    },
+}
+
+/// A template of a conversation: a list of message templates, and few-shot examples
+/// rendered as pairs of user and assistant messages.
+///
+/// A chat template file is a JSON document:
+///
+/// ```json
+/// {
+///   "messages": [
+///     { "role": "system", "content": "{{> persona}}" },
+///     {
+///       "examples": {
+///         "input": "Translate: {{text}}",
+///         "output": "{{translation}}",
+///         "values": [{ "text": "cat", "translation": "chat" }]
+///       }
+///     },
+///     { "role": "user", "content": "Translate: {{text}}" }
+///   ]
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChatTemplate {
+    entries: Vec<ChatEntry>,
+}
+
+impl ChatTemplate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn message(mut self, role: SessionRole, template: PromptTemplate) -> Self {
+        self.entries.push(ChatEntry::Message { role, template });
+        self
+    }
+
+    pub fn system(self, template: PromptTemplate) -> Self {
+        self.message(SessionRole::System, template)
+    }
+
+    pub fn user(self, template: PromptTemplate) -> Self {
+        self.message(SessionRole::User, template)
+    }
+
+    pub fn assistant(self, template: PromptTemplate) -> Self {
+        self.message(SessionRole::Assistant, template)
+    }
+
+    /// Adds a user message rendered with `input` and an assistant message rendered with
+    /// `output` for each example.
+    pub fn examples<I: IntoIterator<Item = Variables>>(
+        mut self,
+        input: PromptTemplate,
+        output: PromptTemplate,
+        examples: I,
+    ) -> Self {
+        self.entries.push(ChatEntry::Examples {
+            input,
+            output,
+            examples: examples.into_iter().collect(),
+        });
+        self
+    }
+
+    /// Parses a chat template file.
+    pub fn from_json(json: &str) -> Result<Self, PromptError> {
+        let file: ChatTemplateFile = serde_json::from_str(json)?;
+        let mut template = ChatTemplate::new();
+        for entry in file.messages {
+            template = match entry {
+                ChatEntryFile::Message { role, content } => {
+                    template.message(parse_role(&role)?, PromptTemplate::new(content)?)
+                }
+                ChatEntryFile::Examples { examples } => template.examples(
+                    PromptTemplate::new(examples.input)?,
+                    PromptTemplate::new(examples.output)?,
+                    examples.values,
+                ),
+            };
+        }
+        Ok(template)
+    }
+
+    /// Loads a chat template file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, PromptError> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn render(&self, variables: &Variables) -> Result<ChatPrompt, PromptError> {
+        self.render_with(variables, &Partials::new())
+    }
+
+    /// Renders the messages. The examples are rendered with `variables` overridden by their
+    /// own values.
+    pub fn render_with(
+        &self,
+        variables: &Variables,
+        partials: &Partials,
+    ) -> Result<ChatPrompt, PromptError> {
+        let mut messages = Vec::new();
+        for entry in &self.entries {
+            match entry {
+                ChatEntry::Message { role, template } => messages.push(PromptMessage {
+                    role: *role,
+                    content: template.render_with(variables, partials)?,
+                }),
+                ChatEntry::Examples {
+                    input,
+                    output,
+                    examples,
+                } => {
+                    for example in examples {
+                        let variables = variables.merged(example);
+                        messages.push(PromptMessage {
+                            role: SessionRole::User,
+                            content: input.render_with(&variables, partials)?,
+                        });
+                        messages.push(PromptMessage {
+                            role: SessionRole::Assistant,
+                            content: output.render_with(&variables, partials)?,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(ChatPrompt { messages })
+    }
+}
+
+fn parse_role(role: &str) -> Result<SessionRole, PromptError> {
+    match role {
+        "system" => Ok(SessionRole::System),
+        "user" => Ok(SessionRole::User),
+        "assistant" => Ok(SessionRole::Assistant),
+        _ => Err(PromptError::InvalidTemplate(format!("unknown role `{}`", role))),
+    }
+}
+
+#[derive(Deserialize)]
+struct ChatTemplateFile {
+    messages: Vec<ChatEntryFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ChatEntryFile {
+    Message { role: String, content: String },
+    Examples { examples: ExamplesFile },
+}
+
+#[derive(Deserialize)]
+struct ExamplesFile {
+    input: String,
+    output: String,
+    #[serde(default)]
+    values: Vec<Variables>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSLATION: &str = r#"{
+        "messages": [
+            { "role": "system", "content": "Translate from English to {{language}}." },
+            {
+                "examples": {
+                    "input": "{{text}}",
+                    "output": "{{translation}}",
+                    "values": [{ "text": "cat", "translation": "chat" }]
+                }
+            },
+            { "role": "user", "content": "{{text}}" }
+        ]
+    }"#;
+
+    #[test]
+    fn test_render_chat_template() {
+        let template = ChatTemplate::from_json(TRANSLATION).unwrap();
+        let variables = Variables::new().with("language", "French").with("text", "dog");
+        let prompt = template.render(&variables).unwrap();
+
+        let ollama = prompt.to_ollama_messages();
+        assert_eq!(ollama.len(), 4);
+        assert_eq!(ollama[1].content, "cat");
+        assert_eq!(ollama[2].role, "assistant");
+        assert_eq!(ollama[3].content, "dog");
+
+        let request = prompt.to_claude_request();
+        assert_eq!(request.system.as_deref(), Some("Translate from English to French."));
+        assert_eq!(request.messages.len(), 3);
+
+        assert_eq!(
+            prompt.to_mistral_prompt(),
+            "<s>[INST] Translate from English to French.\n\ncat [/INST] chat</s>[INST] dog [/INST]"
+        );
+    }
+
+    #[test]
+    fn test_invalid_chat_template() {
+        assert!(matches!(
+            ChatTemplate::from_json(r#"{ "messages": [{ "role": "tool", "content": "" }] }"#),
+            Err(PromptError::InvalidTemplate(_))
+        ));
+        let template = ChatTemplate::from_json(TRANSLATION).unwrap();
+        assert!(matches!(
+            template.render(&Variables::new().with("text", "dog")),
+            Err(PromptError::MissingVariables(names)) if names == vec!["language"]
+        ));
+    }
+}
@@ -0,0 +1,31 @@
+use serde_json::Error as SerdeJsonError;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum PromptError {
+    #[error("JSON error: {0}")]
+    Json(#[from] SerdeJsonError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The template is malformed, e.g. a `{{` is not closed. `offset` is in bytes.
+    #[error("Syntax error at offset {offset}: {message}")]
+    Syntax { offset: usize, message: String },
+
+    #[error("Invalid template: {0}")]
+    InvalidTemplate(String),
+
+    #[error("Missing variables: {}", .0.join(", "))]
+    MissingVariables(Vec<String>),
+
+    #[error("Unknown partial: {0}")]
+    UnknownPartial(String),
+
+    /// The partials include each other more deeply than `MAX_PARTIAL_DEPTH`, usually in a cycle.
+    #[error("Partials nested too deeply at: {0}")]
+    PartialDepth(String),
+
+    #[error("Unknown template: {0}")]
+    UnknownTemplate(String),
+}
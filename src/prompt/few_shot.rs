@@ -0,0 +1,71 @@
+use crate::prompt::error::PromptError;
+use crate::prompt::template::{Partials, PromptTemplate, Variables};
+
+/// A block of examples for few-shot prompting: each example is rendered with the same
+/// template, and the results are joined by a separator.
+///
+/// The rendered block is usually the value of a variable of the main template.
+///
+/// # Example
+///
+/// ```
+/// use hiramu::prompt::{FewShot, PromptTemplate, Variables};
+///
+/// let examples = FewShot::new(PromptTemplate::new("Q: {{q}}\nA: {{a}}").unwrap())
+///     .example(Variables::new().with("q", "2+2").with("a", 4))
+///     .example(Variables::new().with("q", "3+3").with("a", 6));
+/// assert_eq!(examples.render().unwrap(), "Q: 2+2\nA: 4\n\nQ: 3+3\nA: 6");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct FewShot {
+    template: PromptTemplate,
+    examples: Vec<Variables>,
+    separator: String,
+}
+
+impl FewShot {
+    pub fn new(template: PromptTemplate) -> Self {
+        Self {
+            template,
+            examples: Vec::new(),
+            separator: "\n\n".to_string(),
+        }
+    }
+
+    pub fn example(mut self, example: Variables) -> Self {
+        self.examples.push(example);
+        self
+    }
+
+    pub fn examples<I: IntoIterator<Item = Variables>>(mut self, examples: I) -> Self {
+        self.examples.extend(examples);
+        self
+    }
+
+    /// Sets the text between two examples, a blank line by default.
+    pub fn separator<S: Into<String>>(mut self, separator: S) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    pub fn render(&self) -> Result<String, PromptError> {
+        self.render_with(&Variables::new(), &Partials::new())
+    }
+
+    /// Renders the examples, each with `variables` overridden by its own values.
+    pub fn render_with(
+        &self,
+        variables: &Variables,
+        partials: &Partials,
+    ) -> Result<String, PromptError> {
+        let examples = self
+            .examples
+            .iter()
+            .map(|example| {
+                self.template
+                    .render_with(&variables.merged(example), partials)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(examples.join(&self.separator))
+    }
+}
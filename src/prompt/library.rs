@@ -0,0 +1,117 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::prompt::chat::{ChatPrompt, ChatTemplate};
+use crate::prompt::error::PromptError;
+use crate::prompt::template::{Partials, PromptTemplate, Variables};
+
+/// The templates of a directory, so they can be edited without changing the code.
+///
+/// Each `.txt`, `.md` or `.prompt` file is a text template, named after the file without
+/// its extension, and each `.json` file a chat template. The text templates are also the
+/// partials of all the templates, so `{{> persona}}` includes `persona.txt`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PromptLibrary {
+    templates: Partials,
+    chats: BTreeMap<String, ChatTemplate>,
+}
+
+impl PromptLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the templates of a directory. The subdirectories are ignored.
+    pub fn from_dir<P: AsRef<Path>>(directory: P) -> Result<Self, PromptError> {
+        let mut library = PromptLibrary::new();
+        for entry in std::fs::read_dir(directory)? {
+            let path = entry?.path();
+            let (Some(name), Some(extension)) = (
+                path.file_stem().and_then(|stem| stem.to_str()),
+                path.extension().and_then(|extension| extension.to_str()),
+            ) else {
+                continue;
+            };
+            match extension {
+                "txt" | "md" | "prompt" => {
+                    library.insert_template(name, PromptTemplate::from_file(&path)?)
+                }
+                "json" => library.insert_chat(name, ChatTemplate::from_file(&path)?),
+                _ => {}
+            }
+        }
+        Ok(library)
+    }
+
+    pub fn insert_template<S: Into<String>>(&mut self, name: S, template: PromptTemplate) {
+        self.templates.insert(name, template);
+    }
+
+    pub fn insert_chat<S: Into<String>>(&mut self, name: S, template: ChatTemplate) {
+        self.chats.insert(name.into(), template);
+    }
+
+    pub fn template(&self, name: &str) -> Option<&PromptTemplate> {
+        self.templates.get(name)
+    }
+
+    pub fn chat(&self, name: &str) -> Option<&ChatTemplate> {
+        self.chats.get(name)
+    }
+
+    /// Renders the text template `name` with the library as partials.
+    pub fn render(&self, name: &str, variables: &Variables) -> Result<String, PromptError> {
+        self.template(name)
+            .ok_or_else(|| PromptError::UnknownTemplate(name.to_string()))?
+            .render_with(variables, &self.templates)
+    }
+
+    /// Renders the chat template `name` with the library as partials.
+    pub fn render_chat(
+        &self,
+        name: &str,
+        variables: &Variables,
+    ) -> Result<ChatPrompt, PromptError> {
+        self.chat(name)
+            .ok_or_else(|| PromptError::UnknownTemplate(name.to_string()))?
+            .render_with(variables, &self.templates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_directory() {
+        let directory =
+            std::env::temp_dir().join(format!("hiramu-prompt-library-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("persona.txt"), "You are {{name}}.").unwrap();
+        std::fs::write(directory.join("greeting.prompt"), "{{> persona}} Say hello.").unwrap();
+        std::fs::write(
+            directory.join("chat.json"),
+            r#"{ "messages": [
+                { "role": "system", "content": "{{> persona}}" },
+                { "role": "user", "content": "{{question}}" }
+            ] }"#,
+        )
+        .unwrap();
+        std::fs::write(directory.join("notes.csv"), "ignored").unwrap();
+
+        let library = PromptLibrary::from_dir(&directory).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        let variables = Variables::new().with("name", "Hiramu").with("question", "Hi?");
+        assert_eq!(
+            library.render("greeting", &variables).unwrap(),
+            "You are Hiramu. Say hello."
+        );
+        let prompt = library.render_chat("chat", &variables).unwrap();
+        assert_eq!(prompt.system().as_deref(), Some("You are Hiramu."));
+        assert!(matches!(
+            library.render("notes", &variables),
+            Err(PromptError::UnknownTemplate(_))
+        ));
+    }
+}
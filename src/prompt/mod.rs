@@ -0,0 +1,14 @@
+//! Prompt templates: text templates with named variables and partials, few-shot example
+//! blocks, and chat templates rendering into the messages of Ollama, Claude and Mistral.
+
+pub mod chat;
+pub mod error;
+pub mod few_shot;
+pub mod library;
+pub mod template;
+
+pub use chat::{ChatPrompt, ChatTemplate, PromptMessage};
+pub use error::PromptError;
+pub use few_shot::FewShot;
+pub use library::PromptLibrary;
+pub use template::{Partials, PromptTemplate, Variables, MAX_PARTIAL_DEPTH};
@@ -0,0 +1,313 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::prompt::error::PromptError;
+
+/// How deeply partials may include other partials.
+pub const MAX_PARTIAL_DEPTH: usize = 8;
+
+/// The values of the variables of a template, by name.
+///
+/// # Example
+///
+/// ```
+/// use hiramu::prompt::Variables;
+///
+/// let variables = Variables::new().with("city", "Paris").with("days", 3);
+/// assert_eq!(variables.get("days"), Some("3"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Variables(BTreeMap<String, String>);
+
+impl Variables {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with<K: Into<String>, V: ToString>(mut self, name: K, value: V) -> Self {
+        self.insert(name, value);
+        self
+    }
+
+    pub fn insert<K: Into<String>, V: ToString>(&mut self, name: K, value: V) {
+        self.0.insert(name.into(), value.to_string());
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    /// These variables, overridden by `other`.
+    pub fn merged(&self, other: &Variables) -> Variables {
+        let mut merged = self.clone();
+        merged
+            .0
+            .extend(other.0.iter().map(|(name, value)| (name.clone(), value.clone())));
+        merged
+    }
+}
+
+impl<K: Into<String>, V: ToString> FromIterator<(K, V)> for Variables {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut variables = Variables::new();
+        for (name, value) in iter {
+            variables.insert(name, value);
+        }
+        variables
+    }
+}
+
+/// Named templates that other templates include with `{{> name}}`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Partials(BTreeMap<String, PromptTemplate>);
+
+impl Partials {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with<S: Into<String>>(mut self, name: S, template: PromptTemplate) -> Self {
+        self.insert(name, template);
+        self
+    }
+
+    pub fn insert<S: Into<String>>(&mut self, name: S, template: PromptTemplate) {
+        self.0.insert(name.into(), template);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PromptTemplate> {
+        self.0.get(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.0.keys()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    Variable(String),
+    Partial(String),
+}
+
+/// A text template with named variables.
+///
+/// `{{name}}` is replaced by the value of the variable `name`, `{{> name}}` by the partial
+/// `name` rendered with the same variables, and `\{{` is a literal `{{`. Names are made of
+/// letters, digits, `_`, `-` and `.`.
+///
+/// # Example
+///
+/// ```
+/// use hiramu::prompt::{Partials, PromptTemplate, Variables};
+///
+/// let template = PromptTemplate::new("{{> persona}} Plan {{days}} days in {{city}}.").unwrap();
+/// let partials = Partials::new().with(
+///     "persona",
+///     PromptTemplate::new("You are a travel agent.").unwrap(),
+/// );
+/// let variables = Variables::new().with("city", "Paris").with("days", 3);
+/// assert_eq!(
+///     template.render_with(&variables, &partials).unwrap(),
+///     "You are a travel agent. Plan 3 days in Paris."
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptTemplate {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl PromptTemplate {
+    /// Parses a template, failing with a `Syntax` error if it is malformed.
+    pub fn new<S: Into<String>>(source: S) -> Result<Self, PromptError> {
+        let source = source.into();
+        let segments = parse(&source)?;
+        Ok(Self { source, segments })
+    }
+
+    /// Loads a template from a text file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, PromptError> {
+        Self::new(std::fs::read_to_string(path)?)
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The variables of the template, in order of first use, without those of its partials.
+    pub fn variables(&self) -> Vec<&str> {
+        self.names(|segment| match segment {
+            Segment::Variable(name) => Some(name),
+            _ => None,
+        })
+    }
+
+    /// The partials included by the template, in order of first use.
+    pub fn partials(&self) -> Vec<&str> {
+        self.names(|segment| match segment {
+            Segment::Partial(name) => Some(name),
+            _ => None,
+        })
+    }
+
+    /// Renders a template without partials.
+    pub fn render(&self, variables: &Variables) -> Result<String, PromptError> {
+        self.render_with(variables, &Partials::new())
+    }
+
+    /// Renders the template, failing with `MissingVariables` listing every variable without
+    /// a value, including those of the partials.
+    pub fn render_with(
+        &self,
+        variables: &Variables,
+        partials: &Partials,
+    ) -> Result<String, PromptError> {
+        let mut output = String::with_capacity(self.source.len());
+        let mut missing = Vec::new();
+        self.render_into(&mut output, &mut missing, variables, partials, 0)?;
+        if missing.is_empty() {
+            Ok(output)
+        } else {
+            Err(PromptError::MissingVariables(missing))
+        }
+    }
+
+    fn render_into(
+        &self,
+        output: &mut String,
+        missing: &mut Vec<String>,
+        variables: &Variables,
+        partials: &Partials,
+        depth: usize,
+    ) -> Result<(), PromptError> {
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => output.push_str(text),
+                Segment::Variable(name) => match variables.get(name) {
+                    Some(value) => output.push_str(value),
+                    None if !missing.contains(name) => missing.push(name.clone()),
+                    None => {}
+                },
+                Segment::Partial(name) => {
+                    if depth >= MAX_PARTIAL_DEPTH {
+                        return Err(PromptError::PartialDepth(name.clone()));
+                    }
+                    let partial = partials
+                        .get(name)
+                        .ok_or_else(|| PromptError::UnknownPartial(name.clone()))?;
+                    partial.render_into(output, missing, variables, partials, depth + 1)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn names<'s>(&'s self, name: impl Fn(&'s Segment) -> Option<&'s String>) -> Vec<&'s str> {
+        let mut names: Vec<&str> = Vec::new();
+        for found in self.segments.iter().filter_map(name) {
+            if !names.contains(&found.as_str()) {
+                names.push(found);
+            }
+        }
+        names
+    }
+}
+
+fn parse(source: &str) -> Result<Vec<Segment>, PromptError> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut rest = source;
+    while let Some(start) = rest.find("{{") {
+        if rest[..start].ends_with('\\') {
+            text.push_str(&rest[..start - 1]);
+            text.push_str("{{");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        text.push_str(&rest[..start]);
+        let offset = source.len() - rest.len() + start;
+        let tag_len = rest[start + 2..].find("}}").ok_or_else(|| PromptError::Syntax {
+            offset,
+            message: "`{{` is not closed".to_string(),
+        })?;
+        let tag = rest[start + 2..start + 2 + tag_len].trim();
+        let segment = match tag.strip_prefix('>') {
+            Some(name) => Segment::Partial(check_name(name.trim(), offset)?),
+            None => Segment::Variable(check_name(tag, offset)?),
+        };
+        if !text.is_empty() {
+            segments.push(Segment::Text(std::mem::take(&mut text)));
+        }
+        segments.push(segment);
+        rest = &rest[start + 2 + tag_len + 2..];
+    }
+    text.push_str(rest);
+    if !text.is_empty() {
+        segments.push(Segment::Text(text));
+    }
+    Ok(segments)
+}
+
+fn check_name(name: &str, offset: usize) -> Result<String, PromptError> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if valid {
+        Ok(name.to_string())
+    } else {
+        Err(PromptError::Syntax {
+            offset,
+            message: format!("invalid name `{}`", name),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_variables() {
+        let template = PromptTemplate::new("Hello {{ name }}, \\{{name}} is {{name}}.").unwrap();
+        assert_eq!(template.variables(), vec!["name"]);
+        assert_eq!(
+            template.render(&Variables::new().with("name", "Ada")).unwrap(),
+            "Hello Ada, {{name}} is Ada."
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let template = PromptTemplate::new("{{a}} {{> footer}}").unwrap();
+        let partials = Partials::new().with("footer", PromptTemplate::new("{{b}} {{a}}").unwrap());
+        match template.render_with(&Variables::new(), &partials) {
+            Err(PromptError::MissingVariables(names)) => assert_eq!(names, vec!["a", "b"]),
+            other => panic!("expected missing variables, got {:?}", other),
+        }
+        assert!(matches!(
+            template.render(&Variables::new()),
+            Err(PromptError::UnknownPartial(name)) if name == "footer"
+        ));
+
+        let cycle = Partials::new().with("footer", PromptTemplate::new("{{> footer}}").unwrap());
+        assert!(matches!(
+            template.render_with(&Variables::new().with("a", 1), &cycle),
+            Err(PromptError::PartialDepth(_))
+        ));
+
+        assert!(matches!(
+            PromptTemplate::new("Hi {{name"),
+            Err(PromptError::Syntax { offset: 3, .. })
+        ));
+        assert!(matches!(
+            PromptTemplate::new("Hi {{first name}}"),
+            Err(PromptError::Syntax { .. })
+        ));
+    }
+}
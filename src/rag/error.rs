@@ -0,0 +1,21 @@
+use thiserror::Error;
+
+use crate::prompt::error::PromptError;
+use crate::session::error::SessionError;
+use crate::vector::error::VectorError;
+
+#[derive(Error, Debug)]
+pub enum RagError {
+    #[error("Vector store error: {0}")]
+    Vector(#[from] VectorError),
+
+    #[error("Chat error: {0}")]
+    Chat(#[from] SessionError),
+
+    #[error("Prompt error: {0}")]
+    Prompt(#[from] PromptError),
+
+    /// A step of the pipeline failed, e.g. a custom re-ranker.
+    #[error("{step} failed: {message}")]
+    Step { step: &'static str, message: String },
+}
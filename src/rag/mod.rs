@@ -0,0 +1,10 @@
+//! Retrieval-augmented generation: answers questions from the documents of a vector store,
+//! with citations of the chunks the answer is based on.
+
+pub mod error;
+pub mod pipeline;
+pub mod steps;
+
+pub use error::RagError;
+pub use pipeline::{Rag, RagAnswer, Source, DEFAULT_CONTEXT_TOKENS, DEFAULT_TOP_K};
+pub use steps::{KeywordReranker, ModelQueryRewriter, QueryRewriter, Reranker};
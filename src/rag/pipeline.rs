@@ -0,0 +1,457 @@
+use std::sync::Arc;
+
+use futures::TryStreamExt;
+
+use crate::prompt::{PromptTemplate, Variables};
+use crate::rag::error::RagError;
+use crate::rag::steps::{QueryRewriter, Reranker};
+use crate::session::{ChatBackend, SessionMessage};
+use crate::splitter::Metadata;
+use crate::tokenizer::{HeuristicTokenizer, Tokenizer};
+use crate::vector::{Embedder, Filter, SearchResult, VectorStore};
+
+/// The number of chunks given to the model by default.
+pub const DEFAULT_TOP_K: usize = 5;
+
+/// The default token budget of the sources in the prompt.
+pub const DEFAULT_CONTEXT_TOKENS: usize = 3000;
+
+const DEFAULT_SYSTEM: &str = "Answer the question using only the numbered sources. Cite the \
+     sources supporting each statement with their numbers in square brackets, e.g. [1] or \
+     [2][3]. If the sources do not contain the answer, say that you don't know.";
+
+const DEFAULT_TEMPLATE: &str = "Sources:\n\n{{sources}}\n\nQuestion: {{question}}";
+
+/// A chunk given to the model, with the number it is cited by.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Source {
+    pub number: usize,
+    pub id: String,
+    pub score: f32,
+    pub text: String,
+    pub metadata: Metadata,
+}
+
+/// The answer of the model and the chunks it was given.
+///
+/// # Fields
+///
+/// * `answer` - The text of the answer, citing the sources as `[n]`.
+/// * `sources` - The chunks given to the model, numbered from 1.
+/// * `citations` - The numbers of the sources cited by the answer, in order of first citation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RagAnswer {
+    pub answer: String,
+    pub sources: Vec<Source>,
+    pub citations: Vec<usize>,
+}
+
+impl RagAnswer {
+    /// The sources cited by the answer, in order of first citation.
+    pub fn cited_sources(&self) -> Vec<&Source> {
+        self.citations
+            .iter()
+            .filter_map(|number| number.checked_sub(1).and_then(|index| self.sources.get(index)))
+            .collect()
+    }
+}
+
+/// Answers questions from the documents of a vector store: retrieves the chunks most
+/// similar to the question, packs them into the prompt within a token budget, and asks the
+/// chat model to answer citing them.
+///
+/// A `QueryRewriter` can search several reformulations of the question, and a `Reranker`
+/// reorder the retrieved chunks before the best `top_k` are kept.
+///
+/// # Example
+///
+/// ```no_run
+/// use hiramu::ollama::OllamaClient;
+/// use hiramu::rag::{KeywordReranker, Rag, RagError};
+/// use hiramu::session::OllamaChatOptions;
+/// use hiramu::vector::{Metric, OllamaEmbedder, VectorStore};
+///
+/// # async fn run(store: VectorStore) -> Result<(), RagError> {
+/// let client = OllamaClient::new("http://localhost:11434".to_string());
+/// let embedder = OllamaEmbedder::new(&client, "nomic-embed-text");
+/// let rag = Rag::new(&embedder, &store, &client, OllamaChatOptions::new("llama3"))
+///     .top_k(4)
+///     .reranker(KeywordReranker::new());
+///
+/// let answer = rag.ask("How do I install hiramu?").await?;
+/// println!("{}", answer.answer);
+/// for source in answer.cited_sources() {
+///     println!("[{}] {}", source.number, source.id);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct Rag<'a, E: Embedder, B: ChatBackend> {
+    embedder: &'a E,
+    store: &'a VectorStore,
+    backend: &'a B,
+    options: B::Options,
+    system: String,
+    template: PromptTemplate,
+    top_k: usize,
+    candidates: Option<usize>,
+    filter: Option<Filter>,
+    context_tokens: usize,
+    tokenizer: Arc<dyn Tokenizer>,
+    rewriter: Option<Arc<dyn QueryRewriter>>,
+    reranker: Option<Arc<dyn Reranker>>,
+}
+
+impl<'a, E: Embedder, B: ChatBackend> Rag<'a, E, B> {
+    pub fn new(
+        embedder: &'a E,
+        store: &'a VectorStore,
+        backend: &'a B,
+        options: B::Options,
+    ) -> Self {
+        Self {
+            embedder,
+            store,
+            backend,
+            options,
+            system: DEFAULT_SYSTEM.to_string(),
+            template: PromptTemplate::new(DEFAULT_TEMPLATE).expect("the default template is valid"),
+            top_k: DEFAULT_TOP_K,
+            candidates: None,
+            filter: None,
+            context_tokens: DEFAULT_CONTEXT_TOKENS,
+            tokenizer: Arc::new(HeuristicTokenizer::new()),
+            rewriter: None,
+            reranker: None,
+        }
+    }
+
+    /// Sets the system prompt, which should ask for citations as `[n]`.
+    pub fn system<S: Into<String>>(mut self, system: S) -> Self {
+        self.system = system.into();
+        self
+    }
+
+    /// Sets the template of the user message, rendered with the variables `sources` and
+    /// `question`.
+    pub fn template(mut self, template: PromptTemplate) -> Self {
+        self.template = template;
+        self
+    }
+
+    /// Sets the maximum number of chunks given to the model.
+    pub fn top_k(mut self, top_k: usize) -> Self {
+        self.top_k = top_k;
+        self
+    }
+
+    /// Sets the number of chunks retrieved per query before re-ranking, four times `top_k`
+    /// by default with a re-ranker.
+    pub fn candidates(mut self, candidates: usize) -> Self {
+        self.candidates = Some(candidates);
+        self
+    }
+
+    /// Only retrieves the chunks whose metadata match `filter`.
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Sets the token budget of the sources in the prompt. The chunks that would exceed it
+    /// are left out.
+    pub fn context_tokens(mut self, context_tokens: usize) -> Self {
+        self.context_tokens = context_tokens;
+        self
+    }
+
+    /// Sets the tokenizer measuring the sources, `HeuristicTokenizer` by default.
+    pub fn tokenizer<T: Tokenizer + 'static>(mut self, tokenizer: T) -> Self {
+        self.tokenizer = Arc::new(tokenizer);
+        self
+    }
+
+    pub fn rewriter<W: QueryRewriter + 'static>(mut self, rewriter: W) -> Self {
+        self.rewriter = Some(Arc::new(rewriter));
+        self
+    }
+
+    pub fn reranker<R: Reranker + 'static>(mut self, reranker: R) -> Self {
+        self.reranker = Some(Arc::new(reranker));
+        self
+    }
+
+    /// The `top_k` chunks for `question`, best first. A chunk found by several queries is
+    /// kept once, with its best score.
+    pub async fn retrieve(&self, question: &str) -> Result<Vec<SearchResult>, RagError> {
+        let queries = match &self.rewriter {
+            Some(rewriter) => rewriter.rewrite(question).await?,
+            None => vec![question.to_string()],
+        };
+        let candidates = match (self.candidates, &self.reranker) {
+            (Some(candidates), _) => candidates,
+            (None, Some(_)) => self.top_k * 4,
+            (None, None) => self.top_k,
+        };
+
+        let mut found: Vec<SearchResult> = Vec::new();
+        for query in &queries {
+            let results = self
+                .store
+                .search_text(
+                    self.embedder,
+                    query,
+                    candidates.max(self.top_k),
+                    self.filter.as_ref(),
+                )
+                .await?;
+            for result in results {
+                match found.iter_mut().find(|chunk| chunk.id == result.id) {
+                    Some(chunk) if chunk.score < result.score => *chunk = result,
+                    Some(_) => {}
+                    None => found.push(result),
+                }
+            }
+        }
+        found.sort_by(|a, b| b.score.total_cmp(&a.score));
+        if let Some(reranker) = &self.reranker {
+            found = reranker.rerank(question, found).await?;
+        }
+        found.truncate(self.top_k);
+        Ok(found)
+    }
+
+    /// Numbers the chunks that fit the token budget, in order.
+    pub fn pack(&self, chunks: Vec<SearchResult>) -> Vec<Source> {
+        let mut sources = Vec::new();
+        let mut tokens = 0;
+        for chunk in chunks {
+            let source = Source {
+                number: sources.len() + 1,
+                id: chunk.id,
+                score: chunk.score,
+                text: chunk.text,
+                metadata: chunk.metadata,
+            };
+            let source_tokens = self.tokenizer.count_tokens(&format_source(&source));
+            if tokens + source_tokens > self.context_tokens {
+                continue;
+            }
+            tokens += source_tokens;
+            sources.push(source);
+        }
+        sources
+    }
+
+    /// Retrieves the chunks for `question` and asks the model to answer from them.
+    pub async fn ask(&self, question: &str) -> Result<RagAnswer, RagError> {
+        let sources = self.pack(self.retrieve(question).await?);
+        let context = sources
+            .iter()
+            .map(format_source)
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let prompt = self.template.render(
+            &Variables::new()
+                .with("sources", context)
+                .with("question", question),
+        )?;
+
+        let messages = [B::Message::new_user(prompt)];
+        let answer: String = self
+            .backend
+            .chat_stream(Some(&self.system), &messages, &self.options)
+            .await?
+            .try_collect()
+            .await?;
+        let citations = citations(&answer, sources.len());
+        Ok(RagAnswer {
+            answer,
+            sources,
+            citations,
+        })
+    }
+}
+
+/// A source as shown to the model: its number, its `source` metadata or id, and its text.
+fn format_source(source: &Source) -> String {
+    let label = source.metadata.get("source").unwrap_or(&source.id);
+    format!("[{}] {}\n{}", source.number, label, source.text)
+}
+
+/// The source numbers cited as `[1]` or `[1, 2]`, in order of first citation. Numbers out
+/// of `1..=count` are ignored.
+fn citations(answer: &str, count: usize) -> Vec<usize> {
+    let mut cited = Vec::new();
+    let mut rest = answer;
+    while let Some(open) = rest.find('[') {
+        rest = &rest[open + 1..];
+        let Some(close) = rest.find(']') else {
+            break;
+        };
+        let inside = &rest[..close];
+        if !inside
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == ',' || c == ' ')
+        {
+            continue;
+        }
+        for number in inside
+            .split(',')
+            .filter_map(|part| part.trim().parse().ok())
+        {
+            if (1..=count).contains(&number) && !cited.contains(&number) {
+                cited.push(number);
+            }
+        }
+        rest = &rest[close + 1..];
+    }
+    cited
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_citations() {
+        assert_eq!(
+            citations(
+                "Paris [2]. It is big [1, 3][2]. See [7] and [a] or [ 1 ].",
+                3
+            ),
+            vec![2, 1, 3]
+        );
+        assert!(citations("No [citation", 3).is_empty());
+    }
+
+    #[test]
+    fn test_cited_sources_ignores_invalid_numbers() {
+        let source = Source {
+            number: 1,
+            id: "a".to_string(),
+            score: 1.0,
+            text: "Paris is the capital of France.".to_string(),
+            metadata: Metadata::new(),
+        };
+        let answer = RagAnswer {
+            answer: "Paris [1].".to_string(),
+            sources: vec![source],
+            citations: vec![0, 1, 2],
+        };
+
+        let cited: Vec<&str> = answer.cited_sources().iter().map(|source| source.id.as_str()).collect();
+
+        assert_eq!(cited, vec!["a"]);
+    }
+}
+
+#[cfg(test)]
+mod server_tests {
+    use super::*;
+    use crate::ollama::OllamaClient;
+    use crate::rag::steps::{KeywordReranker, ModelQueryRewriter};
+    use crate::session::OllamaChatOptions;
+    use crate::testing::{MockEndpoint, MockOllamaServer, MockResponse};
+    use crate::vector::{Document, Metric, OllamaEmbedder};
+
+    const DOCUMENTS: [(&str, &str); 3] = [
+        ("france", "Paris is the capital of France."),
+        ("spain", "Madrid is the capital of Spain."),
+        ("cats", "Cats sleep sixteen hours a day."),
+    ];
+
+    async fn store(embedder: &OllamaEmbedder<'_>) -> VectorStore {
+        let store = VectorStore::new(Metric::Cosine);
+        let documents = DOCUMENTS
+            .iter()
+            .map(|(id, text)| {
+                Document::new(*id, *text).with_metadata(Metadata::from([(
+                    "source".to_string(),
+                    format!("{}.md", id),
+                )]))
+            })
+            .collect();
+        store.add_documents(embedder, documents).await.unwrap();
+        store
+    }
+
+    #[tokio::test]
+    async fn test_ask_with_citations() {
+        let server = MockOllamaServer::start().await.unwrap();
+        let client = OllamaClient::new(server.base_url());
+        let embedder = OllamaEmbedder::new(&client, "nomic-embed-text");
+        let store = store(&embedder).await;
+        server.enqueue(
+            MockEndpoint::Chat,
+            MockResponse::text(["The capital ", "of France is Paris [1]."]),
+        );
+
+        let answer = Rag::new(&embedder, &store, &client, OllamaChatOptions::new("llama3"))
+            .top_k(2)
+            .ask("Paris is the capital of France.")
+            .await
+            .unwrap();
+        assert_eq!(answer.answer, "The capital of France is Paris [1].");
+        assert_eq!(answer.sources.len(), 2);
+        assert_eq!(answer.citations, vec![1]);
+        assert_eq!(answer.cited_sources()[0].id, "france");
+
+        let request = &server.requests_to(MockEndpoint::Chat)[0].body;
+        assert!(request["messages"][0]["content"]
+            .as_str()
+            .unwrap()
+            .contains("[2]"));
+        let prompt = request["messages"][1]["content"].as_str().unwrap();
+        assert!(
+            prompt.starts_with("Sources:\n\n[1] france.md\nParis is the capital of France.\n\n[2]"),
+            "{}",
+            prompt
+        );
+        assert!(prompt.ends_with("Question: Paris is the capital of France."));
+    }
+
+    #[tokio::test]
+    async fn test_rewrite_rerank_and_budget() {
+        let server = MockOllamaServer::start().await.unwrap();
+        let client = Arc::new(OllamaClient::new(server.base_url()));
+        let embedder = OllamaEmbedder::new(&client, "nomic-embed-text");
+        let store = store(&embedder).await;
+        server.enqueue(
+            MockEndpoint::Chat,
+            MockResponse::text([
+                "1. Cats sleep sixteen hours a day.\n",
+                "- capital of Spain\n",
+            ]),
+        );
+        let embeddings_before = server.requests_to(MockEndpoint::Embeddings).len();
+
+        let rag = Rag::new(
+            &embedder,
+            &store,
+            &*client,
+            OllamaChatOptions::new("llama3"),
+        )
+        .top_k(3)
+        .context_tokens(20)
+        .rewriter(ModelQueryRewriter::new(
+            client.clone(),
+            OllamaChatOptions::new("llama3"),
+        ))
+        .reranker(KeywordReranker::new());
+        let chunks = rag.retrieve("Where do cats sleep?").await.unwrap();
+        assert_eq!(chunks[0].id, "cats");
+        assert_eq!(
+            server.requests_to(MockEndpoint::Embeddings).len() - embeddings_before,
+            3
+        );
+
+        let sources = rag.pack(chunks);
+        assert_eq!(sources.len(), 1);
+
+        server.enqueue(MockEndpoint::Chat, MockResponse::text(["sleeping cats"]));
+        server.enqueue(MockEndpoint::Chat, MockResponse::text(["Everywhere [1]."]));
+        let answer = rag.ask("Where do cats sleep?").await.unwrap();
+        assert_eq!(answer.cited_sources()[0].id, "cats");
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use futures::TryStreamExt;
+
+use crate::rag::error::RagError;
+use crate::session::{ChatBackend, SessionMessage};
+use crate::vector::SearchResult;
+
+const DEFAULT_REWRITE_PROMPT: &str = "Rewrite the question of the user as short queries for a \
+     document search engine, covering the different ways the answer could be phrased. Reply \
+     with one query per line, without numbering or any other text.";
+
+/// Turns a question into the queries searched in the vector store.
+pub trait QueryRewriter: Send + Sync {
+    fn rewrite<'a>(&'a self, question: &'a str) -> BoxFuture<'a, Result<Vec<String>, RagError>>;
+}
+
+/// Reorders or drops the chunks retrieved for a question, best first.
+pub trait Reranker: Send + Sync {
+    fn rerank<'a>(
+        &'a self,
+        question: &'a str,
+        chunks: Vec<SearchResult>,
+    ) -> BoxFuture<'a, Result<Vec<SearchResult>, RagError>>;
+}
+
+/// A query rewriter asking a chat model for alternative search queries, searched along
+/// with the question itself.
+pub struct ModelQueryRewriter<B: ChatBackend> {
+    backend: Arc<B>,
+    options: B::Options,
+    prompt: String,
+    max_queries: usize,
+}
+
+impl<B: ChatBackend> ModelQueryRewriter<B> {
+    pub fn new(backend: Arc<B>, options: B::Options) -> Self {
+        Self {
+            backend,
+            options,
+            prompt: DEFAULT_REWRITE_PROMPT.to_string(),
+            max_queries: 3,
+        }
+    }
+
+    pub fn prompt<S: Into<String>>(mut self, prompt: S) -> Self {
+        self.prompt = prompt.into();
+        self
+    }
+
+    /// Sets the maximum number of queries added to the question.
+    pub fn max_queries(mut self, max_queries: usize) -> Self {
+        self.max_queries = max_queries;
+        self
+    }
+}
+
+impl<B> QueryRewriter for ModelQueryRewriter<B>
+where
+    B: ChatBackend + Send + Sync,
+{
+    fn rewrite<'a>(&'a self, question: &'a str) -> BoxFuture<'a, Result<Vec<String>, RagError>> {
+        Box::pin(async move {
+            let messages = [B::Message::new_user(question.to_string())];
+            let reply: String = self
+                .backend
+                .chat_stream(Some(&self.prompt), &messages, &self.options)
+                .await?
+                .try_collect()
+                .await?;
+            let mut queries = vec![question.to_string()];
+            let rewritten = reply
+                .lines()
+                .map(|line| {
+                    line.trim()
+                        .trim_start_matches(|c: char| {
+                            c.is_ascii_digit() || matches!(c, '-' | '*' | '.' | ')')
+                        })
+                        .trim()
+                        .trim_matches('"')
+                })
+                .filter(|query| !query.is_empty() && *query != question)
+                .take(self.max_queries);
+            queries.extend(rewritten.map(str::to_string));
+            Ok(queries)
+        })
+    }
+}
+
+/// A re-ranker ordering the chunks by the share of the words of the question they contain,
+/// keeping the retrieval order on ties. Words of less than three letters are ignored.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KeywordReranker;
+
+impl KeywordReranker {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 3)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+impl Reranker for KeywordReranker {
+    fn rerank<'a>(
+        &'a self,
+        question: &'a str,
+        mut chunks: Vec<SearchResult>,
+    ) -> BoxFuture<'a, Result<Vec<SearchResult>, RagError>> {
+        Box::pin(async move {
+            let question = words(question);
+            if question.is_empty() {
+                return Ok(chunks);
+            }
+            chunks.sort_by_cached_key(|chunk| {
+                std::cmp::Reverse(words(&chunk.text).intersection(&question).count())
+            });
+            Ok(chunks)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::splitter::Metadata;
+
+    fn chunk(id: &str, text: &str) -> SearchResult {
+        SearchResult {
+            id: id.to_string(),
+            score: 0.5,
+            text: text.to_string(),
+            metadata: Metadata::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_keyword_reranker() {
+        let chunks = vec![
+            chunk("a", "Cats sleep a lot."),
+            chunk("b", "The capital of France is Paris."),
+            chunk("c", "France borders Spain."),
+            chunk("d", "Dogs bark."),
+        ];
+        let ranked = KeywordReranker::new()
+            .rerank("What is the capital of France?", chunks)
+            .await
+            .unwrap();
+        let ids: Vec<&str> = ranked.iter().map(|chunk| chunk.id.as_str()).collect();
+        assert_eq!(ids, vec!["b", "c", "a", "d"]);
+    }
+}
@@ -0,0 +1,137 @@
+use std::future::Future;
+
+use futures::stream::{BoxStream, StreamExt, TryStreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::bedrock::models::claude::claude_request_message::{
+    ChatOptions, ConversationRequest, Message as ClaudeMessage,
+};
+use crate::bedrock::models::claude::ClaudeClient;
+use crate::ollama::{ChatRequestBuilder, Message as OllamaMessage, OllamaClient};
+use crate::session::error::SessionError;
+use crate::session::session_message::SessionMessage;
+
+/// A stream of text deltas produced by a `ChatBackend`.
+pub type TextStream = BoxStream<'static, Result<String, SessionError>>;
+
+/// A chat model that a `ChatSession` can send its history to.
+pub trait ChatBackend {
+    /// The message type stored in the session history.
+    type Message: SessionMessage;
+
+    /// The options stored in the session and used for every request.
+    type Options: Clone + Serialize + DeserializeOwned + Send + Sync;
+
+    /// Sends the conversation to the model and returns a stream of the response text.
+    fn chat_stream(
+        &self,
+        system: Option<&str>,
+        messages: &[Self::Message],
+        options: &Self::Options,
+    ) -> impl Future<Output = Result<TextStream, SessionError>> + Send;
+}
+
+/// Options used by a `ChatSession` talking to Ollama.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OllamaChatOptions {
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub options: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub keep_alive: Option<String>,
+}
+
+impl OllamaChatOptions {
+    pub fn new<S: Into<String>>(model: S) -> Self {
+        Self {
+            model: model.into(),
+            options: None,
+            format: None,
+            keep_alive: None,
+        }
+    }
+
+    pub fn options(mut self, options: Value) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    pub fn format<S: Into<String>>(mut self, format: S) -> Self {
+        self.format = Some(format.into());
+        self
+    }
+
+    pub fn keep_alive<S: Into<String>>(mut self, keep_alive: S) -> Self {
+        self.keep_alive = Some(keep_alive.into());
+        self
+    }
+}
+
+impl ChatBackend for OllamaClient {
+    type Message = OllamaMessage;
+    type Options = OllamaChatOptions;
+
+    fn chat_stream(
+        &self,
+        system: Option<&str>,
+        messages: &[Self::Message],
+        options: &Self::Options,
+    ) -> impl Future<Output = Result<TextStream, SessionError>> + Send {
+        let mut all_messages = Vec::with_capacity(messages.len() + 1);
+        if let Some(system) = system {
+            all_messages.push(OllamaMessage::new(
+                "system".to_string(),
+                system.to_string(),
+            ));
+        }
+        all_messages.extend_from_slice(messages);
+
+        let mut builder = ChatRequestBuilder::new(options.model.clone()).messages(all_messages);
+        if let Some(chat_options) = &options.options {
+            builder = builder.options(chat_options.clone());
+        }
+        if let Some(format) = &options.format {
+            builder = builder.format(format.clone());
+        }
+        if let Some(keep_alive) = &options.keep_alive {
+            builder = builder.keep_alive(keep_alive.clone());
+        }
+        let request = builder.build();
+
+        async move {
+            let stream = self.chat(request).await?;
+            let stream = stream
+                .map_ok(|response| response.message.content)
+                .map_err(SessionError::from);
+            Ok(stream.boxed())
+        }
+    }
+}
+
+impl ChatBackend for ClaudeClient {
+    type Message = ClaudeMessage;
+    type Options = ChatOptions;
+
+    fn chat_stream(
+        &self,
+        system: Option<&str>,
+        messages: &[Self::Message],
+        options: &Self::Options,
+    ) -> impl Future<Output = Result<TextStream, SessionError>> + Send {
+        let request = ConversationRequest {
+            system: system.map(str::to_string),
+            messages: messages.to_vec(),
+            max_tokens: Some(options.max_tokens as i32),
+            ..Default::default()
+        };
+
+        async move {
+            let stream = self.chat_with_text_stream(&request, options).await?;
+            Ok(stream.map_err(SessionError::from).boxed())
+        }
+    }
+}
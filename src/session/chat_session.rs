@@ -0,0 +1,577 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::stream::{Stream, StreamExt, TryStreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::bedrock::models::claude::claude_request_message::{ChatOptions, Message as ClaudeMessage};
+use crate::ollama::Message as OllamaMessage;
+use crate::session::chat_backend::{ChatBackend, OllamaChatOptions, TextStream};
+use crate::session::context_policy::{with_summary, ContextPolicy};
+use crate::session::error::SessionError;
+use crate::session::session_message::{SessionMessage, SessionRole};
+use crate::session::summary_memory::SummaryMemory;
+
+/// A conversation with a chat model: the history, the system prompt and the options
+/// used for every request.
+///
+/// The session appends the user turn when a message is sent, and the assistant turn once
+/// the response stream completes. If the stream fails, the user turn is kept so the
+/// request can be sent again with `retry`, or dropped with `undo`.
+///
+/// When a `ContextPolicy` is set, the history is trimmed to fit the context window of the
+/// model before every request. The trimming only applies to the request: the session keeps
+/// the full history.
+///
+/// When a `SummaryMemory` is set, the older turns are folded into a rolling summary before
+/// a message is sent once the history exceeds its threshold. Unlike the trimming of a
+/// context policy, this removes them from the history. The summary is sent at the end of
+/// the system prompt.
+///
+/// A session can be saved to a JSON file and loaded later to resume the conversation. The
+/// summary is saved with the history, but the context policy and the memory are not and
+/// must be set again after loading.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "M: Serialize, O: Serialize",
+    deserialize = "M: DeserializeOwned, O: DeserializeOwned"
+))]
+pub struct ChatSession<M, O> {
+    system: Option<String>,
+    options: O,
+    history: Vec<M>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    summary: Option<String>,
+    #[serde(skip)]
+    context_policy: Option<ContextPolicy>,
+    #[serde(skip)]
+    memory: Option<SummaryMemory>,
+}
+
+/// A `ChatSession` for Ollama chat models.
+pub type OllamaChatSession = ChatSession<OllamaMessage, OllamaChatOptions>;
+
+/// A `ChatSession` for Claude models on Bedrock.
+pub type ClaudeChatSession = ChatSession<ClaudeMessage, ChatOptions>;
+
+impl<M, O> ChatSession<M, O>
+where
+    M: SessionMessage,
+    O: Clone + Serialize + DeserializeOwned + Send + Sync,
+{
+    pub fn new(options: O) -> Self {
+        Self {
+            system: None,
+            options,
+            history: Vec::new(),
+            summary: None,
+            context_policy: None,
+            memory: None,
+        }
+    }
+
+    pub fn with_system<S: Into<String>>(mut self, system: S) -> Self {
+        self.system = Some(system.into());
+        self
+    }
+
+    pub fn with_history(mut self, history: Vec<M>) -> Self {
+        self.history = history;
+        self
+    }
+
+    pub fn with_context_policy(mut self, context_policy: ContextPolicy) -> Self {
+        self.context_policy = Some(context_policy);
+        self
+    }
+
+    pub fn set_context_policy(&mut self, context_policy: Option<ContextPolicy>) {
+        self.context_policy = context_policy;
+    }
+
+    pub fn context_policy(&self) -> Option<&ContextPolicy> {
+        self.context_policy.as_ref()
+    }
+
+    pub fn with_memory(mut self, memory: SummaryMemory) -> Self {
+        self.memory = Some(memory);
+        self
+    }
+
+    pub fn set_memory(&mut self, memory: Option<SummaryMemory>) {
+        self.memory = memory;
+    }
+
+    pub fn memory(&self) -> Option<&SummaryMemory> {
+        self.memory.as_ref()
+    }
+
+    /// The rolling summary of the turns removed from the history by the memory.
+    pub fn summary(&self) -> Option<&str> {
+        self.summary.as_deref()
+    }
+
+    pub fn set_summary(&mut self, summary: Option<String>) {
+        self.summary = summary;
+    }
+
+    /// Folds the older turns into the summary if the history exceeds the threshold of the
+    /// memory. Returns whether it did; does nothing without a memory.
+    ///
+    /// This runs before every message sent, and can be called after loading a session.
+    pub async fn compact(&mut self) -> Result<bool, SessionError> {
+        match &self.memory {
+            Some(memory) => memory.compact(&mut self.summary, &mut self.history).await,
+            None => Ok(false),
+        }
+    }
+
+    pub fn system(&self) -> Option<&str> {
+        self.system.as_deref()
+    }
+
+    pub fn set_system(&mut self, system: Option<String>) {
+        self.system = system;
+    }
+
+    pub fn options(&self) -> &O {
+        &self.options
+    }
+
+    pub fn options_mut(&mut self) -> &mut O {
+        &mut self.options
+    }
+
+    pub fn history(&self) -> &[M] {
+        &self.history
+    }
+
+    pub fn push(&mut self, message: M) {
+        self.history.push(message);
+    }
+
+    /// Removes all messages and the summary, keeping the system prompt and options.
+    pub fn clear(&mut self) {
+        self.history.clear();
+        self.summary = None;
+    }
+
+    /// Sends a user message and streams the response.
+    ///
+    /// The assistant turn is appended to the history once the returned stream is exhausted.
+    /// If the previous turn failed, the history ends with its user message, and this fails
+    /// with `SessionError::UnansweredTurn`: use `retry` to send it again, or `undo` to
+    /// remove it.
+    pub async fn send_stream<B>(
+        &mut self,
+        backend: &B,
+        input: impl Into<String>,
+    ) -> Result<SessionStream<'_, M>, SessionError>
+    where
+        B: ChatBackend<Message = M, Options = O>,
+    {
+        if self.last_role() == Some(SessionRole::User) {
+            return Err(SessionError::UnansweredTurn(
+                "the last user message has no response, retry or undo it first".to_string(),
+            ));
+        }
+        self.compact().await?;
+        self.history.push(M::new_user(input.into()));
+        match self.start_turn(backend).await {
+            Ok(stream) => Ok(SessionStream::new(stream, &mut self.history)),
+            Err(err) => {
+                self.history.pop();
+                Err(err)
+            }
+        }
+    }
+
+    /// Sends a user message and returns the full response text.
+    pub async fn send<B>(&mut self, backend: &B, input: impl Into<String>) -> Result<String, SessionError>
+    where
+        B: ChatBackend<Message = M, Options = O>,
+    {
+        self.send_stream(backend, input).await?.collect_text().await
+    }
+
+    /// Sends the last user turn again, replacing the assistant response if there is one.
+    pub async fn retry_stream<B>(&mut self, backend: &B) -> Result<SessionStream<'_, M>, SessionError>
+    where
+        B: ChatBackend<Message = M, Options = O>,
+    {
+        if self.last_role() == Some(SessionRole::Assistant) {
+            self.history.pop();
+        }
+        if self.last_role() != Some(SessionRole::User) {
+            return Err(SessionError::NothingToRetry(
+                "the history does not end with a user message".to_string(),
+            ));
+        }
+        let stream = self.start_turn(backend).await?;
+        Ok(SessionStream::new(stream, &mut self.history))
+    }
+
+    /// Sends the last user turn again and returns the full response text.
+    pub async fn retry<B>(&mut self, backend: &B) -> Result<String, SessionError>
+    where
+        B: ChatBackend<Message = M, Options = O>,
+    {
+        self.retry_stream(backend).await?.collect_text().await
+    }
+
+    /// Removes the last turn: the assistant response, if any, and the user message before it.
+    ///
+    /// Returns the removed user message so it can be edited and sent again.
+    pub fn undo(&mut self) -> Option<M> {
+        if self.last_role() == Some(SessionRole::Assistant) {
+            self.history.pop();
+        }
+        match self.last_role() {
+            Some(SessionRole::User) => self.history.pop(),
+            _ => None,
+        }
+    }
+
+    /// Saves the session as JSON.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SessionError> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    /// Loads a session previously written by `save`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SessionError> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    fn last_role(&self) -> Option<SessionRole> {
+        self.history.last().map(SessionMessage::role)
+    }
+
+    async fn start_turn<B>(&self, backend: &B) -> Result<TextStream, SessionError>
+    where
+        B: ChatBackend<Message = M, Options = O>,
+    {
+        let system = match &self.summary {
+            Some(summary) => Some(with_summary(self.system.clone(), summary)),
+            None => self.system.clone(),
+        };
+        match &self.context_policy {
+            Some(policy) => {
+                let fitted = policy.fit(system.as_deref(), &self.history).await?;
+                backend
+                    .chat_stream(fitted.system.as_deref(), &fitted.messages, &self.options)
+                    .await
+            }
+            None => {
+                backend
+                    .chat_stream(system.as_deref(), &self.history, &self.options)
+                    .await
+            }
+        }
+    }
+}
+
+/// The response stream of a `ChatSession` turn.
+///
+/// Yields the text deltas of the response. When the stream completes without error, the
+/// assistant message is appended to the session history.
+pub struct SessionStream<'a, M> {
+    inner: TextStream,
+    history: &'a mut Vec<M>,
+    text: String,
+    failed: bool,
+    finished: bool,
+}
+
+impl<'a, M: SessionMessage> SessionStream<'a, M> {
+    fn new(inner: TextStream, history: &'a mut Vec<M>) -> Self {
+        Self {
+            inner,
+            history,
+            text: String::new(),
+            failed: false,
+            finished: false,
+        }
+    }
+
+    /// Drains the stream and returns the full response text.
+    pub async fn collect_text(mut self) -> Result<String, SessionError> {
+        while self.try_next().await?.is_some() {}
+        Ok(self
+            .history
+            .last()
+            .map(SessionMessage::text)
+            .unwrap_or_default())
+    }
+}
+
+impl<M: SessionMessage> Stream for SessionStream<'_, M> {
+    type Item = Result<String, SessionError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.finished {
+            return Poll::Ready(None);
+        }
+        let this = &mut *self;
+        match futures::ready!(this.inner.poll_next_unpin(cx)) {
+            Some(Ok(text)) => {
+                this.text.push_str(&text);
+                Poll::Ready(Some(Ok(text)))
+            }
+            Some(Err(err)) => {
+                this.failed = true;
+                Poll::Ready(Some(Err(err)))
+            }
+            None => {
+                this.finished = true;
+                if !this.failed {
+                    let text = std::mem::take(&mut this.text);
+                    this.history.push(M::new_assistant(text));
+                }
+                Poll::Ready(None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::context_policy::Summarizer;
+    use futures::future::BoxFuture;
+    use std::future::Future;
+    use std::sync::{Arc, Mutex};
+
+    struct ScriptedBackend {
+        replies: Mutex<Vec<Result<Vec<&'static str>, &'static str>>>,
+        received: Mutex<Vec<usize>>,
+        systems: Mutex<Vec<Option<String>>>,
+    }
+
+    impl ScriptedBackend {
+        fn new(replies: Vec<Result<Vec<&'static str>, &'static str>>) -> Self {
+            Self {
+                replies: Mutex::new(replies),
+                received: Mutex::new(Vec::new()),
+                systems: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    impl ChatBackend for ScriptedBackend {
+        type Message = OllamaMessage;
+        type Options = OllamaChatOptions;
+
+        fn chat_stream(
+            &self,
+            system: Option<&str>,
+            messages: &[Self::Message],
+            _options: &Self::Options,
+        ) -> impl Future<Output = Result<TextStream, SessionError>> + Send {
+            self.received.lock().unwrap().push(messages.len());
+            self.systems.lock().unwrap().push(system.map(str::to_string));
+            let reply = self.replies.lock().unwrap().remove(0);
+            async move {
+                let items: Vec<Result<String, SessionError>> = match reply {
+                    Ok(chunks) => chunks.into_iter().map(|c| Ok(c.to_string())).collect(),
+                    Err(err) => vec![
+                        Ok("partial".to_string()),
+                        Err(SessionError::NothingToRetry(err.to_string())),
+                    ],
+                };
+                Ok(futures::stream::iter(items).boxed())
+            }
+        }
+    }
+
+    fn session() -> OllamaChatSession {
+        ChatSession::new(OllamaChatOptions::new("mistral")).with_system("Be brief.")
+    }
+
+    #[tokio::test]
+    async fn test_send_appends_turns() {
+        let backend = ScriptedBackend::new(vec![Ok(vec!["Par", "is"])]);
+        let mut session = session();
+
+        let response = session.send(&backend, "Capital of France?").await.unwrap();
+
+        assert_eq!(response, "Paris");
+        let roles: Vec<_> = session.history().iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, vec!["user", "assistant"]);
+        assert_eq!(session.history()[1].content, "Paris");
+    }
+
+    #[tokio::test]
+    async fn test_failed_stream_keeps_user_turn_for_retry() {
+        let backend = ScriptedBackend::new(vec![Err("boom"), Ok(vec!["Paris"])]);
+        let mut session = session();
+
+        assert!(session.send(&backend, "Capital of France?").await.is_err());
+        assert_eq!(session.history().len(), 1);
+
+        let response = session.retry(&backend).await.unwrap();
+
+        assert_eq!(response, "Paris");
+        assert_eq!(session.history().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_send_after_failed_stream_requires_retry() {
+        let backend = ScriptedBackend::new(vec![Err("boom"), Ok(vec!["Paris"])]);
+        let mut session = session();
+        assert!(session.send(&backend, "Capital of France?").await.is_err());
+
+        let result = session.send(&backend, "Hello?").await;
+
+        assert!(matches!(result, Err(SessionError::UnansweredTurn(_))));
+        assert_eq!(session.history().len(), 1);
+        assert_eq!(session.undo().unwrap().content, "Capital of France?");
+        assert_eq!(session.send(&backend, "Hello?").await.unwrap(), "Paris");
+    }
+
+    #[tokio::test]
+    async fn test_retry_replaces_assistant_turn() {
+        let backend = ScriptedBackend::new(vec![Ok(vec!["Lyon"]), Ok(vec!["Paris"])]);
+        let mut session = session();
+        session.send(&backend, "Capital of France?").await.unwrap();
+
+        session.retry(&backend).await.unwrap();
+
+        assert_eq!(session.history().len(), 2);
+        assert_eq!(session.history()[1].content, "Paris");
+    }
+
+    #[tokio::test]
+    async fn test_context_policy_trims_request_only() {
+        let backend = ScriptedBackend::new(vec![Ok(vec!["ok"])]);
+        let mut session = session().with_context_policy(ContextPolicy::new(30));
+        session.push(OllamaMessage::new_user("a long question that no longer fits".to_string()));
+        session.push(OllamaMessage::new_assistant("a long answer that no longer fits".to_string()));
+
+        session.send(&backend, "Next?").await.unwrap();
+
+        assert_eq!(session.history().len(), 4);
+        assert_eq!(backend.received.lock().unwrap().as_slice(), &[1]);
+    }
+
+    #[test]
+    fn test_undo_removes_last_turn() {
+        let mut session = session();
+        session.push(OllamaMessage::new_user("one".to_string()));
+        session.push(OllamaMessage::new_assistant("1".to_string()));
+        session.push(OllamaMessage::new_user("two".to_string()));
+        session.push(OllamaMessage::new_assistant("2".to_string()));
+
+        let undone = session.undo().unwrap();
+
+        assert_eq!(undone.content, "two");
+        assert_eq!(session.history().len(), 2);
+        assert!(session.undo().is_some());
+        assert!(session.undo().is_none());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join("hiramu_test_chat_session.json");
+        let mut session = session();
+        session.push(OllamaMessage::new_user("Hello".to_string()));
+
+        session.save(&path).unwrap();
+        let loaded = OllamaChatSession::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.system(), Some("Be brief."));
+        assert_eq!(loaded.options().model, "mistral");
+        assert_eq!(loaded.history()[0].content, "Hello");
+    }
+
+    #[derive(Default)]
+    struct RecordingSummarizer {
+        inputs: Mutex<Vec<String>>,
+    }
+
+    impl Summarizer for RecordingSummarizer {
+        fn summarize<'a>(&'a self, transcript: &'a str) -> BoxFuture<'a, Result<String, SessionError>> {
+            let mut inputs = self.inputs.lock().unwrap();
+            inputs.push(transcript.to_string());
+            let summary = format!("summary {}", inputs.len());
+            Box::pin(async move { Ok(summary) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summary_memory() {
+        let summarizer = Arc::new(RecordingSummarizer::default());
+        let backend = ScriptedBackend::new(vec![
+            Ok(vec!["A1"]),
+            Ok(vec!["A2"]),
+            Ok(vec!["A3"]),
+            Ok(vec!["A4"]),
+        ]);
+        let memory = SummaryMemory::new(summarizer.clone())
+            .keep_last_turns(1)
+            .max_history_turns(1);
+        let mut session = session().with_memory(memory);
+
+        for question in ["Q1", "Q2", "Q3", "Q4"] {
+            session.send(&backend, question).await.unwrap();
+        }
+
+        assert_eq!(
+            *summarizer.inputs.lock().unwrap(),
+            vec![
+                "User: Q1\n\nAssistant: A1".to_string(),
+                "Summary of the earlier conversation:\nsummary 1\n\nUser: Q2\n\nAssistant: A2"
+                    .to_string(),
+            ]
+        );
+        assert_eq!(*backend.received.lock().unwrap(), vec![1, 3, 3, 3]);
+        let systems = backend.systems.lock().unwrap();
+        assert_eq!(systems[1].as_deref(), Some("Be brief."));
+        assert_eq!(
+            systems[3].as_deref(),
+            Some("Be brief.\n\nSummary of the earlier conversation:\nsummary 2")
+        );
+        let contents: Vec<_> = session.history().iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec!["Q3", "A3", "Q4", "A4"]);
+
+        let path = std::env::temp_dir().join("hiramu_test_chat_session_summary.json");
+        session.save(&path).unwrap();
+        let loaded = OllamaChatSession::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.summary(), Some("summary 2"));
+        assert!(loaded.memory().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_summary_memory_token_threshold() {
+        let summarizer = Arc::new(RecordingSummarizer::default());
+        let memory = SummaryMemory::new(summarizer.clone())
+            .keep_last_turns(2)
+            .max_history_tokens(10);
+        let long = "a long message of about ten tokens in all".to_string();
+        let mut history = vec![
+            OllamaMessage::new("system".to_string(), "rules".to_string()),
+            OllamaMessage::new_user(long.clone()),
+            OllamaMessage::new_assistant("ok".to_string()),
+            OllamaMessage::new_user("short".to_string()),
+        ];
+        let mut summary = None;
+
+        assert!(!memory.compact(&mut summary, &mut history).await.unwrap());
+        history.push(OllamaMessage::new_assistant("ok".to_string()));
+        history.push(OllamaMessage::new_user("again".to_string()));
+        assert!(memory.compact(&mut summary, &mut history).await.unwrap());
+
+        let contents: Vec<_> = history.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec!["rules", "short", "ok", "again"]);
+        assert_eq!(summary.as_deref(), Some("summary 1"));
+        assert!(!memory.should_summarize(&history[..1]));
+    }
+}
@@ -0,0 +1,522 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+use futures::future::BoxFuture;
+use futures::TryStreamExt;
+
+use crate::bedrock::model_info::{ModelInfo, ModelName};
+use crate::session::chat_backend::ChatBackend;
+use crate::session::error::SessionError;
+use crate::session::session_message::{SessionMessage, SessionRole};
+use crate::tokenizer::{HeuristicTokenizer, Tokenizer};
+
+/// The number of tokens added to every message for the role and separators.
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+
+/// The default instructions given to the model that summarizes the overflow.
+pub const DEFAULT_SUMMARY_PROMPT: &str = "Summarize the following conversation between a user and an assistant. \
+Keep the facts, decisions and open questions needed to continue the conversation. \
+Answer with the summary only.";
+
+/// Condenses the part of a conversation that no longer fits in the context window.
+pub trait Summarizer: Send + Sync {
+    /// Returns a summary of `transcript`, a plain-text rendering of the dropped messages.
+    fn summarize<'a>(&'a self, transcript: &'a str) -> BoxFuture<'a, Result<String, SessionError>>;
+}
+
+/// A `Summarizer` that asks a chat model for the summary. A smaller, cheaper model than
+/// the one used for the conversation usually does the job.
+pub struct BackendSummarizer<B: ChatBackend> {
+    backend: Arc<B>,
+    options: B::Options,
+    prompt: String,
+}
+
+impl<B: ChatBackend> BackendSummarizer<B> {
+    pub fn new(backend: Arc<B>, options: B::Options) -> Self {
+        Self {
+            backend,
+            options,
+            prompt: DEFAULT_SUMMARY_PROMPT.to_string(),
+        }
+    }
+
+    pub fn prompt<S: Into<String>>(mut self, prompt: S) -> Self {
+        self.prompt = prompt.into();
+        self
+    }
+}
+
+impl<B> Summarizer for BackendSummarizer<B>
+where
+    B: ChatBackend + Send + Sync,
+{
+    fn summarize<'a>(&'a self, transcript: &'a str) -> BoxFuture<'a, Result<String, SessionError>> {
+        Box::pin(async move {
+            let messages = [B::Message::new_user(transcript.to_string())];
+            let stream = self
+                .backend
+                .chat_stream(Some(&self.prompt), &messages, &self.options)
+                .await?;
+            stream.try_collect::<String>().await
+        })
+    }
+}
+
+/// How to shorten a conversation that does not fit in the context window.
+///
+/// System messages are always kept. Whatever the strategy, the oldest messages are
+/// dropped as a last resort until the conversation fits.
+#[derive(Clone)]
+pub enum TrimStrategy {
+    /// Drops the oldest messages until the conversation fits.
+    DropOldest,
+    /// Keeps only the last N messages, even when more would fit.
+    KeepLast(usize),
+    /// Replaces the oldest messages with a summary, added to the system prompt.
+    ///
+    /// The summary is kept by the policy: while the same messages are dropped it is reused,
+    /// and when more are dropped only the new ones are folded into it.
+    Summarize {
+        summarizer: Arc<dyn Summarizer>,
+        /// Tokens set aside for the summary when deciding what to drop.
+        max_summary_tokens: usize,
+    },
+}
+
+impl fmt::Debug for TrimStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrimStrategy::DropOldest => write!(f, "DropOldest"),
+            TrimStrategy::KeepLast(count) => write!(f, "KeepLast({})", count),
+            TrimStrategy::Summarize {
+                max_summary_tokens, ..
+            } => write!(f, "Summarize {{ max_summary_tokens: {} }}", max_summary_tokens),
+        }
+    }
+}
+
+/// The conversation to send, once trimmed to fit the context window.
+#[derive(Debug, Clone)]
+pub struct FittedContext<M> {
+    pub system: Option<String>,
+    pub messages: Vec<M>,
+    /// The number of messages left out of the request.
+    pub dropped: usize,
+    /// The estimated number of prompt tokens.
+    pub tokens: usize,
+}
+
+/// The summary of the messages dropped by the last `fit`.
+struct CachedSummary {
+    /// The number of messages summarized.
+    count: usize,
+    /// The hash of their transcript, to detect a history that was changed.
+    hash: u64,
+    summary: String,
+}
+
+/// Keeps a conversation within the context window of a model.
+///
+/// The policy estimates the size of the system prompt and history with a `Tokenizer`, and
+/// trims the history with a `TrimStrategy` when it exceeds the context window minus the
+/// tokens reserved for the response.
+#[derive(Clone)]
+pub struct ContextPolicy {
+    context_window: usize,
+    reserved_output_tokens: usize,
+    tokenizer: Arc<dyn Tokenizer>,
+    strategy: TrimStrategy,
+    summary: Arc<Mutex<Option<CachedSummary>>>,
+}
+
+impl fmt::Debug for ContextPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ContextPolicy")
+            .field("context_window", &self.context_window)
+            .field("reserved_output_tokens", &self.reserved_output_tokens)
+            .field("strategy", &self.strategy)
+            .finish()
+    }
+}
+
+impl ContextPolicy {
+    pub fn new(context_window: usize) -> Self {
+        Self {
+            context_window,
+            reserved_output_tokens: 0,
+            tokenizer: Arc::new(HeuristicTokenizer::new()),
+            strategy: TrimStrategy::DropOldest,
+            summary: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Creates a policy from the context window of a Bedrock model.
+    /// Returns `None` if the model does not take a text prompt.
+    pub fn for_model(name: ModelName) -> Option<Self> {
+        ModelInfo::context_window_of(name).map(|window| Self::new(window as usize))
+    }
+
+    /// Sets the number of tokens kept free for the response, usually the `max_tokens` of
+    /// the request.
+    pub fn reserve_output_tokens(mut self, tokens: usize) -> Self {
+        self.reserved_output_tokens = tokens;
+        self
+    }
+
+    pub fn tokenizer<T: Tokenizer + 'static>(mut self, tokenizer: T) -> Self {
+        self.tokenizer = Arc::new(tokenizer);
+        self
+    }
+
+    pub fn strategy(mut self, strategy: TrimStrategy) -> Self {
+        self.strategy = strategy;
+        self.summary = Arc::new(Mutex::new(None));
+        self
+    }
+
+    /// The number of tokens available for the system prompt and the history.
+    pub fn prompt_budget(&self) -> usize {
+        self.context_window.saturating_sub(self.reserved_output_tokens)
+    }
+
+    /// Estimates the number of prompt tokens used by a system prompt and history.
+    pub fn count_tokens<M: SessionMessage>(&self, system: Option<&str>, messages: &[M]) -> usize {
+        self.system_tokens(system) + messages.iter().map(|m| self.message_tokens(m)).sum::<usize>()
+    }
+
+    /// Trims the conversation so that it fits in the prompt budget.
+    ///
+    /// Fails with `SessionError::ContextOverflow` if the system prompt and the last
+    /// message alone do not fit.
+    pub async fn fit<M: SessionMessage>(
+        &self,
+        system: Option<&str>,
+        messages: &[M],
+    ) -> Result<FittedContext<M>, SessionError> {
+        let budget = self.prompt_budget();
+        let sizes: Vec<usize> = messages.iter().map(|m| self.message_tokens(m)).collect();
+        let system_tokens = self.system_tokens(system);
+
+        let mut start = match self.strategy {
+            TrimStrategy::KeepLast(count) => messages.len().saturating_sub(count),
+            _ => 0,
+        };
+
+        let summary_reserve = match &self.strategy {
+            TrimStrategy::Summarize {
+                max_summary_tokens, ..
+            } => *max_summary_tokens,
+            _ => 0,
+        };
+        if system_tokens + kept_tokens(messages, &sizes, start) > budget {
+            let target = budget.saturating_sub(summary_reserve);
+            start = first_fitting(messages, &sizes, start, target.saturating_sub(system_tokens));
+        }
+        start = skip_to_user_turn(messages, start);
+
+        let mut system = system.map(str::to_string);
+        if let TrimStrategy::Summarize { summarizer, .. } = &self.strategy {
+            let dropped: Vec<&M> = messages[..start]
+                .iter()
+                .filter(|m| m.role() != SessionRole::System)
+                .collect();
+            if !dropped.is_empty() {
+                let summary = self.summarize(summarizer.as_ref(), &dropped).await?;
+                system = Some(with_summary(system, &summary));
+            }
+        }
+
+        let system_tokens = self.system_tokens(system.as_deref());
+        if system_tokens + kept_tokens(messages, &sizes, start) > budget {
+            start = first_fitting(messages, &sizes, start, budget.saturating_sub(system_tokens));
+            start = skip_to_user_turn(messages, start);
+        }
+
+        let tokens = system_tokens + kept_tokens(messages, &sizes, start);
+        if tokens > budget {
+            return Err(SessionError::ContextOverflow(format!(
+                "the conversation needs {} tokens but only {} are available",
+                tokens, budget
+            )));
+        }
+
+        let kept: Vec<M> = messages
+            .iter()
+            .enumerate()
+            .filter(|(index, m)| *index >= start || m.role() == SessionRole::System)
+            .map(|(_, m)| m.clone())
+            .collect();
+
+        Ok(FittedContext {
+            system,
+            dropped: messages.len() - kept.len(),
+            messages: kept,
+            tokens,
+        })
+    }
+
+    /// The summary of `dropped`, reusing the summary of the last call when it covers the
+    /// same messages or a prefix of them.
+    async fn summarize<M: SessionMessage>(
+        &self,
+        summarizer: &dyn Summarizer,
+        dropped: &[&M],
+    ) -> Result<String, SessionError> {
+        let previous = {
+            let cached = self.summary.lock().unwrap();
+            cached
+                .as_ref()
+                .filter(|cached| {
+                    cached.count <= dropped.len()
+                        && cached.hash == transcript_hash(&dropped[..cached.count])
+                })
+                .map(|cached| (cached.count, cached.summary.clone()))
+        };
+
+        let summary = match previous {
+            Some((count, summary)) if count == dropped.len() => return Ok(summary),
+            Some((count, summary)) => {
+                let input = format!(
+                    "Summary of the earlier conversation:\n{}\n\n{}",
+                    summary.trim(),
+                    transcript(&dropped[count..])
+                );
+                summarizer.summarize(&input).await?
+            }
+            None => summarizer.summarize(&transcript(dropped)).await?,
+        };
+
+        *self.summary.lock().unwrap() = Some(CachedSummary {
+            count: dropped.len(),
+            hash: transcript_hash(dropped),
+            summary: summary.clone(),
+        });
+        Ok(summary)
+    }
+
+    fn system_tokens(&self, system: Option<&str>) -> usize {
+        system
+            .map(|system| self.tokenizer.count_tokens(system) + MESSAGE_OVERHEAD_TOKENS)
+            .unwrap_or(0)
+    }
+
+    fn message_tokens<M: SessionMessage>(&self, message: &M) -> usize {
+        self.tokenizer.count_tokens(&message.text()) + MESSAGE_OVERHEAD_TOKENS
+    }
+}
+
+/// The tokens used by the messages from `start`, plus the system messages before it.
+fn kept_tokens<M: SessionMessage>(messages: &[M], sizes: &[usize], start: usize) -> usize {
+    messages
+        .iter()
+        .zip(sizes)
+        .enumerate()
+        .filter(|(index, (m, _))| *index >= start || m.role() == SessionRole::System)
+        .map(|(_, (_, size))| *size)
+        .sum()
+}
+
+/// The first index from which the messages fit in `budget`, keeping at least the last one.
+fn first_fitting<M: SessionMessage>(
+    messages: &[M],
+    sizes: &[usize],
+    start: usize,
+    budget: usize,
+) -> usize {
+    let last = messages.len().saturating_sub(1);
+    let mut start = start;
+    while start < last && kept_tokens(messages, sizes, start) > budget {
+        start += 1;
+    }
+    start
+}
+
+/// Moves `start` past assistant messages, since providers expect the conversation to
+/// begin with a user turn.
+fn skip_to_user_turn<M: SessionMessage>(messages: &[M], start: usize) -> usize {
+    let last = messages.len().saturating_sub(1);
+    let mut start = start;
+    while start < last && messages[start].role() == SessionRole::Assistant {
+        start += 1;
+    }
+    start
+}
+
+pub(crate) fn transcript<M: SessionMessage>(messages: &[&M]) -> String {
+    messages
+        .iter()
+        .map(|m| {
+            let role = match m.role() {
+                SessionRole::System => "System",
+                SessionRole::User => "User",
+                SessionRole::Assistant => "Assistant",
+            };
+            format!("{}: {}", role, m.text())
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn transcript_hash<M: SessionMessage>(messages: &[&M]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    transcript(messages).hash(&mut hasher);
+    hasher.finish()
+}
+
+pub(crate) fn with_summary(system: Option<String>, summary: &str) -> String {
+    let summary = format!("Summary of the earlier conversation:\n{}", summary.trim());
+    match system {
+        Some(system) => format!("{}\n\n{}", system, summary),
+        None => summary,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ollama::Message;
+
+    struct FixedSummarizer;
+
+    impl Summarizer for FixedSummarizer {
+        fn summarize<'a>(&'a self, transcript: &'a str) -> BoxFuture<'a, Result<String, SessionError>> {
+            let lines = transcript.split("\n\n").count();
+            Box::pin(async move { Ok(format!("{} messages", lines)) })
+        }
+    }
+
+    #[derive(Default)]
+    struct CountingSummarizer {
+        inputs: Mutex<Vec<String>>,
+    }
+
+    impl Summarizer for CountingSummarizer {
+        fn summarize<'a>(&'a self, transcript: &'a str) -> BoxFuture<'a, Result<String, SessionError>> {
+            let mut inputs = self.inputs.lock().unwrap();
+            inputs.push(transcript.to_string());
+            let summary = format!("summary {}", inputs.len());
+            Box::pin(async move { Ok(summary) })
+        }
+    }
+
+    fn conversation() -> Vec<Message> {
+        (0..10)
+            .map(|i| {
+                let text = format!("message number {:02} padded out", i);
+                if i % 2 == 0 {
+                    Message::new_user(text)
+                } else {
+                    Message::new_assistant(text)
+                }
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_fit_keeps_everything_within_budget() {
+        let policy = ContextPolicy::new(10_000);
+
+        let fitted = policy.fit(Some("system"), &conversation()).await.unwrap();
+
+        assert_eq!(fitted.messages.len(), 10);
+        assert_eq!(fitted.dropped, 0);
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest() {
+        // every message is 7 + 4 tokens
+        let policy = ContextPolicy::new(60).reserve_output_tokens(10);
+
+        let fitted = policy.fit(None, &conversation()).await.unwrap();
+
+        assert_eq!(fitted.messages.len(), 4);
+        assert_eq!(fitted.messages[0].content, "message number 06 padded out");
+        assert_eq!(fitted.messages[0].role, "user");
+        assert!(fitted.tokens <= 50);
+    }
+
+    #[tokio::test]
+    async fn test_keep_last_keeps_system_messages() {
+        let mut messages = vec![Message::new("system".to_string(), "rules".to_string())];
+        messages.extend(conversation());
+        let policy = ContextPolicy::new(10_000).strategy(TrimStrategy::KeepLast(2));
+
+        let fitted = policy.fit(None, &messages).await.unwrap();
+
+        let contents: Vec<_> = fitted.messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(
+            contents,
+            vec!["rules", "message number 08 padded out", "message number 09 padded out"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_summarize_overflow() {
+        let policy = ContextPolicy::new(60).strategy(TrimStrategy::Summarize {
+            summarizer: Arc::new(FixedSummarizer),
+            max_summary_tokens: 20,
+        });
+
+        let fitted = policy.fit(Some("Be brief."), &conversation()).await.unwrap();
+
+        let system = fitted.system.unwrap();
+        assert!(system.starts_with("Be brief.\n\nSummary of the earlier conversation:\n"));
+        assert!(system.ends_with(&format!("{} messages", fitted.dropped)));
+        assert!(fitted.tokens <= 60);
+    }
+
+    #[tokio::test]
+    async fn test_summary_is_reused_and_rolled_forward() {
+        let summarizer = Arc::new(CountingSummarizer::default());
+        let policy = ContextPolicy::new(60).strategy(TrimStrategy::Summarize {
+            summarizer: summarizer.clone(),
+            max_summary_tokens: 20,
+        });
+        let mut messages = conversation();
+
+        let first = policy.fit(None, &messages).await.unwrap();
+        let again = policy.fit(None, &messages).await.unwrap();
+        assert_eq!(summarizer.inputs.lock().unwrap().len(), 1);
+        assert_eq!(first.system, again.system);
+
+        messages.push(Message::new_user("message number 10 padded out".to_string()));
+        messages.push(Message::new_assistant("message number 11 padded out".to_string()));
+        let next = policy.fit(None, &messages).await.unwrap();
+
+        let inputs = summarizer.inputs.lock().unwrap();
+        assert_eq!(inputs.len(), 2);
+        assert!(inputs[1].starts_with("Summary of the earlier conversation:\nsummary 1\n\n"));
+        assert!(!inputs[1].contains("message number 00"));
+        assert!(next.system.unwrap().ends_with("summary 2"));
+    }
+
+    #[tokio::test]
+    async fn test_changed_history_is_summarized_again() {
+        let summarizer = Arc::new(CountingSummarizer::default());
+        let policy = ContextPolicy::new(60).strategy(TrimStrategy::Summarize {
+            summarizer: summarizer.clone(),
+            max_summary_tokens: 20,
+        });
+        let mut messages = conversation();
+
+        policy.fit(None, &messages).await.unwrap();
+        messages[0] = Message::new_user("message number 00 edited".to_string());
+        policy.fit(None, &messages).await.unwrap();
+
+        let inputs = summarizer.inputs.lock().unwrap();
+        assert_eq!(inputs.len(), 2);
+        assert!(inputs[1].starts_with("User: message number 00 edited"));
+    }
+
+    #[tokio::test]
+    async fn test_overflow_error() {
+        let policy = ContextPolicy::new(5);
+
+        let result = policy.fit(None, &conversation()).await;
+
+        assert!(matches!(result, Err(SessionError::ContextOverflow(_))));
+    }
+}
@@ -0,0 +1,29 @@
+use serde_json::Error as SerdeJsonError;
+use thiserror::Error;
+
+use crate::bedrock::models::claude::error::ClaudeError;
+use crate::ollama::error::OllamaError;
+
+#[derive(Error, Debug)]
+pub enum SessionError {
+    #[error("JSON error: {0}")]
+    Json(#[from] SerdeJsonError),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Ollama error: {0}")]
+    Ollama(#[from] OllamaError),
+
+    #[error("Claude error: {0}")]
+    Claude(#[from] ClaudeError),
+
+    #[error("Nothing to retry: {0}")]
+    NothingToRetry(String),
+
+    #[error("Unanswered turn: {0}")]
+    UnansweredTurn(String),
+
+    #[error("Context overflow: {0}")]
+    ContextOverflow(String),
+}
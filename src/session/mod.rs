@@ -0,0 +1,13 @@
+pub mod chat_backend;
+pub mod chat_session;
+pub mod context_policy;
+pub mod error;
+pub mod session_message;
+pub mod summary_memory;
+
+pub use chat_backend::{ChatBackend, OllamaChatOptions, TextStream};
+pub use chat_session::{ChatSession, ClaudeChatSession, OllamaChatSession, SessionStream};
+pub use context_policy::{BackendSummarizer, ContextPolicy, FittedContext, Summarizer, TrimStrategy};
+pub use error::SessionError;
+pub use session_message::{SessionMessage, SessionRole};
+pub use summary_memory::{SummaryMemory, DEFAULT_KEEP_LAST_TURNS, DEFAULT_MAX_HISTORY_TOKENS};
@@ -0,0 +1,109 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::bedrock::models::claude::claude_request_message::{
+    ContentBlock, Message as ClaudeMessage, MessageContent, Role,
+};
+use crate::bedrock::models::mistral::MistralMessage;
+use crate::ollama::Message as OllamaMessage;
+
+/// The role of a message in a conversation, independent of the provider.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SessionRole {
+    System,
+    User,
+    Assistant,
+}
+
+/// A provider message type that can be stored in a `ChatSession`.
+///
+/// Implemented for `ollama::Message` and the Claude `Message`, so the same session logic
+/// works for both providers.
+pub trait SessionMessage: Clone + Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// Creates a user message containing the given text.
+    fn new_user(text: String) -> Self;
+
+    /// Creates an assistant message containing the given text.
+    fn new_assistant(text: String) -> Self;
+
+    /// The role of the message.
+    fn role(&self) -> SessionRole;
+
+    /// The text of the message. Non-text content, such as images, is ignored.
+    fn text(&self) -> String;
+}
+
+impl SessionMessage for OllamaMessage {
+    fn new_user(text: String) -> Self {
+        OllamaMessage::new("user".to_string(), text)
+    }
+
+    fn new_assistant(text: String) -> Self {
+        OllamaMessage::new("assistant".to_string(), text)
+    }
+
+    fn role(&self) -> SessionRole {
+        match self.role.as_str() {
+            "system" => SessionRole::System,
+            "assistant" => SessionRole::Assistant,
+            _ => SessionRole::User,
+        }
+    }
+
+    fn text(&self) -> String {
+        self.content.clone()
+    }
+}
+
+impl SessionMessage for MistralMessage {
+    fn new_user(text: String) -> Self {
+        MistralMessage::user(text)
+    }
+
+    fn new_assistant(text: String) -> Self {
+        MistralMessage::assistant(text)
+    }
+
+    fn role(&self) -> SessionRole {
+        match self.role.as_str() {
+            "system" => SessionRole::System,
+            "assistant" => SessionRole::Assistant,
+            _ => SessionRole::User,
+        }
+    }
+
+    fn text(&self) -> String {
+        self.content.clone()
+    }
+}
+
+impl SessionMessage for ClaudeMessage {
+    fn new_user(text: String) -> Self {
+        ClaudeMessage::new_user_message(text)
+    }
+
+    fn new_assistant(text: String) -> Self {
+        ClaudeMessage::new_assistant_message(text)
+    }
+
+    fn role(&self) -> SessionRole {
+        match self.role {
+            Role::User => SessionRole::User,
+            Role::Assistant => SessionRole::Assistant,
+        }
+    }
+
+    fn text(&self) -> String {
+        match &self.content {
+            MessageContent::Text(text) => text.clone(),
+            MessageContent::Blocks(blocks) => blocks
+                .iter()
+                .filter_map(|block| match block {
+                    ContentBlock::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::session::context_policy::{transcript, Summarizer};
+use crate::session::error::SessionError;
+use crate::session::session_message::{SessionMessage, SessionRole};
+use crate::tokenizer::{HeuristicTokenizer, Tokenizer};
+
+/// The number of turns kept verbatim by default.
+pub const DEFAULT_KEEP_LAST_TURNS: usize = 4;
+
+/// The default size of the history above which the older turns are summarized.
+pub const DEFAULT_MAX_HISTORY_TOKENS: usize = 4000;
+
+/// A memory strategy for long-running conversations: once the history exceeds a threshold,
+/// the turns before the last few are folded into a rolling summary and removed from the
+/// history.
+///
+/// A turn is a user message and the responses to it. The summary is updated from the
+/// previous summary and the newly summarized turns, so each turn is summarized once, and
+/// is sent at the end of the system prompt.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::sync::Arc;
+///
+/// use hiramu::ollama::OllamaClient;
+/// use hiramu::session::{BackendSummarizer, OllamaChatOptions, OllamaChatSession, SummaryMemory};
+///
+/// let summarizer = BackendSummarizer::new(
+///     Arc::new(OllamaClient::new("http://localhost:11434".to_string())),
+///     OllamaChatOptions::new("phi3"),
+/// )
+/// .prompt("Summarize this support conversation. Keep the ticket numbers.");
+/// let session = OllamaChatSession::new(OllamaChatOptions::new("llama3"))
+///     .with_memory(SummaryMemory::new(Arc::new(summarizer)).keep_last_turns(3));
+/// ```
+#[derive(Clone)]
+pub struct SummaryMemory {
+    summarizer: Arc<dyn Summarizer>,
+    keep_last_turns: usize,
+    max_history_tokens: usize,
+    max_history_turns: Option<usize>,
+    tokenizer: Arc<dyn Tokenizer>,
+}
+
+impl fmt::Debug for SummaryMemory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SummaryMemory")
+            .field("keep_last_turns", &self.keep_last_turns)
+            .field("max_history_tokens", &self.max_history_tokens)
+            .field("max_history_turns", &self.max_history_turns)
+            .finish()
+    }
+}
+
+impl SummaryMemory {
+    /// Creates a memory summarizing with `summarizer`, whose prompt sets how the summary
+    /// is written.
+    pub fn new(summarizer: Arc<dyn Summarizer>) -> Self {
+        Self {
+            summarizer,
+            keep_last_turns: DEFAULT_KEEP_LAST_TURNS,
+            max_history_tokens: DEFAULT_MAX_HISTORY_TOKENS,
+            max_history_turns: None,
+            tokenizer: Arc::new(HeuristicTokenizer::new()),
+        }
+    }
+
+    /// Sets the number of most recent turns never summarized.
+    pub fn keep_last_turns(mut self, turns: usize) -> Self {
+        self.keep_last_turns = turns;
+        self
+    }
+
+    /// Summarizes when the text of the history exceeds `tokens`.
+    pub fn max_history_tokens(mut self, tokens: usize) -> Self {
+        self.max_history_tokens = tokens;
+        self
+    }
+
+    /// Also summarizes when the history has more than `turns` turns.
+    pub fn max_history_turns(mut self, turns: usize) -> Self {
+        self.max_history_turns = Some(turns);
+        self
+    }
+
+    pub fn tokenizer<T: Tokenizer + 'static>(mut self, tokenizer: T) -> Self {
+        self.tokenizer = Arc::new(tokenizer);
+        self
+    }
+
+    /// Whether `history` exceeds one of the thresholds.
+    pub fn should_summarize<M: SessionMessage>(&self, history: &[M]) -> bool {
+        let turns = turn_starts(history).len();
+        let tokens: usize = history
+            .iter()
+            .map(|message| self.tokenizer.count_tokens(&message.text()))
+            .sum();
+        tokens > self.max_history_tokens || self.max_history_turns.is_some_and(|max| turns > max)
+    }
+
+    /// Folds the turns before the last `keep_last_turns` into `summary` when the history
+    /// exceeds a threshold, and removes them from `history`. System messages are kept.
+    ///
+    /// Returns whether the history was summarized. On error, the history and the summary
+    /// are left unchanged.
+    pub async fn compact<M: SessionMessage>(
+        &self,
+        summary: &mut Option<String>,
+        history: &mut Vec<M>,
+    ) -> Result<bool, SessionError> {
+        if !self.should_summarize(history) {
+            return Ok(false);
+        }
+        let starts = turn_starts(history);
+        if starts.len() <= self.keep_last_turns {
+            return Ok(false);
+        }
+        let split = match self.keep_last_turns {
+            0 => history.len(),
+            kept => starts[starts.len() - kept],
+        };
+
+        let summarized: Vec<&M> = history[..split]
+            .iter()
+            .filter(|message| message.role() != SessionRole::System)
+            .collect();
+        if summarized.is_empty() {
+            return Ok(false);
+        }
+        let input = match summary.as_deref() {
+            Some(previous) => format!(
+                "Summary of the earlier conversation:\n{}\n\n{}",
+                previous.trim(),
+                transcript(&summarized)
+            ),
+            None => transcript(&summarized),
+        };
+        let updated = self.summarizer.summarize(&input).await?;
+
+        let mut index = 0;
+        history.retain(|message| {
+            index += 1;
+            index > split || message.role() == SessionRole::System
+        });
+        *summary = Some(updated.trim().to_string());
+        Ok(true)
+    }
+}
+
+/// The indexes of the user messages starting the turns of `history`.
+fn turn_starts<M: SessionMessage>(history: &[M]) -> Vec<usize> {
+    history
+        .iter()
+        .enumerate()
+        .filter(|(_, message)| message.role() == SessionRole::User)
+        .map(|(index, _)| index)
+        .collect()
+}
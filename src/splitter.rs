@@ -0,0 +1,476 @@
+//! Splitting documents into chunks for retrieval.
+//!
+//! A `TextSplitter` cuts a text into pieces at natural boundaries (paragraphs, lines,
+//! sentences, words), then merges consecutive pieces into chunks no longer than the chunk
+//! size, with consecutive chunks sharing up to `overlap` of text. The size is measured in
+//! characters, or in tokens with a `Tokenizer`, so that the chunks fit the context of an
+//! embedding model.
+//!
+//! ```
+//! use hiramu::splitter::TextSplitter;
+//!
+//! let text = "# Install\nRun cargo add hiramu.\n\n# Usage\nCreate a client. Send a request.";
+//! let chunks = TextSplitter::markdown().chunk_size(40).overlap(0).split(text);
+//!
+//! assert_eq!(chunks[0].text, "# Install\nRun cargo add hiramu.");
+//! assert_eq!(chunks[1].metadata["h1"], "Usage");
+//! assert_eq!(&text[chunks[1].start..chunks[1].end], chunks[1].text);
+//! ```
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::tokenizer::{HeuristicTokenizer, Tokenizer};
+
+/// The separators of `TextSplitter::recursive`, from the coarsest to the finest. The empty
+/// separator splits between characters.
+pub const DEFAULT_SEPARATORS: [&str; 5] = ["\n\n", "\n", ". ", " ", ""];
+
+/// The default maximum length of a chunk.
+pub const DEFAULT_CHUNK_SIZE: usize = 1000;
+
+/// The default length shared by consecutive chunks.
+pub const DEFAULT_OVERLAP: usize = 200;
+
+/// Key-value pairs describing a chunk, such as its source or its section.
+pub type Metadata = BTreeMap<String, String>;
+
+/// A chunk of a text.
+///
+/// # Fields
+///
+/// * `text` - The text of the chunk, without leading and trailing whitespace.
+/// * `index` - The position of the chunk among the chunks of the text.
+/// * `start` - The byte offset of the chunk in the text.
+/// * `end` - The byte offset of the end of the chunk, so `&text[start..end] == chunk.text`.
+/// * `metadata` - The metadata of the text, and for markdown the headers of the section,
+///   as `h1` to `h6`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Chunk {
+    pub text: String,
+    pub index: usize,
+    pub start: usize,
+    pub end: usize,
+    #[serde(default, skip_serializing_if = "Metadata::is_empty")]
+    pub metadata: Metadata,
+}
+
+#[derive(Debug, Clone)]
+enum Strategy {
+    Recursive(Vec<String>),
+    Sentences,
+    Paragraphs,
+    Markdown,
+}
+
+/// Splits texts into chunks of bounded length.
+///
+/// Pieces longer than the chunk size are split further with the next finer boundary, down
+/// to single characters, so no chunk is longer than the chunk size unless one character is.
+#[derive(Clone)]
+pub struct TextSplitter {
+    strategy: Strategy,
+    chunk_size: usize,
+    overlap: usize,
+    tokenizer: Option<Arc<dyn Tokenizer>>,
+}
+
+impl TextSplitter {
+    fn new(strategy: Strategy) -> Self {
+        Self {
+            strategy,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            overlap: DEFAULT_OVERLAP,
+            tokenizer: None,
+        }
+    }
+
+    /// Splits at the `DEFAULT_SEPARATORS`, using the coarsest one that gives short enough
+    /// pieces.
+    pub fn recursive() -> Self {
+        Self::new(Strategy::Recursive(
+            DEFAULT_SEPARATORS.iter().map(|separator| separator.to_string()).collect(),
+        ))
+    }
+
+    /// Splits at the given separators, from the coarsest to the finest.
+    pub fn with_separators<I, S>(separators: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self::new(Strategy::Recursive(
+            separators.into_iter().map(Into::into).collect(),
+        ))
+    }
+
+    /// Keeps whole sentences together, ending at `.`, `!` or `?` followed by whitespace,
+    /// or at a blank line.
+    pub fn sentences() -> Self {
+        Self::new(Strategy::Sentences)
+    }
+
+    /// Keeps whole paragraphs together, separated by blank lines, and splits the long ones
+    /// into sentences.
+    pub fn paragraphs() -> Self {
+        Self::new(Strategy::Paragraphs)
+    }
+
+    /// Splits markdown at its headers, so that a chunk never spans two sections, and adds
+    /// the headers of the section to the metadata of its chunks. Headers in code blocks are
+    /// ignored.
+    pub fn markdown() -> Self {
+        Self::new(Strategy::Markdown)
+    }
+
+    /// Sets the maximum length of a chunk, in characters, or in tokens with a tokenizer.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Sets the maximum length shared by consecutive chunks. The overlap is made of whole
+    /// pieces, so it is often shorter.
+    pub fn overlap(mut self, overlap: usize) -> Self {
+        self.overlap = overlap;
+        self
+    }
+
+    /// Measures the lengths in tokens of `tokenizer`.
+    pub fn tokenizer<T: Tokenizer + 'static>(mut self, tokenizer: T) -> Self {
+        self.tokenizer = Some(Arc::new(tokenizer));
+        self
+    }
+
+    /// Limits the chunks to `max_tokens` estimated with `HeuristicTokenizer`, with an
+    /// overlap of a tenth of it.
+    pub fn token_budget(self, max_tokens: usize) -> Self {
+        self.tokenizer(HeuristicTokenizer::new())
+            .chunk_size(max_tokens)
+            .overlap(max_tokens / 10)
+    }
+
+    pub fn split(&self, text: &str) -> Vec<Chunk> {
+        self.split_with_metadata(text, &Metadata::new())
+    }
+
+    /// Splits a text, copying `metadata` into each chunk.
+    pub fn split_with_metadata(&self, text: &str, metadata: &Metadata) -> Vec<Chunk> {
+        let sections = match self.strategy {
+            Strategy::Markdown => markdown_sections(text),
+            _ => vec![(0..text.len(), Metadata::new())],
+        };
+        let mut chunks = Vec::new();
+        for (section, headers) in sections {
+            let pieces = self.pieces(text, section);
+            for span in self.merge(text, &pieces) {
+                let span = trim(text, span);
+                if span.is_empty() {
+                    continue;
+                }
+                let mut chunk_metadata = metadata.clone();
+                chunk_metadata.extend(headers.clone());
+                chunks.push(Chunk {
+                    text: text[span.clone()].to_string(),
+                    index: chunks.len(),
+                    start: span.start,
+                    end: span.end,
+                    metadata: chunk_metadata,
+                });
+            }
+        }
+        chunks
+    }
+
+    fn len(&self, text: &str) -> usize {
+        match &self.tokenizer {
+            Some(tokenizer) => tokenizer.count_tokens(text),
+            None => text.chars().count(),
+        }
+    }
+
+    fn fits(&self, text: &str, span: &Range<usize>) -> bool {
+        self.len(&text[span.clone()]) <= self.chunk_size
+    }
+
+    /// Cuts a span into contiguous pieces that fit the chunk size.
+    fn pieces(&self, text: &str, span: Range<usize>) -> Vec<Range<usize>> {
+        let mut pieces = Vec::new();
+        match &self.strategy {
+            Strategy::Recursive(separators) => {
+                let separators: Vec<&str> = separators.iter().map(String::as_str).collect();
+                self.split_recursive(text, span, &separators, &mut pieces);
+            }
+            Strategy::Sentences => {
+                for sentence in split_sentences(text, span) {
+                    self.split_recursive(text, sentence, &DEFAULT_SEPARATORS, &mut pieces);
+                }
+            }
+            Strategy::Paragraphs => {
+                for paragraph in split_keeping(text, span, "\n\n") {
+                    if self.fits(text, &paragraph) {
+                        pieces.push(paragraph);
+                        continue;
+                    }
+                    for sentence in split_sentences(text, paragraph) {
+                        self.split_recursive(text, sentence, &DEFAULT_SEPARATORS, &mut pieces);
+                    }
+                }
+            }
+            Strategy::Markdown => {
+                self.split_recursive(text, span, &DEFAULT_SEPARATORS, &mut pieces);
+            }
+        }
+        pieces
+    }
+
+    fn split_recursive(
+        &self,
+        text: &str,
+        span: Range<usize>,
+        separators: &[&str],
+        pieces: &mut Vec<Range<usize>>,
+    ) {
+        if span.is_empty() {
+            return;
+        }
+        if self.fits(text, &span) {
+            pieces.push(span);
+            return;
+        }
+        let position = separators
+            .iter()
+            .position(|separator| separator.is_empty() || text[span.clone()].contains(separator));
+        let Some(position) = position.filter(|&position| !separators[position].is_empty()) else {
+            self.split_characters(text, span, pieces);
+            return;
+        };
+        for part in split_keeping(text, span, separators[position]) {
+            self.split_recursive(text, part, &separators[position + 1..], pieces);
+        }
+    }
+
+    /// Cuts a span into the longest runs of characters that fit the chunk size.
+    fn split_characters(&self, text: &str, span: Range<usize>, pieces: &mut Vec<Range<usize>>) {
+        let mut start = span.start;
+        let mut end = start;
+        for (offset, c) in text[span.clone()].char_indices() {
+            let next = span.start + offset + c.len_utf8();
+            if end > start && !self.fits(text, &(start..next)) {
+                pieces.push(start..end);
+                start = end;
+            }
+            end = next;
+        }
+        if end > start {
+            pieces.push(start..end);
+        }
+    }
+
+    /// Merges consecutive pieces into chunks that fit the chunk size, starting each chunk
+    /// with the last pieces of the previous one that fit the overlap.
+    fn merge(&self, text: &str, pieces: &[Range<usize>]) -> Vec<Range<usize>> {
+        let mut chunks = Vec::new();
+        let mut first = 0;
+        while first < pieces.len() {
+            let mut last = first;
+            while last + 1 < pieces.len()
+                && self.fits(text, &(pieces[first].start..pieces[last + 1].end))
+            {
+                last += 1;
+            }
+            chunks.push(pieces[first].start..pieces[last].end);
+            if last + 1 == pieces.len() {
+                break;
+            }
+            let mut next = last + 1;
+            while next > first + 1
+                && self.len(&text[pieces[next - 1].start..pieces[last].end]) <= self.overlap
+                && self.fits(text, &(pieces[next - 1].start..pieces[last + 1].end))
+            {
+                next -= 1;
+            }
+            first = next;
+        }
+        chunks
+    }
+}
+
+/// Splits a span after each occurrence of `separator`, so the parts cover the whole span.
+fn split_keeping(text: &str, span: Range<usize>, separator: &str) -> Vec<Range<usize>> {
+    let mut parts = Vec::new();
+    let mut start = span.start;
+    for (offset, _) in text[span.clone()].match_indices(separator) {
+        let end = span.start + offset + separator.len();
+        if end > start {
+            parts.push(start..end);
+            start = end;
+        }
+    }
+    if start < span.end {
+        parts.push(start..span.end);
+    }
+    parts
+}
+
+fn split_sentences(text: &str, span: Range<usize>) -> Vec<Range<usize>> {
+    let mut sentences = Vec::new();
+    let mut start = span.start;
+    let mut chars = text[span.clone()].char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        let next = chars.peek().map(|&(_, next)| next);
+        let boundary = match c {
+            '.' | '!' | '?' => next.is_none_or(char::is_whitespace),
+            '\n' => next == Some('\n'),
+            _ => false,
+        };
+        let end = span.start + offset + c.len_utf8();
+        if boundary && end > start {
+            sentences.push(start..end);
+            start = end;
+        }
+    }
+    if start < span.end {
+        sentences.push(start..span.end);
+    }
+    sentences
+}
+
+/// The sections of a markdown text, each starting at a header, with the headers of the
+/// section and of its parents.
+fn markdown_sections(text: &str) -> Vec<(Range<usize>, Metadata)> {
+    let mut sections = Vec::new();
+    let mut headers: Vec<(usize, String)> = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+    let mut in_code = false;
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code = !in_code;
+        } else if let Some((level, title)) = header(trimmed).filter(|_| !in_code) {
+            if offset > start {
+                sections.push((start..offset, header_metadata(&headers)));
+            }
+            start = offset;
+            headers.retain(|(parent, _)| *parent < level);
+            headers.push((level, title));
+        }
+        offset += line.len();
+    }
+    if text.len() > start {
+        sections.push((start..text.len(), header_metadata(&headers)));
+    }
+    sections
+}
+
+fn header(line: &str) -> Option<(usize, String)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let title = line[level..].strip_prefix(' ')?;
+    (1..=6)
+        .contains(&level)
+        .then(|| (level, title.trim().trim_end_matches('#').trim_end().to_string()))
+}
+
+fn header_metadata(headers: &[(usize, String)]) -> Metadata {
+    headers
+        .iter()
+        .map(|(level, title)| (format!("h{}", level), title.clone()))
+        .collect()
+}
+
+fn trim(text: &str, span: Range<usize>) -> Range<usize> {
+    let slice = &text[span.clone()];
+    let start = span.start + (slice.len() - slice.trim_start().len());
+    let end = span.end - (slice.len() - slice.trim_end().len());
+    start..end.max(start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(chunks: &[Chunk]) -> Vec<&str> {
+        chunks.iter().map(|chunk| chunk.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_recursive_split_with_overlap() {
+        let text = "one two three four five six seven eight nine ten";
+        let chunks = TextSplitter::recursive().chunk_size(15).overlap(6).split(text);
+        assert_eq!(
+            texts(&chunks),
+            vec![
+                "one two three",
+                "three four",
+                "four five six",
+                "six seven",
+                "seven eight",
+                "eight nine ten"
+            ]
+        );
+        for chunk in &chunks {
+            assert_eq!(&text[chunk.start..chunk.end], chunk.text);
+            assert!(chunk.text.chars().count() <= 15);
+        }
+        assert_eq!(chunks[5].index, 5);
+
+        let chunks = TextSplitter::recursive().chunk_size(4).overlap(0).split("abcdefghij");
+        assert_eq!(texts(&chunks), vec!["abcd", "efgh", "ij"]);
+    }
+
+    #[test]
+    fn test_sentence_and_paragraph_split() {
+        let text = "Rust is fast. It is safe! Is it fun? Yes.\n\nA second paragraph.";
+        let chunks = TextSplitter::sentences().chunk_size(30).overlap(0).split(text);
+        assert_eq!(
+            texts(&chunks),
+            vec!["Rust is fast. It is safe!", "Is it fun? Yes.", "A second paragraph."]
+        );
+
+        let chunks = TextSplitter::paragraphs().chunk_size(45).overlap(0).split(text);
+        assert_eq!(
+            texts(&chunks),
+            vec!["Rust is fast. It is safe! Is it fun? Yes.", "A second paragraph."]
+        );
+    }
+
+    #[test]
+    fn test_markdown_split() {
+        let text = "Intro.\n# Guide\n## Install\nRun it.\n```\n# not a header\n```\n\
+                    ## Usage\nCall it.\n";
+        let metadata = Metadata::from([("source".to_string(), "guide.md".to_string())]);
+        let chunks = TextSplitter::markdown().chunk_size(100).split_with_metadata(text, &metadata);
+
+        assert_eq!(
+            texts(&chunks),
+            vec![
+                "Intro.",
+                "# Guide",
+                "## Install\nRun it.\n```\n# not a header\n```",
+                "## Usage\nCall it."
+            ]
+        );
+        assert_eq!(chunks[0].metadata, metadata);
+        assert_eq!(chunks[2].metadata["h1"], "Guide");
+        assert_eq!(chunks[2].metadata["h2"], "Install");
+        assert_eq!(chunks[3].metadata["h2"], "Usage");
+        assert_eq!(chunks[3].metadata["source"], "guide.md");
+    }
+
+    #[test]
+    fn test_token_budget() {
+        let text = "word ".repeat(100);
+        let tokenizer = HeuristicTokenizer::new();
+        let chunks = TextSplitter::recursive().token_budget(20).split(&text);
+
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert!(tokenizer.count_tokens(&chunk.text) <= 20, "{:?}", chunk);
+        }
+        assert!(chunks[1].start < chunks[0].end, "the chunks should overlap");
+    }
+}
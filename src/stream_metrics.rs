@@ -0,0 +1,548 @@
+//! Latency and throughput metrics of response streams.
+//!
+//! Wrap any provider stream with `TimedStream` (or `.timed()`) to measure the client-side
+//! time to first token, inter-token latencies, total duration and tokens per second. The
+//! metrics reported by the server in the stream, such as the `eval_duration` of Ollama or
+//! the `amazon-bedrock-invocationMetrics` of Bedrock, are merged in as `ServerMetrics`.
+//!
+//! ```no_run
+//! use futures::TryStreamExt;
+//! use hiramu::ollama::{GenerateRequestBuilder, OllamaClient};
+//! use hiramu::stream_metrics::TimedStreamExt;
+//!
+//! # async fn example() -> Result<(), hiramu::ollama::OllamaError> {
+//! let client = OllamaClient::new("http://localhost:11434".to_string());
+//! let request = GenerateRequestBuilder::new("llama3".to_string())
+//!     .prompt("Why is the sky blue?".to_string())
+//!     .build();
+//!
+//! let mut stream = Box::pin(client.generate(request).await?.into_stream().timed());
+//! while let Some(response) = stream.try_next().await? {
+//!     print!("{}", response.response);
+//! }
+//!
+//! let metrics = stream.metrics();
+//! println!("TTFT: {:?}", metrics.time_to_first_token);
+//! println!("p90 inter-token latency: {:?}", metrics.inter_token_latency(90.0));
+//! println!("tokens/s: {:?}", metrics.tokens_per_second());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures::stream::Stream;
+use pin_project::pin_project;
+use serde_json::Value;
+
+use crate::bedrock::models::claude::claude_request_message::{InvocationMetrics, StreamResultData};
+use crate::bedrock::models::mistral::mistral_request_message::MistralResponse;
+use crate::ollama::model::{ChatResponse, GenerateResponse};
+
+/// The key of the metrics Bedrock appends to the last chunk of a stream.
+const BEDROCK_METRICS_KEY: &str = "amazon-bedrock-invocationMetrics";
+
+/// The metrics reported by the server in a stream. Each field is `None` when the provider
+/// does not report it.
+///
+/// # Fields
+///
+/// * `time_to_first_token` - The `firstByteLatency` of Bedrock, or the model load and prompt
+///   evaluation time of Ollama.
+/// * `total_duration` - The `invocationLatency` of Bedrock, or the `total_duration` of Ollama.
+/// * `prompt_eval_duration` - The time spent evaluating the prompt (Ollama).
+/// * `eval_duration` - The time spent generating the completion (Ollama).
+/// * `input_tokens` - The prompt tokens.
+/// * `output_tokens` - The completion tokens.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ServerMetrics {
+    pub time_to_first_token: Option<Duration>,
+    pub total_duration: Option<Duration>,
+    pub prompt_eval_duration: Option<Duration>,
+    pub eval_duration: Option<Duration>,
+    pub input_tokens: Option<u64>,
+    pub output_tokens: Option<u64>,
+}
+
+impl ServerMetrics {
+    /// Fills the fields that are still `None` with those of `other`.
+    pub fn merge(&mut self, other: ServerMetrics) {
+        self.time_to_first_token = self.time_to_first_token.or(other.time_to_first_token);
+        self.total_duration = self.total_duration.or(other.total_duration);
+        self.prompt_eval_duration = self.prompt_eval_duration.or(other.prompt_eval_duration);
+        self.eval_duration = self.eval_duration.or(other.eval_duration);
+        self.input_tokens = self.input_tokens.or(other.input_tokens);
+        self.output_tokens = self.output_tokens.or(other.output_tokens);
+    }
+
+    /// The generation throughput measured by the server: the output tokens over the
+    /// generation time, or over the time after the first token.
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        let output_tokens = self.output_tokens?;
+        let generation = match (self.eval_duration, self.total_duration, self.time_to_first_token) {
+            (Some(eval_duration), _, _) => eval_duration,
+            (None, Some(total), Some(first_token)) => total.checked_sub(first_token)?,
+            _ => return None,
+        };
+        rate(output_tokens, generation)
+    }
+}
+
+impl From<&InvocationMetrics> for ServerMetrics {
+    fn from(metrics: &InvocationMetrics) -> Self {
+        Self {
+            time_to_first_token: Some(millis(metrics.first_byte_latency as i64)),
+            total_duration: Some(millis(metrics.invocation_latency as i64)),
+            input_tokens: Some(metrics.input_token_count.max(0) as u64),
+            output_tokens: Some(metrics.output_token_count.max(0) as u64),
+            ..Default::default()
+        }
+    }
+}
+
+/// A chunk of a response stream, as seen by `TimedStream`.
+pub trait StreamChunk {
+    /// Whether the chunk carries generated content. The arrival of these chunks is what
+    /// the time to first token and the inter-token latencies measure.
+    fn has_content(&self) -> bool;
+
+    /// The metrics the server reported in the chunk, usually in the last one.
+    fn server_metrics(&self) -> Option<ServerMetrics> {
+        None
+    }
+}
+
+impl StreamChunk for GenerateResponse {
+    fn has_content(&self) -> bool {
+        !self.response.is_empty()
+    }
+
+    fn server_metrics(&self) -> Option<ServerMetrics> {
+        self.done.then(|| {
+            ollama_metrics(
+                self.total_duration,
+                self.load_duration,
+                self.prompt_eval_duration,
+                self.eval_duration,
+                self.prompt_eval_count,
+                self.eval_count,
+            )
+        })
+    }
+}
+
+impl StreamChunk for ChatResponse {
+    fn has_content(&self) -> bool {
+        !self.message.content.is_empty()
+    }
+
+    fn server_metrics(&self) -> Option<ServerMetrics> {
+        self.done.then(|| {
+            ollama_metrics(
+                self.total_duration,
+                self.load_duration,
+                self.prompt_eval_duration,
+                self.eval_duration,
+                self.prompt_eval_count,
+                self.eval_count,
+            )
+        })
+    }
+}
+
+impl StreamChunk for StreamResultData {
+    fn has_content(&self) -> bool {
+        matches!(self, StreamResultData::ContentBlockDelta(_))
+    }
+
+    fn server_metrics(&self) -> Option<ServerMetrics> {
+        match self {
+            StreamResultData::MessageStart(message_start) => Some(ServerMetrics {
+                input_tokens: Some(message_start.message.usage.input_tokens.max(0) as u64),
+                ..Default::default()
+            }),
+            StreamResultData::MessageStop(message_stop) => {
+                Some(ServerMetrics::from(&message_stop.invocation_metrics))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl StreamChunk for MistralResponse {
+    fn has_content(&self) -> bool {
+        self.outputs.iter().any(|output| !output.text.is_empty())
+    }
+
+    fn server_metrics(&self) -> Option<ServerMetrics> {
+        self.invocation_metrics.as_ref().map(ServerMetrics::from)
+    }
+}
+
+/// The text deltas of `ConversationStream`.
+impl StreamChunk for String {
+    fn has_content(&self) -> bool {
+        !self.is_empty()
+    }
+}
+
+/// The raw chunks of `BedrockClient::generate_raw_stream`. A chunk carries content if it is a
+/// Claude `content_block_delta` event, or has the completion field of another model family.
+impl StreamChunk for Value {
+    fn has_content(&self) -> bool {
+        if let Some(chunk_type) = self.get("type").and_then(Value::as_str) {
+            return chunk_type == "content_block_delta";
+        }
+        ["outputs", "generation", "completion", "outputText"]
+            .iter()
+            .any(|key| self.get(key).is_some())
+    }
+
+    fn server_metrics(&self) -> Option<ServerMetrics> {
+        let metrics = self.get(BEDROCK_METRICS_KEY)?;
+        let value = |key: &str| metrics.get(key).and_then(Value::as_u64);
+        Some(ServerMetrics {
+            time_to_first_token: value("firstByteLatency").map(|ms| millis(ms as i64)),
+            total_duration: value("invocationLatency").map(|ms| millis(ms as i64)),
+            input_tokens: value("inputTokenCount"),
+            output_tokens: value("outputTokenCount"),
+            ..Default::default()
+        })
+    }
+}
+
+/// The latency percentiles between content chunks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencyPercentiles {
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+/// The metrics of a stream, measured by the client and reported by the server.
+///
+/// # Fields
+///
+/// * `time_to_first_token` - The time from the start of the measure to the first content
+///   chunk, or `None` if no content was received.
+/// * `total_duration` - The time from the start of the measure to the end of the stream, or
+///   to now if the stream is not finished.
+/// * `content_chunks` - The number of content chunks received.
+/// * `inter_token_latencies` - The time between consecutive content chunks.
+/// * `server` - The metrics reported by the server, if any.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamMetrics {
+    pub time_to_first_token: Option<Duration>,
+    pub total_duration: Duration,
+    pub content_chunks: usize,
+    pub inter_token_latencies: Vec<Duration>,
+    pub server: Option<ServerMetrics>,
+}
+
+impl StreamMetrics {
+    /// The output tokens reported by the server, else the number of content chunks.
+    pub fn output_tokens(&self) -> u64 {
+        self.server
+            .as_ref()
+            .and_then(|server| server.output_tokens)
+            .unwrap_or(self.content_chunks as u64)
+    }
+
+    /// The generation throughput seen by the client: the output tokens over the time from the
+    /// first token to the end of the stream. `None` if that time is zero.
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        let generation = self
+            .total_duration
+            .checked_sub(self.time_to_first_token?)?;
+        rate(self.output_tokens(), generation)
+    }
+
+    /// The inter-token latency at a percentile between 0 and 100, by the nearest-rank method.
+    pub fn inter_token_latency(&self, percentile: f64) -> Option<Duration> {
+        let mut latencies = self.inter_token_latencies.clone();
+        latencies.sort();
+        nearest_rank(&latencies, percentile)
+    }
+
+    /// The p50, p90, p99 and maximum inter-token latencies, or `None` if fewer than two
+    /// content chunks were received.
+    pub fn latency_percentiles(&self) -> Option<LatencyPercentiles> {
+        let mut latencies = self.inter_token_latencies.clone();
+        latencies.sort();
+        Some(LatencyPercentiles {
+            p50: nearest_rank(&latencies, 50.0)?,
+            p90: nearest_rank(&latencies, 90.0)?,
+            p99: nearest_rank(&latencies, 99.0)?,
+            max: *latencies.last()?,
+        })
+    }
+}
+
+/// A stream wrapper that measures the latency and throughput of the stream it wraps.
+///
+/// The items are passed through unchanged. The measure starts when the wrapper is created,
+/// or at the instant given to `started_at`, e.g. before the request was sent.
+#[pin_project]
+pub struct TimedStream<S> {
+    #[pin]
+    inner: S,
+    start: Instant,
+    last_content: Option<Instant>,
+    time_to_first_token: Option<Duration>,
+    inter_token_latencies: Vec<Duration>,
+    content_chunks: usize,
+    server: Option<ServerMetrics>,
+    finished_at: Option<Instant>,
+}
+
+impl<S> TimedStream<S> {
+    pub fn new(inner: S) -> Self {
+        Self::started_at(inner, Instant::now())
+    }
+
+    /// Wraps a stream, measuring from `start`.
+    pub fn started_at(inner: S, start: Instant) -> Self {
+        Self {
+            inner,
+            start,
+            last_content: None,
+            time_to_first_token: None,
+            inter_token_latencies: Vec::new(),
+            content_chunks: 0,
+            server: None,
+            finished_at: None,
+        }
+    }
+
+    /// The metrics of the chunks received so far.
+    pub fn metrics(&self) -> StreamMetrics {
+        let end = self.finished_at.unwrap_or_else(Instant::now);
+        StreamMetrics {
+            time_to_first_token: self.time_to_first_token,
+            total_duration: end.saturating_duration_since(self.start),
+            content_chunks: self.content_chunks,
+            inter_token_latencies: self.inter_token_latencies.clone(),
+            server: self.server.clone(),
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, T, E> Stream for TimedStream<S>
+where
+    S: Stream<Item = Result<T, E>>,
+    T: StreamChunk,
+{
+    type Item = Result<T, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let item = futures::ready!(this.inner.poll_next(cx));
+        let now = Instant::now();
+        match &item {
+            Some(Ok(chunk)) => {
+                if chunk.has_content() {
+                    *this.content_chunks += 1;
+                    match this.last_content {
+                        Some(last) => this.inter_token_latencies.push(now - *last),
+                        None => *this.time_to_first_token = Some(now - *this.start),
+                    }
+                    *this.last_content = Some(now);
+                }
+                if let Some(metrics) = chunk.server_metrics() {
+                    this.server.get_or_insert_with(Default::default).merge(metrics);
+                }
+            }
+            Some(Err(_)) => {}
+            None => *this.finished_at = Some(now),
+        }
+        Poll::Ready(item)
+    }
+}
+
+/// Adds `timed` to the streams of `Result` items.
+pub trait TimedStreamExt: Stream + Sized {
+    /// Wraps the stream in a `TimedStream`.
+    fn timed(self) -> TimedStream<Self> {
+        TimedStream::new(self)
+    }
+}
+
+impl<S: Stream> TimedStreamExt for S {}
+
+fn ollama_metrics(
+    total_duration: Option<u128>,
+    load_duration: Option<u128>,
+    prompt_eval_duration: Option<u128>,
+    eval_duration: Option<u128>,
+    prompt_eval_count: Option<u32>,
+    eval_count: Option<u32>,
+) -> ServerMetrics {
+    let nanos = |nanos: u128| Duration::from_nanos(nanos.min(u64::MAX as u128) as u64);
+    let time_to_first_token = match (load_duration, prompt_eval_duration) {
+        (None, None) => None,
+        (load, prompt_eval) => Some(nanos(load.unwrap_or(0) + prompt_eval.unwrap_or(0))),
+    };
+    ServerMetrics {
+        time_to_first_token,
+        total_duration: total_duration.map(nanos),
+        prompt_eval_duration: prompt_eval_duration.map(nanos),
+        eval_duration: eval_duration.map(nanos),
+        input_tokens: prompt_eval_count.map(u64::from),
+        output_tokens: eval_count.map(u64::from),
+    }
+}
+
+fn millis(millis: i64) -> Duration {
+    Duration::from_millis(millis.max(0) as u64)
+}
+
+fn rate(tokens: u64, duration: Duration) -> Option<f64> {
+    let seconds = duration.as_secs_f64();
+    (seconds > 0.0).then(|| tokens as f64 / seconds)
+}
+
+fn nearest_rank(sorted: &[Duration], percentile: f64) -> Option<Duration> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (percentile.clamp(0.0, 100.0) / 100.0 * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bedrock::models::claude::claude_client::deserialize_stream_result;
+    use futures::stream::{self, StreamExt};
+
+    #[tokio::test]
+    async fn test_ollama_stream_metrics() {
+        let chunks: Vec<Result<GenerateResponse, ()>> = vec![
+            Ok(serde_json::from_value(serde_json::json!({
+                "model": "llama3", "created_at": "2024-04-01T00:00:00Z",
+                "response": "Paris", "done": false
+            }))
+            .unwrap()),
+            Ok(serde_json::from_value(serde_json::json!({
+                "model": "llama3", "created_at": "2024-04-01T00:00:00Z",
+                "response": ".", "done": false
+            }))
+            .unwrap()),
+            Ok(serde_json::from_value(serde_json::json!({
+                "model": "llama3", "created_at": "2024-04-01T00:00:00Z",
+                "response": "", "done": true,
+                "total_duration": 3_000_000_000u64, "load_duration": 500_000_000u64,
+                "prompt_eval_count": 12, "prompt_eval_duration": 500_000_000u64,
+                "eval_count": 4, "eval_duration": 2_000_000_000u64
+            }))
+            .unwrap()),
+        ];
+
+        let mut stream = stream::iter(chunks).timed();
+        while stream.next().await.is_some() {}
+        let metrics = stream.metrics();
+
+        assert_eq!(metrics.content_chunks, 2);
+        assert!(metrics.time_to_first_token.is_some());
+        assert_eq!(metrics.inter_token_latencies.len(), 1);
+        assert_eq!(metrics.output_tokens(), 4);
+
+        let server = metrics.server.unwrap();
+        assert_eq!(server.time_to_first_token, Some(Duration::from_secs(1)));
+        assert_eq!(server.total_duration, Some(Duration::from_secs(3)));
+        assert_eq!(server.input_tokens, Some(12));
+        assert_eq!(server.tokens_per_second(), Some(2.0));
+    }
+
+    #[tokio::test]
+    async fn test_claude_stream_metrics() {
+        let events = vec![
+            serde_json::json!({"type": "message_start", "message": {
+                "id": "msg_1", "model": "claude-3-haiku", "type": "message", "role": "assistant",
+                "content": [], "stop_reason": null, "stop_sequence": null,
+                "usage": {"input_tokens": 10, "output_tokens": 1}}}),
+            serde_json::json!({"type": "content_block_delta", "index": 0,
+                "delta": {"type": "text_delta", "text": "Hello"}}),
+            serde_json::json!({"type": "message_stop",
+                "amazon-bedrock-invocationMetrics": {"inputTokenCount": 10,
+                "outputTokenCount": 5, "invocationLatency": 900, "firstByteLatency": 300}}),
+        ];
+        let events: Vec<_> = events.into_iter().map(deserialize_stream_result).collect();
+
+        let mut stream = stream::iter(events).timed();
+        while stream.next().await.is_some() {}
+        let metrics = stream.metrics();
+
+        assert_eq!(metrics.content_chunks, 1);
+        assert!(metrics.latency_percentiles().is_none());
+        let server = metrics.server.unwrap();
+        assert_eq!(server.input_tokens, Some(10));
+        assert_eq!(server.output_tokens, Some(5));
+        assert_eq!(server.time_to_first_token, Some(Duration::from_millis(300)));
+        assert_eq!(server.tokens_per_second(), Some(5.0 / 0.6));
+    }
+
+    #[test]
+    fn test_latency_percentiles() {
+        let metrics = StreamMetrics {
+            time_to_first_token: Some(Duration::from_millis(200)),
+            total_duration: Duration::from_millis(1200),
+            content_chunks: 11,
+            inter_token_latencies: (1..=10).rev().map(|ms| Duration::from_millis(ms * 10)).collect(),
+            server: None,
+        };
+
+        let percentiles = metrics.latency_percentiles().unwrap();
+        assert_eq!(percentiles.p50, Duration::from_millis(50));
+        assert_eq!(percentiles.p90, Duration::from_millis(90));
+        assert_eq!(percentiles.p99, Duration::from_millis(100));
+        assert_eq!(percentiles.max, Duration::from_millis(100));
+        assert_eq!(metrics.inter_token_latency(0.0), Some(Duration::from_millis(10)));
+        assert_eq!(metrics.tokens_per_second(), Some(11.0));
+    }
+
+    #[test]
+    fn test_raw_bedrock_chunks() {
+        let delta = serde_json::json!({"type": "content_block_delta", "delta": {"text": "Hi"}});
+        let start = serde_json::json!({"type": "message_start", "message": {}});
+        let mistral = serde_json::json!({"outputs": [{"text": "Hi", "stop_reason": null}]});
+        assert!(delta.has_content());
+        assert!(!start.has_content());
+        assert!(mistral.has_content());
+
+        let last = serde_json::json!({"outputs": [], "amazon-bedrock-invocationMetrics": {
+            "inputTokenCount": 7, "outputTokenCount": 3,
+            "invocationLatency": 400, "firstByteLatency": 100}});
+        let server = last.server_metrics().unwrap();
+        assert_eq!(server.output_tokens, Some(3));
+        assert_eq!(server.total_duration, Some(Duration::from_millis(400)));
+    }
+
+    #[test]
+    fn test_mistral_chunks() {
+        let first: MistralResponse =
+            serde_json::from_value(serde_json::json!({"outputs": [{"text": "Hi", "stop_reason": null}]}))
+                .unwrap();
+        let last: MistralResponse = serde_json::from_value(serde_json::json!({
+            "outputs": [{"text": "", "stop_reason": "stop"}],
+            "amazon-bedrock-invocationMetrics": {
+                "inputTokenCount": 7, "outputTokenCount": 3,
+                "invocationLatency": 400, "firstByteLatency": 100}}))
+        .unwrap();
+        assert!(first.server_metrics().is_none());
+        assert!(!last.has_content());
+
+        let server = last.server_metrics().unwrap();
+        assert_eq!(server.input_tokens, Some(7));
+        assert_eq!(server.time_to_first_token, Some(Duration::from_millis(100)));
+    }
+}
@@ -0,0 +1,382 @@
+//! Tracing instrumentation of the clients, following the OpenTelemetry GenAI semantic
+//! conventions.
+//!
+//! With the `tracing` feature enabled, every call made by `OllamaClient`, `BedrockClient`,
+//! `ClaudeClient` and `MistralClient` runs in a span carrying the `gen_ai.*` attributes:
+//! the operation, provider and model, the request parameters, the token counts, the
+//! time to first token, the finish reason and the error type. The spans can be exported to
+//! an OpenTelemetry collector with `tracing-opentelemetry`.
+//!
+//! `ClaudeClient` and `MistralClient` open an `info` span per call. The `BedrockClient` call
+//! they make runs in a nested `debug` span, so a subscriber at the `info` level only sees one
+//! span per call.
+//!
+//! The prompts and responses are only recorded, as `gen_ai.input.messages` and
+//! `gen_ai.output.messages`, once content capture is enabled with `set_capture_content`,
+//! or with the `OTEL_INSTRUMENTATION_GENAI_CAPTURE_MESSAGE_CONTENT=true` environment variable.
+//!
+//! Without the `tracing` feature the instrumentation compiles to nothing.
+
+use std::fmt::Debug;
+use std::future::Future;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::{Duration, Instant};
+
+/// The `gen_ai.operation.name` of a chat completion.
+pub const OPERATION_CHAT: &str = "chat";
+/// The `gen_ai.operation.name` of a text completion.
+pub const OPERATION_TEXT_COMPLETION: &str = "text_completion";
+/// The `gen_ai.operation.name` of an embeddings call.
+pub const OPERATION_EMBEDDINGS: &str = "embeddings";
+/// The `gen_ai.operation.name` of a raw `InvokeModel` call of `BedrockClient`.
+pub const OPERATION_INVOKE_MODEL: &str = "invoke_model";
+
+/// The `gen_ai.provider.name` of the Bedrock clients.
+pub const PROVIDER_BEDROCK: &str = "aws.bedrock";
+/// The `gen_ai.provider.name` of `OllamaClient`.
+pub const PROVIDER_OLLAMA: &str = "ollama";
+
+const CAPTURE_CONTENT_ENV: &str = "OTEL_INSTRUMENTATION_GENAI_CAPTURE_MESSAGE_CONTENT";
+
+const CAPTURE_UNSET: u8 = 0;
+const CAPTURE_OFF: u8 = 1;
+const CAPTURE_ON: u8 = 2;
+
+static CAPTURE_CONTENT: AtomicU8 = AtomicU8::new(CAPTURE_UNSET);
+
+/// Enables or disables the recording of the prompts and responses in the spans.
+///
+/// Overrides the `OTEL_INSTRUMENTATION_GENAI_CAPTURE_MESSAGE_CONTENT` environment variable.
+pub fn set_capture_content(enabled: bool) {
+    let state = if enabled { CAPTURE_ON } else { CAPTURE_OFF };
+    CAPTURE_CONTENT.store(state, Ordering::Relaxed);
+}
+
+/// Whether the prompts and responses are recorded in the spans.
+pub fn capture_content() -> bool {
+    match CAPTURE_CONTENT.load(Ordering::Relaxed) {
+        CAPTURE_ON => true,
+        CAPTURE_OFF => false,
+        _ => {
+            let enabled = std::env::var(CAPTURE_CONTENT_ENV)
+                .map(|value| value.eq_ignore_ascii_case("true"))
+                .unwrap_or(false);
+            set_capture_content(enabled);
+            enabled
+        }
+    }
+}
+
+/// The `error.type` of an error: the name of its variant, e.g. `NotFound` for
+/// `OllamaError::NotFound(..)`.
+#[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+pub(crate) fn error_type<E: Debug>(err: &E) -> String {
+    let debug = format!("{:?}", err);
+    let end = debug
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(debug.len());
+    debug[..end].to_string()
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! gen_ai_span {
+    ($level:expr, $operation:expr, $provider:expr, $model:expr) => {
+        tracing::span!(
+            $level,
+            "gen_ai",
+            otel.name = %format_args!("{} {}", $operation, $model),
+            otel.kind = "client",
+            otel.status_code = tracing::field::Empty,
+            gen_ai.operation.name = $operation,
+            gen_ai.provider.name = $provider,
+            gen_ai.request.model = $model,
+            gen_ai.request.max_tokens = tracing::field::Empty,
+            gen_ai.request.temperature = tracing::field::Empty,
+            gen_ai.request.top_p = tracing::field::Empty,
+            gen_ai.response.model = tracing::field::Empty,
+            gen_ai.response.finish_reasons = tracing::field::Empty,
+            gen_ai.usage.input_tokens = tracing::field::Empty,
+            gen_ai.usage.output_tokens = tracing::field::Empty,
+            gen_ai.server.time_to_first_token = tracing::field::Empty,
+            gen_ai.client.operation.duration = tracing::field::Empty,
+            gen_ai.input.messages = tracing::field::Empty,
+            gen_ai.output.messages = tracing::field::Empty,
+            "error.type" = tracing::field::Empty,
+        )
+    };
+}
+
+/// The span of one call. The duration is recorded when it is dropped, so a streaming call
+/// moves its span into the stream.
+pub(crate) struct GenAiSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    start: Instant,
+    time_to_first_token: Option<Duration>,
+}
+
+impl GenAiSpan {
+    /// Opens an `info` span for a call of a typed client.
+    pub(crate) fn new(operation: &str, provider: &str, model: &str) -> Self {
+        #[cfg(not(feature = "tracing"))]
+        let _ = (operation, provider, model);
+        Self {
+            #[cfg(feature = "tracing")]
+            span: gen_ai_span!(tracing::Level::INFO, operation, provider, model),
+            start: Instant::now(),
+            time_to_first_token: None,
+        }
+    }
+
+    /// Opens a `debug` span for a raw call, usually nested in the span of a typed client.
+    pub(crate) fn debug(operation: &str, provider: &str, model: &str) -> Self {
+        #[cfg(not(feature = "tracing"))]
+        let _ = (operation, provider, model);
+        Self {
+            #[cfg(feature = "tracing")]
+            span: gen_ai_span!(tracing::Level::DEBUG, operation, provider, model),
+            start: Instant::now(),
+            time_to_first_token: None,
+        }
+    }
+
+    /// Runs a future in the span, so the spans it opens are nested in it.
+    #[cfg(feature = "tracing")]
+    pub(crate) fn instrument<F: Future>(&self, future: F) -> impl Future<Output = F::Output> {
+        tracing::Instrument::instrument(future, self.span.clone())
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub(crate) fn instrument<F: Future>(&self, future: F) -> impl Future<Output = F::Output> {
+        future
+    }
+
+    /// Records the sampling parameters of the request.
+    pub(crate) fn request(
+        &self,
+        max_tokens: Option<u64>,
+        temperature: Option<f64>,
+        top_p: Option<f64>,
+    ) {
+        #[cfg(feature = "tracing")]
+        {
+            if let Some(max_tokens) = max_tokens {
+                self.span.record("gen_ai.request.max_tokens", max_tokens);
+            }
+            if let Some(temperature) = temperature {
+                self.span.record("gen_ai.request.temperature", temperature);
+            }
+            if let Some(top_p) = top_p {
+                self.span.record("gen_ai.request.top_p", top_p);
+            }
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = (max_tokens, temperature, top_p);
+    }
+
+    /// Records the prompt, if content capture is enabled. `input` is only called then.
+    pub(crate) fn input<F: FnOnce() -> String>(&self, input: F) {
+        #[cfg(feature = "tracing")]
+        if capture_content() {
+            self.span.record("gen_ai.input.messages", input().as_str());
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = input;
+    }
+
+    /// Records the response, if content capture is enabled. `output` is only called then.
+    pub(crate) fn output<F: FnOnce() -> String>(&self, output: F) {
+        #[cfg(feature = "tracing")]
+        if capture_content() {
+            self.span.record("gen_ai.output.messages", output().as_str());
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = output;
+    }
+
+    /// Records the time to first token, on the first call only.
+    pub(crate) fn first_token(&mut self) {
+        if self.time_to_first_token.is_some() {
+            return;
+        }
+        let elapsed = self.start.elapsed();
+        self.time_to_first_token = Some(elapsed);
+        #[cfg(feature = "tracing")]
+        self.span
+            .record("gen_ai.server.time_to_first_token", elapsed.as_secs_f64());
+    }
+
+    pub(crate) fn response_model(&self, model: &str) {
+        #[cfg(feature = "tracing")]
+        self.span.record("gen_ai.response.model", model);
+        #[cfg(not(feature = "tracing"))]
+        let _ = model;
+    }
+
+    pub(crate) fn usage(&self, input_tokens: u64, output_tokens: u64) {
+        #[cfg(feature = "tracing")]
+        {
+            self.span.record("gen_ai.usage.input_tokens", input_tokens);
+            self.span.record("gen_ai.usage.output_tokens", output_tokens);
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = (input_tokens, output_tokens);
+    }
+
+    pub(crate) fn finish_reason(&self, finish_reason: &str) {
+        #[cfg(feature = "tracing")]
+        self.span
+            .record("gen_ai.response.finish_reasons", finish_reason);
+        #[cfg(not(feature = "tracing"))]
+        let _ = finish_reason;
+    }
+
+    /// Marks the call as failed, and logs the error in the span.
+    pub(crate) fn error<E: Debug + std::fmt::Display>(&self, err: &E) {
+        #[cfg(feature = "tracing")]
+        {
+            self.span.record("error.type", error_type(err).as_str());
+            self.span.record("otel.status_code", "ERROR");
+            self.span.in_scope(|| tracing::warn!(error = %err, "gen_ai call failed"));
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = err;
+    }
+}
+
+impl Drop for GenAiSpan {
+    fn drop(&mut self) {
+        #[cfg(feature = "tracing")]
+        self.span.record(
+            "gen_ai.client.operation.duration",
+            self.start.elapsed().as_secs_f64(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ollama::error::OllamaError;
+
+    #[test]
+    fn test_error_type() {
+        let err = OllamaError::NotFound("model not found".to_string());
+        assert_eq!(error_type(&err), "NotFound");
+
+        let err = OllamaError::Unknown("oops".to_string());
+        assert_eq!(error_type(&err), "Unknown");
+    }
+
+    #[test]
+    fn test_first_token_is_recorded_once() {
+        let mut span = GenAiSpan::new(OPERATION_CHAT, PROVIDER_OLLAMA, "llama3");
+        span.first_token();
+        let first = span.time_to_first_token;
+        std::thread::sleep(Duration::from_millis(2));
+        span.first_token();
+        assert!(first.is_some());
+        assert_eq!(span.time_to_first_token, first);
+    }
+
+    #[cfg(feature = "tracing")]
+    mod spans {
+        use std::collections::HashMap;
+        use std::sync::atomic::{AtomicU64, Ordering};
+        use std::sync::{Arc, Mutex};
+
+        use futures::TryStreamExt;
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        use crate::ollama::{ChatRequestBuilder, Message, OllamaClient};
+        use crate::testing::{MockEndpoint, MockOllamaServer, MockResponse};
+
+        type Fields = HashMap<String, String>;
+
+        /// A subscriber keeping the fields recorded in the `gen_ai` spans.
+        #[derive(Clone, Default)]
+        struct SpanCapture {
+            next_id: Arc<AtomicU64>,
+            spans: Arc<Mutex<HashMap<u64, Fields>>>,
+        }
+
+        struct FieldVisitor<'a>(&'a mut Fields);
+
+        impl Visit for FieldVisitor<'_> {
+            fn record_str(&mut self, field: &Field, value: &str) {
+                self.0.insert(field.name().to_string(), value.to_string());
+            }
+
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                self.0.insert(field.name().to_string(), format!("{:?}", value));
+            }
+        }
+
+        impl Subscriber for SpanCapture {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+                let mut fields = Fields::new();
+                span.record(&mut FieldVisitor(&mut fields));
+                if span.metadata().name() == "gen_ai" {
+                    self.spans.lock().unwrap().insert(id, fields);
+                }
+                Id::from_u64(id)
+            }
+
+            fn record(&self, span: &Id, values: &Record<'_>) {
+                if let Some(fields) = self.spans.lock().unwrap().get_mut(&span.into_u64()) {
+                    values.record(&mut FieldVisitor(fields));
+                }
+            }
+
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+            fn event(&self, _: &Event<'_>) {}
+
+            fn enter(&self, _: &Id) {}
+
+            fn exit(&self, _: &Id) {}
+        }
+
+        #[tokio::test]
+        async fn test_chat_span_attributes() {
+            let server = MockOllamaServer::start().await.unwrap();
+            server.enqueue(MockEndpoint::Chat, MockResponse::text(["Paris", "."]));
+            let capture = SpanCapture::default();
+            let _guard = tracing::subscriber::set_default(capture.clone());
+
+            let client = OllamaClient::new(server.base_url());
+            let request = ChatRequestBuilder::new("llama3".to_string())
+                .add_message(Message::new(
+                    "user".to_string(),
+                    "What is the capital of France?".to_string(),
+                ))
+                .options(serde_json::json!({ "temperature": 0.0, "num_predict": 20 }))
+                .build();
+            let stream = client.chat(request).await.unwrap();
+            let _: Vec<_> = stream.try_collect().await.unwrap();
+
+            let spans = capture.spans.lock().unwrap();
+            assert_eq!(spans.len(), 1);
+            let fields = spans.values().next().unwrap();
+            assert_eq!(fields["gen_ai.operation.name"], "chat");
+            assert_eq!(fields["gen_ai.provider.name"], "ollama");
+            assert_eq!(fields["gen_ai.request.model"], "llama3");
+            assert_eq!(fields["gen_ai.request.max_tokens"], "20");
+            assert_eq!(fields["gen_ai.request.temperature"], "0.0");
+            assert_eq!(fields["gen_ai.response.model"], "llama3");
+            assert_eq!(fields["gen_ai.response.finish_reasons"], "stop");
+            assert!(fields["gen_ai.usage.input_tokens"].parse::<u64>().unwrap() > 0);
+            assert_eq!(fields["gen_ai.usage.output_tokens"], "2");
+            assert!(fields.contains_key("gen_ai.server.time_to_first_token"));
+            assert!(fields.contains_key("gen_ai.client.operation.duration"));
+            assert!(!fields.contains_key("error.type"));
+        }
+    }
+}
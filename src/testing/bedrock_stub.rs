@@ -0,0 +1,1040 @@
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use aws_smithy_eventstream::frame::write_message_to;
+use aws_smithy_types::event_stream::{Header, HeaderValue, Message};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::Bytes;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+use serde_json::{json, Value};
+use tokio::sync::oneshot;
+
+use crate::bedrock::bedrock_client::BedrockClientOptions;
+use crate::testing::error::FixtureError;
+use crate::tokenizer::{HeuristicTokenizer, Tokenizer};
+
+/// The reply of the stub server when no response is scripted.
+pub const DEFAULT_REPLY: &str = "This is a stub response.";
+
+/// A Bedrock runtime operation served by `BedrockStubServer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StubOperation {
+    InvokeModel,
+    InvokeModelWithResponseStream,
+}
+
+/// The request and response format of a model, read from the prefix of its id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelFamily {
+    Claude,
+    Mistral,
+    Titan,
+}
+
+impl ModelFamily {
+    /// The family of a model id, an inference profile id such as `us.anthropic.claude-...`
+    /// included. Defaults to `Claude`.
+    pub fn from_model_id(model_id: &str) -> Self {
+        if model_id.contains("mistral.") {
+            ModelFamily::Mistral
+        } else if model_id.contains("amazon.titan") {
+            ModelFamily::Titan
+        } else {
+            ModelFamily::Claude
+        }
+    }
+}
+
+/// An error of the Bedrock runtime API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BedrockException {
+    AccessDenied,
+    InternalServer,
+    ModelStreamError,
+    ModelTimeout,
+    ResourceNotFound,
+    ServiceUnavailable,
+    Throttling,
+    Validation,
+}
+
+impl BedrockException {
+    /// The error type, e.g. `ThrottlingException`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            BedrockException::AccessDenied => "AccessDeniedException",
+            BedrockException::InternalServer => "InternalServerException",
+            BedrockException::ModelStreamError => "ModelStreamErrorException",
+            BedrockException::ModelTimeout => "ModelTimeoutException",
+            BedrockException::ResourceNotFound => "ResourceNotFoundException",
+            BedrockException::ServiceUnavailable => "ServiceUnavailableException",
+            BedrockException::Throttling => "ThrottlingException",
+            BedrockException::Validation => "ValidationException",
+        }
+    }
+
+    pub fn status(&self) -> u16 {
+        match self {
+            BedrockException::AccessDenied => 403,
+            BedrockException::InternalServer => 500,
+            BedrockException::ModelStreamError => 424,
+            BedrockException::ModelTimeout => 408,
+            BedrockException::ResourceNotFound => 404,
+            BedrockException::ServiceUnavailable => 503,
+            BedrockException::Throttling => 429,
+            BedrockException::Validation => 400,
+        }
+    }
+
+    /// The name of the exception event in a response stream, e.g. `throttlingException`.
+    fn event_type(&self) -> String {
+        let name = self.name();
+        name[..1].to_lowercase() + &name[1..]
+    }
+}
+
+#[derive(Debug, Clone)]
+enum StubBody {
+    /// The tokens of a completion, rendered in the format of the model family.
+    Tokens(Vec<String>),
+    Json(Value),
+    Chunks(Vec<Value>),
+    RawChunks(Vec<String>),
+    Error(BedrockException, String),
+}
+
+/// A scripted response of `BedrockStubServer`.
+///
+/// # Example
+///
+/// ```
+/// use hiramu::testing::{BedrockException, StubResponse};
+///
+/// let completion = StubResponse::text(["Paris", " is", " the", " capital."]);
+/// let throttled = StubResponse::error(BedrockException::Throttling, "Too many requests");
+/// let interrupted = StubResponse::text(["Par"])
+///     .then_exception(BedrockException::ModelStreamError, "The model stopped");
+/// ```
+#[derive(Debug, Clone)]
+pub struct StubResponse {
+    body: StubBody,
+    exception: Option<(BedrockException, String)>,
+    delay: Duration,
+    chunk_delay: Duration,
+}
+
+impl StubResponse {
+    fn new(body: StubBody) -> Self {
+        Self {
+            body,
+            exception: None,
+            delay: Duration::ZERO,
+            chunk_delay: Duration::ZERO,
+        }
+    }
+
+    /// A completion in the format of the model, e.g. a Claude message or a Mistral output.
+    /// A streamed completion sends one chunk per token, and reports the invocation metrics in
+    /// the last chunk.
+    pub fn text<I, S>(tokens: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self::new(StubBody::Tokens(tokens.into_iter().map(Into::into).collect()))
+    }
+
+    /// A model response, sent as is. A streamed response sends it as a single chunk.
+    pub fn json(body: Value) -> Self {
+        Self::new(StubBody::Json(body))
+    }
+
+    /// The chunks of a streamed response, sent as is. Fails an `InvokeModel` call.
+    pub fn chunks(chunks: Vec<Value>) -> Self {
+        Self::new(StubBody::Chunks(chunks))
+    }
+
+    /// The chunks of a streamed response, sent as is without being checked, e.g. to send
+    /// malformed JSON. Fails an `InvokeModel` call.
+    pub fn raw_chunks<I, S>(chunks: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self::new(StubBody::RawChunks(chunks.into_iter().map(Into::into).collect()))
+    }
+
+    /// An error response, with the status and error type of `exception`.
+    pub fn error<S: Into<String>>(exception: BedrockException, message: S) -> Self {
+        Self::new(StubBody::Error(exception, message.into()))
+    }
+
+    /// Ends a streamed response with an exception event, after its chunks.
+    pub fn then_exception<S: Into<String>>(
+        mut self,
+        exception: BedrockException,
+        message: S,
+    ) -> Self {
+        self.exception = Some((exception, message.into()));
+        self
+    }
+
+    /// Waits before sending the response headers, e.g. to trigger a client timeout.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Waits between the events of a streamed response.
+    pub fn chunk_delay(mut self, delay: Duration) -> Self {
+        self.chunk_delay = delay;
+        self
+    }
+}
+
+/// A request received by `BedrockStubServer`.
+///
+/// # Fields
+///
+/// * `operation` - The operation, or `None` for an unknown path.
+/// * `model_id` - The model id, decoded from the path.
+/// * `body` - The JSON payload, or `Null` if there is none.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct StubRequest {
+    pub operation: Option<StubOperation>,
+    pub model_id: String,
+    pub body: Value,
+}
+
+#[derive(Debug, Default)]
+struct StubState {
+    scripted: VecDeque<StubResponse>,
+    default: Option<StubResponse>,
+    requests: Vec<StubRequest>,
+}
+
+/// An in-process HTTP server implementing the `InvokeModel` and
+/// `InvokeModelWithResponseStream` operations of the Bedrock runtime, to test `ClaudeClient`,
+/// `MistralClient` and the code built on `BedrockClient` offline.
+///
+/// Streamed responses use the `application/vnd.amazon.eventstream` framing of the real
+/// service, so they go through the event-stream decoder of the AWS SDK. The responses are
+/// scripted with `enqueue`, served once each in order for either operation, or with
+/// `set_default`; without a script, the models reply `DEFAULT_REPLY`. The server stops when it
+/// is dropped.
+///
+/// # Example
+///
+/// ```no_run
+/// use hiramu::bedrock::models::claude::{ChatOptions, ClaudeClient, ConversationRequest, Message};
+/// use hiramu::testing::{BedrockException, BedrockStubServer, StubResponse};
+///
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let server = BedrockStubServer::start().await?;
+/// server.enqueue(StubResponse::text(["Paris", "."]));
+/// server.enqueue(StubResponse::error(BedrockException::Throttling, "Too many requests"));
+///
+/// let client = ClaudeClient::new(server.client_options()).await?;
+/// let mut request = ConversationRequest::default();
+/// request.messages.push(Message::new_user_message("What is the capital of France?".to_string()));
+/// let response = client.chat(&request, &ChatOptions::default()).await?;
+/// assert!(client.chat(&request, &ChatOptions::default()).await.is_err());
+/// # Ok(())
+/// # }
+/// ```
+pub struct BedrockStubServer {
+    address: SocketAddr,
+    state: Arc<Mutex<StubState>>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl BedrockStubServer {
+    /// Starts a server on a free local port.
+    pub async fn start() -> Result<Self, FixtureError> {
+        let state = Arc::new(Mutex::new(StubState::default()));
+
+        let service_state = state.clone();
+        let make_service = make_service_fn(move |_| {
+            let state = service_state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| handle(state.clone(), request)))
+            }
+        });
+
+        let server = Server::try_bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+            .map_err(|err| FixtureError::Http(err.to_string()))?
+            .serve(make_service);
+        let address = server.local_addr();
+
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            stopped.await.ok();
+        }));
+
+        Ok(Self {
+            address,
+            state,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    /// The URL to give to `BedrockClientOptions::endpoint_url`, e.g. `http://127.0.0.1:49152`.
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Client options pointing at the server, with static test credentials and without
+    /// retries, so that a scripted error reaches the caller.
+    pub fn client_options(&self) -> BedrockClientOptions {
+        BedrockClientOptions::new()
+            .region("us-east-1")
+            .endpoint_url(self.base_url())
+            .credentials("AKIDSTUB", "stub-secret", None)
+            .max_attempts(1)
+    }
+
+    /// Scripts the next response. The scripted responses are served once each, in order,
+    /// before the default response.
+    pub fn enqueue(&self, response: StubResponse) {
+        self.state.lock().unwrap().scripted.push_back(response);
+    }
+
+    /// Replaces the response served once the scripted responses are served.
+    pub fn set_default(&self, response: StubResponse) {
+        self.state.lock().unwrap().default = Some(response);
+    }
+
+    /// The requests received so far, in order.
+    pub fn requests(&self) -> Vec<StubRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+impl Drop for BedrockStubServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+/// The operation and model id of a request path, e.g. `/model/{model_id}/invoke`.
+fn parse_path(path: &str) -> Option<(StubOperation, String)> {
+    let rest = path.strip_prefix("/model/")?;
+    let (model_id, operation) = rest.rsplit_once('/')?;
+    let operation = match operation {
+        "invoke" => StubOperation::InvokeModel,
+        "invoke-with-response-stream" => StubOperation::InvokeModelWithResponseStream,
+        _ => return None,
+    };
+    Some((operation, percent_decode(model_id)))
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = (bytes[index] == b'%')
+            .then(|| text.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+async fn handle(
+    state: Arc<Mutex<StubState>>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let path = request.uri().path().to_string();
+    let body = hyper::body::to_bytes(request.into_body())
+        .await
+        .unwrap_or_default();
+    let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    let parsed = parse_path(&path);
+
+    let response = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(StubRequest {
+            operation: parsed.as_ref().map(|(operation, _)| *operation),
+            model_id: parsed.as_ref().map(|(_, model_id)| model_id.clone()).unwrap_or_default(),
+            body: body.clone(),
+        });
+        state
+            .scripted
+            .pop_front()
+            .or_else(|| state.default.clone())
+            .unwrap_or_else(|| StubResponse::text(DEFAULT_REPLY.split_inclusive(' ')))
+    };
+    let Some((operation, model_id)) = parsed else {
+        return Ok(error_response(
+            404,
+            "UnknownOperationException",
+            &format!("No operation at {}", path),
+        ));
+    };
+
+    if !response.delay.is_zero() {
+        tokio::time::sleep(response.delay).await;
+    }
+    let completion = Completion::new(ModelFamily::from_model_id(&model_id), &model_id, &body);
+    let response = match operation {
+        StubOperation::InvokeModel => invoke_response(&response, &completion),
+        StubOperation::InvokeModelWithResponseStream => stream_response(response, &completion),
+    };
+    Ok(response)
+}
+
+fn error_response(status: u16, error_type: &str, message: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .header("x-amzn-errortype", error_type)
+        .body(Body::from(json!({ "message": message }).to_string()))
+        .unwrap()
+}
+
+fn invoke_response(response: &StubResponse, completion: &Completion) -> Response<Body> {
+    let (body, output_tokens) = match &response.body {
+        StubBody::Error(exception, message) => {
+            return error_response(exception.status(), exception.name(), message)
+        }
+        StubBody::Chunks(_) | StubBody::RawChunks(_) => {
+            return error_response(
+                500,
+                BedrockException::InternalServer.name(),
+                "The stub response has chunks: stream it with InvokeModelWithResponseStream",
+            )
+        }
+        StubBody::Json(body) => (body.clone(), 0),
+        StubBody::Tokens(tokens) => (completion.response(tokens), tokens.len()),
+    };
+    Response::builder()
+        .status(200)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .header("x-amzn-requestid", "00000000-0000-0000-0000-000000000000")
+        .header("x-amzn-bedrock-input-token-count", completion.input_tokens)
+        .header("x-amzn-bedrock-output-token-count", output_tokens)
+        .header("x-amzn-bedrock-invocation-latency", 100)
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn stream_response(response: StubResponse, completion: &Completion) -> Response<Body> {
+    let mut events: Vec<Bytes> = match &response.body {
+        StubBody::Error(exception, message) => {
+            return error_response(exception.status(), exception.name(), message)
+        }
+        StubBody::Json(body) => vec![chunk_event(body)],
+        StubBody::Chunks(chunks) => chunks.iter().map(chunk_event).collect(),
+        StubBody::RawChunks(chunks) => chunks.iter().map(|chunk| raw_chunk_event(chunk)).collect(),
+        StubBody::Tokens(tokens) => completion.chunks(tokens).iter().map(chunk_event).collect(),
+    };
+    if let Some((exception, message)) = &response.exception {
+        events.push(exception_event(*exception, message));
+    }
+
+    let chunk_delay = response.chunk_delay;
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        for (index, event) in events.into_iter().enumerate() {
+            if index > 0 && !chunk_delay.is_zero() {
+                tokio::time::sleep(chunk_delay).await;
+            }
+            if sender.send_data(event).await.is_err() {
+                return;
+            }
+        }
+    });
+    Response::builder()
+        .status(200)
+        .header(hyper::header::CONTENT_TYPE, "application/vnd.amazon.eventstream")
+        .header("x-amzn-bedrock-content-type", "application/json")
+        .header("x-amzn-requestid", "00000000-0000-0000-0000-000000000000")
+        .body(body)
+        .unwrap()
+}
+
+/// Frames an event-stream message: the prelude with its CRC, the headers, the payload and
+/// the message CRC.
+fn frame(headers: Vec<Header>, payload: Vec<u8>) -> Bytes {
+    let mut buffer = Vec::new();
+    write_message_to(&Message::new_from_parts(headers, payload), &mut buffer)
+        .expect("the event fits in a frame");
+    Bytes::from(buffer)
+}
+
+/// A `chunk` event, carrying a model response chunk encoded in base64.
+fn chunk_event(chunk: &Value) -> Bytes {
+    raw_chunk_event(&chunk.to_string())
+}
+
+fn raw_chunk_event(chunk: &str) -> Bytes {
+    let payload = json!({ "bytes": STANDARD.encode(chunk) });
+    frame(
+        vec![
+            Header::new(":event-type", HeaderValue::String("chunk".into())),
+            Header::new(":content-type", HeaderValue::String("application/json".into())),
+            Header::new(":message-type", HeaderValue::String("event".into())),
+        ],
+        payload.to_string().into_bytes(),
+    )
+}
+
+fn exception_event(exception: BedrockException, message: &str) -> Bytes {
+    frame(
+        vec![
+            Header::new(":exception-type", HeaderValue::String(exception.event_type().into())),
+            Header::new(":content-type", HeaderValue::String("application/json".into())),
+            Header::new(":message-type", HeaderValue::String("exception".into())),
+        ],
+        json!({ "message": message }).to_string().into_bytes(),
+    )
+}
+
+/// Renders the completions of a request in the format of its model family.
+struct Completion {
+    family: ModelFamily,
+    model_id: String,
+    input_tokens: usize,
+}
+
+impl Completion {
+    fn new(family: ModelFamily, model_id: &str, request: &Value) -> Self {
+        let prompt = match family {
+            ModelFamily::Claude => {
+                let messages = request["messages"].as_array().into_iter().flatten();
+                let texts = messages.flat_map(|message| match &message["content"] {
+                    Value::String(text) => vec![text.clone()],
+                    Value::Array(blocks) => blocks
+                        .iter()
+                        .filter_map(|block| block["text"].as_str().map(str::to_string))
+                        .collect(),
+                    _ => Vec::new(),
+                });
+                request["system"]
+                    .as_str()
+                    .map(str::to_string)
+                    .into_iter()
+                    .chain(texts)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+            ModelFamily::Mistral => request["prompt"].as_str().unwrap_or_default().to_string(),
+            ModelFamily::Titan => request["inputText"].as_str().unwrap_or_default().to_string(),
+        };
+        Self {
+            family,
+            model_id: model_id.to_string(),
+            input_tokens: HeuristicTokenizer::new().count_tokens(&prompt),
+        }
+    }
+
+    fn metrics(&self, output_tokens: usize) -> Value {
+        json!({
+            "inputTokenCount": self.input_tokens,
+            "outputTokenCount": output_tokens,
+            "invocationLatency": 100,
+            "firstByteLatency": 50,
+        })
+    }
+
+    /// The response of `InvokeModel`.
+    fn response(&self, tokens: &[String]) -> Value {
+        let text = tokens.concat();
+        match self.family {
+            ModelFamily::Claude => json!({
+                "id": "msg_stub",
+                "type": "message",
+                "role": "assistant",
+                "model": self.model_id,
+                "content": [{ "type": "text", "text": text }],
+                "stop_reason": "end_turn",
+                "stop_sequence": null,
+                "usage": { "input_tokens": self.input_tokens, "output_tokens": tokens.len() },
+            }),
+            ModelFamily::Mistral => json!({
+                "outputs": [{ "text": text, "stop_reason": "stop" }],
+            }),
+            ModelFamily::Titan => json!({
+                "inputTextTokenCount": self.input_tokens,
+                "results": [{
+                    "tokenCount": tokens.len(),
+                    "outputText": text,
+                    "completionReason": "FINISH",
+                }],
+            }),
+        }
+    }
+
+    /// The chunks of `InvokeModelWithResponseStream`.
+    fn chunks(&self, tokens: &[String]) -> Vec<Value> {
+        let metrics = self.metrics(tokens.len());
+        match self.family {
+            ModelFamily::Claude => {
+                let mut chunks = vec![
+                    json!({
+                        "type": "message_start",
+                        "message": {
+                            "id": "msg_stub",
+                            "type": "message",
+                            "role": "assistant",
+                            "model": self.model_id,
+                            "content": [],
+                            "stop_reason": null,
+                            "stop_sequence": null,
+                            "usage": { "input_tokens": self.input_tokens, "output_tokens": 1 },
+                        },
+                    }),
+                    json!({
+                        "type": "content_block_start",
+                        "index": 0,
+                        "content_block": { "type": "text", "text": "" },
+                    }),
+                ];
+                chunks.extend(tokens.iter().map(|token| {
+                    json!({
+                        "type": "content_block_delta",
+                        "index": 0,
+                        "delta": { "type": "text_delta", "text": token },
+                    })
+                }));
+                chunks.push(json!({ "type": "content_block_stop", "index": 0 }));
+                chunks.push(json!({
+                    "type": "message_delta",
+                    "delta": { "stop_reason": "end_turn", "stop_sequence": null },
+                    "usage": { "output_tokens": tokens.len() },
+                }));
+                chunks.push(json!({
+                    "type": "message_stop",
+                    "amazon-bedrock-invocationMetrics": metrics,
+                }));
+                chunks
+            }
+            ModelFamily::Mistral => {
+                let last = tokens.len().saturating_sub(1);
+                let mut chunks: Vec<Value> = tokens
+                    .iter()
+                    .enumerate()
+                    .map(|(index, token)| {
+                        let stop_reason = (index == last).then_some("stop");
+                        json!({ "outputs": [{ "text": token, "stop_reason": stop_reason }] })
+                    })
+                    .collect();
+                if chunks.is_empty() {
+                    chunks.push(json!({ "outputs": [{ "text": "", "stop_reason": "stop" }] }));
+                }
+                if let Some(last) = chunks.last_mut() {
+                    last["amazon-bedrock-invocationMetrics"] = metrics;
+                }
+                chunks
+            }
+            ModelFamily::Titan => {
+                let last = tokens.len().saturating_sub(1);
+                let mut chunks: Vec<Value> = tokens
+                    .iter()
+                    .enumerate()
+                    .map(|(index, token)| {
+                        json!({
+                            "outputText": token,
+                            "index": 0,
+                            "totalOutputTextTokenCount": index + 1,
+                            "completionReason": (index == last).then_some("FINISH"),
+                            "inputTextTokenCount": self.input_tokens,
+                        })
+                    })
+                    .collect();
+                if let Some(last) = chunks.last_mut() {
+                    last["amazon-bedrock-invocationMetrics"] = metrics;
+                }
+                chunks
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bedrock::bedrock_client::BedrockClient;
+    use crate::bedrock::error::BedrockError;
+    use crate::bedrock::models::claude::claude_client::ClaudeClient;
+    use crate::bedrock::models::claude::claude_stream::ConversationStream;
+    use crate::bedrock::models::claude::claude_request_message::{
+        ChatOptions, ContentBlock, ConversationRequest, Message as ClaudeMessage,
+        StreamResultData,
+    };
+    use crate::bedrock::models::claude::error::ClaudeError;
+    use crate::bedrock::models::mistral::error::MistralError;
+    use crate::bedrock::models::mistral::mistral_client::MistralClient;
+    use crate::bedrock::models::mistral::mistral_request_message::MistralRequestBuilder;
+    use crate::bedrock::model_info::Pricing;
+    use crate::usage::{Budget, UsageError, UsageLedger, UsageReporter};
+    use futures::{StreamExt, TryStreamExt};
+
+    fn conversation() -> ConversationRequest {
+        let mut request = ConversationRequest::default();
+        request.messages.push(ClaudeMessage::new_user_message(
+            "What is the capital of France?".to_string(),
+        ));
+        request
+    }
+
+    #[test]
+    fn test_chunk_event_framing() {
+        let event = chunk_event(&json!({ "type": "message_stop" }));
+        let message = aws_smithy_eventstream::frame::read_message_from(event).unwrap();
+
+        let header = |name: &str| {
+            message
+                .headers()
+                .iter()
+                .find(|header| header.name().as_str() == name)
+                .and_then(|header| header.value().as_string().ok())
+                .map(|value| value.as_str().to_string())
+        };
+        assert_eq!(header(":event-type").as_deref(), Some("chunk"));
+        assert_eq!(header(":message-type").as_deref(), Some("event"));
+
+        let payload: Value = serde_json::from_slice(message.payload()).unwrap();
+        let chunk = STANDARD.decode(payload["bytes"].as_str().unwrap()).unwrap();
+        assert_eq!(chunk, br#"{"type":"message_stop"}"#);
+    }
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(
+            parse_path("/model/mistral.mistral-7b-instruct-v0%3A2/invoke-with-response-stream"),
+            Some((
+                StubOperation::InvokeModelWithResponseStream,
+                "mistral.mistral-7b-instruct-v0:2".to_string()
+            ))
+        );
+        assert_eq!(parse_path("/guardrail/abc/version/1/apply"), None);
+    }
+
+    #[tokio::test]
+    async fn test_claude_chat_and_stream() {
+        let server = BedrockStubServer::start().await.unwrap();
+        server.enqueue(StubResponse::text(["Paris", "."]));
+        server.enqueue(StubResponse::text(["Paris", " is", " the", " capital."]));
+        let client = ClaudeClient::new(server.client_options()).await.unwrap();
+        let options = ChatOptions::default();
+
+        let response = client.chat(&conversation(), &options).await.unwrap();
+        assert!(matches!(&response.content[0], ContentBlock::Text { text } if text == "Paris."));
+        assert_eq!(response.usage.output_tokens, 2);
+
+        let events: Vec<StreamResultData> = client
+            .chat_with_stream(&conversation(), &options)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        let text: String = events
+            .iter()
+            .filter_map(|event| match event {
+                StreamResultData::ContentBlockDelta(delta) => delta.delta.text(),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(text, "Paris is the capital.");
+        assert!(matches!(
+            events.last(),
+            Some(StreamResultData::MessageStop(stop)) if stop.invocation_metrics.output_token_count == 4
+        ));
+
+        let requests = server.requests();
+        assert_eq!(requests[1].operation, Some(StubOperation::InvokeModelWithResponseStream));
+        assert_eq!(requests[1].model_id, options.model_id);
+        assert_eq!(requests[1].body["anthropic_version"], "bedrock-2023-05-31");
+    }
+
+    #[tokio::test]
+    async fn test_claude_stream_tool_use() {
+        let server = BedrockStubServer::start().await.unwrap();
+        server.enqueue(StubResponse::chunks(vec![
+            json!({
+                "type": "message_start",
+                "message": {
+                    "id": "msg_01",
+                    "type": "message",
+                    "role": "assistant",
+                    "content": [],
+                    "model": "claude-3-haiku-20240307",
+                    "stop_reason": null,
+                    "stop_sequence": null,
+                    "usage": { "input_tokens": 12, "output_tokens": 1 }
+                }
+            }),
+            json!({
+                "type": "content_block_start",
+                "index": 0,
+                "content_block": {
+                    "type": "tool_use",
+                    "id": "toolu_01",
+                    "name": "get_weather",
+                    "input": {}
+                }
+            }),
+            json!({
+                "type": "content_block_delta",
+                "index": 0,
+                "delta": { "type": "input_json_delta", "partial_json": "{\"city\": \"Pa" }
+            }),
+            json!({
+                "type": "content_block_delta",
+                "index": 0,
+                "delta": { "type": "input_json_delta", "partial_json": "ris\"}" }
+            }),
+            json!({ "type": "content_block_stop", "index": 0 }),
+            json!({
+                "type": "message_delta",
+                "delta": { "stop_reason": "tool_use", "stop_sequence": null },
+                "usage": { "output_tokens": 8 }
+            }),
+            json!({
+                "type": "message_stop",
+                "amazon-bedrock-invocationMetrics": {
+                    "inputTokenCount": 12,
+                    "outputTokenCount": 8,
+                    "invocationLatency": 10,
+                    "firstByteLatency": 5
+                }
+            }),
+        ]));
+        let client = ClaudeClient::new(server.client_options()).await.unwrap();
+
+        let stream = client
+            .chat_with_stream(&conversation(), &ChatOptions::default())
+            .await
+            .unwrap();
+        let response = ConversationStream::new(stream).collect_response().await.unwrap();
+
+        assert!(matches!(
+            &response.content[0],
+            ContentBlock::ToolUse { id, name, input }
+                if id == "toolu_01" && name == "get_weather" && input == &json!({ "city": "Paris" })
+        ));
+        assert_eq!(response.usage.output_tokens, 8);
+    }
+
+    #[tokio::test]
+    async fn test_claude_sampling_parameters() {
+        let server = BedrockStubServer::start().await.unwrap();
+        let client = ClaudeClient::new(server.client_options()).await.unwrap();
+        let options = ChatOptions::default()
+            .with_temperature(0.0)
+            .with_top_k(10)
+            .with_stop_sequences(vec!["END".to_string()]);
+
+        client.chat(&conversation(), &options).await.unwrap();
+
+        let body = &server.requests()[0].body;
+        assert_eq!(body["temperature"], 0.0);
+        assert_eq!(body["top_k"], 10);
+        assert_eq!(body["stop_sequences"], json!(["END"]));
+        assert!(body.get("top_p").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_claude_budget_uses_request_max_tokens() {
+        let server = BedrockStubServer::start().await.unwrap();
+        let options = ChatOptions::default().with_max_tokens(100);
+        let pricing = Pricing {
+            input_per_1k_tokens: 0.0,
+            output_per_1k_tokens: 1.0,
+        };
+        // 100 tokens cost 0.1 and fit the budget, the 1000 tokens of the request do not.
+        let ledger = UsageLedger::new()
+            .with_pricing(options.model_id.clone(), pricing)
+            .with_budget(Budget::per_session(0.5));
+        let client = ClaudeClient::new(server.client_options())
+            .await
+            .unwrap()
+            .with_usage(UsageReporter::new(Arc::new(ledger)));
+        let mut request = conversation();
+        request.max_tokens = Some(1000);
+
+        let result = client.chat(&request, &options).await;
+
+        assert!(matches!(
+            result,
+            Err(ClaudeError::Usage(UsageError::BudgetExceeded(_)))
+        ));
+        assert!(server.requests().is_empty());
+
+        request.max_tokens = Some(100);
+        client.chat(&request, &options).await.unwrap();
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_mistral_budget_uses_max_output_tokens() {
+        let server = BedrockStubServer::start().await.unwrap();
+        let model_id = "mistral.mistral-7b-instruct-v0:2".to_string();
+        let pricing = Pricing {
+            input_per_1k_tokens: 0.0,
+            output_per_1k_tokens: 1.0,
+        };
+        // Without max_tokens, the 8192 output tokens of the model cost 8.192.
+        let ledger = UsageLedger::new()
+            .with_pricing(model_id.clone(), pricing)
+            .with_budget(Budget::per_session(5.0));
+        let client = MistralClient::new(server.client_options())
+            .await
+            .unwrap()
+            .with_usage(UsageReporter::new(Arc::new(ledger)));
+        let prompt = "<s>[INST] Hi [/INST]".to_string();
+
+        let request = MistralRequestBuilder::new(prompt.clone()).build();
+        let result = client.generate(model_id.clone(), &request).await;
+        assert!(matches!(
+            result,
+            Err(MistralError::Usage(UsageError::BudgetExceeded(_)))
+        ));
+        assert!(server.requests().is_empty());
+
+        let request = MistralRequestBuilder::new(prompt).max_tokens(100).build();
+        client.generate(model_id, &request).await.unwrap();
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_mistral_generate_and_stream() {
+        let server = BedrockStubServer::start().await.unwrap();
+        let client = MistralClient::new(server.client_options()).await.unwrap();
+        let model_id = "mistral.mistral-7b-instruct-v0:2".to_string();
+        let request = MistralRequestBuilder::new("<s>[INST] Hi [/INST]".to_string()).build();
+
+        let response = client.generate(model_id.clone(), &request).await.unwrap();
+        assert_eq!(response.outputs[0].text, DEFAULT_REPLY);
+
+        let chunks: Vec<_> = client
+            .generate_with_stream(model_id, &request)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        let text: String = chunks.iter().map(|chunk| chunk.outputs[0].text.as_str()).collect();
+        assert_eq!(text, DEFAULT_REPLY);
+        assert_eq!(chunks.last().unwrap().outputs[0].stop_reason.as_deref(), Some("stop"));
+        let metrics = chunks.last().unwrap().invocation_metrics.as_ref().unwrap();
+        assert!(metrics.output_token_count > 0);
+    }
+
+    #[tokio::test]
+    async fn test_titan_raw_stream() {
+        let server = BedrockStubServer::start().await.unwrap();
+        server.enqueue(StubResponse::text(["Bonjour", "!"]));
+        let client = BedrockClient::new(server.client_options()).await.unwrap();
+
+        let chunks: Vec<Value> = client
+            .generate_raw_stream(
+                "amazon.titan-text-express-v1".to_string(),
+                json!({ "inputText": "Say hello in French" }),
+            )
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1]["completionReason"], "FINISH");
+        assert_eq!(chunks[1]["amazon-bedrock-invocationMetrics"]["outputTokenCount"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_unexpected_response_shape() {
+        let server = BedrockStubServer::start().await.unwrap();
+        server.enqueue(StubResponse::json(json!({ "outputs": [{ "text": "Paris" }] })));
+        let client = ClaudeClient::new(server.client_options()).await.unwrap();
+
+        let result = client.chat(&conversation(), &ChatOptions::default()).await;
+
+        assert!(matches!(result, Err(ClaudeError::Json(_))));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_stream_chunk() {
+        let server = BedrockStubServer::start().await.unwrap();
+        server.enqueue(StubResponse::raw_chunks([
+            r#"{"type":"message_start""#.to_string(),
+            json!({ "type": "message_stop" }).to_string(),
+        ]));
+        let client = BedrockClient::new(server.client_options()).await.unwrap();
+
+        let items: Vec<_> = client
+            .generate_raw_stream("anthropic.claude-3-haiku-20240307-v1:0".to_string(), json!({}))
+            .await
+            .unwrap()
+            .collect()
+            .await;
+
+        assert_eq!(items.len(), 2);
+        assert!(matches!(items[0], Err(BedrockError::Json(_))));
+        assert_eq!(items[1].as_ref().unwrap()["type"], "message_stop");
+    }
+
+    #[tokio::test]
+    async fn test_error_responses() {
+        let server = BedrockStubServer::start().await.unwrap();
+        server.enqueue(StubResponse::error(BedrockException::Throttling, "Too many requests"));
+        server.enqueue(StubResponse::error(BedrockException::Validation, "Malformed input"));
+        server.enqueue(
+            StubResponse::text(["Par"])
+                .then_exception(BedrockException::ModelStreamError, "The model stopped"),
+        );
+        let client = ClaudeClient::new(server.client_options()).await.unwrap();
+        let options = ChatOptions::default();
+
+        match client.chat(&conversation(), &options).await {
+            Err(ClaudeError::Aws(BedrockError::AwsSdkErrorInvokeModel(err))) => {
+                assert!(err.as_service_error().unwrap().is_throttling_exception());
+            }
+            other => panic!("expected a throttling error, got {:?}", other.map(|_| ())),
+        }
+
+        // The stream is opened in the background, so its errors are the items of the stream.
+        let mut stream = Box::pin(client.chat_with_stream(&conversation(), &options).await.unwrap());
+        match futures::StreamExt::next(&mut stream).await {
+            Some(Err(ClaudeError::Aws(BedrockError::AwsSdkErrorInvoke(err)))) => {
+                assert!(err.as_service_error().unwrap().is_validation_exception());
+            }
+            other => panic!("expected a validation error, got an item that is ok: {:?}", other.map(|item| item.is_ok())),
+        }
+
+        let events: Vec<_> = futures::StreamExt::collect(
+            client.chat_with_stream(&conversation(), &options).await.unwrap(),
+        )
+        .await;
+        assert!(events[..events.len() - 1].iter().all(Result::is_ok));
+        assert!(matches!(
+            events.last(),
+            Some(Err(ClaudeError::Aws(BedrockError::AwsSdkError(_))))
+        ));
+    }
+}
@@ -0,0 +1,348 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::testing::error::FixtureError;
+
+/// The environment variable read by `FixtureMode::from_env`.
+pub const FIXTURES_ENV: &str = "HIRAMU_FIXTURES";
+
+/// Whether a cassette records the interactions with the real service or replays them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixtureMode {
+    /// Sends the requests to the real service, and overwrites the fixture file.
+    Record,
+    /// Serves the responses from the fixture file. A request without a recording fails.
+    Replay,
+    /// Replays if the fixture file exists, else records it.
+    Auto,
+}
+
+impl FixtureMode {
+    /// The mode set by `HIRAMU_FIXTURES`: `record`, `replay` or `auto`. Defaults to `Replay`,
+    /// so that the tests never reach the network by accident.
+    pub fn from_env() -> Self {
+        match std::env::var(FIXTURES_ENV).as_deref() {
+            Ok("record") => FixtureMode::Record,
+            Ok("auto") => FixtureMode::Auto,
+            _ => FixtureMode::Replay,
+        }
+    }
+}
+
+/// A request or response body. Stored as text when it is valid UTF-8, else as base64, e.g.
+/// the binary frames of an AWS event stream.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FixtureBody {
+    Text(String),
+    Base64(String),
+}
+
+impl FixtureBody {
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => FixtureBody::Text(text.to_string()),
+            Err(_) => FixtureBody::Base64(STANDARD.encode(bytes)),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, FixtureError> {
+        match self {
+            FixtureBody::Text(text) => Ok(text.as_bytes().to_vec()),
+            FixtureBody::Base64(encoded) => Ok(STANDARD.decode(encoded)?),
+        }
+    }
+
+    /// Whether two bodies carry the same content. JSON bodies are compared as values, so the
+    /// order of their keys does not matter.
+    fn matches(&self, other: &FixtureBody) -> bool {
+        if let (FixtureBody::Text(a), FixtureBody::Text(b)) = (self, other) {
+            if let (Ok(a), Ok(b)) = (
+                serde_json::from_str::<Value>(a),
+                serde_json::from_str::<Value>(b),
+            ) {
+                return a == b;
+            }
+        }
+        self == other
+    }
+}
+
+/// A recorded request. The headers are not recorded, since they carry the credentials and
+/// signatures of the requests.
+///
+/// # Fields
+///
+/// * `method` - The HTTP method, e.g. `POST`.
+/// * `path` - The path and query of the URL, without the host, so that a fixture recorded in
+///   one region or against one Ollama host replays against another.
+/// * `body` - The request body, if not empty.
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<FixtureBody>,
+}
+
+impl RecordedRequest {
+    pub fn new<M: Into<String>, P: Into<String>>(method: M, path: P, body: &[u8]) -> Self {
+        Self {
+            method: method.into(),
+            path: path.into(),
+            body: (!body.is_empty()).then(|| FixtureBody::from_bytes(body)),
+        }
+    }
+
+    fn matches(&self, other: &RecordedRequest) -> bool {
+        self.method.eq_ignore_ascii_case(&other.method)
+            && self.path == other.path
+            && match (&self.body, &other.body) {
+                (Some(a), Some(b)) => a.matches(b),
+                (None, None) => true,
+                _ => false,
+            }
+    }
+}
+
+/// A recorded response.
+///
+/// # Fields
+///
+/// * `status` - The HTTP status code.
+/// * `headers` - The response headers.
+/// * `chunks` - The body, as the chunks it was received in, so that a streamed response
+///   is replayed chunk by chunk.
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    pub chunks: Vec<FixtureBody>,
+}
+
+impl RecordedResponse {
+    /// The body, with the chunks concatenated.
+    pub fn body(&self) -> Result<Vec<u8>, FixtureError> {
+        let mut body = Vec::new();
+        for chunk in &self.chunks {
+            body.extend(chunk.to_bytes()?);
+        }
+        Ok(body)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Default)]
+struct Tape {
+    interactions: Vec<Interaction>,
+    replayed: Vec<bool>,
+}
+
+/// The recorded interactions of a test, usually backed by a JSON fixture file.
+///
+/// In replay mode, a request is served the first recorded interaction with the same method,
+/// path and body that was not served yet, so a test making the same request twice replays
+/// both responses in order.
+#[derive(Debug)]
+pub struct Cassette {
+    path: Option<PathBuf>,
+    mode: FixtureMode,
+    tape: Mutex<Tape>,
+}
+
+impl Cassette {
+    /// Opens a fixture file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The JSON fixture file.
+    /// * `mode` - `Record` starts an empty cassette saved to `path` after each interaction,
+    ///   `Replay` loads `path` and fails if it is missing, and `Auto` picks one of the two.
+    ///
+    pub fn open<P: AsRef<Path>>(path: P, mode: FixtureMode) -> Result<Arc<Self>, FixtureError> {
+        let path = path.as_ref().to_path_buf();
+        let mode = match mode {
+            FixtureMode::Auto if path.exists() => FixtureMode::Replay,
+            FixtureMode::Auto => FixtureMode::Record,
+            mode => mode,
+        };
+        let interactions = match mode {
+            FixtureMode::Replay => {
+                let file = File::open(&path).map_err(|_| {
+                    FixtureError::MissingFixture(format!(
+                        "{} (record it with {}=record)",
+                        path.display(),
+                        FIXTURES_ENV
+                    ))
+                })?;
+                serde_json::from_reader(BufReader::new(file))?
+            }
+            _ => Vec::new(),
+        };
+        Ok(Arc::new(Self::with_interactions(Some(path), mode, interactions)))
+    }
+
+    /// A cassette replaying `interactions`, without a fixture file.
+    pub fn replaying(interactions: Vec<Interaction>) -> Arc<Self> {
+        Arc::new(Self::with_interactions(None, FixtureMode::Replay, interactions))
+    }
+
+    /// A cassette recording in memory, without a fixture file.
+    pub fn recording() -> Arc<Self> {
+        Arc::new(Self::with_interactions(None, FixtureMode::Record, Vec::new()))
+    }
+
+    fn with_interactions(
+        path: Option<PathBuf>,
+        mode: FixtureMode,
+        interactions: Vec<Interaction>,
+    ) -> Self {
+        let replayed = vec![false; interactions.len()];
+        Self {
+            path,
+            mode,
+            tape: Mutex::new(Tape {
+                interactions,
+                replayed,
+            }),
+        }
+    }
+
+    /// `Record` or `Replay`, once `Auto` is resolved.
+    pub fn mode(&self) -> FixtureMode {
+        self.mode
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.mode == FixtureMode::Record
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn interactions(&self) -> Vec<Interaction> {
+        self.tape.lock().unwrap().interactions.clone()
+    }
+
+    /// The number of recorded interactions that were not replayed yet.
+    pub fn remaining(&self) -> usize {
+        let tape = self.tape.lock().unwrap();
+        tape.replayed.iter().filter(|replayed| !**replayed).count()
+    }
+
+    /// The response recorded for a request, which is then marked as replayed.
+    pub fn replay(&self, request: &RecordedRequest) -> Result<RecordedResponse, FixtureError> {
+        let mut tape = self.tape.lock().unwrap();
+        let Tape {
+            interactions,
+            replayed,
+        } = &mut *tape;
+        let position = interactions
+            .iter()
+            .zip(replayed.iter())
+            .position(|(interaction, replayed)| !replayed && interaction.request.matches(request))
+            .ok_or_else(|| {
+                FixtureError::NoInteraction(format!("{} {}", request.method, request.path))
+            })?;
+        replayed[position] = true;
+        Ok(interactions[position].response.clone())
+    }
+
+    /// Appends an interaction, and saves the fixture file.
+    pub fn record(&self, interaction: Interaction) -> Result<(), FixtureError> {
+        let mut tape = self.tape.lock().unwrap();
+        tape.interactions.push(interaction);
+        tape.replayed.push(true);
+        match &self.path {
+            Some(path) => write_fixture(path, &tape.interactions),
+            None => Ok(()),
+        }
+    }
+
+    /// Saves the interactions to `path`, e.g. to keep an in-memory recording.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), FixtureError> {
+        write_fixture(path.as_ref(), &self.tape.lock().unwrap().interactions)
+    }
+}
+
+fn write_fixture(path: &Path, interactions: &[Interaction]) -> Result<(), FixtureError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), interactions)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interaction(body: &str, response: &str) -> Interaction {
+        Interaction {
+            request: RecordedRequest::new("POST", "/api/chat", body.as_bytes()),
+            response: RecordedResponse {
+                status: 200,
+                headers: Vec::new(),
+                chunks: vec![FixtureBody::Text(response.to_string())],
+            },
+        }
+    }
+
+    #[test]
+    fn test_replay_matches_json_bodies_in_order() {
+        let cassette = Cassette::replaying(vec![
+            interaction(r#"{"model":"llama3","stream":true}"#, "first"),
+            interaction(r#"{"model":"llama3","stream":true}"#, "second"),
+        ]);
+
+        let request = RecordedRequest::new("post", "/api/chat", br#"{"stream":true,"model":"llama3"}"#);
+        assert_eq!(cassette.replay(&request).unwrap().body().unwrap(), b"first");
+        assert_eq!(cassette.replay(&request).unwrap().body().unwrap(), b"second");
+        assert!(matches!(
+            cassette.replay(&request),
+            Err(FixtureError::NoInteraction(_))
+        ));
+        assert_eq!(cassette.remaining(), 0);
+    }
+
+    #[test]
+    fn test_binary_bodies_round_trip() {
+        let bytes = [0u8, 0, 0, 42, 0xff, 0xfe];
+        let body = FixtureBody::from_bytes(&bytes);
+        assert!(matches!(body, FixtureBody::Base64(_)));
+        assert_eq!(body.to_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_open_auto_records_then_replays() {
+        let path = std::env::temp_dir()
+            .join(format!("hiramu-cassette-{}", std::process::id()))
+            .join("chat.json");
+
+        let cassette = Cassette::open(&path, FixtureMode::Auto).unwrap();
+        assert!(cassette.is_recording());
+        cassette.record(interaction(r#"{"model":"llama3"}"#, "Paris")).unwrap();
+
+        let cassette = Cassette::open(&path, FixtureMode::Auto).unwrap();
+        assert_eq!(cassette.mode(), FixtureMode::Replay);
+        assert_eq!(cassette.remaining(), 1);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,23 @@
+use serde_json::Error as SerdeJsonError;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum FixtureError {
+    #[error("JSON error: {0}")]
+    Json(#[from] SerdeJsonError),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Base64 error: {0}")]
+    Base64(#[from] base64::DecodeError),
+
+    #[error("HTTP error: {0}")]
+    Http(String),
+
+    #[error("Missing fixture file: {0}")]
+    MissingFixture(String),
+
+    #[error("No recorded interaction for: {0}")]
+    NoInteraction(String),
+}
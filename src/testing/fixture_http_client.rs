@@ -0,0 +1,226 @@
+use std::sync::Arc;
+
+use aws_smithy_runtime_api::client::http::{
+    HttpClient, HttpConnector, HttpConnectorFuture, HttpConnectorSettings, SharedHttpClient,
+    SharedHttpConnector,
+};
+use aws_smithy_runtime_api::client::orchestrator::{HttpRequest, HttpResponse};
+use aws_smithy_runtime_api::client::result::ConnectorError;
+use aws_smithy_runtime_api::client::runtime_components::RuntimeComponents;
+use aws_smithy_runtime_api::http::StatusCode;
+use aws_smithy_types::body::SdkBody;
+use aws_smithy_types::byte_stream::ByteStream;
+
+use crate::testing::cassette::{Cassette, FixtureBody, Interaction, RecordedRequest, RecordedResponse};
+use crate::testing::error::FixtureError;
+
+/// An AWS SDK HTTP client that records or replays the interactions of a `Cassette`.
+///
+/// In record mode, the requests are sent with the default HTTPS client of the SDK, or the
+/// client given to `with_inner`, and the response bodies are recorded chunk by chunk,
+/// including the binary frames of `InvokeModelWithResponseStream`. In replay mode, no request
+/// leaves the process, so any static credentials will do.
+///
+/// # Example
+///
+/// ```no_run
+/// use hiramu::bedrock::{BedrockClient, BedrockClientOptions};
+/// use hiramu::testing::{Cassette, FixtureHttpClient, FixtureMode};
+///
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let cassette = Cassette::open("tests/fixtures/claude_chat.json", FixtureMode::from_env())?;
+/// let options = BedrockClientOptions::new()
+///     .region("us-west-2")
+///     .http_client(FixtureHttpClient::new(cassette));
+/// let client = BedrockClient::new(options).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct FixtureHttpClient {
+    cassette: Arc<Cassette>,
+    inner: Option<SharedHttpClient>,
+}
+
+impl FixtureHttpClient {
+    pub fn new(cassette: Arc<Cassette>) -> Self {
+        let inner = if cassette.is_recording() {
+            aws_smithy_runtime::client::http::hyper_014::default_client()
+        } else {
+            None
+        };
+        Self { cassette, inner }
+    }
+
+    /// Records the requests sent with `inner` instead of the default client.
+    pub fn with_inner(cassette: Arc<Cassette>, inner: SharedHttpClient) -> Self {
+        Self {
+            cassette,
+            inner: Some(inner),
+        }
+    }
+
+    pub fn cassette(&self) -> &Arc<Cassette> {
+        &self.cassette
+    }
+}
+
+impl HttpClient for FixtureHttpClient {
+    fn http_connector(
+        &self,
+        settings: &HttpConnectorSettings,
+        components: &RuntimeComponents,
+    ) -> SharedHttpConnector {
+        let inner = match (self.cassette.is_recording(), &self.inner) {
+            (true, Some(inner)) => Some(inner.http_connector(settings, components)),
+            _ => None,
+        };
+        SharedHttpConnector::new(FixtureConnector {
+            cassette: self.cassette.clone(),
+            inner,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct FixtureConnector {
+    cassette: Arc<Cassette>,
+    inner: Option<SharedHttpConnector>,
+}
+
+impl HttpConnector for FixtureConnector {
+    fn call(&self, request: HttpRequest) -> HttpConnectorFuture {
+        let cassette = self.cassette.clone();
+        let inner = self.inner.clone();
+        HttpConnectorFuture::new(async move {
+            let recorded = RecordedRequest::new(
+                request.method(),
+                path_of(request.uri()),
+                request.body().bytes().unwrap_or_default(),
+            );
+            match inner {
+                Some(inner) => record(&cassette, inner, recorded, request).await,
+                None if cassette.is_recording() => Err(ConnectorError::user(
+                    "no HTTP client to record with: enable the rustls feature of the SDK".into(),
+                )),
+                None => {
+                    let response = cassette
+                        .replay(&recorded)
+                        .map_err(|err| ConnectorError::user(err.into()))?;
+                    to_http_response(&response).map_err(|err| ConnectorError::user(err.into()))
+                }
+            }
+        })
+    }
+}
+
+async fn record(
+    cassette: &Cassette,
+    inner: SharedHttpConnector,
+    recorded: RecordedRequest,
+    request: HttpRequest,
+) -> Result<HttpResponse, ConnectorError> {
+    let response = inner.call(request).await?;
+    let status = response.status().as_u16();
+    let headers: Vec<(String, String)> = response
+        .headers()
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+
+    let mut body = ByteStream::new(response.into_body());
+    let mut chunks = Vec::new();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|err| ConnectorError::io(err.into()))?;
+        chunks.push(FixtureBody::from_bytes(&chunk));
+    }
+
+    let response = RecordedResponse {
+        status,
+        headers,
+        chunks,
+    };
+    let http_response =
+        to_http_response(&response).map_err(|err| ConnectorError::other(err.into(), None))?;
+    cassette
+        .record(Interaction {
+            request: recorded,
+            response,
+        })
+        .map_err(|err| ConnectorError::other(err.into(), None))?;
+    Ok(http_response)
+}
+
+fn to_http_response(response: &RecordedResponse) -> Result<HttpResponse, FixtureError> {
+    let status = StatusCode::try_from(response.status)
+        .map_err(|err| FixtureError::Http(err.to_string()))?;
+    let mut http_response = HttpResponse::new(status, SdkBody::from(response.body()?));
+    for (name, value) in &response.headers {
+        http_response
+            .headers_mut()
+            .try_append(name.clone(), value.clone())
+            .map_err(|err| FixtureError::Http(err.to_string()))?;
+    }
+    Ok(http_response)
+}
+
+/// The path and query of a URL, without the scheme and host.
+fn path_of(uri: &str) -> String {
+    match url::Url::parse(uri) {
+        Ok(url) => match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        },
+        Err(_) => uri.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bedrock::{BedrockClient, BedrockClientOptions};
+
+    #[tokio::test]
+    async fn test_replay_invoke_model() {
+        let cassette = Cassette::replaying(vec![Interaction {
+            request: RecordedRequest::new(
+                "POST",
+                "/model/mistral.mistral-7b-instruct-v0%3A2/invoke",
+                br#"{"prompt":"<s>[INST] Hi [/INST]","max_tokens":10}"#,
+            ),
+            response: RecordedResponse {
+                status: 200,
+                headers: vec![("content-type".to_string(), "application/json".to_string())],
+                chunks: vec![FixtureBody::Text(
+                    r#"{"outputs":[{"text":"Hello!","stop_reason":"stop"}]}"#.to_string(),
+                )],
+            },
+        }]);
+
+        let options = BedrockClientOptions::new()
+            .region("us-west-2")
+            .credentials("AKIDEXAMPLE", "secret", None)
+            .http_client(FixtureHttpClient::new(cassette.clone()));
+        let client = BedrockClient::new(options).await.unwrap();
+
+        let response = client
+            .generate_raw(
+                "mistral.mistral-7b-instruct-v0:2".to_string(),
+                serde_json::json!({"prompt": "<s>[INST] Hi [/INST]", "max_tokens": 10}),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response["outputs"][0]["text"], "Hello!");
+        assert_eq!(cassette.remaining(), 0);
+    }
+
+    #[test]
+    fn test_path_of() {
+        assert_eq!(
+            path_of("https://bedrock-runtime.us-west-2.amazonaws.com/model/m/invoke"),
+            "/model/m/invoke"
+        );
+        assert_eq!(path_of("http://localhost:11434/api/tags?x=1"), "/api/tags?x=1");
+    }
+}
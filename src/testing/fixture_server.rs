@@ -0,0 +1,311 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::StreamExt;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use tokio::sync::oneshot;
+
+use crate::testing::cassette::{Cassette, FixtureBody, Interaction, RecordedRequest, RecordedResponse};
+use crate::testing::error::FixtureError;
+
+/// A local HTTP server that records or replays the interactions of a `Cassette`, for the
+/// clients built on reqwest such as `OllamaClient`.
+///
+/// In record mode, the requests are forwarded to `upstream` and the responses are streamed
+/// back as they arrive, while their chunks are recorded. In replay mode, the recorded
+/// responses are served chunk by chunk. The server stops when it is dropped.
+///
+/// # Example
+///
+/// ```no_run
+/// use hiramu::ollama::OllamaClient;
+/// use hiramu::testing::{Cassette, FixtureMode, FixtureServer};
+///
+/// # async fn example() -> Result<(), hiramu::testing::FixtureError> {
+/// let cassette = Cassette::open("tests/fixtures/ollama_chat.json", FixtureMode::from_env())?;
+/// let server = FixtureServer::start(cassette, "http://localhost:11434").await?;
+/// let client = OllamaClient::new(server.base_url());
+/// # Ok(())
+/// # }
+/// ```
+pub struct FixtureServer {
+    address: SocketAddr,
+    cassette: Arc<Cassette>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl FixtureServer {
+    /// Starts a server on a free local port.
+    ///
+    /// # Arguments
+    ///
+    /// * `cassette` - The interactions to record or replay.
+    /// * `upstream` - The base URL of the real service, only used in record mode.
+    ///
+    pub async fn start<S: Into<String>>(
+        cassette: Arc<Cassette>,
+        upstream: S,
+    ) -> Result<Self, FixtureError> {
+        let upstream = Arc::new(upstream.into().trim_end_matches('/').to_string());
+        let client = reqwest::Client::new();
+
+        let service_cassette = cassette.clone();
+        let make_service = make_service_fn(move |_| {
+            let cassette = service_cassette.clone();
+            let upstream = upstream.clone();
+            let client = client.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    handle(cassette.clone(), client.clone(), upstream.clone(), request)
+                }))
+            }
+        });
+
+        let server = Server::try_bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+            .map_err(|err| FixtureError::Http(err.to_string()))?
+            .serve(make_service);
+        let address = server.local_addr();
+
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            stopped.await.ok();
+        }));
+
+        Ok(Self {
+            address,
+            cassette,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    /// Starts a server replaying `cassette`.
+    pub async fn replay(cassette: Arc<Cassette>) -> Result<Self, FixtureError> {
+        Self::start(cassette, "").await
+    }
+
+    /// The URL to give to the client, e.g. `http://127.0.0.1:49152`.
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn cassette(&self) -> &Arc<Cassette> {
+        &self.cassette
+    }
+}
+
+impl Drop for FixtureServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+async fn handle(
+    cassette: Arc<Cassette>,
+    client: reqwest::Client,
+    upstream: Arc<String>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let response = if cassette.is_recording() {
+        forward(&cassette, &client, &upstream, request).await
+    } else {
+        replay(&cassette, request).await
+    };
+    Ok(response.unwrap_or_else(|err| {
+        Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from(err.to_string()))
+            .unwrap()
+    }))
+}
+
+async fn read_request(request: Request<Body>) -> Result<(RecordedRequest, Bytes), FixtureError> {
+    let (parts, body) = request.into_parts();
+    let body = hyper::body::to_bytes(body)
+        .await
+        .map_err(|err| FixtureError::Http(err.to_string()))?;
+    let path = parts
+        .uri
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or("/");
+    Ok((RecordedRequest::new(parts.method.as_str(), path, &body), body))
+}
+
+async fn replay(cassette: &Cassette, request: Request<Body>) -> Result<Response<Body>, FixtureError> {
+    let (recorded, _) = read_request(request).await?;
+    let response = cassette.replay(&recorded)?;
+
+    let mut builder = Response::builder().status(response.status);
+    for (name, value) in &response.headers {
+        builder = builder.header(name, value);
+    }
+    let chunks = response
+        .chunks
+        .iter()
+        .map(|chunk| chunk.to_bytes().map(Bytes::from))
+        .collect::<Result<Vec<_>, _>>()?;
+    let body = Body::wrap_stream(futures::stream::iter(
+        chunks.into_iter().map(Ok::<_, Infallible>),
+    ));
+    builder
+        .body(body)
+        .map_err(|err| FixtureError::Http(err.to_string()))
+}
+
+async fn forward(
+    cassette: &Arc<Cassette>,
+    client: &reqwest::Client,
+    upstream: &str,
+    request: Request<Body>,
+) -> Result<Response<Body>, FixtureError> {
+    let headers = request.headers().clone();
+    let method = request.method().clone();
+    let (recorded, body) = read_request(request).await?;
+
+    let mut upstream_request = client
+        .request(method, format!("{}{}", upstream, recorded.path))
+        .body(body);
+    if let Some(content_type) = headers.get(hyper::header::CONTENT_TYPE) {
+        upstream_request = upstream_request.header(hyper::header::CONTENT_TYPE, content_type);
+    }
+    let upstream_response = upstream_request
+        .send()
+        .await
+        .map_err(|err| FixtureError::Http(err.to_string()))?;
+
+    let status = upstream_response.status();
+    let response_headers: Vec<(String, String)> = upstream_response
+        .headers()
+        .iter()
+        .filter(|(name, _)| *name != hyper::header::TRANSFER_ENCODING)
+        .filter(|(name, _)| *name != hyper::header::CONTENT_LENGTH)
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+
+    let mut builder = Response::builder().status(status);
+    for (name, value) in &response_headers {
+        builder = builder.header(name, value);
+    }
+
+    // The chunks are streamed to the client as they arrive, and the interaction is recorded
+    // once the upstream response is complete.
+    let (mut sender, body) = Body::channel();
+    let cassette = cassette.clone();
+    let mut upstream_body = upstream_response.bytes_stream();
+    tokio::spawn(async move {
+        let mut chunks = Vec::new();
+        while let Some(chunk) = upstream_body.next().await {
+            match chunk {
+                Ok(chunk) => {
+                    chunks.push(FixtureBody::from_bytes(&chunk));
+                    if sender.send_data(chunk).await.is_err() {
+                        return;
+                    }
+                }
+                Err(_) => {
+                    sender.abort();
+                    return;
+                }
+            }
+        }
+        let _ = cassette.record(Interaction {
+            request: recorded,
+            response: RecordedResponse {
+                status: status.as_u16(),
+                headers: response_headers,
+                chunks,
+            },
+        });
+    });
+
+    builder
+        .body(body)
+        .map_err(|err| FixtureError::Http(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ollama::{GenerateRequestBuilder, OllamaClient};
+    use futures::TryStreamExt;
+
+    fn generate_interaction() -> Interaction {
+        let chunk = |text: &str, done: bool| {
+            FixtureBody::Text(format!(
+                concat!(
+                    r#"{{"model":"llama3","created_at":"2024-04-01T00:00:00Z","response":"{}","done":{}}}"#,
+                    "\n"
+                ),
+                text, done
+            ))
+        };
+        Interaction {
+            request: RecordedRequest::new(
+                "POST",
+                "/api/generate",
+                &serde_json::to_vec(&generate_request()).unwrap(),
+            ),
+            response: RecordedResponse {
+                status: 200,
+                headers: vec![("content-type".to_string(), "application/x-ndjson".to_string())],
+                chunks: vec![chunk("Paris", false), chunk(".", false), chunk("", true)],
+            },
+        }
+    }
+
+    fn generate_request() -> crate::ollama::GenerateRequest {
+        GenerateRequestBuilder::new("llama3".to_string())
+            .prompt("What is the capital of France?".to_string())
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_replay_ollama_stream() {
+        let server = FixtureServer::replay(Cassette::replaying(vec![generate_interaction()]))
+            .await
+            .unwrap();
+        let client = OllamaClient::new(server.base_url());
+
+        let text = client.generate_text(generate_request()).await.unwrap();
+
+        assert_eq!(text, "Paris.");
+        assert_eq!(server.cassette().remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_record_ollama_stream() {
+        // The upstream is itself a replaying server, standing in for Ollama.
+        let upstream = FixtureServer::replay(Cassette::replaying(vec![generate_interaction()]))
+            .await
+            .unwrap();
+        let recorder = FixtureServer::start(Cassette::recording(), upstream.base_url())
+            .await
+            .unwrap();
+        let client = OllamaClient::new(recorder.base_url());
+
+        let responses: Vec<_> = client
+            .generate(generate_request())
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(responses.len(), 3);
+
+        // The interaction is recorded once the body is fully sent.
+        tokio::task::yield_now().await;
+        let interactions = recorder.cassette().interactions();
+        assert_eq!(interactions.len(), 1);
+        assert_eq!(interactions[0].request.path, "/api/generate");
+        assert_eq!(interactions[0].response.chunks.len(), 3);
+    }
+}
@@ -0,0 +1,694 @@
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::Bytes;
+use chrono::Utc;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::sync::oneshot;
+
+use crate::testing::error::FixtureError;
+
+/// The reply of the mock server when no response is scripted for `/api/generate` or `/api/chat`.
+pub const DEFAULT_REPLY: &str = "This is a mock response.";
+
+/// An endpoint of the Ollama API served by `MockOllamaServer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MockEndpoint {
+    Generate,
+    Chat,
+    Embeddings,
+    Tags,
+}
+
+impl MockEndpoint {
+    pub fn path(&self) -> &'static str {
+        match self {
+            MockEndpoint::Generate => "/api/generate",
+            MockEndpoint::Chat => "/api/chat",
+            MockEndpoint::Embeddings => "/api/embeddings",
+            MockEndpoint::Tags => "/api/tags",
+        }
+    }
+
+    fn from_path(path: &str) -> Option<Self> {
+        [
+            MockEndpoint::Generate,
+            MockEndpoint::Chat,
+            MockEndpoint::Embeddings,
+            MockEndpoint::Tags,
+        ]
+        .into_iter()
+        .find(|endpoint| endpoint.path() == path)
+    }
+}
+
+/// How the body of a mock response is split into HTTP chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chunking {
+    /// One chunk per NDJSON line, as Ollama sends them.
+    Lines,
+    /// The whole body in a single chunk.
+    Whole,
+    /// Chunks of at most this many bytes, so that lines are split across chunks.
+    Bytes(usize),
+}
+
+#[derive(Debug, Clone)]
+enum MockBody {
+    /// The tokens of a completion, rendered as the responses of the endpoint.
+    Tokens(Vec<String>),
+    Embedding(Vec<f32>),
+    Json(Value),
+    Lines(Vec<Value>),
+    Raw(String),
+}
+
+/// A scripted response of `MockOllamaServer`.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use hiramu::testing::{Chunking, MockResponse};
+///
+/// let response = MockResponse::text(["Paris", " is", " the", " capital."])
+///     .chunking(Chunking::Bytes(16))
+///     .chunk_delay(Duration::from_millis(10));
+/// ```
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    status: u16,
+    body: MockBody,
+    chunking: Chunking,
+    delay: Duration,
+    chunk_delay: Duration,
+}
+
+impl MockResponse {
+    fn new(body: MockBody) -> Self {
+        Self {
+            status: 200,
+            body,
+            chunking: Chunking::Lines,
+            delay: Duration::ZERO,
+            chunk_delay: Duration::ZERO,
+        }
+    }
+
+    /// A completion streamed token by token by `/api/generate` or `/api/chat`, ending with a
+    /// `done` response carrying the token counts. A request with `"stream": false` gets a
+    /// single response with the whole text.
+    pub fn text<I, S>(tokens: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self::new(MockBody::Tokens(tokens.into_iter().map(Into::into).collect()))
+    }
+
+    /// The response of `/api/embeddings`.
+    pub fn embedding(embedding: Vec<f32>) -> Self {
+        Self::new(MockBody::Embedding(embedding))
+    }
+
+    /// A JSON body, sent as is.
+    pub fn json(body: Value) -> Self {
+        Self::new(MockBody::Json(body))
+    }
+
+    /// An NDJSON body, one line per value.
+    pub fn ndjson(lines: Vec<Value>) -> Self {
+        Self::new(MockBody::Lines(lines))
+    }
+
+    /// A raw body, e.g. to send malformed JSON.
+    pub fn raw<S: Into<String>>(body: S) -> Self {
+        Self::new(MockBody::Raw(body.into()))
+    }
+
+    /// An error, with the `{"error": message}` body of Ollama.
+    pub fn error<S: Into<String>>(status: u16, message: S) -> Self {
+        Self::json(json!({ "error": message.into() })).status(status)
+    }
+
+    pub fn status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn chunking(mut self, chunking: Chunking) -> Self {
+        self.chunking = chunking;
+        self
+    }
+
+    /// Waits before sending the response headers, e.g. to trigger a client timeout.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Waits between the chunks of the body.
+    pub fn chunk_delay(mut self, delay: Duration) -> Self {
+        self.chunk_delay = delay;
+        self
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self.body {
+            MockBody::Tokens(_) | MockBody::Lines(_) => "application/x-ndjson",
+            MockBody::Raw(_) => "text/plain; charset=utf-8",
+            _ => "application/json; charset=utf-8",
+        }
+    }
+
+    /// The body sent for `request` to `endpoint`.
+    fn render(&self, endpoint: MockEndpoint, request: &Value) -> Vec<u8> {
+        match &self.body {
+            MockBody::Tokens(tokens) => {
+                let lines = completion_lines(endpoint, request, tokens);
+                if request.get("stream") == Some(&Value::Bool(false)) {
+                    let mut done = lines.last().cloned().unwrap_or(Value::Null);
+                    let text = tokens.concat();
+                    match endpoint {
+                        MockEndpoint::Chat => done["message"]["content"] = json!(text),
+                        _ => done["response"] = json!(text),
+                    }
+                    serde_json::to_vec(&done).unwrap_or_default()
+                } else {
+                    ndjson(&lines)
+                }
+            }
+            MockBody::Embedding(embedding) => {
+                serde_json::to_vec(&json!({ "embedding": embedding })).unwrap_or_default()
+            }
+            MockBody::Json(body) => serde_json::to_vec(body).unwrap_or_default(),
+            MockBody::Lines(lines) => ndjson(lines),
+            MockBody::Raw(body) => body.clone().into_bytes(),
+        }
+    }
+
+    fn chunks(&self, body: Vec<u8>) -> Vec<Bytes> {
+        let body = Bytes::from(body);
+        match self.chunking {
+            Chunking::Whole => vec![body],
+            Chunking::Bytes(size) => {
+                let size = size.max(1);
+                (0..body.len())
+                    .step_by(size)
+                    .map(|start| body.slice(start..(start + size).min(body.len())))
+                    .collect()
+            }
+            Chunking::Lines => {
+                let mut chunks = Vec::new();
+                let mut start = 0;
+                for (index, byte) in body.iter().enumerate() {
+                    if *byte == b'\n' {
+                        chunks.push(body.slice(start..=index));
+                        start = index + 1;
+                    }
+                }
+                if start < body.len() {
+                    chunks.push(body.slice(start..));
+                }
+                chunks
+            }
+        }
+    }
+}
+
+fn ndjson(lines: &[Value]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for line in lines {
+        body.extend(serde_json::to_vec(line).unwrap_or_default());
+        body.push(b'\n');
+    }
+    body
+}
+
+/// The streamed responses of a completion, in the format of `endpoint`.
+fn completion_lines(endpoint: MockEndpoint, request: &Value, tokens: &[String]) -> Vec<Value> {
+    let model = request.get("model").cloned().unwrap_or(json!("mock"));
+    let prompt = match endpoint {
+        MockEndpoint::Chat => request["messages"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|message| message["content"].as_str())
+            .collect::<Vec<_>>()
+            .join(" "),
+        _ => request["prompt"].as_str().unwrap_or_default().to_string(),
+    };
+    let created_at = Utc::now().to_rfc3339();
+    let line = |token: &str, done: bool| match endpoint {
+        MockEndpoint::Chat => json!({
+            "model": model,
+            "created_at": created_at,
+            "message": { "role": "assistant", "content": token },
+            "done": done,
+        }),
+        _ => json!({
+            "model": model,
+            "created_at": created_at,
+            "response": token,
+            "done": done,
+        }),
+    };
+
+    let mut lines: Vec<Value> = tokens.iter().map(|token| line(token, false)).collect();
+    let mut done = line("", true);
+    let stats = json!({
+        "done_reason": "stop",
+        "total_duration": 1_000_000,
+        "load_duration": 100_000,
+        "prompt_eval_count": prompt.split_whitespace().count(),
+        "prompt_eval_duration": 200_000,
+        "eval_count": tokens.len(),
+        "eval_duration": 700_000,
+    });
+    for (key, value) in stats.as_object().unwrap() {
+        done[key] = value.clone();
+    }
+    if endpoint != MockEndpoint::Chat {
+        done["context"] = json!([1, 2, 3]);
+    }
+    lines.push(done);
+    lines
+}
+
+/// A deterministic embedding of `text`, so that equal texts get equal vectors.
+fn default_embedding(text: &str, dimensions: usize) -> Vec<f32> {
+    let mut embedding = vec![0.0f32; dimensions];
+    for (index, byte) in text.bytes().enumerate() {
+        embedding[index % dimensions] += byte as f32;
+    }
+    let norm = embedding.iter().map(|value| value * value).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        embedding.iter_mut().for_each(|value| *value /= norm);
+    }
+    embedding
+}
+
+/// A request received by `MockOllamaServer`.
+///
+/// # Fields
+///
+/// * `endpoint` - The endpoint, or `None` for an unknown path.
+/// * `path` - The path of the URL.
+/// * `body` - The JSON body, or `Null` if there is none.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockRequest {
+    pub endpoint: Option<MockEndpoint>,
+    pub path: String,
+    pub body: Value,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    scripted: HashMap<MockEndpoint, VecDeque<MockResponse>>,
+    defaults: HashMap<MockEndpoint, MockResponse>,
+    models: Vec<String>,
+    requests: Vec<MockRequest>,
+}
+
+impl MockState {
+    fn response(&mut self, endpoint: MockEndpoint, request: &Value) -> MockResponse {
+        if let Some(response) = self
+            .scripted
+            .get_mut(&endpoint)
+            .and_then(VecDeque::pop_front)
+        {
+            return response;
+        }
+        if let Some(response) = self.defaults.get(&endpoint) {
+            return response.clone();
+        }
+        match endpoint {
+            MockEndpoint::Generate | MockEndpoint::Chat => {
+                MockResponse::text(DEFAULT_REPLY.split_inclusive(' '))
+            }
+            MockEndpoint::Embeddings => MockResponse::embedding(default_embedding(
+                request["prompt"].as_str().unwrap_or_default(),
+                8,
+            )),
+            MockEndpoint::Tags => MockResponse::json(json!({
+                "models": self.models.iter().map(|name| json!({
+                    "name": name,
+                    "model": name,
+                    "modified_at": "2024-04-01T00:00:00Z",
+                    "size": 4_661_224_676u64,
+                    "digest": format!("{:x}", Sha256::digest(name.as_bytes())),
+                    "details": { "format": "gguf", "family": "llama" },
+                })).collect::<Vec<_>>(),
+            })),
+        }
+    }
+}
+
+/// An in-process HTTP server implementing the Ollama endpoints used by `OllamaClient`, to
+/// test the code built on the client without an Ollama install or a model download.
+///
+/// The responses are scripted per endpoint with `enqueue`, served once each in order, or
+/// with `set_default`. Without a script, `/api/generate` and `/api/chat` stream
+/// `DEFAULT_REPLY`, `/api/embeddings` returns a deterministic vector of the prompt, and
+/// `/api/tags` lists the models set with `set_models`. The server stops when it is dropped.
+///
+/// # Example
+///
+/// ```no_run
+/// use hiramu::ollama::{GenerateRequestBuilder, OllamaClient};
+/// use hiramu::testing::{MockEndpoint, MockOllamaServer, MockResponse};
+///
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let server = MockOllamaServer::start().await?;
+/// server.enqueue(MockEndpoint::Generate, MockResponse::text(["Paris", "."]));
+/// server.enqueue(MockEndpoint::Generate, MockResponse::error(429, "slow down"));
+///
+/// let client = OllamaClient::new(server.base_url());
+/// let request = GenerateRequestBuilder::new("llama3".to_string())
+///     .prompt("What is the capital of France?".to_string())
+///     .build();
+/// assert_eq!(client.generate_text(request.clone()).await?, "Paris.");
+/// assert!(client.generate_text(request).await.is_err());
+/// # Ok(())
+/// # }
+/// ```
+pub struct MockOllamaServer {
+    address: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockOllamaServer {
+    /// Starts a server on a free local port.
+    pub async fn start() -> Result<Self, FixtureError> {
+        let state = Arc::new(Mutex::new(MockState {
+            models: vec!["llama3:latest".to_string()],
+            ..MockState::default()
+        }));
+
+        let service_state = state.clone();
+        let make_service = make_service_fn(move |_| {
+            let state = service_state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| handle(state.clone(), request)))
+            }
+        });
+
+        let server = Server::try_bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+            .map_err(|err| FixtureError::Http(err.to_string()))?
+            .serve(make_service);
+        let address = server.local_addr();
+
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            stopped.await.ok();
+        }));
+
+        Ok(Self {
+            address,
+            state,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    /// The URL to give to `OllamaClient::new`, e.g. `http://127.0.0.1:49152`.
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Scripts the next response of `endpoint`. The responses enqueued for an endpoint are
+    /// served once each, in order, before the default response.
+    pub fn enqueue(&self, endpoint: MockEndpoint, response: MockResponse) {
+        let mut state = self.state.lock().unwrap();
+        state.scripted.entry(endpoint).or_default().push_back(response);
+    }
+
+    /// Replaces the response of `endpoint` once its scripted responses are served.
+    pub fn set_default(&self, endpoint: MockEndpoint, response: MockResponse) {
+        self.state.lock().unwrap().defaults.insert(endpoint, response);
+    }
+
+    /// Sets the models listed by `/api/tags`.
+    pub fn set_models<S: AsRef<str>>(&self, models: &[S]) {
+        self.state.lock().unwrap().models =
+            models.iter().map(|model| model.as_ref().to_string()).collect();
+    }
+
+    /// The requests received so far, in order.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// The requests received so far by `endpoint`.
+    pub fn requests_to(&self, endpoint: MockEndpoint) -> Vec<MockRequest> {
+        self.requests()
+            .into_iter()
+            .filter(|request| request.endpoint == Some(endpoint))
+            .collect()
+    }
+}
+
+impl Drop for MockOllamaServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+async fn handle(
+    state: Arc<Mutex<MockState>>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let path = request.uri().path().to_string();
+    let body = hyper::body::to_bytes(request.into_body())
+        .await
+        .unwrap_or_default();
+    let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    let endpoint = MockEndpoint::from_path(&path);
+
+    let response = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(MockRequest {
+            endpoint,
+            path,
+            body: body.clone(),
+        });
+        endpoint.map(|endpoint| (endpoint, state.response(endpoint, &body)))
+    };
+    let Some((endpoint, response)) = response else {
+        // The plain text 404 of the Ollama router.
+        return Ok(Response::builder()
+            .status(404)
+            .body(Body::from("404 page not found"))
+            .unwrap());
+    };
+
+    if !response.delay.is_zero() {
+        tokio::time::sleep(response.delay).await;
+    }
+    let chunks = response.chunks(response.render(endpoint, &body));
+    let builder = Response::builder()
+        .status(response.status)
+        .header(hyper::header::CONTENT_TYPE, response.content_type());
+
+    let chunk_delay = response.chunk_delay;
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            if index > 0 && !chunk_delay.is_zero() {
+                tokio::time::sleep(chunk_delay).await;
+            }
+            if sender.send_data(chunk).await.is_err() {
+                return;
+            }
+        }
+    });
+    Ok(builder.body(body).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ollama::{
+        ChatRequestBuilder, EmbeddingsRequestBuilder, GenerateRequestBuilder, Message,
+        OllamaClient, OllamaError,
+    };
+    use futures::TryStreamExt;
+
+    fn generate_request() -> crate::ollama::GenerateRequest {
+        GenerateRequestBuilder::new("llama3".to_string())
+            .prompt("What is the capital of France?".to_string())
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_generate_with_split_lines() {
+        let server = MockOllamaServer::start().await.unwrap();
+        server.enqueue(
+            MockEndpoint::Generate,
+            MockResponse::text(["Paris", " is", " the", " capital."]).chunking(Chunking::Bytes(7)),
+        );
+        server.enqueue(
+            MockEndpoint::Generate,
+            MockResponse::text(["Paris", "."]).chunking(Chunking::Whole),
+        );
+        let client = OllamaClient::new(server.base_url());
+
+        let responses: Vec<_> = client
+            .generate(generate_request())
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        let text: String = responses.iter().map(|response| response.response.as_str()).collect();
+        assert_eq!(text, "Paris is the capital.");
+        let done = responses.last().unwrap();
+        assert!(done.done);
+        assert_eq!(done.eval_count, Some(4));
+        assert_eq!(done.prompt_eval_count, Some(6));
+
+        assert_eq!(client.generate_text(generate_request()).await.unwrap(), "Paris.");
+        assert_eq!(client.generate_text(generate_request()).await.unwrap(), DEFAULT_REPLY);
+
+        let requests = server.requests_to(MockEndpoint::Generate);
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].body["model"], "llama3");
+    }
+
+    #[tokio::test]
+    async fn test_error_status_mapping() {
+        let server = MockOllamaServer::start().await.unwrap();
+        server.enqueue(MockEndpoint::Chat, MockResponse::error(404, "model 'phi' not found"));
+        server.enqueue(MockEndpoint::Chat, MockResponse::error(429, "too many requests"));
+        server.enqueue(MockEndpoint::Chat, MockResponse::raw("{not json}\n"));
+        server.enqueue(MockEndpoint::Embeddings, MockResponse::error(500, "out of memory"));
+        let client = OllamaClient::new(server.base_url());
+
+        let chat = || {
+            ChatRequestBuilder::new("phi".to_string())
+                .messages(vec![Message::new("user".to_string(), "Hi".to_string())])
+                .build()
+        };
+        assert!(matches!(client.chat(chat()).await.err(), Some(OllamaError::NotFound(_))));
+        assert!(matches!(
+            client.chat(chat()).await.err(),
+            Some(OllamaError::TooManyRequests(_))
+        ));
+        let malformed = client.chat(chat()).await.unwrap().try_collect::<Vec<_>>().await;
+        assert!(matches!(malformed, Err(OllamaError::Json(_))));
+
+        let embeddings = EmbeddingsRequestBuilder::new("nomic".to_string(), "llamas".to_string());
+        assert!(matches!(
+            client.embeddings(embeddings.build()).await,
+            Err(OllamaError::InternalServerError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_default_embeddings_and_tags() {
+        let server = MockOllamaServer::start().await.unwrap();
+        server.set_models(&["llama3:latest", "nomic-embed-text:latest"]);
+        let client = OllamaClient::new(server.base_url());
+
+        let embed = |prompt: &str| {
+            EmbeddingsRequestBuilder::new("nomic".to_string(), prompt.to_string()).build()
+        };
+        let first = client.embeddings(embed("llamas")).await.unwrap().embedding;
+        let second = client.embeddings(embed("llamas")).await.unwrap().embedding;
+        assert_eq!(first.len(), 8);
+        assert_eq!(first, second);
+
+        let tags: Value = reqwest::get(format!("{}/api/tags", server.base_url()))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(tags["models"][1]["name"], "nomic-embed-text:latest");
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_usage() {
+        use crate::bedrock::Pricing;
+        use crate::usage::{Budget, UsageError, UsageLedger, UsageReporter};
+
+        let server = MockOllamaServer::start().await.unwrap();
+        let pricing = Pricing {
+            input_per_1k_tokens: 1.0,
+            output_per_1k_tokens: 0.0,
+        };
+        let ledger = Arc::new(UsageLedger::new().with_pricing("nomic", pricing));
+        let client = OllamaClient::new(server.base_url())
+            .with_usage(UsageReporter::new(ledger.clone()).tag("search"));
+        let request =
+            || EmbeddingsRequestBuilder::new("nomic".to_string(), "llamas".to_string()).build();
+
+        client.embeddings(request()).await.unwrap();
+
+        let records = ledger.records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].tag.as_deref(), Some("search"));
+        assert!(records[0].input_tokens > 0);
+        assert_eq!(records[0].output_tokens, 0);
+
+        let ledger = Arc::new(
+            UsageLedger::new()
+                .with_pricing("nomic", pricing)
+                .with_budget(Budget::per_session(0.0)),
+        );
+        let client = OllamaClient::new(server.base_url()).with_usage(UsageReporter::new(ledger));
+        assert!(matches!(
+            client.embeddings(request()).await,
+            Err(OllamaError::Usage(UsageError::BudgetExceeded(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_delay_and_non_streamed_response() {
+        let server = MockOllamaServer::start().await.unwrap();
+        server.set_default(
+            MockEndpoint::Generate,
+            MockResponse::text(["Hello", "!"]).delay(Duration::from_millis(200)),
+        );
+
+        let request = GenerateRequestBuilder::new("llama3".to_string())
+            .prompt("Hi".to_string())
+            .stream(false)
+            .build();
+        let response: Value = reqwest::Client::new()
+            .post(format!("{}/api/generate", server.base_url()))
+            .json(&request)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(response["response"], "Hello!");
+        assert_eq!(response["done"], true);
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(50))
+            .build()
+            .unwrap();
+        let timed_out = client
+            .post(format!("{}/api/generate", server.base_url()))
+            .json(&request)
+            .send()
+            .await;
+        assert!(timed_out.unwrap_err().is_timeout());
+    }
+}
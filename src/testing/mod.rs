@@ -0,0 +1,43 @@
+//! Record/replay HTTP fixtures, to run the client tests offline.
+//!
+//! A `Cassette` holds the HTTP interactions of a test in a JSON fixture file. In record mode
+//! the requests go to the real service and the responses, including every streamed chunk and
+//! the AWS event-stream frames, are appended to the file. In replay mode the responses are
+//! served from the file, without network access.
+//!
+//! * `FixtureServer` is a local HTTP server for the reqwest-based `OllamaClient`: point the
+//!   client at `FixtureServer::base_url`.
+//! * `FixtureHttpClient` is an HTTP client for the AWS SDK: set it on the Bedrock clients with
+//!   `BedrockClientOptions::http_client`.
+//! * `MockOllamaServer` is a scriptable in-process Ollama server, to test the code built on
+//!   `OllamaClient` without a model, including its error paths and the chunking of streams.
+//! * `BedrockStubServer` is a scriptable in-process Bedrock runtime, serving Claude, Mistral
+//!   and Titan responses, streamed with the AWS event-stream framing, and Bedrock errors.
+//!
+//! The mode is usually read from the `HIRAMU_FIXTURES` environment variable with
+//! `FixtureMode::from_env`, so the same test records with `HIRAMU_FIXTURES=record` and
+//! replays in CI.
+//!
+//! The fixtures bundled in `tests/fixtures` are synthetic: they were recorded against
+//! `MockOllamaServer` and `BedrockStubServer`, not against a live Ollama or Bedrock. They pin
+//! the requests the clients send and how they parse the responses, but not how the real
+//! services answer. Re-record them with `HIRAMU_FIXTURES=record` to check the clients against
+//! the services.
+//!
+//! Requires the `testing` feature.
+
+pub mod bedrock_stub;
+pub mod cassette;
+pub mod error;
+pub mod fixture_http_client;
+pub mod fixture_server;
+pub mod mock_ollama;
+
+pub use bedrock_stub::{
+    BedrockException, BedrockStubServer, ModelFamily, StubOperation, StubRequest, StubResponse,
+};
+pub use cassette::{Cassette, FixtureBody, FixtureMode, Interaction, RecordedRequest, RecordedResponse};
+pub use error::FixtureError;
+pub use fixture_http_client::FixtureHttpClient;
+pub use fixture_server::FixtureServer;
+pub use mock_ollama::{Chunking, MockEndpoint, MockOllamaServer, MockRequest, MockResponse};
@@ -0,0 +1,114 @@
+/// Counts the tokens a piece of text uses for a model.
+///
+/// The crate uses a tokenizer to estimate how much of a model context window a
+/// conversation takes, before the request is sent.
+pub trait Tokenizer: Send + Sync {
+    /// Returns the number of tokens in `text`.
+    fn count_tokens(&self, text: &str) -> usize;
+}
+
+/// A tokenizer that estimates the token count from the text length.
+///
+/// Most BPE tokenizers average about four characters per token on English text, and about
+/// one token per character on scripts such as Chinese or Japanese. The estimate is cheap
+/// and needs no vocabulary, but can be off by 10-20% either way, so leave some margin in
+/// the token budget when using it.
+#[derive(Debug, Clone)]
+pub struct HeuristicTokenizer {
+    chars_per_token: f32,
+}
+
+impl HeuristicTokenizer {
+    pub fn new() -> Self {
+        Self {
+            chars_per_token: 4.0,
+        }
+    }
+
+    /// Sets the average number of ASCII characters per token.
+    pub fn chars_per_token(mut self, chars_per_token: f32) -> Self {
+        self.chars_per_token = chars_per_token.max(1.0);
+        self
+    }
+}
+
+impl Default for HeuristicTokenizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tokenizer for HeuristicTokenizer {
+    fn count_tokens(&self, text: &str) -> usize {
+        let (ascii, other) = text.chars().fold((0usize, 0usize), |(ascii, other), c| {
+            if c.is_ascii() {
+                (ascii + 1, other)
+            } else {
+                (ascii, other + 1)
+            }
+        });
+        (ascii as f32 / self.chars_per_token).ceil() as usize + other
+    }
+}
+
+/// An exact BPE tokenizer using the `tiktoken` vocabularies, which are bundled with the
+/// crate and need no download.
+///
+/// These are the OpenAI vocabularies: exact for OpenAI models, and a close estimate for
+/// other models.
+#[cfg(feature = "tiktoken")]
+pub struct TiktokenTokenizer {
+    bpe: tiktoken_rs::CoreBPE,
+}
+
+#[cfg(feature = "tiktoken")]
+impl TiktokenTokenizer {
+    /// The `cl100k_base` vocabulary, used by GPT-3.5 and GPT-4.
+    pub fn cl100k_base() -> Result<Self, String> {
+        let bpe = tiktoken_rs::cl100k_base().map_err(|err| err.to_string())?;
+        Ok(Self { bpe })
+    }
+
+    /// The `o200k_base` vocabulary, used by GPT-4o.
+    pub fn o200k_base() -> Result<Self, String> {
+        let bpe = tiktoken_rs::o200k_base().map_err(|err| err.to_string())?;
+        Ok(Self { bpe })
+    }
+}
+
+#[cfg(feature = "tiktoken")]
+impl Tokenizer for TiktokenTokenizer {
+    fn count_tokens(&self, text: &str) -> usize {
+        self.bpe.encode_with_special_tokens(text).len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heuristic_tokenizer() {
+        let tokenizer = HeuristicTokenizer::new();
+
+        assert_eq!(tokenizer.count_tokens(""), 0);
+        assert_eq!(tokenizer.count_tokens("abcd"), 1);
+        assert_eq!(tokenizer.count_tokens("abcde"), 2);
+        assert_eq!(tokenizer.count_tokens("日本語"), 3);
+    }
+
+    #[test]
+    fn test_heuristic_tokenizer_chars_per_token() {
+        let tokenizer = HeuristicTokenizer::new().chars_per_token(2.0);
+
+        assert_eq!(tokenizer.count_tokens("abcd"), 2);
+    }
+
+    #[cfg(feature = "tiktoken")]
+    #[test]
+    fn test_tiktoken_tokenizer() {
+        let tokenizer = TiktokenTokenizer::cl100k_base().unwrap();
+
+        assert_eq!(tokenizer.count_tokens("hello world"), 2);
+    }
+}
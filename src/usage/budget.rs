@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+
+/// The time window a budget applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BudgetPeriod {
+    /// Everything recorded by the ledger since it was created or loaded.
+    Session,
+    /// The current UTC calendar day.
+    Day,
+}
+
+/// What happens when a call would exceed a budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BudgetAction {
+    /// The call is not sent and fails with `UsageError::BudgetExceeded`.
+    Reject,
+    /// The call is sent and a `BudgetWarning` is reported.
+    Warn,
+}
+
+/// A spend limit in USD, checked by `UsageLedger::check` before each call.
+///
+/// # Fields
+///
+/// * `limit` - The maximum spend in USD over the period.
+/// * `period` - The time window the spend is summed over.
+/// * `action` - Whether to reject the call or only warn when the limit would be exceeded.
+/// * `tag` - Only count and check the calls made with this caller tag. If `None`, all calls.
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Budget {
+    pub limit: f64,
+    pub period: BudgetPeriod,
+    pub action: BudgetAction,
+    pub tag: Option<String>,
+}
+
+impl Budget {
+    pub fn new(limit: f64, period: BudgetPeriod) -> Self {
+        Self {
+            limit,
+            period,
+            action: BudgetAction::Reject,
+            tag: None,
+        }
+    }
+
+    /// A limit on the spend of the current UTC day.
+    pub fn per_day(limit: f64) -> Self {
+        Self::new(limit, BudgetPeriod::Day)
+    }
+
+    /// A limit on the spend of the ledger lifetime.
+    pub fn per_session(limit: f64) -> Self {
+        Self::new(limit, BudgetPeriod::Session)
+    }
+
+    pub fn action(mut self, action: BudgetAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    /// Only warns when the limit would be exceeded, instead of rejecting the call.
+    pub fn warn_only(self) -> Self {
+        self.action(BudgetAction::Warn)
+    }
+
+    pub fn tag<S: Into<String>>(mut self, tag: S) -> Self {
+        self.tag = Some(tag.into());
+        self
+    }
+
+    /// Whether the calls made with `tag` count toward the budget.
+    pub fn applies_to(&self, tag: Option<&str>) -> bool {
+        match &self.tag {
+            Some(budget_tag) => tag == Some(budget_tag.as_str()),
+            None => true,
+        }
+    }
+}
+
+/// A budget that a call would exceed.
+///
+/// # Fields
+///
+/// * `budget` - The budget that would be exceeded.
+/// * `spent` - The spend already recorded over the budget period.
+/// * `estimated` - The estimated cost of the call.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetWarning {
+    pub budget: Budget,
+    pub spent: f64,
+    pub estimated: f64,
+}
+
+impl std::fmt::Display for BudgetWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "spent ${:.4} + estimated ${:.4} exceeds the {:?} budget of ${:.2}",
+            self.spent, self.estimated, self.budget.period, self.budget.limit
+        )?;
+        if let Some(tag) = &self.budget.tag {
+            write!(f, " for {}", tag)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,16 @@
+use serde_json::Error as SerdeJsonError;
+use thiserror::Error;
+
+use crate::usage::budget::BudgetWarning;
+
+#[derive(Error, Debug)]
+pub enum UsageError {
+    #[error("JSON error: {0}")]
+    Json(#[from] SerdeJsonError),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Budget exceeded: {0}")]
+    BudgetExceeded(BudgetWarning),
+}
@@ -0,0 +1,7 @@
+pub mod budget;
+pub mod error;
+pub mod usage_ledger;
+
+pub use budget::{Budget, BudgetAction, BudgetPeriod, BudgetWarning};
+pub use error::UsageError;
+pub use usage_ledger::{UsageGroup, UsageLedger, UsageRecord, UsageReporter, UsageSummary};
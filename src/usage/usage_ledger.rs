@@ -0,0 +1,512 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::ops::RangeBounds;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::bedrock::model_info::Pricing;
+use crate::bedrock::model_target::ModelTarget;
+use crate::usage::budget::{Budget, BudgetAction, BudgetPeriod, BudgetWarning};
+use crate::usage::error::UsageError;
+
+/// The tokens used by one call.
+///
+/// # Fields
+///
+/// * `timestamp` - When the call completed.
+/// * `model` - The model id, inference profile, ARN or Ollama model name the call was sent to.
+/// * `tag` - The caller tag, e.g. the feature that made the call.
+/// * `input_tokens` - The prompt tokens.
+/// * `output_tokens` - The completion tokens.
+/// * `cost` - The cost in USD, or `None` if the model has no known price.
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub timestamp: DateTime<Utc>,
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost: Option<f64>,
+}
+
+/// How `UsageLedger::summarize` groups the records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageGroup {
+    Model,
+    Tag,
+    /// The UTC day, as `YYYY-MM-DD`.
+    Day,
+}
+
+/// The usage of a group of records.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UsageSummary {
+    /// The model, tag or day of the group. Records without a tag are grouped under `""`.
+    pub key: String,
+    pub requests: usize,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// The cost in USD of the priced records.
+    pub cost: f64,
+    /// The number of records whose model has no known price, and is missing from `cost`.
+    pub unpriced_requests: usize,
+}
+
+type WarningHandler = Arc<dyn Fn(&BudgetWarning) + Send + Sync>;
+
+/// Accumulates the token usage and cost of the calls made by the clients, and enforces
+/// spend budgets.
+///
+/// A ledger is shared between clients with an `Arc`, through a `UsageReporter`. Costs are
+/// computed from the catalogue prices of `ModelInfo`, which can be overridden per model with
+/// `with_pricing`, e.g. for provisioned throughputs or local Ollama models.
+pub struct UsageLedger {
+    records: Mutex<Vec<UsageRecord>>,
+    budgets: Vec<Budget>,
+    pricing: HashMap<String, Pricing>,
+    session_start: DateTime<Utc>,
+    warning_handler: Option<WarningHandler>,
+}
+
+impl UsageLedger {
+    pub fn new() -> Self {
+        Self {
+            records: Mutex::new(Vec::new()),
+            budgets: Vec::new(),
+            pricing: HashMap::new(),
+            session_start: Utc::now(),
+            warning_handler: None,
+        }
+    }
+
+    pub fn with_budget(mut self, budget: Budget) -> Self {
+        self.budgets.push(budget);
+        self
+    }
+
+    /// Sets the price of a model, overriding the catalogue price.
+    pub fn with_pricing<S: Into<String>>(mut self, model: S, pricing: Pricing) -> Self {
+        self.pricing.insert(model.into(), pricing);
+        self
+    }
+
+    /// Sets the function called when a call exceeds a `BudgetAction::Warn` budget.
+    pub fn on_budget_warning<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&BudgetWarning) + Send + Sync + 'static,
+    {
+        self.warning_handler = Some(Arc::new(handler));
+        self
+    }
+
+    pub fn budgets(&self) -> &[Budget] {
+        &self.budgets
+    }
+
+    /// The price of a model: the price set with `with_pricing`, else the catalogue price of
+    /// the base model.
+    pub fn pricing_of(&self, model: &str) -> Option<Pricing> {
+        if let Some(pricing) = self.pricing.get(model) {
+            return Some(*pricing);
+        }
+        self.pricing_of_target(&ModelTarget::parse(model).ok()?)
+    }
+
+    /// The price of a model target: the price set with `with_pricing` for its id, else the
+    /// catalogue price of its base model, e.g. the one set with `ModelTarget::with_base_model`
+    /// for a provisioned throughput.
+    pub fn pricing_of_target(&self, target: &ModelTarget) -> Option<Pricing> {
+        if let Some(pricing) = self.pricing.get(target.id()) {
+            return Some(*pricing);
+        }
+        target.model_info().and_then(|info| info.pricing)
+    }
+
+    /// The cost in USD of a call, or `None` if the model has no known price.
+    pub fn cost(&self, model: &str, input_tokens: u64, output_tokens: u64) -> Option<f64> {
+        self.pricing_of(model)
+            .map(|pricing| pricing.cost(input_tokens, output_tokens))
+    }
+
+    /// Checks a call against the budgets before it is sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - The model the call is sent to.
+    /// * `tag` - The caller tag.
+    /// * `input_tokens` - The estimated prompt tokens.
+    /// * `max_output_tokens` - The maximum completion tokens of the call.
+    ///
+    /// # Returns
+    ///
+    /// The `Warn` budgets the call would exceed, which are also passed to the warning handler,
+    /// or `UsageError::BudgetExceeded` if it would exceed a `Reject` budget.
+    ///
+    pub fn check(
+        &self,
+        model: &str,
+        tag: Option<&str>,
+        input_tokens: u64,
+        max_output_tokens: u64,
+    ) -> Result<Vec<BudgetWarning>, UsageError> {
+        let estimated = self
+            .cost(model, input_tokens, max_output_tokens)
+            .unwrap_or(0.0);
+        self.check_cost(tag, estimated)
+    }
+
+    /// Checks a call to a model target against the budgets, pricing it with
+    /// `pricing_of_target`. See `check`.
+    pub fn check_target(
+        &self,
+        target: &ModelTarget,
+        tag: Option<&str>,
+        input_tokens: u64,
+        max_output_tokens: u64,
+    ) -> Result<Vec<BudgetWarning>, UsageError> {
+        let estimated = self
+            .pricing_of_target(target)
+            .map_or(0.0, |pricing| pricing.cost(input_tokens, max_output_tokens));
+        self.check_cost(tag, estimated)
+    }
+
+    fn check_cost(&self, tag: Option<&str>, estimated: f64) -> Result<Vec<BudgetWarning>, UsageError> {
+        let mut warnings = Vec::new();
+        for budget in self.budgets.iter().filter(|budget| budget.applies_to(tag)) {
+            let spent = self.spent(budget.period, budget.tag.as_deref());
+            if spent + estimated <= budget.limit {
+                continue;
+            }
+            let warning = BudgetWarning {
+                budget: budget.clone(),
+                spent,
+                estimated,
+            };
+            match budget.action {
+                BudgetAction::Reject => return Err(UsageError::BudgetExceeded(warning)),
+                BudgetAction::Warn => warnings.push(warning),
+            }
+        }
+
+        if let Some(handler) = &self.warning_handler {
+            warnings.iter().for_each(|warning| handler(warning));
+        }
+        Ok(warnings)
+    }
+
+    /// Records the usage of a call that just completed.
+    pub fn record(
+        &self,
+        model: &str,
+        tag: Option<&str>,
+        input_tokens: u64,
+        output_tokens: u64,
+    ) -> UsageRecord {
+        self.record_at(Utc::now(), model, tag, input_tokens, output_tokens)
+    }
+
+    /// Records the usage of a call to a model target that just completed, pricing it with
+    /// `pricing_of_target`.
+    pub fn record_target(
+        &self,
+        target: &ModelTarget,
+        tag: Option<&str>,
+        input_tokens: u64,
+        output_tokens: u64,
+    ) -> UsageRecord {
+        let cost = self
+            .pricing_of_target(target)
+            .map(|pricing| pricing.cost(input_tokens, output_tokens));
+        self.push(UsageRecord {
+            timestamp: Utc::now(),
+            model: target.id().to_string(),
+            tag: tag.map(str::to_string),
+            input_tokens,
+            output_tokens,
+            cost,
+        })
+    }
+
+    /// Records the usage of a call that completed at `timestamp`, e.g. a batch inference job.
+    pub fn record_at(
+        &self,
+        timestamp: DateTime<Utc>,
+        model: &str,
+        tag: Option<&str>,
+        input_tokens: u64,
+        output_tokens: u64,
+    ) -> UsageRecord {
+        let record = UsageRecord {
+            timestamp,
+            model: model.to_string(),
+            tag: tag.map(str::to_string),
+            input_tokens,
+            output_tokens,
+            cost: self.cost(model, input_tokens, output_tokens),
+        };
+        self.push(record)
+    }
+
+    fn push(&self, record: UsageRecord) -> UsageRecord {
+        self.records.lock().unwrap().push(record.clone());
+        record
+    }
+
+    /// A copy of all the records.
+    pub fn records(&self) -> Vec<UsageRecord> {
+        self.records.lock().unwrap().clone()
+    }
+
+    /// The spend in USD over a budget period, for a tag or for all calls.
+    pub fn spent(&self, period: BudgetPeriod, tag: Option<&str>) -> f64 {
+        let since = match period {
+            BudgetPeriod::Session => self.session_start,
+            BudgetPeriod::Day => Utc::now().date_naive().and_time(NaiveTime::MIN).and_utc(),
+        };
+        self.records
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|record| record.timestamp >= since)
+            .filter(|record| tag.is_none() || record.tag.as_deref() == tag)
+            .filter_map(|record| record.cost)
+            .sum()
+    }
+
+    /// Sums the records of a time range by model, tag or day.
+    ///
+    /// # Arguments
+    ///
+    /// * `group` - How to group the records.
+    /// * `range` - The time range of the records, e.g. `start..end` for a month, or `..` for all.
+    ///
+    pub fn summarize<R: RangeBounds<DateTime<Utc>>>(
+        &self,
+        group: UsageGroup,
+        range: R,
+    ) -> Vec<UsageSummary> {
+        let mut summaries: BTreeMap<String, UsageSummary> = BTreeMap::new();
+        for record in self.records.lock().unwrap().iter() {
+            if !range.contains(&record.timestamp) {
+                continue;
+            }
+            let key = match group {
+                UsageGroup::Model => record.model.clone(),
+                UsageGroup::Tag => record.tag.clone().unwrap_or_default(),
+                UsageGroup::Day => record.timestamp.format("%Y-%m-%d").to_string(),
+            };
+            let summary = summaries.entry(key.clone()).or_insert_with(|| UsageSummary {
+                key,
+                ..Default::default()
+            });
+            summary.requests += 1;
+            summary.input_tokens += record.input_tokens;
+            summary.output_tokens += record.output_tokens;
+            match record.cost {
+                Some(cost) => summary.cost += cost,
+                None => summary.unpriced_requests += 1,
+            }
+        }
+        summaries.into_values().collect()
+    }
+
+    /// Saves the records as JSON.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), UsageError> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, &*self.records.lock().unwrap())?;
+        Ok(())
+    }
+
+    /// Appends the records saved with `save`. They count toward the daily budgets, but not
+    /// the session budgets.
+    pub fn load<P: AsRef<Path>>(&self, path: P) -> Result<(), UsageError> {
+        let reader = BufReader::new(File::open(path)?);
+        let records: Vec<UsageRecord> = serde_json::from_reader(reader)?;
+        self.records.lock().unwrap().extend(records);
+        Ok(())
+    }
+}
+
+impl Default for UsageLedger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A handle on a shared `UsageLedger` that a client reports its calls into, under a caller tag.
+#[derive(Clone)]
+pub struct UsageReporter {
+    ledger: Arc<UsageLedger>,
+    tag: Option<String>,
+}
+
+impl UsageReporter {
+    pub fn new(ledger: Arc<UsageLedger>) -> Self {
+        Self { ledger, tag: None }
+    }
+
+    pub fn tag<S: Into<String>>(mut self, tag: S) -> Self {
+        self.tag = Some(tag.into());
+        self
+    }
+
+    pub fn ledger(&self) -> &Arc<UsageLedger> {
+        &self.ledger
+    }
+
+    /// Checks a call against the budgets of the ledger, see `UsageLedger::check`.
+    pub fn check(
+        &self,
+        model: &str,
+        input_tokens: u64,
+        max_output_tokens: u64,
+    ) -> Result<Vec<BudgetWarning>, UsageError> {
+        self.ledger
+            .check(model, self.tag.as_deref(), input_tokens, max_output_tokens)
+    }
+
+    /// Records the usage of a call in the ledger.
+    pub fn record(&self, model: &str, input_tokens: u64, output_tokens: u64) -> UsageRecord {
+        self.ledger
+            .record(model, self.tag.as_deref(), input_tokens, output_tokens)
+    }
+
+    /// Checks a call to a model target, see `UsageLedger::check_target`.
+    pub fn check_target(
+        &self,
+        target: &ModelTarget,
+        input_tokens: u64,
+        max_output_tokens: u64,
+    ) -> Result<Vec<BudgetWarning>, UsageError> {
+        self.ledger
+            .check_target(target, self.tag.as_deref(), input_tokens, max_output_tokens)
+    }
+
+    /// Records the usage of a call to a model target in the ledger.
+    pub fn record_target(&self, target: &ModelTarget, input_tokens: u64, output_tokens: u64) -> UsageRecord {
+        self.ledger
+            .record_target(target, self.tag.as_deref(), input_tokens, output_tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    const HAIKU: &str = "anthropic.claude-3-haiku-20240307-v1:0";
+
+    fn haiku_cost(input_tokens: u64, output_tokens: u64) -> f64 {
+        ModelTarget::parse(HAIKU)
+            .unwrap()
+            .model_info()
+            .unwrap()
+            .pricing
+            .unwrap()
+            .cost(input_tokens, output_tokens)
+    }
+
+    #[test]
+    fn test_record_computes_cost() {
+        let ledger = UsageLedger::new().with_pricing("llama3", Pricing {
+            input_per_1k_tokens: 0.0,
+            output_per_1k_tokens: 0.0,
+        });
+
+        let record = ledger.record(HAIKU, Some("search"), 1000, 500);
+        assert_eq!(record.cost, Some(haiku_cost(1000, 500)));
+
+        let record = ledger.record(&format!("us.{}", HAIKU), None, 1000, 500);
+        assert_eq!(record.cost, Some(haiku_cost(1000, 500)));
+
+        assert_eq!(ledger.record("llama3", None, 10, 10).cost, Some(0.0));
+        assert_eq!(ledger.record("mistral:7b", None, 10, 10).cost, None);
+    }
+
+    #[test]
+    fn test_record_target_with_base_model() {
+        let ledger = UsageLedger::new();
+        let arn = "arn:aws:bedrock:us-east-1:123456789012:provisioned-model/abc123";
+        let target = ModelTarget::parse(arn)
+            .unwrap()
+            .with_base_model(ModelTarget::parse(HAIKU).unwrap().base_model().unwrap());
+
+        assert_eq!(ledger.record(arn, None, 1000, 500).cost, None);
+
+        let record = ledger.record_target(&target, None, 1000, 500);
+        assert_eq!(record.model, arn);
+        assert_eq!(record.cost, Some(haiku_cost(1000, 500)));
+    }
+
+    #[test]
+    fn test_reject_budget() {
+        let limit = haiku_cost(10_000, 1000);
+        let ledger = UsageLedger::new().with_budget(Budget::per_day(limit).tag("search"));
+
+        ledger.record(HAIKU, Some("search"), 10_000, 0);
+
+        assert!(ledger.check(HAIKU, Some("search"), 0, 500).is_ok());
+        assert!(matches!(
+            ledger.check(HAIKU, Some("search"), 0, 2000),
+            Err(UsageError::BudgetExceeded(_))
+        ));
+        assert!(ledger.check(HAIKU, Some("chat"), 0, 2000).is_ok());
+    }
+
+    #[test]
+    fn test_warn_budget() {
+        let warned = Arc::new(Mutex::new(0));
+        let counter = warned.clone();
+        let ledger = UsageLedger::new()
+            .with_budget(Budget::per_session(0.0001).warn_only())
+            .on_budget_warning(move |_| *counter.lock().unwrap() += 1);
+
+        let warnings = ledger.check(HAIKU, None, 100_000, 1000).unwrap();
+
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(*warned.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_daily_budget_ignores_previous_days() {
+        let ledger = UsageLedger::new().with_budget(Budget::per_day(0.01));
+        let yesterday = Utc::now() - chrono::TimeDelta::try_days(1).unwrap();
+
+        ledger.record_at(yesterday, HAIKU, None, 1_000_000, 0);
+
+        assert!(ledger.check(HAIKU, None, 100, 100).is_ok());
+    }
+
+    #[test]
+    fn test_summarize() {
+        let ledger = UsageLedger::new();
+        let may = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let june = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        ledger.record_at(may, HAIKU, Some("search"), 100, 10);
+        ledger.record_at(may, HAIKU, Some("chat"), 200, 20);
+        ledger.record_at(june, HAIKU, Some("search"), 300, 30);
+        ledger.record_at(june, "mistral:7b", None, 400, 40);
+
+        let by_tag = ledger.summarize(UsageGroup::Tag, ..);
+        let keys: Vec<&str> = by_tag.iter().map(|summary| summary.key.as_str()).collect();
+        assert_eq!(keys, vec!["", "chat", "search"]);
+        assert_eq!(by_tag[2].requests, 2);
+        assert_eq!(by_tag[2].input_tokens, 400);
+        assert_eq!(by_tag[0].unpriced_requests, 1);
+
+        let june_start = Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+        let by_model = ledger.summarize(UsageGroup::Model, june_start..);
+        assert_eq!(by_model.len(), 2);
+
+        let by_day = ledger.summarize(UsageGroup::Day, ..june_start);
+        assert_eq!(by_day.len(), 1);
+        assert_eq!(by_day[0].key, "2024-05-01");
+        assert_eq!(by_day[0].cost, haiku_cost(100, 10) + haiku_cost(200, 20));
+    }
+}
@@ -0,0 +1,121 @@
+use std::future::Future;
+
+use crate::ollama::{EmbeddingsRequestBuilder as OllamaEmbeddingsRequestBuilder, OllamaClient};
+use crate::openai::{EmbeddingsRequestBuilder, OpenAiClient};
+use crate::vector::error::VectorError;
+
+/// Turns texts into embedding vectors, one per text and in the same order.
+pub trait Embedder: Send + Sync {
+    fn embed(
+        &self,
+        texts: &[String],
+    ) -> impl Future<Output = Result<Vec<Vec<f32>>, VectorError>> + Send;
+}
+
+/// Embeds texts with an Ollama model, one request per text.
+pub struct OllamaEmbedder<'a> {
+    client: &'a OllamaClient,
+    model: String,
+}
+
+impl<'a> OllamaEmbedder<'a> {
+    pub fn new<S: Into<String>>(client: &'a OllamaClient, model: S) -> Self {
+        Self {
+            client,
+            model: model.into(),
+        }
+    }
+}
+
+impl Embedder for OllamaEmbedder<'_> {
+    fn embed(
+        &self,
+        texts: &[String],
+    ) -> impl Future<Output = Result<Vec<Vec<f32>>, VectorError>> + Send {
+        let requests: Vec<_> = texts
+            .iter()
+            .map(|text| OllamaEmbeddingsRequestBuilder::new(self.model.clone(), text.clone()).build())
+            .collect();
+        async move {
+            let mut embeddings = Vec::with_capacity(requests.len());
+            for request in requests {
+                embeddings.push(self.client.embeddings(request).await?.embedding);
+            }
+            Ok(embeddings)
+        }
+    }
+}
+
+/// Embeds texts with an OpenAI-compatible model, in one request.
+pub struct OpenAiEmbedder<'a> {
+    client: &'a OpenAiClient,
+    model: String,
+    dimensions: Option<u32>,
+}
+
+impl<'a> OpenAiEmbedder<'a> {
+    pub fn new<S: Into<String>>(client: &'a OpenAiClient, model: S) -> Self {
+        Self {
+            client,
+            model: model.into(),
+            dimensions: None,
+        }
+    }
+
+    /// Shortens the embeddings, for the models that support it.
+    pub fn dimensions(mut self, dimensions: u32) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
+}
+
+impl Embedder for OpenAiEmbedder<'_> {
+    fn embed(
+        &self,
+        texts: &[String],
+    ) -> impl Future<Output = Result<Vec<Vec<f32>>, VectorError>> + Send {
+        let mut builder = EmbeddingsRequestBuilder::new(self.model.clone(), texts.to_vec());
+        if let Some(dimensions) = self.dimensions {
+            builder = builder.dimensions(dimensions);
+        }
+        let request = builder.build();
+        async move { Ok(self.client.embeddings(request).await?.embeddings()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::splitter::TextSplitter;
+    use crate::testing::{MockEndpoint, MockOllamaServer};
+    use crate::vector::{Document, Metric, VectorStore};
+
+    #[tokio::test]
+    async fn test_ingest_with_ollama() {
+        let server = MockOllamaServer::start().await.unwrap();
+        let client = OllamaClient::new(server.base_url());
+        let embedder = OllamaEmbedder::new(&client, "nomic-embed-text");
+
+        let text = "The cat sleeps.\n\nThe dog barks loudly at the mailman.";
+        let chunks = TextSplitter::paragraphs().chunk_size(40).split(text);
+        let store = VectorStore::new(Metric::Cosine);
+        store
+            .add_documents(&embedder, Document::from_chunks("pets.txt", &chunks))
+            .await
+            .unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.dimensions(), Some(8));
+
+        let results = store
+            .search_text(&embedder, "The dog barks loudly at the mailman.", 1, None)
+            .await
+            .unwrap();
+        assert_eq!(results[0].id, "pets.txt#1");
+        assert_eq!(results[0].metadata["source"], "pets.txt");
+        assert!((results[0].score - 1.0).abs() < 1e-5);
+
+        let requests = server.requests_to(MockEndpoint::Embeddings);
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].body["model"], "nomic-embed-text");
+    }
+}
@@ -0,0 +1,30 @@
+use thiserror::Error;
+
+use crate::ollama::error::OllamaError;
+use crate::openai::error::OpenAiError;
+
+#[derive(Error, Debug)]
+pub enum VectorError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Ollama error: {0}")]
+    Ollama(#[from] OllamaError),
+
+    #[error("OpenAI error: {0}")]
+    OpenAi(#[from] OpenAiError),
+
+    /// A vector does not have the dimensions of the vectors of the store.
+    #[error("Dimension mismatch: expected {expected}, got {actual}")]
+    DimensionMismatch { expected: usize, actual: usize },
+
+    #[error("Invalid vector: {0}")]
+    InvalidVector(String),
+
+    /// A file is not a vector store, or is corrupted.
+    #[error("Invalid format: {0}")]
+    InvalidFormat(String),
+
+    #[error("Embedding error: {0}")]
+    Embedding(String),
+}
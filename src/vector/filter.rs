@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+
+use crate::splitter::Metadata;
+
+/// A condition on the metadata of the records returned by a search.
+///
+/// # Example
+///
+/// ```
+/// use hiramu::splitter::Metadata;
+/// use hiramu::vector::Filter;
+///
+/// let filter = Filter::eq("lang", "en").and(!Filter::any_of("source", ["draft.md", "old.md"]));
+/// let metadata = Metadata::from([
+///     ("lang".to_string(), "en".to_string()),
+///     ("source".to_string(), "guide.md".to_string()),
+/// ]);
+/// assert!(filter.matches(&metadata));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    /// The value of the key equals the value.
+    Eq(String, String),
+    /// The value of the key is one of the values.
+    AnyOf(String, Vec<String>),
+    /// The key is present.
+    Exists(String),
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+    Not(Box<Filter>),
+}
+
+impl Filter {
+    pub fn eq<K: Into<String>, V: Into<String>>(key: K, value: V) -> Self {
+        Filter::Eq(key.into(), value.into())
+    }
+
+    pub fn any_of<K, I, V>(key: K, values: I) -> Self
+    where
+        K: Into<String>,
+        I: IntoIterator<Item = V>,
+        V: Into<String>,
+    {
+        Filter::AnyOf(key.into(), values.into_iter().map(Into::into).collect())
+    }
+
+    pub fn exists<K: Into<String>>(key: K) -> Self {
+        Filter::Exists(key.into())
+    }
+
+    pub fn and(self, other: Filter) -> Self {
+        match self {
+            Filter::And(mut filters) => {
+                filters.push(other);
+                Filter::And(filters)
+            }
+            filter => Filter::And(vec![filter, other]),
+        }
+    }
+
+    pub fn or(self, other: Filter) -> Self {
+        match self {
+            Filter::Or(mut filters) => {
+                filters.push(other);
+                Filter::Or(filters)
+            }
+            filter => Filter::Or(vec![filter, other]),
+        }
+    }
+
+    pub fn matches(&self, metadata: &Metadata) -> bool {
+        match self {
+            Filter::Eq(key, value) => metadata.get(key) == Some(value),
+            Filter::AnyOf(key, values) => metadata
+                .get(key)
+                .is_some_and(|value| values.contains(value)),
+            Filter::Exists(key) => metadata.contains_key(key),
+            Filter::And(filters) => filters.iter().all(|filter| filter.matches(metadata)),
+            Filter::Or(filters) => filters.iter().any(|filter| filter.matches(metadata)),
+            Filter::Not(filter) => !filter.matches(metadata),
+        }
+    }
+}
+
+impl std::ops::Not for Filter {
+    type Output = Filter;
+
+    fn not(self) -> Filter {
+        Filter::Not(Box::new(self))
+    }
+}
@@ -0,0 +1,310 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+/// The parameters of an HNSW index.
+///
+/// # Fields
+///
+/// * `m` - The number of neighbors linked to each new point, twice as many on the bottom
+///   layer. Higher values improve the recall and use more memory.
+/// * `ef_construction` - The number of candidates considered when linking a point. Higher
+///   values build a better graph, more slowly.
+/// * `ef_search` - The number of candidates considered by a search, at least `k`. Higher
+///   values improve the recall and slow the search.
+/// * `seed` - The seed of the random levels of the points, so indexes are reproducible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HnswConfig {
+    pub m: usize,
+    pub ef_construction: usize,
+    pub ef_search: usize,
+    pub seed: u64,
+}
+
+impl HnswConfig {
+    pub fn new() -> Self {
+        Self {
+            m: 16,
+            ef_construction: 200,
+            ef_search: 64,
+            seed: 0x5eed,
+        }
+    }
+
+    pub fn m(mut self, m: usize) -> Self {
+        self.m = m.max(2);
+        self
+    }
+
+    pub fn ef_construction(mut self, ef_construction: usize) -> Self {
+        self.ef_construction = ef_construction.max(1);
+        self
+    }
+
+    pub fn ef_search(mut self, ef_search: usize) -> Self {
+        self.ef_search = ef_search.max(1);
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+impl Default for HnswConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The vectors indexed by an `Hnsw`, by position.
+pub(crate) trait Points {
+    /// The vector at `point`, and its norm.
+    fn vector(&self, point: usize) -> (&[f32], f32);
+
+    /// The similarity of `query` to the vector at `point`: the higher, the more similar.
+    fn score(&self, query: &[f32], query_norm: f32, point: usize) -> f32;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Scored {
+    pub score: f32,
+    pub point: usize,
+}
+
+impl PartialEq for Scored {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then_with(|| other.point.cmp(&self.point))
+    }
+}
+
+/// A hierarchical navigable small world graph: each point is linked to its nearest
+/// neighbors on the bottom layer and on a random number of sparser upper layers, and a
+/// search descends the layers greedily from a single entry point.
+///
+/// The points are the positions of the vectors in `Points`, added in order.
+#[derive(Debug, Clone)]
+pub(crate) struct Hnsw {
+    config: HnswConfig,
+    /// The neighbors of each point, by layer.
+    links: Vec<Vec<Vec<usize>>>,
+    entry: Option<usize>,
+    max_level: usize,
+    rng: u64,
+}
+
+const MAX_LEVEL: usize = 16;
+
+impl Hnsw {
+    pub fn new(config: HnswConfig) -> Self {
+        Self {
+            config,
+            links: Vec::new(),
+            entry: None,
+            max_level: 0,
+            rng: config.seed.max(1),
+        }
+    }
+
+    /// Links the next point, which must be at position `len()` in `points`.
+    pub fn insert<P: Points>(&mut self, points: &P) {
+        let point = self.links.len();
+        let level = self.random_level();
+        self.links.push(vec![Vec::new(); level + 1]);
+        let Some(mut entry) = self.entry else {
+            self.entry = Some(point);
+            self.max_level = level;
+            return;
+        };
+
+        let (query, query_norm) = points.vector(point);
+        for layer in (level + 1..=self.max_level).rev() {
+            entry = self.greedy(query, query_norm, entry, layer, points);
+        }
+        for layer in (0..=level.min(self.max_level)).rev() {
+            let candidates = self.search_layer(
+                query,
+                query_norm,
+                entry,
+                self.config.ef_construction,
+                layer,
+                points,
+            );
+            let neighbors: Vec<usize> = candidates
+                .iter()
+                .take(self.config.m)
+                .map(|candidate| candidate.point)
+                .collect();
+            let max_neighbors = self.max_neighbors(layer);
+            for &neighbor in &neighbors {
+                self.links[neighbor][layer].push(point);
+                if self.links[neighbor][layer].len() > max_neighbors {
+                    self.prune(neighbor, layer, max_neighbors, points);
+                }
+            }
+            self.links[point][layer] = neighbors;
+            entry = candidates[0].point;
+        }
+        if level > self.max_level {
+            self.max_level = level;
+            self.entry = Some(point);
+        }
+    }
+
+    /// The `k` points most similar to `query` among those `accept`ed, best first.
+    ///
+    /// When the filter rejects most candidates, the search is repeated with more
+    /// candidates, up to all the points.
+    pub fn search<P: Points>(
+        &self,
+        query: &[f32],
+        query_norm: f32,
+        k: usize,
+        points: &P,
+        accept: impl Fn(usize) -> bool,
+    ) -> Vec<Scored> {
+        let Some(mut entry) = self.entry else {
+            return Vec::new();
+        };
+        for layer in (1..=self.max_level).rev() {
+            entry = self.greedy(query, query_norm, entry, layer, points);
+        }
+        let mut ef = self.config.ef_search.max(k);
+        loop {
+            let found: Vec<Scored> = self
+                .search_layer(query, query_norm, entry, ef, 0, points)
+                .into_iter()
+                .filter(|candidate| accept(candidate.point))
+                .take(k)
+                .collect();
+            if found.len() >= k || ef >= self.links.len() {
+                return found;
+            }
+            ef = (ef * 4).min(self.links.len());
+        }
+    }
+
+    fn max_neighbors(&self, layer: usize) -> usize {
+        if layer == 0 {
+            self.config.m * 2
+        } else {
+            self.config.m
+        }
+    }
+
+    /// A random level, exponentially rarer as it grows.
+    fn random_level(&mut self) -> usize {
+        // xorshift64
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        let uniform = ((self.rng >> 11) as f64 + 0.5) / (1u64 << 53) as f64;
+        let level_factor = 1.0 / (self.config.m.max(2) as f64).ln();
+        ((-uniform.ln() * level_factor) as usize).min(MAX_LEVEL)
+    }
+
+    /// Keeps the `max_neighbors` nearest neighbors of `point` on `layer`.
+    fn prune<P: Points>(&mut self, point: usize, layer: usize, max_neighbors: usize, points: &P) {
+        let (vector, norm) = points.vector(point);
+        let mut neighbors: Vec<Scored> = self.links[point][layer]
+            .iter()
+            .map(|&neighbor| Scored {
+                score: points.score(vector, norm, neighbor),
+                point: neighbor,
+            })
+            .collect();
+        neighbors.sort_unstable_by(|a, b| b.cmp(a));
+        neighbors.truncate(max_neighbors);
+        self.links[point][layer] = neighbors.into_iter().map(|neighbor| neighbor.point).collect();
+    }
+
+    /// Moves from `entry` to the most similar neighbor until there is none more similar.
+    fn greedy<P: Points>(
+        &self,
+        query: &[f32],
+        query_norm: f32,
+        mut entry: usize,
+        layer: usize,
+        points: &P,
+    ) -> usize {
+        let mut best = points.score(query, query_norm, entry);
+        loop {
+            let mut moved = false;
+            for &neighbor in &self.links[entry][layer] {
+                let score = points.score(query, query_norm, neighbor);
+                if score > best {
+                    best = score;
+                    entry = neighbor;
+                    moved = true;
+                }
+            }
+            if !moved {
+                return entry;
+            }
+        }
+    }
+
+    /// The `ef` points most similar to `query` found on `layer` from `entry`, best first.
+    fn search_layer<P: Points>(
+        &self,
+        query: &[f32],
+        query_norm: f32,
+        entry: usize,
+        ef: usize,
+        layer: usize,
+        points: &P,
+    ) -> Vec<Scored> {
+        let start = Scored {
+            score: points.score(query, query_norm, entry),
+            point: entry,
+        };
+        let mut visited = HashSet::from([entry]);
+        let mut candidates = BinaryHeap::from([start]);
+        let mut results = BinaryHeap::from([Reverse(start)]);
+
+        while let Some(candidate) = candidates.pop() {
+            let worst = results.peek().map_or(f32::MIN, |worst| worst.0.score);
+            if candidate.score < worst && results.len() >= ef {
+                break;
+            }
+            for &neighbor in &self.links[candidate.point][layer] {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let scored = Scored {
+                    score: points.score(query, query_norm, neighbor),
+                    point: neighbor,
+                };
+                let worst = results.peek().map_or(f32::MIN, |worst| worst.0.score);
+                if results.len() < ef || scored.score > worst {
+                    candidates.push(scored);
+                    results.push(Reverse(scored));
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+        let mut results: Vec<Scored> = results.into_iter().map(|result| result.0).collect();
+        results.sort_unstable_by(|a, b| b.cmp(a));
+        results
+    }
+}
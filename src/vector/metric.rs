@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+
+/// How the similarity of two vectors is measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Metric {
+    /// The cosine of the angle between the vectors, from -1 to 1.
+    Cosine,
+    /// The dot product, for normalized embeddings or when the norm is meaningful.
+    Dot,
+    /// The Euclidean distance, reported negated so that higher scores are more similar.
+    L2,
+}
+
+impl Metric {
+    /// The similarity of `a` and `b`: the higher, the more similar. `a_norm` and `b_norm`
+    /// are the Euclidean norms of the vectors, used by `Cosine`.
+    pub(crate) fn score_with_norms(&self, a: &[f32], b: &[f32], a_norm: f32, b_norm: f32) -> f32 {
+        match self {
+            Metric::Cosine => {
+                if a_norm == 0.0 || b_norm == 0.0 {
+                    0.0
+                } else {
+                    dot(a, b) / (a_norm * b_norm)
+                }
+            }
+            Metric::Dot => dot(a, b),
+            Metric::L2 => -a
+                .iter()
+                .zip(b)
+                .map(|(x, y)| (x - y) * (x - y))
+                .sum::<f32>()
+                .sqrt(),
+        }
+    }
+
+    /// The similarity of `a` and `b`: the higher, the more similar.
+    pub fn score(&self, a: &[f32], b: &[f32]) -> f32 {
+        self.score_with_norms(a, b, norm(a), norm(b))
+    }
+
+    pub(crate) fn to_byte(self) -> u8 {
+        match self {
+            Metric::Cosine => 0,
+            Metric::Dot => 1,
+            Metric::L2 => 2,
+        }
+    }
+
+    pub(crate) fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Metric::Cosine),
+            1 => Some(Metric::Dot),
+            2 => Some(Metric::L2),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+pub(crate) fn norm(vector: &[f32]) -> f32 {
+    dot(vector, vector).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scores() {
+        let a = [1.0, 0.0];
+        let b = [3.0, 4.0];
+        assert!((Metric::Cosine.score(&a, &b) - 0.6).abs() < 1e-6);
+        assert_eq!(Metric::Dot.score(&a, &b), 3.0);
+        assert!((Metric::L2.score(&a, &b) + 20f32.sqrt()).abs() < 1e-6);
+        assert_eq!(Metric::Cosine.score(&a, &[0.0, 0.0]), 0.0);
+    }
+}
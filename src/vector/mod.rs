@@ -0,0 +1,18 @@
+//! An in-memory vector store: top-k similarity search with cosine, dot or L2 metrics,
+//! metadata filters, a flat or HNSW index, binary persistence, and embedders that ingest
+//! texts with the Ollama and OpenAI clients.
+
+pub mod embedder;
+pub mod error;
+pub mod filter;
+pub mod hnsw;
+pub mod metric;
+mod persist;
+pub mod store;
+
+pub use embedder::{Embedder, OllamaEmbedder, OpenAiEmbedder};
+pub use error::VectorError;
+pub use filter::Filter;
+pub use hnsw::HnswConfig;
+pub use metric::Metric;
+pub use store::{Document, IndexKind, Record, SearchResult, VectorStore};
@@ -0,0 +1,139 @@
+//! The binary format of a saved `VectorStore`, in little-endian:
+//!
+//! * the magic `HIRAMUVS` and the format version, as a byte;
+//! * the metric and the index kind, as bytes, then for HNSW `m`, `ef_construction` and
+//!   `ef_search` as u32 and `seed` as u64;
+//! * the dimensions, as u32, and the number of records, as u64;
+//! * each record: its id, text and metadata, then its vector as f32.
+//!
+//! Strings are a u32 length followed by UTF-8 bytes, and metadata a u32 count followed by
+//! key-value pairs of strings.
+
+use std::io::{Read, Write};
+
+use crate::splitter::Metadata;
+use crate::vector::error::VectorError;
+use crate::vector::hnsw::HnswConfig;
+use crate::vector::metric::Metric;
+use crate::vector::store::{IndexKind, Inner, Record};
+
+const MAGIC: &[u8; 8] = b"HIRAMUVS";
+const VERSION: u8 = 1;
+
+pub(crate) fn write<W: Write>(inner: &Inner, writer: &mut W) -> Result<(), VectorError> {
+    let (metric, index, dimensions, records) = inner.parts();
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[VERSION, metric.to_byte()])?;
+    match index {
+        IndexKind::Flat => writer.write_all(&[0])?,
+        IndexKind::Hnsw(config) => {
+            writer.write_all(&[1])?;
+            write_u32(writer, config.m)?;
+            write_u32(writer, config.ef_construction)?;
+            write_u32(writer, config.ef_search)?;
+            writer.write_all(&config.seed.to_le_bytes())?;
+        }
+    }
+    write_u32(writer, dimensions.unwrap_or(0))?;
+    writer.write_all(&(records.len() as u64).to_le_bytes())?;
+    for record in records {
+        write_string(writer, &record.id)?;
+        write_string(writer, &record.text)?;
+        write_u32(writer, record.metadata.len())?;
+        for (key, value) in &record.metadata {
+            write_string(writer, key)?;
+            write_string(writer, value)?;
+        }
+        for value in &record.vector {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn read<R: Read>(reader: &mut R) -> Result<Inner, VectorError> {
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(VectorError::InvalidFormat("not a vector store file".to_string()));
+    }
+    let version = read_u8(reader)?;
+    if version != VERSION {
+        return Err(VectorError::InvalidFormat(format!(
+            "unsupported version {}",
+            version
+        )));
+    }
+    let metric = Metric::from_byte(read_u8(reader)?)
+        .ok_or_else(|| VectorError::InvalidFormat("unknown metric".to_string()))?;
+    let index = match read_u8(reader)? {
+        0 => IndexKind::Flat,
+        1 => IndexKind::Hnsw(HnswConfig {
+            m: read_u32(reader)?,
+            ef_construction: read_u32(reader)?,
+            ef_search: read_u32(reader)?,
+            seed: u64::from_le_bytes(read_array(reader)?),
+        }),
+        _ => return Err(VectorError::InvalidFormat("unknown index".to_string())),
+    };
+    let dimensions = read_u32(reader)?;
+    let count = u64::from_le_bytes(read_array(reader)?);
+
+    let mut records = Vec::new();
+    for _ in 0..count {
+        let id = read_string(reader)?;
+        let text = read_string(reader)?;
+        let mut metadata = Metadata::new();
+        for _ in 0..read_u32(reader)? {
+            let key = read_string(reader)?;
+            metadata.insert(key, read_string(reader)?);
+        }
+        let vector = (0..dimensions)
+            .map(|_| read_array(reader).map(f32::from_le_bytes))
+            .collect::<Result<Vec<_>, _>>()?;
+        records.push(Record {
+            id,
+            vector,
+            text,
+            metadata,
+        });
+    }
+    Inner::from_parts(metric, index, records)
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: usize) -> Result<(), VectorError> {
+    let value = u32::try_from(value)
+        .map_err(|_| VectorError::InvalidFormat(format!("{} does not fit a u32", value)))?;
+    writer.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_string<W: Write>(writer: &mut W, value: &str) -> Result<(), VectorError> {
+    write_u32(writer, value.len())?;
+    writer.write_all(value.as_bytes())?;
+    Ok(())
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N], VectorError> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> Result<u8, VectorError> {
+    Ok(read_array::<R, 1>(reader)?[0])
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<usize, VectorError> {
+    Ok(u32::from_le_bytes(read_array(reader)?) as usize)
+}
+
+fn read_string<R: Read>(reader: &mut R) -> Result<String, VectorError> {
+    let length = read_u32(reader)?;
+    let mut bytes = Vec::new();
+    reader.take(length as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != length {
+        return Err(VectorError::InvalidFormat("truncated string".to_string()));
+    }
+    String::from_utf8(bytes).map_err(|_| VectorError::InvalidFormat("invalid UTF-8".to_string()))
+}
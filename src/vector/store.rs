@@ -0,0 +1,648 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use serde::{Deserialize, Serialize};
+
+use crate::splitter::{Chunk, Metadata};
+use crate::vector::embedder::Embedder;
+use crate::vector::error::VectorError;
+use crate::vector::filter::Filter;
+use crate::vector::hnsw::{Hnsw, HnswConfig, Points, Scored};
+use crate::vector::metric::{norm, Metric};
+use crate::vector::persist;
+
+/// A vector with its text and metadata.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub id: String,
+    pub vector: Vec<f32>,
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub metadata: Metadata,
+}
+
+impl Record {
+    pub fn new<S: Into<String>>(id: S, vector: Vec<f32>) -> Self {
+        Self {
+            id: id.into(),
+            vector,
+            text: String::new(),
+            metadata: Metadata::new(),
+        }
+    }
+
+    pub fn with_text<S: Into<String>>(mut self, text: S) -> Self {
+        self.text = text.into();
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+}
+
+/// A text to embed and store.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    pub id: String,
+    pub text: String,
+    pub metadata: Metadata,
+}
+
+impl Document {
+    pub fn new<I: Into<String>, T: Into<String>>(id: I, text: T) -> Self {
+        Self {
+            id: id.into(),
+            text: text.into(),
+            metadata: Metadata::new(),
+        }
+    }
+
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// The chunks of the document `source`, with the ids `{source}#{index}` and `source` in
+    /// their metadata.
+    pub fn from_chunks(source: &str, chunks: &[Chunk]) -> Vec<Document> {
+        chunks
+            .iter()
+            .map(|chunk| {
+                let mut metadata = chunk.metadata.clone();
+                metadata.insert("source".to_string(), source.to_string());
+                Document::new(format!("{}#{}", source, chunk.index), chunk.text.clone())
+                    .with_metadata(metadata)
+            })
+            .collect()
+    }
+}
+
+/// A record found by a search.
+///
+/// # Fields
+///
+/// * `score` - The similarity to the query under the metric of the store: the cosine, the
+///   dot product, or the negated L2 distance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    pub id: String,
+    pub score: f32,
+    pub text: String,
+    pub metadata: Metadata,
+}
+
+/// How a store finds the nearest vectors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexKind {
+    /// Compares the query to every vector: exact, and fast enough up to about 100k vectors.
+    Flat,
+    /// Searches an HNSW graph: approximate, and much faster on large sets.
+    Hnsw(HnswConfig),
+}
+
+struct Entry {
+    record: Record,
+    norm: f32,
+    deleted: bool,
+}
+
+pub(crate) struct Inner {
+    metric: Metric,
+    index: IndexKind,
+    dimensions: Option<usize>,
+    entries: Vec<Entry>,
+    ids: HashMap<String, usize>,
+    hnsw: Option<Hnsw>,
+}
+
+impl Points for Inner {
+    fn vector(&self, point: usize) -> (&[f32], f32) {
+        let entry = &self.entries[point];
+        (&entry.record.vector, entry.norm)
+    }
+
+    fn score(&self, query: &[f32], query_norm: f32, point: usize) -> f32 {
+        let entry = &self.entries[point];
+        self.metric
+            .score_with_norms(query, &entry.record.vector, query_norm, entry.norm)
+    }
+}
+
+impl Inner {
+    fn new(metric: Metric, index: IndexKind) -> Self {
+        Self {
+            metric,
+            index,
+            dimensions: None,
+            entries: Vec::new(),
+            ids: HashMap::new(),
+            hnsw: match index {
+                IndexKind::Flat => None,
+                IndexKind::Hnsw(config) => Some(Hnsw::new(config)),
+            },
+        }
+    }
+
+    fn check(&self, vector: &[f32]) -> Result<(), VectorError> {
+        check_vector(vector, self.dimensions)
+    }
+
+    fn insert(&mut self, record: Record) {
+        self.dimensions = Some(record.vector.len());
+        let entry = Entry {
+            norm: norm(&record.vector),
+            record,
+            deleted: false,
+        };
+        if let Some(&position) = self.ids.get(&entry.record.id) {
+            if self.hnsw.is_none() {
+                self.entries[position] = entry;
+                return;
+            }
+            // The graph links the old vector: leave it as a tombstone.
+            self.entries[position].deleted = true;
+        }
+        self.ids.insert(entry.record.id.clone(), self.entries.len());
+        self.entries.push(entry);
+        if let Some(mut hnsw) = self.hnsw.take() {
+            hnsw.insert(&*self);
+            self.hnsw = Some(hnsw);
+        }
+    }
+
+    fn remove(&mut self, id: &str) -> bool {
+        let Some(position) = self.ids.remove(id) else {
+            return false;
+        };
+        if self.hnsw.is_some() {
+            self.entries[position].deleted = true;
+        } else {
+            self.entries.swap_remove(position);
+            if let Some(moved) = self.entries.get(position) {
+                self.ids.insert(moved.record.id.clone(), position);
+            }
+        }
+        true
+    }
+
+    fn records(&self) -> impl Iterator<Item = &Record> {
+        self.entries
+            .iter()
+            .filter(|entry| !entry.deleted)
+            .map(|entry| &entry.record)
+    }
+
+    fn search(&self, query: &[f32], k: usize, filter: Option<&Filter>) -> Vec<SearchResult> {
+        let query_norm = norm(query);
+        let accept = |point: usize| {
+            let entry = &self.entries[point];
+            !entry.deleted && filter.is_none_or(|filter| filter.matches(&entry.record.metadata))
+        };
+        let found = match &self.hnsw {
+            Some(hnsw) => hnsw.search(query, query_norm, k, self, accept),
+            None => {
+                let mut best = BinaryHeap::with_capacity(k + 1);
+                for point in (0..self.entries.len()).filter(|&point| accept(point)) {
+                    best.push(Reverse(Scored {
+                        score: self.score(query, query_norm, point),
+                        point,
+                    }));
+                    if best.len() > k {
+                        best.pop();
+                    }
+                }
+                let mut found: Vec<Scored> = best.into_iter().map(|scored| scored.0).collect();
+                found.sort_unstable_by(|a, b| b.cmp(a));
+                found
+            }
+        };
+        found
+            .into_iter()
+            .map(|scored| {
+                let record = &self.entries[scored.point].record;
+                SearchResult {
+                    id: record.id.clone(),
+                    score: scored.score,
+                    text: record.text.clone(),
+                    metadata: record.metadata.clone(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// An in-memory vector store with top-k similarity search and metadata filtering.
+///
+/// The store can be shared between threads: searches take a read lock and run
+/// concurrently, inserts and removals take a write lock. Inserting a record with the id of
+/// another replaces it.
+///
+/// # Example
+///
+/// ```
+/// use hiramu::splitter::Metadata;
+/// use hiramu::vector::{Filter, Metric, Record, VectorStore};
+///
+/// let store = VectorStore::new(Metric::Cosine);
+/// store.insert(Record::new("a", vec![1.0, 0.0]).with_text("apples")).unwrap();
+/// store
+///     .insert(
+///         Record::new("b", vec![0.7, 0.7])
+///             .with_metadata(Metadata::from([("lang".to_string(), "fr".to_string())])),
+///     )
+///     .unwrap();
+///
+/// let results = store.search(&[1.0, 0.1], 1, None).unwrap();
+/// assert_eq!(results[0].text, "apples");
+/// let results = store.search(&[1.0, 0.1], 1, Some(&Filter::eq("lang", "fr"))).unwrap();
+/// assert_eq!(results[0].id, "b");
+/// ```
+pub struct VectorStore {
+    inner: RwLock<Inner>,
+}
+
+impl VectorStore {
+    /// A store with a flat index.
+    pub fn new(metric: Metric) -> Self {
+        Self::with_index(metric, IndexKind::Flat)
+    }
+
+    /// A store with an HNSW index.
+    pub fn hnsw(metric: Metric, config: HnswConfig) -> Self {
+        Self::with_index(metric, IndexKind::Hnsw(config))
+    }
+
+    pub fn with_index(metric: Metric, index: IndexKind) -> Self {
+        Self {
+            inner: RwLock::new(Inner::new(metric, index)),
+        }
+    }
+
+    pub fn metric(&self) -> Metric {
+        self.read().metric
+    }
+
+    pub fn index(&self) -> IndexKind {
+        self.read().index
+    }
+
+    /// The dimensions of the vectors, set by the first insert.
+    pub fn dimensions(&self) -> Option<usize> {
+        self.read().dimensions
+    }
+
+    pub fn len(&self) -> usize {
+        self.read().ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn insert(&self, record: Record) -> Result<(), VectorError> {
+        self.insert_many(vec![record])
+    }
+
+    /// Inserts records, or none if one of them is invalid.
+    pub fn insert_many(&self, records: Vec<Record>) -> Result<(), VectorError> {
+        let mut inner = self.write();
+        let mut dimensions = inner.dimensions;
+        for record in &records {
+            check_vector(&record.vector, dimensions)?;
+            // The first record of an empty store sets the dimensions of the others.
+            dimensions.get_or_insert(record.vector.len());
+        }
+        for record in records {
+            inner.insert(record);
+        }
+        Ok(())
+    }
+
+    /// Removes a record, returning whether it was present.
+    pub fn remove(&self, id: &str) -> bool {
+        self.write().remove(id)
+    }
+
+    pub fn get(&self, id: &str) -> Option<Record> {
+        let inner = self.read();
+        inner
+            .ids
+            .get(id)
+            .map(|&position| inner.entries[position].record.clone())
+    }
+
+    /// The records, in no particular order.
+    pub fn records(&self) -> Vec<Record> {
+        self.read().records().cloned().collect()
+    }
+
+    /// The `k` records most similar to `query` that match `filter`, best first.
+    pub fn search(
+        &self,
+        query: &[f32],
+        k: usize,
+        filter: Option<&Filter>,
+    ) -> Result<Vec<SearchResult>, VectorError> {
+        let inner = self.read();
+        if inner.dimensions.is_none() || k == 0 {
+            return Ok(Vec::new());
+        }
+        inner.check(query)?;
+        Ok(inner.search(query, k, filter))
+    }
+
+    /// Rebuilds the index without the replaced and removed records, which an HNSW index
+    /// keeps until then.
+    pub fn compact(&self) {
+        let mut inner = self.write();
+        let mut compacted = Inner::new(inner.metric, inner.index);
+        for entry in inner.entries.drain(..).filter(|entry| !entry.deleted) {
+            compacted.insert(entry.record);
+        }
+        *inner = compacted;
+    }
+
+    /// Embeds the documents with `embedder` and inserts them.
+    pub async fn add_documents<E: Embedder>(
+        &self,
+        embedder: &E,
+        documents: Vec<Document>,
+    ) -> Result<(), VectorError> {
+        if documents.is_empty() {
+            return Ok(());
+        }
+        let texts: Vec<String> = documents.iter().map(|document| document.text.clone()).collect();
+        let vectors = embedder.embed(&texts).await?;
+        if vectors.len() != documents.len() {
+            return Err(VectorError::Embedding(format!(
+                "expected {} embeddings, got {}",
+                documents.len(),
+                vectors.len()
+            )));
+        }
+        let records = documents
+            .into_iter()
+            .zip(vectors)
+            .map(|(document, vector)| Record {
+                id: document.id,
+                vector,
+                text: document.text,
+                metadata: document.metadata,
+            })
+            .collect();
+        self.insert_many(records)
+    }
+
+    /// Embeds `query` with `embedder` and searches the store.
+    pub async fn search_text<E: Embedder>(
+        &self,
+        embedder: &E,
+        query: &str,
+        k: usize,
+        filter: Option<&Filter>,
+    ) -> Result<Vec<SearchResult>, VectorError> {
+        let vector = embedder
+            .embed(&[query.to_string()])
+            .await?
+            .pop()
+            .ok_or_else(|| VectorError::Embedding("no embedding returned".to_string()))?;
+        self.search(&vector, k, filter)
+    }
+
+    /// Saves the store to a binary file, replacing it atomically. The HNSW graph is not
+    /// saved, but rebuilt by `load`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), VectorError> {
+        let path = path.as_ref();
+        let temporary = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&temporary)?);
+            persist::write(&self.read(), &mut writer)?;
+            writer.flush()?;
+        }
+        std::fs::rename(&temporary, path)?;
+        Ok(())
+    }
+
+    /// Loads a store saved by `save`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, VectorError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        Ok(Self {
+            inner: RwLock::new(persist::read(&mut reader)?),
+        })
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, Inner> {
+        self.inner.read().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Inner> {
+        self.inner.write().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Checks that a vector is valid and has the expected dimensions, if there are any yet.
+fn check_vector(vector: &[f32], dimensions: Option<usize>) -> Result<(), VectorError> {
+    if vector.is_empty() {
+        return Err(VectorError::InvalidVector("the vector is empty".to_string()));
+    }
+    if vector.iter().any(|value| !value.is_finite()) {
+        return Err(VectorError::InvalidVector(
+            "the vector has a NaN or infinite value".to_string(),
+        ));
+    }
+    match dimensions {
+        Some(expected) if expected != vector.len() => Err(VectorError::DimensionMismatch {
+            expected,
+            actual: vector.len(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+impl Inner {
+    pub(crate) fn parts(&self) -> (Metric, IndexKind, Option<usize>, Vec<&Record>) {
+        (self.metric, self.index, self.dimensions, self.records().collect())
+    }
+
+    pub(crate) fn from_parts(
+        metric: Metric,
+        index: IndexKind,
+        records: Vec<Record>,
+    ) -> Result<Self, VectorError> {
+        let mut inner = Inner::new(metric, index);
+        for record in records {
+            inner.check(&record.vector)?;
+            inner.insert(record);
+        }
+        Ok(inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic pseudo-random vectors.
+    fn random_vectors(count: usize, dimensions: usize) -> Vec<Vec<f32>> {
+        let mut state = 42u64;
+        (0..count)
+            .map(|_| {
+                (0..dimensions)
+                    .map(|_| {
+                        state = state
+                            .wrapping_mul(6364136223846793005)
+                            .wrapping_add(1442695040888963407);
+                        (state >> 40) as f32 / (1u64 << 24) as f32 - 0.5
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn metadata(key: &str, value: &str) -> Metadata {
+        Metadata::from([(key.to_string(), value.to_string())])
+    }
+
+    #[test]
+    fn test_flat_search() {
+        let store = VectorStore::new(Metric::L2);
+        store
+            .insert_many(vec![
+                Record::new("a", vec![0.0, 0.0]).with_metadata(metadata("lang", "en")),
+                Record::new("b", vec![1.0, 0.0]).with_metadata(metadata("lang", "fr")),
+                Record::new("c", vec![3.0, 0.0]).with_metadata(metadata("lang", "en")),
+            ])
+            .unwrap();
+
+        let results = store.search(&[0.9, 0.0], 2, None).unwrap();
+        let ids: Vec<&str> = results.iter().map(|result| result.id.as_str()).collect();
+        assert_eq!(ids, vec!["b", "a"]);
+        assert!((results[0].score + 0.1).abs() < 1e-6);
+
+        let results = store
+            .search(&[0.9, 0.0], 5, Some(&Filter::eq("lang", "en")))
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1].id, "c");
+
+        store.insert(Record::new("a", vec![5.0, 0.0])).unwrap();
+        assert!(store.remove("b"));
+        assert!(!store.remove("b"));
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.search(&[0.0, 0.0], 1, None).unwrap()[0].id, "c");
+
+        assert!(matches!(
+            store.insert(Record::new("d", vec![1.0])),
+            Err(VectorError::DimensionMismatch { expected: 2, actual: 1 })
+        ));
+        assert!(matches!(
+            store.insert_many(vec![Record::new("e", vec![1.0, f32::NAN])]),
+            Err(VectorError::InvalidVector(_))
+        ));
+        assert_eq!(store.len(), 2);
+    }
+
+    #[test]
+    fn test_failed_first_batch_keeps_store_empty() {
+        let store = VectorStore::new(Metric::Cosine);
+
+        let result = store.insert_many(vec![
+            Record::new("a", vec![1.0, 0.0, 0.0]),
+            Record::new("b", vec![f32::NAN, 0.0, 0.0]),
+        ]);
+
+        assert!(matches!(result, Err(VectorError::InvalidVector(_))));
+        assert!(store.is_empty());
+        store.insert(Record::new("c", vec![1.0, 0.0])).unwrap();
+        assert_eq!(store.search(&[1.0, 0.0], 1, None).unwrap()[0].id, "c");
+    }
+
+    #[test]
+    fn test_hnsw_recall() {
+        let vectors = random_vectors(1000, 16);
+        let flat = VectorStore::new(Metric::Cosine);
+        let hnsw = VectorStore::hnsw(Metric::Cosine, HnswConfig::new().m(8));
+        let records: Vec<Record> = vectors
+            .iter()
+            .enumerate()
+            .map(|(index, vector)| {
+                let parity = if index % 2 == 0 { "even" } else { "odd" };
+                Record::new(index.to_string(), vector.clone())
+                    .with_metadata(metadata("parity", parity))
+            })
+            .collect();
+        flat.insert_many(records.clone()).unwrap();
+        hnsw.insert_many(records).unwrap();
+
+        let mut found = 0;
+        for query in random_vectors(1020, 16).iter().skip(1000) {
+            let expected = flat.search(query, 10, None).unwrap();
+            let results = hnsw.search(query, 10, None).unwrap();
+            found += results
+                .iter()
+                .filter(|result| expected.iter().any(|e| e.id == result.id))
+                .count();
+        }
+        assert!(found >= 180, "recall@10 of {}/200", found);
+
+        let filter = Filter::eq("parity", "odd");
+        let results = hnsw.search(&vectors[3], 5, Some(&filter)).unwrap();
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[0].id, "3");
+        assert!(results.iter().all(|result| result.metadata["parity"] == "odd"));
+
+        hnsw.remove("3");
+        hnsw.insert(Record::new("4", vectors[3].clone())).unwrap();
+        assert_eq!(hnsw.search(&vectors[3], 1, None).unwrap()[0].id, "4");
+        hnsw.compact();
+        assert_eq!(hnsw.len(), 999);
+        assert_eq!(hnsw.search(&vectors[3], 1, None).unwrap()[0].id, "4");
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path =
+            std::env::temp_dir().join(format!("hiramu-vector-store-{}.bin", std::process::id()));
+        let store = VectorStore::hnsw(Metric::Dot, HnswConfig::new().seed(7));
+        store
+            .insert_many(
+                random_vectors(50, 4)
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, vector)| {
+                        Record::new(format!("doc#{}", index), vector)
+                            .with_text(format!("chunk {} é", index))
+                            .with_metadata(metadata("source", "doc"))
+                    })
+                    .collect(),
+            )
+            .unwrap();
+        store.remove("doc#0");
+        store.save(&path).unwrap();
+
+        let loaded = VectorStore::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.len(), 49);
+        assert_eq!(loaded.metric(), Metric::Dot);
+        assert_eq!(loaded.index(), store.index());
+        assert_eq!(loaded.get("doc#7"), store.get("doc#7"));
+        assert!(loaded.get("doc#0").is_none());
+        let query = store.get("doc#9").unwrap().vector;
+        assert_eq!(
+            loaded.search(&query, 3, None).unwrap(),
+            store.search(&query, 3, None).unwrap()
+        );
+
+        std::fs::write(&path, b"not a store").unwrap();
+        let result = VectorStore::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(VectorError::InvalidFormat(_))));
+    }
+}
@@ -1,29 +1,42 @@
-use futures_util::stream::TryStream; 
+use futures_util::stream::TryStream;
 use futures_util::stream::TryStreamExt;
 use hiramu::ollama::GenerateRequestBuilder;
-use hiramu::ollama::GenerateResponse; 
+use hiramu::ollama::GenerateResponse;
 use hiramu::ollama::OllamaClient;
 use hiramu::ollama::OllamaError;
+use hiramu::testing::{Cassette, FixtureMode, FixtureServer};
 use hiramu::util::fetch_and_base64_encode_image;
-use std::io::{self, Write}; 
+use std::io::{self, Write};
+
+/// A client replaying the synthetic `tests/fixtures/<name>.json`, or recording it from a
+/// local Ollama with `HIRAMU_FIXTURES=record`.
+async fn fixture_client(name: &str) -> (FixtureServer, OllamaClient) {
+    let path = format!("{}/tests/fixtures/{}.json", env!("CARGO_MANIFEST_DIR"), name);
+    let cassette = Cassette::open(path, FixtureMode::from_env()).unwrap();
+    let server = FixtureServer::start(cassette, "http://localhost:11434")
+        .await
+        .unwrap();
+    let client = OllamaClient::new(server.base_url());
+    (server, client)
+}
 
 async fn display_response(
     response_stream: impl TryStream<Ok = GenerateResponse, Error = OllamaError>,
-) {
+) -> String {
     response_stream
-        .try_for_each(|response| async {
-            let response = response.response;
-            print!("{}", response);
+        .try_fold(String::new(), |mut acc, response| async move {
+            print!("{}", response.response);
             io::stdout().flush().unwrap();
-            Ok(())
+            acc.push_str(&response.response);
+            Ok(acc)
         })
         .await
-        .unwrap();
+        .unwrap()
 }
 
 #[tokio::test]
 async fn test_generate() {
-    let client = OllamaClient::new("http://localhost:11434".to_string());
+    let (_server, client) = fixture_client("generate").await;
     println!("✅ Client created.");
 
     let request = GenerateRequestBuilder::new("mistral".to_string())
@@ -33,7 +46,8 @@ async fn test_generate() {
     let response_stream = client.generate(request).await.unwrap();
     println!("✅ Request sent to the pirate model. 😂");
 
-    display_response(response_stream).await;
+    let response = display_response(response_stream).await;
+    assert!(!response.is_empty());
 }
 
 #[tokio::test]
@@ -42,7 +56,7 @@ async fn test_generate_with_image() {
     let image = fetch_and_base64_encode_image(image_url).await.unwrap();
     println!("✅ Image path: {} downloaded and transformed.", image_url);
 
-    let client = OllamaClient::new("http://localhost:11434".to_string());
+    let (_server, client) = fixture_client("generate_with_image").await;
     let request = GenerateRequestBuilder::new("llava".to_string())
         .prompt("Describe this image in a few words.".to_string())
         .images(vec![image])
@@ -51,12 +65,13 @@ async fn test_generate_with_image() {
     let response_stream = client.generate(request).await.unwrap();
     println!("✅ Request sent to the llava model. 🌋");
 
-    display_response(response_stream).await;
+    let response = display_response(response_stream).await;
+    assert!(!response.is_empty());
 }
 
 #[tokio::test]
 async fn test_generate_with_options() {
-    let client = OllamaClient::new("http://localhost:11434".to_string());
+    let (_server, client) = fixture_client("generate_with_options").await;
     println!("✅ Client created.");
 
     let options = serde_json::json!({
@@ -73,5 +88,6 @@ async fn test_generate_with_options() {
     let response_stream = client.generate(request).await.unwrap();
     println!("✅ Request sent to the mistral model with custom options.");
 
-    display_response(response_stream).await;
+    let response = display_response(response_stream).await;
+    assert!(!response.is_empty());
 }